@@ -18,6 +18,9 @@ pub fn create(
     progress: impl Progress,
     should_interrupt: &AtomicBool,
     object_hash: gix::hash::Kind,
+    thread_limit: Option<usize>,
+    preferred_pack: Option<PathBuf>,
+    drop_redundant_indices: bool,
 ) -> anyhow::Result<()> {
     let mut out = BufWriter::new(gix::lock::File::acquire_to_update_resource(
         output_path,
@@ -29,7 +32,13 @@ pub fn create(
         &mut out,
         progress,
         should_interrupt,
-        gix::odb::pack::multi_index::write::Options { object_hash },
+        gix::odb::pack::multi_index::write::Options {
+            object_hash,
+            thread_limit,
+            preferred_pack,
+            drop_redundant_indices,
+            ..Default::default()
+        },
     )?;
     out.into_inner()?.commit()?;
     Ok(())
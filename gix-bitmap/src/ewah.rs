@@ -8,6 +8,9 @@ pub mod decode {
     }
 }
 
+/// Decode `data` as EWAH bitmap, returning the bitmap along with all data past it.
+///
+/// Bitmaps which claim to have more bits than their words can represent are rejected.
 pub fn decode(data: &[u8]) -> Result<(Vec, &[u8]), decode::Error> {
     use self::decode::Error;
     use crate::decode;
@@ -23,9 +26,12 @@ pub fn decode(data: &[u8]) -> Result<(Vec, &[u8]), decode::Error> {
     // NOTE: git does this by copying all bytes first, and then it will change the endianness in a separate loop.
     //       Maybe it's faster, but we can't do it without unsafe. Let's leave it to the optimizer and maybe
     //       one day somebody will find out that it's worth it to use unsafe here.
-    let (mut bits, data) = decode::split_at_pos(data, len * std::mem::size_of::<u64>()).ok_or(Error::Corrupt {
-        message: "eof while reading bit data",
-    })?;
+    let (mut bits, data) = len
+        .checked_mul(std::mem::size_of::<u64>())
+        .and_then(|num_bytes| decode::split_at_pos(data, num_bytes))
+        .ok_or(Error::Corrupt {
+            message: "eof while reading bit data",
+        })?;
     let mut buf = std::vec::Vec::<u64>::with_capacity(len);
     for _ in 0..len {
         let (bit_num, rest) = bits.split_at(std::mem::size_of::<u64>());
@@ -37,6 +43,24 @@ pub fn decode(data: &[u8]) -> Result<(Vec, &[u8]), decode::Error> {
         message: "eof while reading run length width",
     })?;
 
+    let mut num_bits_in_words = 0u64;
+    let mut words = buf.iter();
+    while let Some(word) = words.next() {
+        num_bits_in_words += access::rlw_running_len_bits(word);
+        let num_literal_words = access::rlw_literal_words(word);
+        for _ in 0..num_literal_words {
+            words.next().ok_or(Error::Corrupt {
+                message: "ran out of words while going through uncompressed portion",
+            })?;
+        }
+        num_bits_in_words += num_literal_words * 64;
+    }
+    if u64::from(num_bits) > num_bits_in_words {
+        return Err(Error::Corrupt {
+            message: "the declared amount of bits exceeds the amount of bits in all words",
+        });
+    }
+
     Ok((
         Vec {
             num_bits,
@@ -47,6 +71,8 @@ pub fn decode(data: &[u8]) -> Result<(Vec, &[u8]), decode::Error> {
     ))
 }
 
+pub use access::Iter;
+
 mod access {
     use std::convert::{TryFrom, TryInto};
 
@@ -58,62 +84,252 @@ mod access {
         ///
         /// The index is sequential like in any other vector.
         pub fn for_each_set_bit(&self, mut f: impl FnMut(usize) -> Option<()>) -> Option<()> {
-            let mut index = 0usize;
-            let mut iter = self.bits.iter();
-            while let Some(word) = iter.next() {
-                if rlw_runbit_is_set(word) {
-                    let len = rlw_running_len_bits(word);
-                    for _ in 0..len {
-                        f(index)?;
-                        index += 1;
-                    }
-                } else {
-                    index += usize::try_from(rlw_running_len_bits(word)).ok()?;
-                }
-
-                for _ in 0..rlw_literal_words(word) {
-                    let word = iter
-                        .next()
-                        .expect("BUG: ran out of words while going through uncompressed portion");
-                    for bit_index in 0..64 {
-                        if word & (1 << bit_index) != 0 {
-                            f(index)?;
-                        }
-                        index += 1;
-                    }
-                }
+            for index in self.iter() {
+                f(index)?;
             }
             Some(())
         }
 
+        /// Return an iterator over the indices of all bits that are true, in ascending order.
+        pub fn iter(&self) -> Iter<'_> {
+            Iter {
+                words: self.bits.iter(),
+                num_bits: self.num_bits(),
+                index: 0,
+                run_is_set: false,
+                run_len: 0,
+                num_literal_words: 0,
+                literal: 0,
+                literal_index: 0,
+            }
+        }
+
         /// The amount of bits we are currently holding.
         pub fn num_bits(&self) -> usize {
             self.num_bits.try_into().expect("we are not on 16 bit systems")
         }
     }
 
+    /// An iterator over the indices of set bits in a [`Vec`], created by [`Vec::iter()`].
+    pub struct Iter<'a> {
+        words: std::slice::Iter<'a, u64>,
+        num_bits: usize,
+        /// The index of the bit following the current run or literal word.
+        index: usize,
+        run_is_set: bool,
+        /// The amount of bits left in the current run.
+        run_len: usize,
+        /// The amount of literal words following the current one.
+        num_literal_words: u64,
+        /// The bits of the current literal word that we are yet to return.
+        literal: u64,
+        /// The index of the first bit in `literal`.
+        literal_index: usize,
+    }
+
+    impl<'a> Iterator for Iter<'a> {
+        type Item = usize;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            loop {
+                if self.run_len != 0 {
+                    if self.run_is_set {
+                        let index = self.index;
+                        self.index += 1;
+                        self.run_len -= 1;
+                        return (index < self.num_bits).then_some(index);
+                    }
+                    self.index = self.index.checked_add(self.run_len)?;
+                    self.run_len = 0;
+                    continue;
+                }
+                if self.literal != 0 {
+                    let index = self.literal_index + self.literal.trailing_zeros() as usize;
+                    self.literal &= self.literal - 1;
+                    return (index < self.num_bits).then_some(index);
+                }
+                if self.num_literal_words != 0 {
+                    self.num_literal_words -= 1;
+                    self.literal = *self.words.next()?;
+                    self.literal_index = self.index;
+                    self.index = self.index.checked_add(64)?;
+                    continue;
+                }
+                let word = self.words.next()?;
+                self.run_is_set = rlw_runbit_is_set(word);
+                self.run_len = usize::try_from(rlw_running_len_bits(word)).ok()?;
+                self.num_literal_words = rlw_literal_words(word);
+            }
+        }
+    }
+
     #[inline]
-    fn rlw_running_len_bits(w: &u64) -> u64 {
+    pub(super) fn rlw_running_len_bits(w: &u64) -> u64 {
         rlw_running_len(w) * 64
     }
 
     #[inline]
-    fn rlw_running_len(w: &u64) -> u64 {
+    pub(super) fn rlw_running_len(w: &u64) -> u64 {
         (w >> 1) & RLW_LARGEST_RUNNING_COUNT
     }
 
     #[inline]
-    fn rlw_literal_words(w: &u64) -> u64 {
+    pub(super) fn rlw_literal_words(w: &u64) -> u64 {
         w >> (1 + RLW_RUNNING_BITS)
     }
 
     #[inline]
-    fn rlw_runbit_is_set(w: &u64) -> bool {
+    pub(super) fn rlw_runbit_is_set(w: &u64) -> bool {
         w & 1 == 1
     }
 
-    const RLW_RUNNING_BITS: u64 = 4 * 8;
-    const RLW_LARGEST_RUNNING_COUNT: u64 = (1 << RLW_RUNNING_BITS) - 1;
+    pub(super) const RLW_RUNNING_BITS: u64 = 4 * 8;
+    pub(super) const RLW_LARGEST_RUNNING_COUNT: u64 = (1 << RLW_RUNNING_BITS) - 1;
+    pub(super) const RLW_LARGEST_LITERAL_COUNT: u64 = (1 << (64 - 1 - RLW_RUNNING_BITS)) - 1;
+}
+
+mod encode {
+    use std::convert::TryInto;
+
+    use super::{
+        access::{
+            rlw_literal_words, rlw_runbit_is_set, rlw_running_len, RLW_LARGEST_LITERAL_COUNT,
+            RLW_LARGEST_RUNNING_COUNT, RLW_RUNNING_BITS,
+        },
+        Vec,
+    };
+
+    impl Default for Vec {
+        fn default() -> Self {
+            Vec {
+                num_bits: 0,
+                bits: vec![0],
+                rlw: 0,
+            }
+        }
+    }
+
+    impl Vec {
+        /// Set the bit at `index`, which must be past all bits we hold as bits can only be appended, leaving all bits
+        /// in between unset. Afterwards, we hold `index + 1` bits.
+        ///
+        /// Bits are encoded exactly like git does, so bitmaps built the same way serialize to the same bytes.
+        ///
+        /// # Panics
+        ///
+        /// If `index` isn't past the bits we hold, or if it doesn't fit into 32 bits.
+        pub fn set(&mut self, index: usize) {
+            let num_bits = self.num_bits();
+            assert!(index >= num_bits, "bits can only be set in ascending order");
+            let num_new_words = (index + 1 + 63) / 64 - (num_bits + 63) / 64;
+            self.num_bits = (index + 1).try_into().expect("at most 2^32 bits");
+
+            let bit = 1_u64 << (index % 64);
+            if num_new_words > 0 {
+                if num_new_words > 1 {
+                    self.add_unset_words(num_new_words as u64 - 1);
+                }
+                self.add_literal(bit);
+                return;
+            }
+
+            let rlw = *self.rlw_mut();
+            let num_literal_words = rlw_literal_words(&rlw);
+            if num_literal_words == 0 {
+                set_running_len(self.rlw_mut(), rlw_running_len(&rlw) - 1);
+                self.add_literal(bit);
+                return;
+            }
+
+            let last_word = self.bits.last_mut().expect("there is at least one literal word");
+            *last_word |= bit;
+            if *last_word == u64::MAX {
+                // the literal word is full and turns into a run of set bits.
+                self.bits.pop();
+                set_literal_words(self.rlw_mut(), num_literal_words - 1);
+                self.add_set_word();
+            }
+        }
+
+        fn rlw_mut(&mut self) -> &mut u64 {
+            &mut self.bits[self.rlw as usize]
+        }
+
+        fn push_rlw(&mut self) {
+            self.bits.push(0);
+            self.rlw = (self.bits.len() - 1) as u64;
+        }
+
+        fn add_literal(&mut self, word: u64) {
+            let num_literal_words = rlw_literal_words(self.rlw_mut());
+            if num_literal_words >= RLW_LARGEST_LITERAL_COUNT {
+                self.push_rlw();
+                set_literal_words(self.rlw_mut(), 1);
+            } else {
+                set_literal_words(self.rlw_mut(), num_literal_words + 1);
+            }
+            self.bits.push(word);
+        }
+
+        fn add_set_word(&mut self) {
+            let rlw = *self.rlw_mut();
+            let no_literal_words = rlw_literal_words(&rlw) == 0;
+            let running_len = rlw_running_len(&rlw);
+            if no_literal_words && running_len == 0 {
+                set_run_bit(self.rlw_mut(), true);
+            }
+            if no_literal_words && rlw_runbit_is_set(self.rlw_mut()) && running_len < RLW_LARGEST_RUNNING_COUNT {
+                set_running_len(self.rlw_mut(), running_len + 1);
+            } else {
+                self.push_rlw();
+                set_run_bit(self.rlw_mut(), true);
+                set_running_len(self.rlw_mut(), 1);
+            }
+        }
+
+        fn add_unset_words(&mut self, mut num_words: u64) {
+            let rlw = *self.rlw_mut();
+            if rlw_runbit_is_set(&rlw) && rlw_running_len(&rlw) + rlw_literal_words(&rlw) == 0 {
+                set_run_bit(self.rlw_mut(), false);
+            } else if rlw_literal_words(&rlw) != 0 || rlw_runbit_is_set(&rlw) {
+                self.push_rlw();
+            }
+
+            let running_len = rlw_running_len(self.rlw_mut());
+            let num_added = num_words.min(RLW_LARGEST_RUNNING_COUNT - running_len);
+            set_running_len(self.rlw_mut(), running_len + num_added);
+            num_words -= num_added;
+
+            while num_words >= RLW_LARGEST_RUNNING_COUNT {
+                self.push_rlw();
+                set_running_len(self.rlw_mut(), RLW_LARGEST_RUNNING_COUNT);
+                num_words -= RLW_LARGEST_RUNNING_COUNT;
+            }
+            if num_words > 0 {
+                self.push_rlw();
+                set_running_len(self.rlw_mut(), num_words);
+            }
+        }
+    }
+
+    #[inline]
+    fn set_run_bit(w: &mut u64, is_set: bool) {
+        if is_set {
+            *w |= 1;
+        } else {
+            *w &= !1;
+        }
+    }
+
+    #[inline]
+    fn set_running_len(w: &mut u64, len: u64) {
+        *w = (*w & !(RLW_LARGEST_RUNNING_COUNT << 1)) | (len << 1);
+    }
+
+    #[inline]
+    fn set_literal_words(w: &mut u64, num_words: u64) {
+        *w = (*w & ((1 << (1 + RLW_RUNNING_BITS)) - 1)) | (num_words << (1 + RLW_RUNNING_BITS));
+    }
 }
 
 /// A growable collection of u64 that are seen as stream of individual bits.
@@ -125,3 +341,28 @@ pub struct Vec {
     /// RLW is an offset into the `bits` buffer, so `1` translates into &bits\[1] essentially.
     rlw: u64,
 }
+
+impl Vec {
+    /// Write this bitmap to `out` in the format understood by [`decode()`], returning the amount of written bytes.
+    pub fn write_to(&self, mut out: impl std::io::Write) -> std::io::Result<usize> {
+        out.write_all(&self.num_bits.to_be_bytes())?;
+        let len: u32 = self.bits.len().try_into().map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Cannot write bitmaps with more than 2^32 words",
+            )
+        })?;
+        out.write_all(&len.to_be_bytes())?;
+        for word in &self.bits {
+            out.write_all(&word.to_be_bytes())?;
+        }
+        let rlw: u32 = self.rlw.try_into().map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "The run length word position doesn't fit into 32 bits",
+            )
+        })?;
+        out.write_all(&rlw.to_be_bytes())?;
+        Ok(4 + 4 + self.bits.len() * std::mem::size_of::<u64>() + 4)
+    }
+}
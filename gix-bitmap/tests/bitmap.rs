@@ -0,0 +1,161 @@
+mod ewah {
+    use gix_bitmap::ewah;
+
+    fn hex_to_bytes(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|pos| u8::from_str_radix(&hex[pos..][..2], 16).expect("valid hex"))
+            .collect()
+    }
+
+    fn set_bits(hex: &str) -> Vec<usize> {
+        let data = hex_to_bytes(hex);
+        let (bitmap, rest) = ewah::decode(&data).expect("valid bitmap");
+        assert!(rest.is_empty(), "the bitmap is fully consumed");
+
+        let mut via_callback = Vec::new();
+        bitmap.for_each_set_bit(|index| {
+            via_callback.push(index);
+            Some(())
+        });
+        let via_iter: Vec<_> = bitmap.iter().collect();
+        assert_eq!(via_iter, via_callback, "both ways of iterating produce the same result");
+        via_iter
+    }
+
+    #[test]
+    fn decode_bitmaps_from_real_indices() {
+        assert_eq!(
+            set_bits("00000006000000020000000200000000000000000000003f00000000"),
+            [0, 1, 2, 3, 4, 5],
+            "fsmonitor dirty entries"
+        );
+        assert_eq!(
+            set_bits("00000004000000020000000200000000000000000000000d00000000"),
+            [0, 2, 3],
+            "split-index entries to delete"
+        );
+        assert_eq!(
+            set_bits("00000006000000020000000200000000000000000000003200000000"),
+            [1, 4, 5],
+            "split-index entries to replace"
+        );
+        assert_eq!(
+            set_bits("0000000000000001000000000000000000000000"),
+            Vec::<usize>::new(),
+            "empty split-index bitmap"
+        );
+        assert_eq!(
+            set_bits("00000001000000020000000200000000000000000000000100000000"),
+            [0],
+            "split-index bitmap with a single entry"
+        );
+    }
+
+    #[test]
+    fn write_reproduces_decoded_bytes() {
+        for hex in [
+            "00000006000000020000000200000000000000000000003f00000000",
+            "0000000000000001000000000000000000000000",
+            "000000c50000000300000000000000020000000200000005000000000000000600000001",
+        ] {
+            let data = hex_to_bytes(hex);
+            let (bitmap, _) = ewah::decode(&data).expect("valid bitmap");
+            let mut out = Vec::new();
+            let written = bitmap.write_to(&mut out).expect("writing to memory works");
+            assert_eq!(out, data);
+            assert_eq!(written, data.len());
+        }
+    }
+
+    fn to_hex(bitmap: &ewah::Vec) -> String {
+        let mut out = Vec::new();
+        bitmap.write_to(&mut out).expect("writing to memory works");
+        out.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    fn bitmap_with(bits: impl IntoIterator<Item = usize>) -> ewah::Vec {
+        let mut bitmap = ewah::Vec::default();
+        for bit in bits {
+            bitmap.set(bit);
+        }
+        bitmap
+    }
+
+    #[test]
+    fn set_bits_are_encoded_like_git_does() {
+        for (bits, expected) in [
+            (
+                vec![0, 1, 2, 3, 4, 5],
+                "00000006000000020000000200000000000000000000003f00000000",
+            ),
+            (
+                vec![0, 2, 3],
+                "00000004000000020000000200000000000000000000000d00000000",
+            ),
+            (
+                vec![1, 4, 5],
+                "00000006000000020000000200000000000000000000003200000000",
+            ),
+            (vec![], "0000000000000001000000000000000000000000"),
+            (vec![0], "00000001000000020000000200000000000000000000000100000000"),
+        ] {
+            assert_eq!(to_hex(&bitmap_with(bits.iter().copied())), expected, "{bits:?}");
+        }
+    }
+
+    #[test]
+    fn set_bits_roundtrip_through_runs_and_literals() {
+        let bits: Vec<_> = (0..200)
+            .chain([300, 1000, 1001])
+            .chain(1024..1088)
+            .chain([5000])
+            .collect();
+        let bitmap = bitmap_with(bits.iter().copied());
+        assert_eq!(bitmap.num_bits(), 5001);
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), bits);
+
+        let mut data = Vec::new();
+        bitmap.write_to(&mut data).expect("writing to memory works");
+        let (decoded, rest) = ewah::decode(&data).expect("valid bitmap");
+        assert!(rest.is_empty());
+        assert_eq!(decoded.iter().collect::<Vec<_>>(), bits);
+    }
+
+    #[test]
+    #[should_panic(expected = "ascending order")]
+    fn bits_can_only_be_appended() {
+        bitmap_with([5, 3]);
+    }
+
+    #[test]
+    fn decode_runs_of_set_and_unset_bits() {
+        // 64 unset bits, then 128 set bits, then one literal word, followed by the position of the last RLW.
+        let bits = set_bits("000000c50000000300000000000000020000000200000005000000000000000600000001");
+        assert_eq!(bits.len(), 128 + 2);
+        assert!(bits[..128].iter().copied().eq(64..192));
+        assert_eq!(bits[128..], [193, 194]);
+    }
+
+    #[test]
+    fn declared_bits_must_fit_into_words() {
+        let data = hex_to_bytes("00000041000000020000000200000000000000000000003f00000000");
+        assert!(
+            ewah::decode(&data).is_err(),
+            "65 bits can't be stored in a single literal word"
+        );
+    }
+
+    #[test]
+    fn literal_words_must_be_present() {
+        // A single RLW announcing 2 literal words that don't exist.
+        let data = hex_to_bytes("0000004000000001000000040000000000000000");
+        assert!(ewah::decode(&data).is_err());
+    }
+
+    #[test]
+    fn truncated_data_is_an_error() {
+        let data = hex_to_bytes("00000006000000020000000200000000000000000000003f000000");
+        assert!(ewah::decode(&data).is_err());
+    }
+}
@@ -99,6 +99,11 @@ impl Index {
         Ok(&data[crate::range::into_usize(offset).ok_or(data_by_kind::Error::FileTooLarge)?])
     }
 
+    /// Return all chunks in the order of their entries in the index.
+    pub fn iter(&self) -> impl Iterator<Item = &Entry> {
+        self.chunks.iter()
+    }
+
     /// Return the end offset lf the last chunk, which is the highest offset as well.
     /// It's definitely available as we have one or more chunks.
     pub fn highest_offset(&self) -> crate::file::Offset {
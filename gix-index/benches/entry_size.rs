@@ -0,0 +1,55 @@
+//! Measure the memory used by a decoded synthetic index with two million entries, and compare it to what it would be if
+//! entries referred to their path with a range of `usize`, which is twice as large on 64 bit systems.
+//!
+//! Run with `cargo bench -p gix-index --bench entry_size`.
+use std::{mem::size_of, ops::Range};
+
+use gix_index::{entry, Entry, State};
+
+mod shared;
+
+const NUM_ENTRIES: usize = 2_000_000;
+
+/// The layout of an entry with a `usize` range into the path backing.
+#[allow(dead_code)]
+struct EntryWithUsizePathRange {
+    stat: entry::Stat,
+    id: gix_hash::ObjectId,
+    flags: entry::Flags,
+    mode: entry::Mode,
+    path: Range<usize>,
+}
+
+fn main() {
+    let data = shared::synthetic_index(NUM_ENTRIES);
+    let (bytes, allocations, state) = shared::measure(|| {
+        State::from_bytes(
+            &data,
+            filetime::FileTime::now(),
+            gix_hash::Kind::Sha1,
+            Default::default(),
+        )
+        .expect("valid")
+        .0
+    });
+    assert_eq!(state.entries().len(), NUM_ENTRIES);
+    let path_bytes = state.path_backing().capacity();
+    let entry_bytes = bytes - path_bytes;
+    let usize_range_entry_bytes = entry_bytes / size_of::<Entry>() * size_of::<EntryWithUsizePathRange>();
+
+    let mb = |bytes: usize| bytes as f64 / 1024.0 / 1024.0;
+    println!(
+        "{NUM_ENTRIES} entries with {:.1}MB of paths in {allocations} allocations",
+        mb(path_bytes)
+    );
+    println!(
+        "u32 path ranges:   {:>3} bytes per entry, {:>6.1}MB in total",
+        size_of::<Entry>(),
+        mb(bytes)
+    );
+    println!(
+        "usize path ranges: {:>3} bytes per entry, {:>6.1}MB in total",
+        size_of::<EntryWithUsizePathRange>(),
+        mb(path_bytes + usize_range_entry_bytes)
+    );
+}
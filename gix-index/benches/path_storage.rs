@@ -0,0 +1,51 @@
+//! Compare the memory used by paths of a synthetic index with a million entries when stored in the shared path backing
+//! of a `State`, as opposed to storing them as one string per entry.
+//!
+//! Run with `cargo bench -p gix-index --bench path_storage`.
+use bstr::BString;
+use gix_index::State;
+
+mod shared;
+use shared::measure;
+
+const NUM_ENTRIES: usize = 1_000_000;
+
+fn main() {
+    let data = shared::synthetic_index(NUM_ENTRIES);
+
+    let (shared_bytes, shared_allocations, state) = measure(|| {
+        let (mut state, _) = State::from_bytes(
+            &data,
+            filetime::FileTime::now(),
+            gix_hash::Kind::Sha1,
+            Default::default(),
+        )
+        .expect("valid");
+        state.compact_path_backing();
+        state
+    });
+    let (string_bytes, string_allocations, paths) = measure(|| {
+        state
+            .entries()
+            .iter()
+            .map(|e| BString::from(e.path(&state)))
+            .collect::<Vec<_>>()
+    });
+    let path_bytes = state.path_backing().len();
+    // Entries would store a string instead of a range into the path backing, which doesn't exist then, while the vector
+    // holding the strings for this measurement wouldn't exist either.
+    let string_bytes =
+        shared_bytes - path_bytes - NUM_ENTRIES * std::mem::size_of::<std::ops::Range<u32>>() + string_bytes;
+    let string_allocations = shared_allocations - 1 + string_allocations - 1;
+    drop(paths);
+
+    println!("{NUM_ENTRIES} entries with {path_bytes} bytes of paths");
+    println!(
+        "shared path backing: {:>6.1}MB in {shared_allocations} allocations",
+        shared_bytes as f64 / 1024.0 / 1024.0
+    );
+    println!(
+        "string per path:     {:>6.1}MB in {string_allocations} allocations, without allocator overhead",
+        string_bytes as f64 / 1024.0 / 1024.0
+    );
+}
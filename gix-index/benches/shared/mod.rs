@@ -0,0 +1,70 @@
+//! A global allocator that keeps track of the memory that is alive, to measure the memory usage of data structures.
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+struct CountingAllocator;
+
+static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+static LIVE_ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        LIVE_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+        LIVE_ALLOCATIONS.fetch_sub(1, Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        LIVE_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        LIVE_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Return the amount of bytes and allocations that are alive after calling `f`, along with its result,
+/// which remains alive until it's dropped by the caller.
+pub fn measure<T>(f: impl FnOnce() -> T) -> (usize, usize, T) {
+    let (bytes, allocations) = (
+        LIVE_BYTES.load(Ordering::Relaxed),
+        LIVE_ALLOCATIONS.load(Ordering::Relaxed),
+    );
+    let value = f();
+    (
+        LIVE_BYTES.load(Ordering::Relaxed) - bytes,
+        LIVE_ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+        value,
+    )
+}
+
+/// Return a serialized index with `num_entries` entries with paths in a directory hierarchy, like in a larger project.
+pub fn synthetic_index(num_entries: usize) -> Vec<u8> {
+    let mut state = gix_index::State::new(gix_hash::Kind::Sha1);
+    for idx in 0..num_entries {
+        let path = format!("src/module-{}/sub-{}/file-{idx}.rs", idx / 10_000, idx / 100);
+        state.dangerously_push_entry(
+            Default::default(),
+            gix_hash::Kind::Sha1.null(),
+            gix_index::entry::Flags::empty(),
+            gix_index::entry::Mode::FILE,
+            path.as_str().into(),
+        );
+    }
+    state.sort_entries();
+    let mut data = Vec::new();
+    state
+        .write_to(&mut data, Default::default())
+        .expect("writing to memory works");
+    data.extend_from_slice(&[0; 20]);
+    data
+}
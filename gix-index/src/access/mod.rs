@@ -1,6 +1,7 @@
-use std::cmp::Ordering;
+use std::{cmp::Ordering, ops::Range};
 
-use bstr::{BStr, ByteSlice, ByteVec};
+use bstr::{BStr, BString, ByteSlice, ByteVec};
+use filetime::FileTime;
 
 use crate::{entry, extension, Entry, PathStorage, State, Version};
 
@@ -20,6 +21,14 @@ impl State {
         self.object_hash
     }
 
+    /// Return the time at which this state was created, which is the modification time of the index file it was read from,
+    /// if any.
+    ///
+    /// It's used to determine which entries are [racily clean][State::is_entry_racy()].
+    pub fn timestamp(&self) -> FileTime {
+        self.timestamp
+    }
+
     /// Return our entries
     pub fn entries(&self) -> &[Entry] {
         &self.entries
@@ -45,7 +54,7 @@ impl State {
         backing: &'backing PathStorage,
     ) -> impl Iterator<Item = (&'state mut Entry, &'backing BStr)> {
         self.entries.iter_mut().map(move |e| {
-            let path = backing[e.path.clone()].as_bstr();
+            let path = backing[e.path_range()].as_bstr();
             (e, path)
         })
     }
@@ -56,10 +65,55 @@ impl State {
     /// Use the index for accessing multiple stages if they exists, but at least the single matching entry.
     pub fn entry_index_by_path_and_stage(&self, path: &BStr, stage: entry::Stage) -> Option<usize> {
         self.entries
-            .binary_search_by(|e| e.path(self).cmp(path).then_with(|| e.stage().cmp(&stage)))
+            .binary_search_by(|e| entry::compare(e.path(self), e.stage(), path, stage))
             .ok()
     }
 
+    /// Find the index of the first entry in [`entries()`][State::entries()] matching the given repository-relative `path`,
+    /// in whichever stage it is, or the position at which an entry with `path` would have to be inserted to keep
+    /// entries sorted.
+    ///
+    /// As entries are sorted by path and then by stage, the entries of all stages of a conflicted path follow the returned one.
+    pub fn entry_index_by_path(&self, path: &BStr) -> Result<usize, usize> {
+        let idx = self
+            .entries
+            .partition_point(|e| Entry::cmp_filepaths(e.path(self), path).is_lt());
+        match self.entries.get(idx) {
+            Some(entry) if entry.path(self) == path => Ok(idx),
+            _ => Err(idx),
+        }
+    }
+
+    /// Return all entries whose path starts with `prefix`, which is a plain byte-prefix of the path, or an empty slice if
+    /// there is no such entry. To obtain all entries in a directory, let `prefix` end with a slash, like `dir/`.
+    ///
+    /// As entries are sorted, this only takes two binary searches.
+    pub fn prefixed_entries(&self, prefix: &BStr) -> &[Entry] {
+        &self.entries[self.prefixed_entries_range(prefix)]
+    }
+
+    /// Return the range of entries in [`entries()`][State::entries()] whose path starts with `prefix`, which is empty if
+    /// there is no such entry. The empty `prefix` matches all entries.
+    ///
+    /// Note that `prefix` is a plain byte-prefix, so `dir` matches `dir-other/file` and `dir!` as well, which sort before
+    /// `dir/`. Use `dir/` to only match what's inside of the directory.
+    pub fn prefixed_entries_range(&self, prefix: &BStr) -> Range<usize> {
+        let start = self
+            .entries
+            .partition_point(|e| Entry::cmp_filepaths(e.path(self), prefix).is_lt());
+        let len = self.entries[start..].partition_point(|e| e.path(self).starts_with(prefix));
+        start..start + len
+    }
+
+    /// Return an iterator over all entries whose path starts with `prefix` along with their path, see
+    /// [`prefixed_entries_range()`][State::prefixed_entries_range()] for details.
+    pub fn prefixed_entries_with_paths<'a>(
+        &'a self,
+        prefix: &BStr,
+    ) -> impl Iterator<Item = (&'a BStr, &'a Entry)> + 'a {
+        self.prefixed_entries(prefix).iter().map(move |e| (e.path(self), e))
+    }
+
     /// Find the entry index in [`entries()[..upper_bound]`][State::entries()] matching the given repository-relative
     /// `path` and `stage`, or `None`.
     ///
@@ -75,7 +129,7 @@ impl State {
         upper_bound: usize,
     ) -> Option<usize> {
         self.entries[..upper_bound]
-            .binary_search_by(|e| e.path(self).cmp(path).then_with(|| e.stage().cmp(&stage)))
+            .binary_search_by(|e| entry::compare(e.path(self), e.stage(), path, stage))
             .ok()
     }
 
@@ -113,15 +167,70 @@ impl State {
         self.path_backing = backing;
     }
 
+    /// Remove all paths from the [path backing][State::path_backing()] that entries don't refer to anymore, for instance
+    /// after many entries were removed or replaced, and release the memory it doesn't need.
+    ///
+    /// Paths are stored in the order of entries afterwards, and consecutive entries with the same path, like the stages
+    /// of a conflict, share it.
+    pub fn compact_path_backing(&mut self) {
+        let mut backing = PathStorage::with_capacity(self.entries.iter().map(|e| e.path_range().len()).sum());
+        let mut previous = None::<Range<usize>>;
+        for entry in &mut self.entries {
+            let path = &self.path_backing[entry.path_range()];
+            let range = match previous {
+                Some(previous) if backing[previous.clone()] == *path => previous,
+                _ => {
+                    let start = backing.len();
+                    backing.extend_from_slice(path);
+                    start..backing.len()
+                }
+            };
+            entry.path = entry::stored_path_range(range.clone());
+            previous = Some(range);
+        }
+        backing.shrink_to_fit();
+        self.path_backing = backing;
+    }
+
+    /// Set the time at which this state was created to `timestamp`, which affects which entries are
+    /// [racily clean][State::is_entry_racy()].
+    ///
+    /// It's set automatically to the modification time of the index file after [writing it][crate::File::write()].
+    pub fn set_timestamp(&mut self, timestamp: FileTime) {
+        self.timestamp = timestamp;
+    }
+
     /// Return mutable entries in a slice.
+    ///
+    /// Lookups by path rely on entries being sorted by path and stage, which is why paths can't be changed here.
+    /// Changing the stage of an entry, for instance via its [flags][entry::Flags::STAGE_MASK], requires a call to
+    /// [`sort_entries()`][State::sort_entries()] before the next lookup.
     pub fn entries_mut(&mut self) -> &mut [Entry] {
         &mut self.entries
     }
+    /// Set or clear the [skip-worktree][Entry::set_skip_worktree()] flag of all entries whose path starts with `prefix`,
+    /// returning the amount of entries that changed. See [`prefixed_entries_range()`][State::prefixed_entries_range()]
+    /// for how `prefix` is matched.
+    ///
+    /// This is useful for sparse checkouts, which toggle the flag for entire directories at once.
+    pub fn set_skip_worktree_by_pathspec(&mut self, prefix: &BStr, value: bool) -> usize {
+        let range = self.prefixed_entries_range(prefix);
+        let mut num_changed = 0;
+        for entry in &mut self.entries[range] {
+            if entry.is_skip_worktree() != value {
+                entry.set_skip_worktree(value);
+                num_changed += 1;
+            }
+        }
+        num_changed
+    }
     /// Return mutable entries along with their paths in an iterator.
+    ///
+    /// The same invariants as for [`entries_mut()`][State::entries_mut()] apply.
     pub fn entries_mut_with_paths(&mut self) -> impl Iterator<Item = (&mut Entry, &BStr)> {
         let paths = &self.path_backing;
         self.entries.iter_mut().map(move |e| {
-            let path = paths[e.path.clone()].as_bstr();
+            let path = paths[e.path_range()].as_bstr();
             (e, path)
         })
     }
@@ -165,7 +274,7 @@ impl State {
         let path = {
             let path_start = self.path_backing.len();
             self.path_backing.push_str(path);
-            path_start..self.path_backing.len()
+            entry::stored_path_range(path_start..self.path_backing.len())
         };
 
         self.entries.push(Entry {
@@ -180,10 +289,8 @@ impl State {
     /// Unconditionally sort entries as needed to perform lookups quickly.
     pub fn sort_entries(&mut self) {
         let path_backing = &self.path_backing;
-        self.entries.sort_by(|a, b| {
-            Entry::cmp_filepaths(a.path_in(path_backing), b.path_in(path_backing))
-                .then_with(|| a.stage().cmp(&b.stage()))
-        });
+        self.entries
+            .sort_by(|a, b| entry::compare(a.path_in(path_backing), a.stage(), b.path_in(path_backing), b.stage()));
     }
 
     /// Similar to [`sort_entries()`][State::sort_entries()], but applies `compare` after comparing
@@ -191,8 +298,7 @@ impl State {
     pub fn sort_entries_by(&mut self, mut compare: impl FnMut(&Entry, &Entry) -> Ordering) {
         let path_backing = &self.path_backing;
         self.entries.sort_by(|a, b| {
-            Entry::cmp_filepaths(a.path_in(path_backing), b.path_in(path_backing))
-                .then_with(|| a.stage().cmp(&b.stage()))
+            entry::compare(a.path_in(path_backing), a.stage(), b.path_in(path_backing), b.stage())
                 .then_with(|| compare(a, b))
         });
     }
@@ -208,10 +314,34 @@ impl State {
     pub fn link(&self) -> Option<&extension::Link> {
         self.link.as_ref()
     }
-    /// Obtain the resolve-undo extension.
+    /// Obtain the resolve-undo extension, which holds the stages of resolved conflicts, sorted by path.
     pub fn resolve_undo(&self) -> Option<&extension::resolve_undo::Paths> {
         self.resolve_undo.as_ref()
     }
+    /// Return the stages of the conflict at `path` before it was resolved, or `None` if there is no such record.
+    pub fn resolve_undo_for(&self, path: &BStr) -> Option<&extension::resolve_undo::ResolvePath> {
+        let paths = self.resolve_undo.as_ref()?;
+        paths
+            .binary_search_by(|p| p.name().cmp(path))
+            .ok()
+            .map(|idx| &paths[idx])
+    }
+    /// Remove the resolve-undo record of `path` and return it, for instance after the conflict was recreated from it.
+    ///
+    /// The extension is dropped once it's empty.
+    pub fn remove_resolve_undo(&mut self, path: &BStr) -> Option<extension::resolve_undo::ResolvePath> {
+        let paths = self.resolve_undo.as_mut()?;
+        let idx = paths.binary_search_by(|p| p.name().cmp(path)).ok()?;
+        let record = paths.remove(idx);
+        if paths.is_empty() {
+            self.resolve_undo = None;
+        }
+        Some(record)
+    }
+    /// Remove all resolve-undo records and return them, which is what git does once the resolved paths are committed.
+    pub fn take_resolve_undo(&mut self) -> Option<extension::resolve_undo::Paths> {
+        self.resolve_undo.take()
+    }
     /// Obtain the untracked extension.
     pub fn untracked(&self) -> Option<&extension::UntrackedCache> {
         self.untracked.as_ref()
@@ -220,4 +350,107 @@ impl State {
     pub fn fs_monitor(&self) -> Option<&extension::FsMonitor> {
         self.fs_monitor.as_ref()
     }
+    /// Obtain all optional extensions that aren't understood, in the order they were read.
+    pub fn unknown_extensions(&self) -> &[extension::Unknown] {
+        &self.unknown_extensions
+    }
+    /// Return the signatures of all optional extensions that were present, but [skipped][crate::decode::Options::extensions]
+    /// when decoding. Writing fails if any of them would be written.
+    pub fn skipped_extensions(&self) -> &[extension::Signature] {
+        &self.skipped_extensions
+    }
+    /// Obtain all optional extensions that aren't understood mutably, for instance to drop them.
+    pub fn unknown_extensions_mut(&mut self) -> &mut Vec<extension::Unknown> {
+        &mut self.unknown_extensions
+    }
+    /// Obtain the fsmonitor extension mutably, for example to [set the token][extension::FsMonitor::set_token()] for the next query.
+    pub fn fs_monitor_mut(&mut self) -> Option<&mut extension::FsMonitor> {
+        self.fs_monitor.as_mut()
+    }
+    /// Use the fsmonitor extension to mark all entries as [valid][entry::Flags::FSMONITOR_VALID] unless they are dirty
+    /// according to it, in which case the flag is removed so their stat data is checked.
+    ///
+    /// Return `false` without changing any entry if there is no fsmonitor extension or if it refers to entries we don't have.
+    pub fn apply_fs_monitor(&mut self) -> bool {
+        let fs_monitor = match self.fs_monitor.as_ref() {
+            Some(fs_monitor) => fs_monitor,
+            None => return false,
+        };
+        let num_entries = self.entries.len();
+        if fs_monitor.entry_dirty().iter().any(|index| index >= num_entries) {
+            return false;
+        }
+        for entry in &mut self.entries {
+            entry.flags.insert(entry::Flags::FSMONITOR_VALID);
+        }
+        for index in fs_monitor.entry_dirty().iter() {
+            self.entries[index].flags.remove(entry::Flags::FSMONITOR_VALID);
+        }
+        true
+    }
+
+    /// Mark the entries at `dirty_paths`, as reported by the filesystem monitor since its last query, as no longer
+    /// [valid][entry::Flags::FSMONITOR_VALID] so their stat data is checked, and update the bitmap of dirty entries in the
+    /// fsmonitor extension accordingly if there is one. This is expected to be called after [`apply_fs_monitor()`][State::apply_fs_monitor()].
+    ///
+    /// Paths ending with a slash mark all entries in the directory, and so do paths of directories without it, just like git
+    /// handles them. Return all paths that didn't mark any entry as they refer to untracked files, which should be invalidated
+    /// in the untracked cache.
+    pub fn apply_fs_monitor_changes<'a>(&mut self, dirty_paths: impl IntoIterator<Item = &'a BStr>) -> Vec<&'a BStr> {
+        let mut untracked = Vec::new();
+        let mut dir = BString::default();
+        for path in dirty_paths {
+            let range = match self.entry_index_by_path(path) {
+                Ok(start) => start..start + self.entries[start..].partition_point(|e| e.path(self) == path),
+                Err(_) if path.ends_with(b"/") => self.prefixed_entries_range(path),
+                Err(_) => {
+                    dir.clear();
+                    dir.push_str(path);
+                    dir.push(b'/');
+                    self.prefixed_entries_range(dir.as_ref())
+                }
+            };
+            if range.is_empty() {
+                untracked.push(path);
+                continue;
+            }
+            for entry in &mut self.entries[range] {
+                entry.flags.remove(entry::Flags::FSMONITOR_VALID);
+            }
+        }
+        if let Some(fs_monitor) = self.fs_monitor.as_mut() {
+            fs_monitor.entry_dirty = dirty_entries(&self.entries);
+        }
+        untracked
+    }
+
+    /// Set the `token` the filesystem monitor returned for the most recent query, so that the next query can ask for changes
+    /// since then once the index is written, and record all entries that aren't [valid][entry::Flags::FSMONITOR_VALID] as
+    /// dirty, just like git does before writing the fsmonitor extension. It's created if it doesn't exist yet.
+    ///
+    /// Note that without [applying the extension][State::apply_fs_monitor()] first, all entries are recorded as dirty.
+    pub fn set_fs_monitor_token(&mut self, token: extension::fs_monitor::Token) {
+        let entry_dirty = dirty_entries(&self.entries);
+        match self.fs_monitor.as_mut() {
+            Some(fs_monitor) => {
+                fs_monitor.token = token;
+                fs_monitor.entry_dirty = entry_dirty;
+            }
+            None => self.fs_monitor = Some(extension::FsMonitor { token, entry_dirty }),
+        }
+    }
+}
+
+/// Return a bitmap with a set bit for each of `entries` that isn't [valid][entry::Flags::FSMONITOR_VALID] according to
+/// the filesystem monitor.
+fn dirty_entries(entries: &[Entry]) -> gix_bitmap::ewah::Vec {
+    let mut bitmap = gix_bitmap::ewah::Vec::default();
+    for (index, _) in entries
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| !e.flags.contains(entry::Flags::FSMONITOR_VALID))
+    {
+        bitmap.set(index);
+    }
+    bitmap
 }
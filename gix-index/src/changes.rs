@@ -0,0 +1,147 @@
+use std::cmp::Ordering;
+
+use bstr::BStr;
+
+use crate::{entry, Entry, State};
+
+/// A change of a path between two states, as returned by [`State::changes_against()`].
+///
+/// Conflicted paths have more than one entry, in which case the entry with the lowest stage is the one referred to here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change<'a> {
+    /// The path was added as it only exists in the new state.
+    Add {
+        /// The path of the added entry.
+        path: &'a BStr,
+        /// The added entry.
+        entry: &'a Entry,
+    },
+    /// The path was removed as it only exists in the old state.
+    Remove {
+        /// The path of the removed entry.
+        path: &'a BStr,
+        /// The removed entry.
+        entry: &'a Entry,
+    },
+    /// The path exists in both states, but its entry changed, or the entries of its conflict changed.
+    Modify {
+        /// The path of both entries.
+        path: &'a BStr,
+        /// The entry in the old state.
+        old: &'a Entry,
+        /// The entry in the new state.
+        new: &'a Entry,
+    },
+}
+
+impl<'a> Change<'a> {
+    /// Return the path that changed.
+    pub fn path(&self) -> &'a BStr {
+        match self {
+            Change::Add { path, .. } | Change::Remove { path, .. } | Change::Modify { path, .. } => path,
+        }
+    }
+}
+
+/// Changes
+impl State {
+    /// Return an iterator over the changes of each path in this state compared to the `other` state, which is the old
+    /// state the changes are relative to. The changes are ordered by path.
+    ///
+    /// Entries are considered unchanged if their id and mode are the same, and if `stat_options` is set, their stat
+    /// information has to [match][entry::Stat::matches()] as well. Conflicted paths, i.e. paths with entries in stages
+    /// other than 0, are compared stage by stage, and are considered modified if any of their stages changed, was added
+    /// or removed, for instance when the conflict was resolved.
+    ///
+    /// The states are compared by walking both of their sorted entries, without hashing or allocating.
+    pub fn changes_against<'a>(
+        &'a self,
+        other: &'a State,
+        stat_options: Option<entry::stat::Options>,
+    ) -> impl Iterator<Item = Change<'a>> + 'a {
+        let mut old = PathGroups {
+            state: other,
+            entries: other.entries(),
+        };
+        let mut new = PathGroups {
+            state: self,
+            entries: self.entries(),
+        };
+        std::iter::from_fn(move || loop {
+            let change = match (old.peek(), new.peek()) {
+                (None, None) => return None,
+                (Some((path, old_entries)), None) => {
+                    old.advance(old_entries.len());
+                    Change::Remove {
+                        path,
+                        entry: &old_entries[0],
+                    }
+                }
+                (None, Some((path, new_entries))) => {
+                    new.advance(new_entries.len());
+                    Change::Add {
+                        path,
+                        entry: &new_entries[0],
+                    }
+                }
+                (Some((old_path, old_entries)), Some((new_path, new_entries))) => {
+                    match Entry::cmp_filepaths(old_path, new_path) {
+                        Ordering::Less => {
+                            old.advance(old_entries.len());
+                            Change::Remove {
+                                path: old_path,
+                                entry: &old_entries[0],
+                            }
+                        }
+                        Ordering::Greater => {
+                            new.advance(new_entries.len());
+                            Change::Add {
+                                path: new_path,
+                                entry: &new_entries[0],
+                            }
+                        }
+                        Ordering::Equal => {
+                            old.advance(old_entries.len());
+                            new.advance(new_entries.len());
+                            let is_unchanged = old_entries.len() == new_entries.len()
+                                && old_entries.iter().zip(new_entries).all(|(old, new)| {
+                                    old.stage() == new.stage()
+                                        && old.id == new.id
+                                        && old.mode == new.mode
+                                        && stat_options.map_or(true, |options| old.stat.matches(&new.stat, options))
+                                });
+                            if is_unchanged {
+                                continue;
+                            }
+                            Change::Modify {
+                                path: new_path,
+                                old: &old_entries[0],
+                                new: &new_entries[0],
+                            }
+                        }
+                    }
+                }
+            };
+            return Some(change);
+        })
+    }
+}
+
+/// The entries of a state that are yet to be compared, to be consumed one path at a time.
+struct PathGroups<'a> {
+    state: &'a State,
+    entries: &'a [Entry],
+}
+
+impl<'a> PathGroups<'a> {
+    /// Return the next path along with all of its entries, one per stage.
+    fn peek(&self) -> Option<(&'a BStr, &'a [Entry])> {
+        let path = self.entries.first()?.path(self.state);
+        let len = self.entries.partition_point(|e| e.path(self.state) == path);
+        Some((path, &self.entries[..len]))
+    }
+
+    fn advance(&mut self, len: usize) {
+        self.entries = &self.entries[len..];
+    }
+}
@@ -0,0 +1,157 @@
+use bstr::BStr;
+
+use crate::{entry, extension::resolve_undo, Entry, State};
+
+///
+pub mod resolve {
+    use bstr::BString;
+
+    /// The error returned by [State::resolve_conflict()][crate::State::resolve_conflict()].
+    #[derive(Debug, thiserror::Error)]
+    #[allow(missing_docs)]
+    pub enum Error {
+        #[error("Path '{path}' is not in conflict")]
+        NotConflicted { path: BString },
+        #[error("Path '{path}' has no entry in stage {stage} to resolve its conflict with")]
+        MissingStage { path: BString, stage: crate::entry::Stage },
+    }
+}
+
+/// The entries of a conflicted path in the base, ours and theirs stage, in that order, with missing stages being `None`.
+pub type Stages<'a> = [Option<&'a Entry>; 3];
+
+/// The way to resolve a conflict with [`State::resolve_conflict()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// Use the entry of the common ancestor in stage 1.
+    Base,
+    /// Use our entry in stage 2.
+    Ours,
+    /// Use their entry in stage 3.
+    Theirs,
+    /// Use an entry with the given `mode` and `id`, for example one of the merged blob.
+    Entry {
+        /// The mode of the new entry.
+        mode: entry::Mode,
+        /// The id of the new entry.
+        id: gix_hash::ObjectId,
+    },
+    /// Remove the path from the index, for example to accept that it was deleted on one side.
+    Remove,
+}
+
+/// Conflicts
+impl State {
+    /// Return an iterator over all conflicted paths along with their entries in the base, ours and theirs stage.
+    ///
+    /// Stages that don't exist for a path, like the one of the deleting side of a delete/modify conflict, are `None`.
+    pub fn conflicts(&self) -> impl Iterator<Item = (&BStr, Stages<'_>)> + '_ {
+        self.conflicts_in(&self.entries)
+    }
+
+    /// Resolve the conflict at `path` according to `resolution` by replacing all of its entries with a single stage-0 entry,
+    /// or by removing them, and return the index of the new entry if there is one.
+    ///
+    /// The stages of the conflict are recorded in the [resolve-undo extension][State::resolve_undo()], like git does,
+    /// and the cache tree is invalidated along `path`. The new entry has no stat information.
+    pub fn resolve_conflict(&mut self, path: &BStr, resolution: Resolution) -> Result<Option<usize>, resolve::Error> {
+        let start = self
+            .entries
+            .partition_point(|e| Entry::cmp_filepaths(e.path(self), path).is_lt());
+        let num_entries = self.entries[start..].partition_point(|e| e.path(self) == path);
+        let range = start..start + num_entries;
+
+        let mut stages = [None; 3];
+        for entry in self.entries[range.clone()].iter().filter(|e| e.stage() != 0) {
+            set_stage(&mut stages, entry);
+        }
+        if stages.iter().all(Option::is_none) {
+            return Err(resolve::Error::NotConflicted { path: path.into() });
+        }
+
+        let stage = |stage: entry::Stage| {
+            stages[stage as usize - 1]
+                .map(|e| (e.mode, e.id))
+                .ok_or_else(|| resolve::Error::MissingStage {
+                    path: path.into(),
+                    stage,
+                })
+        };
+        let resolved = match resolution {
+            Resolution::Base => Some(stage(1)?),
+            Resolution::Ours => Some(stage(2)?),
+            Resolution::Theirs => Some(stage(3)?),
+            Resolution::Entry { mode, id } => Some((mode, id)),
+            Resolution::Remove => None,
+        };
+        let undo = resolve_path(path, stages);
+
+        let new_entry = resolved.map(|(mode, id)| Entry {
+            stat: entry::Stat::default(),
+            id,
+            flags: entry::Flags::empty(),
+            mode,
+            path: self.entries[start].path.clone(),
+        });
+        let has_new_entry = new_entry.is_some();
+        self.entries.splice(range, new_entry);
+        if num_entries != usize::from(has_new_entry) {
+            self.drop_fs_monitor_as_entries_moved();
+        }
+
+        self.record_resolve_undo(undo);
+        self.invalidate_tree_at(path);
+        Ok(has_new_entry.then_some(start))
+    }
+}
+
+impl State {
+    /// Like [`conflicts()`][State::conflicts()], but only for the given `entries` of this state.
+    pub(crate) fn conflicts_in<'a>(
+        &'a self,
+        entries: &'a [Entry],
+    ) -> impl Iterator<Item = (&'a BStr, Stages<'a>)> + 'a {
+        let mut entries = entries.iter().peekable();
+        std::iter::from_fn(move || {
+            let first = entries.find(|e| e.stage() != 0)?;
+            let path = first.path(self);
+            let mut stages = [None; 3];
+            set_stage(&mut stages, first);
+            while let Some(entry) = entries.next_if(|e| e.path(self) == path) {
+                set_stage(&mut stages, entry);
+            }
+            Some((path, stages))
+        })
+    }
+
+    /// Add `undo` to the resolve-undo extension, replacing the information previously recorded for its path.
+    pub(crate) fn record_resolve_undo(&mut self, undo: resolve_undo::ResolvePath) {
+        let paths = self.resolve_undo.get_or_insert_with(Vec::new);
+        match paths.binary_search_by(|p| p.name.cmp(&undo.name)) {
+            Ok(idx) => paths[idx] = undo,
+            Err(idx) => paths.insert(idx, undo),
+        }
+    }
+}
+
+/// Return the resolve-undo information for the conflict at `path` with `stages`.
+pub(crate) fn resolve_path(path: &BStr, stages: Stages<'_>) -> resolve_undo::ResolvePath {
+    resolve_undo::ResolvePath {
+        name: path.into(),
+        stages: stages.map(|e| {
+            e.map(|e| resolve_undo::Stage {
+                mode: e.mode.bits(),
+                id: e.id,
+            })
+        }),
+    }
+}
+
+fn set_stage<'a>(stages: &mut Stages<'a>, entry: &'a Entry) {
+    if let Some(slot) = (entry.stage() as usize)
+        .checked_sub(1)
+        .and_then(|idx| stages.get_mut(idx))
+    {
+        *slot = Some(entry);
+    }
+}
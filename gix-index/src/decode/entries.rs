@@ -35,23 +35,38 @@ pub fn estimate_path_storage_requirements_in_bytes(
                 .saturating_sub(num_entries as usize * on_disk_entry_sans_path(object_hash))
                 .saturating_sub(header::SIZE)
         }
-        Version::V4 => num_entries as usize * AVERAGE_V4_DELTA_PATH_LEN_IN_BYTES,
+        Version::V4 => capacity_for(num_entries, on_disk_size, object_hash) * AVERAGE_V4_DELTA_PATH_LEN_IN_BYTES,
     }
 }
 
-/// Note that `data` must point to the beginning of the entries, right past the header.
+/// Return `num_entries` as claimed by the header, but not more than could possibly fit into `on_disk_size` bytes, as the
+/// header can't be trusted with allocations.
+pub fn capacity_for(num_entries: u32, on_disk_size: usize, object_hash: gix_hash::Kind) -> usize {
+    // Each entry has at least its stat information, hash and flags, followed by a path and padding of at least one byte,
+    // or the path prefix length and NUL in V4.
+    let min_on_disk_entry_size = 40 + object_hash.len_in_bytes() + 2 + 2;
+    (num_entries as usize).min(on_disk_size / min_on_disk_entry_size)
+}
+
+/// Note that `data` must point to the beginning of an entry, the one at `first_entry_index`, which is at `offset_in_file`
+/// for use in errors.
+#[allow(clippy::too_many_arguments)]
 pub fn chunk<'a>(
     mut data: &'a [u8],
     entries: &mut Vec<Entry>,
     path_backing: &mut Vec<u8>,
     num_entries: u32,
+    first_entry_index: u32,
+    offset_in_file: usize,
     object_hash: gix_hash::Kind,
     version: Version,
 ) -> Result<(Outcome, &'a [u8]), decode::Error> {
     let mut is_sparse = false;
     let has_delta_paths = version == Version::V4;
+    let may_have_extended_flags = version != Version::V2;
     let mut prev_path = None;
     let mut delta_buf = Vec::<u8>::with_capacity(AVERAGE_V4_DELTA_PATH_LEN_IN_BYTES);
+    let start_len = data.len();
 
     for idx in 0..num_entries {
         let (entry, remaining) = load_one(
@@ -59,9 +74,19 @@ pub fn chunk<'a>(
             path_backing,
             object_hash.len_in_bytes(),
             has_delta_paths,
+            may_have_extended_flags,
             prev_path,
         )
-        .ok_or(decode::Error::Entry { index: idx })?;
+        .ok_or_else(|| {
+            if path_backing.len() > u32::MAX as usize {
+                decode::Error::PathBackingTooLarge
+            } else {
+                decode::Error::Entry {
+                    index: first_entry_index + idx,
+                    offset: offset_in_file + (start_len - data.len()),
+                }
+            }
+        })?;
 
         data = remaining;
         if entry.mode.is_sparse() {
@@ -71,18 +96,211 @@ pub fn chunk<'a>(
         //       also don't yet handle but probably could, maybe even smartly with the collection.
         //       For now it's unclear to me how they access the index, they could iterate quickly, and have fast access by path.
         entries.push(entry);
-        prev_path = entries.last().map(|e| (e.path.clone(), &mut delta_buf));
+        prev_path = entries.last().map(|e| (e.path_range(), &mut delta_buf));
     }
 
     Ok((Outcome { is_sparse }, data))
 }
 
-/// Note that `prev_path` is only useful if the version is V4
-fn load_one<'a>(
+/// Decode V2 or V3 entries from `data` until it is fully consumed, or return `None` if `data` doesn't start at an entry
+/// or doesn't end exactly where an entry ends.
+pub fn chunk_exact(
+    mut data: &[u8],
+    entries: &mut Vec<Entry>,
+    path_backing: &mut Vec<u8>,
+    object_hash: gix_hash::Kind,
+    version: Version,
+) -> Option<Outcome> {
+    debug_assert_ne!(version, Version::V4, "delta paths need their previous path");
+    let mut is_sparse = false;
+    while !data.is_empty() {
+        let (entry, remaining) = load_one(
+            data,
+            path_backing,
+            object_hash.len_in_bytes(),
+            false,
+            version != Version::V2,
+            None,
+        )?;
+        is_sparse |= entry.mode.is_sparse();
+        entries.push(entry);
+        data = remaining;
+    }
+    Some(Outcome { is_sparse })
+}
+
+/// Decode up to `max_entries` V2 or V3 entries from `data`, stopping at the first one that fails to decode,
+/// and return the amount of bytes consumed after each decoded entry.
+///
+/// Note that trailing entries may be garbage if `data` continues past the last entry.
+pub fn chunk_greedy(
+    mut data: &[u8],
+    entries: &mut Vec<Entry>,
+    path_backing: &mut Vec<u8>,
+    max_entries: u32,
+    object_hash: gix_hash::Kind,
+    version: Version,
+) -> Vec<usize> {
+    debug_assert_ne!(version, Version::V4, "delta paths need their previous path");
+    let start = data.len();
+    let mut consumed_after_entry = Vec::new();
+    for _ in 0..max_entries {
+        match load_one(
+            data,
+            path_backing,
+            object_hash.len_in_bytes(),
+            false,
+            version != Version::V2,
+            None,
+        ) {
+            Some((entry, remaining)) => {
+                entries.push(entry);
+                data = remaining;
+                consumed_after_entry.push(start - data.len());
+            }
+            None => break,
+        }
+    }
+    consumed_after_entry
+}
+
+/// Decode up to `max_entries` entries of any version from `data`, which starts at the first entry, stopping at the first one
+/// that fails to decode or whose stat information [isn't plausible][is_plausible_stat()], and return the amount of bytes
+/// consumed by all decoded entries.
+///
+/// This salvages the entries of an index whose header claims more entries than there are, or which has garbage after its entries,
+/// without taking unrelated data for entries if possible.
+pub fn chunk_lenient(
+    mut data: &[u8],
+    entries: &mut Vec<Entry>,
+    path_backing: &mut Vec<u8>,
+    max_entries: u32,
+    object_hash: gix_hash::Kind,
+    version: Version,
+) -> (Outcome, usize) {
+    let mut is_sparse = false;
+    let start_len = data.len();
+    let mut delta_buf = Vec::<u8>::with_capacity(AVERAGE_V4_DELTA_PATH_LEN_IN_BYTES);
+    for _ in 0..max_entries {
+        let path_backing_len = path_backing.len();
+        let prev_path = entries.last().map(|e| (e.path_range(), &mut delta_buf));
+        let entry = is_plausible_stat(data).and_then(|()| {
+            load_one(
+                data,
+                path_backing,
+                object_hash.len_in_bytes(),
+                version == Version::V4,
+                version != Version::V2,
+                prev_path,
+            )
+        });
+        match entry {
+            Some((entry, remaining)) => {
+                is_sparse |= entry.mode.is_sparse();
+                entries.push(entry);
+                data = remaining;
+            }
+            None => {
+                // Entries that fail to decode may have added part of their path already.
+                path_backing.truncate(path_backing_len);
+                break;
+            }
+        }
+    }
+    (Outcome { is_sparse }, start_len - data.len())
+}
+
+/// Guess the offsets at which to split V2 or V3 entries in `data` into `num_chunks` chunks of similar size, with `data` starting
+/// at the first entry. The first offset is always 0.
+///
+/// As entries are padded to a multiple of 8 bytes, only these positions are considered, and each of them is checked for
+/// looking like an entry. Return `None` if no plausible entry could be found for a chunk.
+/// Note that the returned offsets are guesses and must be validated by decoding the entries in between.
+pub fn guess_offsets(
+    data: &[u8],
+    num_chunks: usize,
+    object_hash: gix_hash::Kind,
+    version: Version,
+) -> Option<Vec<usize>> {
+    let approx_chunk_len = data.len() / num_chunks;
+    let mut out = Vec::with_capacity(num_chunks);
+    out.push(0);
+    for chunk in 1..num_chunks {
+        let end = (chunk + 1) * approx_chunk_len;
+        let mut offset = (chunk * approx_chunk_len) & !7;
+        loop {
+            if offset >= end {
+                return None;
+            }
+            if is_plausible_entry(&data[offset..], object_hash.len_in_bytes(), version != Version::V2).is_some() {
+                break;
+            }
+            offset += 8;
+        }
+        out.push(offset);
+    }
+    Some(out)
+}
+
+/// Return `Some(())` if `data` looks like it starts with the stat information of an entry of any version, i.e. if its
+/// nanoseconds are in range and its mode is known.
+pub fn is_plausible_stat(data: &[u8]) -> Option<()> {
+    const NANOS_PER_SEC: u32 = 1_000_000_000;
+    let (_ctime_secs, data) = read_u32(data)?;
+    let (ctime_nsecs, data) = read_u32(data)?;
+    let (_mtime_secs, data) = read_u32(data)?;
+    let (mtime_nsecs, data) = read_u32(data)?;
+    if ctime_nsecs >= NANOS_PER_SEC || mtime_nsecs >= NANOS_PER_SEC {
+        return None;
+    }
+    let (_dev, data) = read_u32(data)?;
+    let (_ino, data) = read_u32(data)?;
+    let (mode, _data) = read_u32(data)?;
+    matches!(mode, 0o040000 | 0o100644 | 0o100755 | 0o120000 | 0o160000).then_some(())
+}
+
+/// Return `Some(())` if `data` looks like it starts with a V2 or V3 entry.
+fn is_plausible_entry(data: &[u8], hash_len: usize, may_have_extended_flags: bool) -> Option<()> {
+    let first_byte_of_entry = data.as_ptr() as usize;
+    is_plausible_stat(data)?;
+    let (_ctime_to_mode, data) = split_at_pos(data, 4 * 7)?;
+    let (_uid, data) = read_u32(data)?;
+    let (_gid, data) = read_u32(data)?;
+    let (_size, data) = read_u32(data)?;
+    let (_hash, data) = split_at_pos(data, hash_len)?;
+    let (flags, data) = read_u16(data)?;
+    let flags = entry::at_rest::Flags::from_bits(flags)?;
+    let data = if flags.contains(entry::at_rest::Flags::EXTENDED) {
+        if !may_have_extended_flags {
+            return None;
+        }
+        let (extended_flags, data) = read_u16(data)?;
+        entry::at_rest::FlagsExtended::from_bits(extended_flags)?;
+        data
+    } else {
+        data
+    };
+
+    let path_len = (flags & entry::at_rest::Flags::PATH_LEN).bits() as usize;
+    if path_len == entry::at_rest::Flags::PATH_LEN.bits() as usize {
+        let (path, _data) = split_at_byte_exclusive(data, 0)?;
+        return (path.len() >= path_len).then_some(());
+    }
+    let (path, data) = split_at_pos(data, path_len)?;
+    if path.is_empty() || path.contains(&0) {
+        return None;
+    }
+    let padding_len = data.len() - skip_padding(data, first_byte_of_entry)?.len();
+    data[..padding_len].iter().all(|b| *b == 0).then_some(())
+}
+
+/// Note that `prev_path` is only useful if the version is V4, and that extended flags are only valid from V3 onwards.
+pub fn load_one<'a>(
     data: &'a [u8],
     path_backing: &mut Vec<u8>,
     hash_len: usize,
     has_delta_paths: bool,
+    may_have_extended_flags: bool,
     prev_path_and_buf: Option<(Range<usize>, &mut Vec<u8>)>,
 ) -> Option<(Entry, &'a [u8])> {
     let first_byte_of_entry = data.as_ptr() as usize;
@@ -100,6 +318,9 @@ fn load_one<'a>(
     let (flags, data) = read_u16(data)?;
     let flags = entry::at_rest::Flags::from_bits(flags)?;
     let (flags, data) = if flags.contains(entry::at_rest::Flags::EXTENDED) {
+        if !may_have_extended_flags {
+            return None;
+        }
         let (extended_flags, data) = read_u16(data)?;
         let extended_flags = entry::at_rest::FlagsExtended::from_bits(extended_flags)?;
         let extended_flags = extended_flags.to_flags()?;
@@ -111,6 +332,7 @@ fn load_one<'a>(
     let start = path_backing.len();
     let data = if has_delta_paths {
         let (strip_len, data) = var_int(data)?;
+        // The strip length of the first entry of a block is ignored as there is no previous path, just like git does.
         if let Some((prev_path, buf)) = prev_path_and_buf {
             let end = prev_path.end.checked_sub(strip_len.try_into().ok()?)?;
             let copy_len = end.checked_sub(prev_path.start)?;
@@ -126,18 +348,19 @@ fn load_one<'a>(
 
         data
     } else {
-        let (path, data) = if flags.contains(entry::Flags::PATH_LEN) {
-            split_at_byte_exclusive(data, 0)?
+        // Lengths that don't fit into the flags are saturated, and the path ends at its NUL just like git assumes.
+        let path_len = if flags.contains(entry::Flags::PATH_LEN) {
+            data.iter().position(|b| *b == 0)?
         } else {
-            let path_len = (flags.bits() & entry::Flags::PATH_LEN.bits()) as usize;
-            let (path, data) = split_at_pos(data, path_len)?;
-            (path, skip_padding(data, first_byte_of_entry))
+            (flags.bits() & entry::Flags::PATH_LEN.bits()) as usize
         };
+        let (path, data) = split_at_pos(data, path_len)?;
+        let data = skip_padding(data, first_byte_of_entry)?;
 
         path_backing.extend_from_slice(path);
         data
     };
-    let path_range = start..path_backing.len();
+    let path_range = entry::try_stored_path_range(start..path_backing.len())?;
 
     Some((
         Entry {
@@ -167,12 +390,12 @@ fn load_one<'a>(
 }
 
 #[inline]
-fn skip_padding(data: &[u8], first_byte_of_entry: usize) -> &[u8] {
+fn skip_padding(data: &[u8], first_byte_of_entry: usize) -> Option<&[u8]> {
     let current_offset = data.as_ptr() as usize;
     let c_padding = (current_offset - first_byte_of_entry + 8) & !7;
     let skip = (first_byte_of_entry + c_padding) - current_offset;
 
-    &data[skip..]
+    data.get(skip..)
 }
 
 #[inline]
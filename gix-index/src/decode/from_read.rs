@@ -0,0 +1,225 @@
+use std::io::{BufRead, Read};
+
+use filetime::FileTime;
+
+use crate::{
+    decode::{self, assemble, check_trailer, entries, header, EntriesOutcome},
+    entry::at_rest,
+    extension,
+    util::from_be_u32,
+    State, Version,
+};
+
+mod error {
+    /// The error returned by [State::from_read()][crate::State::from_read()].
+    #[derive(Debug, thiserror::Error)]
+    #[allow(missing_docs)]
+    pub enum Error {
+        #[error("An IO error occurred while reading the index")]
+        Io(#[from] std::io::Error),
+        #[error(transparent)]
+        Decode(#[from] crate::decode::Error),
+    }
+}
+pub use error::Error;
+
+impl State {
+    /// Decode an index state from `read` just like [`from_bytes()`][State::from_bytes()] would decode all of its bytes, but
+    /// without having them in memory at once. This is useful to read an index from a stream, like an archive, or if there
+    /// is too little address space to map large indices.
+    ///
+    /// Only the entry or extension that is currently decoded is buffered, which is why all of them are decoded one after
+    /// another on the current thread. Thus, the threading options [`thread_limit`][decode::Options::thread_limit],
+    /// [`min_extension_block_in_bytes_for_threading`][decode::Options::min_extension_block_in_bytes_for_threading] and
+    /// [`guess_entry_offsets_for_threading`][decode::Options::guess_entry_offsets_for_threading] are ignored.
+    /// The index is also always decoded in [`Strict`][decode::Mode::Strict] mode, as salvaging it requires to look at all of
+    /// its bytes at once, which is what [`from_bytes()`][State::from_bytes()] can do.
+    pub fn from_read(
+        mut read: impl BufRead,
+        timestamp: FileTime,
+        object_hash: gix_hash::Kind,
+        decode::Options {
+            expected_checksum,
+            verify_checksum,
+            verify_tree,
+            verify_paths,
+            extensions: load_extensions,
+            thread_limit: _,
+            min_extension_block_in_bytes_for_threading: _,
+            guess_entry_offsets_for_threading: _,
+            mode: _,
+        }: decode::Options,
+    ) -> Result<(Self, decode::Outcome), Error> {
+        let hash_len = object_hash.len_in_bytes();
+        let mut hasher = verify_checksum.then(|| gix_features::hash::hasher(object_hash));
+        let mut hash = |data: &[u8]| {
+            if let Some(hasher) = hasher.as_mut() {
+                hasher.update(data);
+            }
+        };
+
+        let mut buf = Vec::new();
+        fill(&mut read, &mut buf, header::SIZE + hash_len)?;
+        let (version, num_entries, _) = header::decode(&buf, object_hash).map_err(decode::Error::from)?;
+        hash(&buf[..header::SIZE]);
+        let mut read = std::io::Cursor::new(buf.split_off(header::SIZE)).chain(read);
+
+        let mut entries = Vec::new();
+        let mut path_backing = Vec::new();
+        let mut is_sparse = false;
+        let mut delta_buf = Vec::with_capacity(entries::AVERAGE_V4_DELTA_PATH_LEN_IN_BYTES);
+        let mut offset = header::SIZE;
+        for index in 0..num_entries {
+            buf.clear();
+            let is_complete = read_entry(&mut read, &mut buf, hash_len, version)?;
+            let prev_path = entries.last().map(|e: &crate::Entry| (e.path_range(), &mut delta_buf));
+            let entry = if is_complete {
+                entries::load_one(
+                    &buf,
+                    &mut path_backing,
+                    hash_len,
+                    version == Version::V4,
+                    version != Version::V2,
+                    prev_path,
+                )
+            } else {
+                None
+            };
+            let entry = match entry {
+                Some((entry, _padding)) => entry,
+                None if path_backing.len() > u32::MAX as usize => return Err(decode::Error::PathBackingTooLarge.into()),
+                None => return Err(decode::Error::Entry { index, offset }.into()),
+            };
+            hash(&buf);
+            offset += buf.len();
+            is_sparse |= entry.mode.is_sparse();
+            entries.push(entry);
+        }
+        // Without knowing the amount of entries in advance, buffers grow as needed and may have a lot of spare capacity.
+        entries.shrink_to_fit();
+        path_backing.shrink_to_fit();
+
+        let start_of_extensions = offset;
+        let mut ext = extension::decode::Outcome::default();
+        let mut ext_headers = Vec::new();
+        let mut is_end_of_index_entry_valid = None;
+        buf.clear();
+        loop {
+            // Whatever follows the last extension is the trailer, so only a complete extension header followed by
+            // enough bytes for the trailer indicates another extension.
+            if !fill(&mut read, &mut buf, 4 + 4 + hash_len)? {
+                if buf.len() != hash_len {
+                    return Err(decode::Error::UnexpectedTrailerLength {
+                        expected: hash_len,
+                        actual: buf.len(),
+                    }
+                    .into());
+                }
+                break;
+            }
+            let (signature, size, _) = extension::decode::header(&buf);
+            let ext_len = 4 + 4 + size as usize;
+            if !fill(&mut read, &mut buf, ext_len + hash_len)? {
+                return Err(decode::Error::Extension {
+                    signature,
+                    offset,
+                    source: extension::decode::Error::SizeExceedsData {
+                        size,
+                        available: buf.len() - hash_len - (4 + 4),
+                    },
+                }
+                .into());
+            }
+            let ext_data = &buf[4 + 4..ext_len];
+            is_end_of_index_entry_valid = (signature == extension::end_of_index_entry::SIGNATURE
+                && ext_data.len() == extension::end_of_index_entry::MIN_SIZE)
+                .then(|| is_valid_end_of_index_entry(ext_data, start_of_extensions, &ext_headers));
+            ext_headers.extend_from_slice(&buf[..4 + 4]);
+            extension::decode::one(&mut ext, signature, ext_data, offset, object_hash, load_extensions)?;
+
+            hash(&buf[..ext_len]);
+            offset += ext_len;
+            buf.drain(..ext_len);
+        }
+
+        let checksum = gix_hash::ObjectId::from(buf.as_slice());
+        let actual_checksum = hasher
+            .filter(|_| !checksum.is_null())
+            .map(|hasher| gix_hash::ObjectId::from(hasher.digest()));
+        let checksum = check_trailer(checksum, actual_checksum, expected_checksum)?;
+        let entries = EntriesOutcome {
+            entries,
+            path_backing,
+            is_sparse,
+        };
+        let state = assemble(object_hash, timestamp, version, entries, ext, verify_tree, verify_paths)?;
+        Ok((
+            state,
+            decode::Outcome {
+                checksum,
+                end_of_index_entry_mismatch: is_end_of_index_entry_valid == Some(false),
+                recovery: None,
+            },
+        ))
+    }
+}
+
+/// Read from `read` into `buf` until it's `len` bytes long, and return `false` if the data ended before.
+fn fill(read: &mut impl BufRead, buf: &mut Vec<u8>, len: usize) -> std::io::Result<bool> {
+    if let Some(missing) = len.checked_sub(buf.len()) {
+        read.by_ref().take(missing as u64).read_to_end(buf)?;
+    }
+    Ok(buf.len() >= len)
+}
+
+/// Read all bytes of the next entry into the empty `buf`, without validating them, and return `false` if the data ended
+/// before the entry did.
+fn read_entry(read: &mut impl BufRead, buf: &mut Vec<u8>, hash_len: usize, version: Version) -> std::io::Result<bool> {
+    let flags_end = 40 + hash_len + 2;
+    if !fill(read, buf, flags_end)? {
+        return Ok(false);
+    }
+    let flags = at_rest::Flags::from_bits_truncate(u16::from_be_bytes([buf[flags_end - 2], buf[flags_end - 1]]));
+    if flags.contains(at_rest::Flags::EXTENDED) && !fill(read, buf, flags_end + 2)? {
+        return Ok(false);
+    }
+    Ok(match version {
+        Version::V4 => {
+            // The length to strip from the previous path is a variable integer of up to 10 bytes.
+            let start = buf.len();
+            loop {
+                if !fill(read, buf, buf.len() + 1)? {
+                    return Ok(false);
+                }
+                if buf[buf.len() - 1] & 0x80 == 0 || buf.len() - start == 10 {
+                    break;
+                }
+            }
+            read.read_until(0, buf)?;
+            buf.last() == Some(&0)
+        }
+        Version::V2 | Version::V3 => {
+            let path_len = (flags & at_rest::Flags::PATH_LEN).bits() as usize;
+            let path_end = if path_len == at_rest::Flags::PATH_LEN.bits() as usize {
+                read.read_until(0, buf)?;
+                if buf.last() != Some(&0) {
+                    return Ok(false);
+                }
+                buf.len() - 1
+            } else {
+                buf.len() + path_len
+            };
+            // Entries are padded with 1 to 8 NUL bytes so their size is a multiple of 8.
+            fill(read, buf, (path_end + 8) & !7)?
+        }
+    })
+}
+
+/// Return `true` if the end-of-index-entry extension with `ext_data` points to `start_of_extensions`, and if its hash matches
+/// the headers of all extensions before it in `ext_headers`, just like [`extension::end_of_index_entry::decode()`] checks.
+fn is_valid_end_of_index_entry(ext_data: &[u8], start_of_extensions: usize, ext_headers: &[u8]) -> bool {
+    let (offset, checksum) = ext_data.split_at(4);
+    let mut hasher = gix_features::hash::hasher(gix_hash::Kind::Sha1);
+    hasher.update(ext_headers);
+    from_be_u32(offset) as usize == start_of_extensions && !ext_headers.is_empty() && hasher.digest() == checksum
+}
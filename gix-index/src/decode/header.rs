@@ -5,13 +5,16 @@ use crate::{util::from_be_u32, Version};
 pub(crate) const SIGNATURE: &[u8] = b"DIRC";
 
 mod error {
+    use bstr::BString;
 
     /// The error produced when failing to decode an index header.
     #[derive(Debug, thiserror::Error)]
     #[allow(missing_docs)]
     pub enum Error {
-        #[error("{0}")]
-        Corrupt(&'static str),
+        #[error("File is too small with {size} bytes even for a header with zero entries and smallest hash")]
+        TooSmall { size: usize },
+        #[error("Signature mismatch - this doesn't claim to be an index file as its signature is '{signature}'")]
+        SignatureMismatch { signature: BString },
         #[error("Index version {0} is not supported")]
         UnsupportedVersion(u32),
     }
@@ -20,16 +23,14 @@ pub use error::Error;
 
 pub(crate) fn decode(data: &[u8], object_hash: gix_hash::Kind) -> Result<(Version, u32, &[u8]), Error> {
     if data.len() < (3 * 4) + object_hash.len_in_bytes() {
-        return Err(Error::Corrupt(
-            "File is too small even for header with zero entries and smallest hash",
-        ));
+        return Err(Error::TooSmall { size: data.len() });
     }
 
     let (signature, data) = data.split_at(4);
     if signature != SIGNATURE {
-        return Err(Error::Corrupt(
-            "Signature mismatch - this doesn't claim to be a header file",
-        ));
+        return Err(Error::SignatureMismatch {
+            signature: signature.into(),
+        });
     }
 
     let (version, data) = data.split_at(4);
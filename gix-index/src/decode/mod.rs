@@ -4,9 +4,12 @@ use crate::{entry, extension, Entry, State, Version};
 
 mod entries;
 ///
+pub mod from_read;
+///
 pub mod header;
 
 mod error {
+    use bstr::ByteSlice;
 
     use crate::{decode, extension};
 
@@ -16,17 +19,27 @@ mod error {
     pub enum Error {
         #[error(transparent)]
         Header(#[from] decode::header::Error),
-        #[error("Could not parse entry at index {index}")]
-        Entry { index: u32 },
-        #[error("Mandatory extension wasn't implemented or malformed.")]
-        Extension(#[from] extension::decode::Error),
+        #[error("Could not parse entry at index {index} at byte offset {offset}")]
+        Entry { index: u32, offset: usize },
+        #[error("Could not decode extension '{}' at byte offset {offset}", signature.as_bstr())]
+        Extension {
+            signature: extension::Signature,
+            offset: usize,
+            source: extension::decode::Error,
+        },
+        #[error("The paths of all entries need more than 4GiB of memory, which isn't supported")]
+        PathBackingTooLarge,
         #[error("Index trailer should have been {expected} bytes long, but was {actual}")]
         UnexpectedTrailerLength { expected: usize, actual: usize },
-        #[error("Shared index checksum was {actual_checksum} but should have been {expected_checksum}")]
+        #[error("Index checksum was {actual_checksum} but should have been {expected_checksum}")]
         ChecksumMismatch {
             actual_checksum: gix_hash::ObjectId,
             expected_checksum: gix_hash::ObjectId,
         },
+        #[error("The cache tree doesn't match the entries of the index")]
+        Tree(#[from] crate::verify::tree::Error),
+        #[error(transparent)]
+        Path(#[from] crate::verify::paths::Error),
     }
 }
 pub use error::Error;
@@ -35,7 +48,10 @@ use gix_features::parallel::InOrderIter;
 use crate::util::read_u32;
 
 /// Options to define how to decode an index state [from bytes][State::from_bytes()].
-#[derive(Default, Clone, Copy)]
+///
+/// The [default][Options::default()] uses as many threads as there are logical cores and doesn't verify anything beyond
+/// the structure of the index, which is what most callers need.
+#[derive(Debug, Default, Clone, Copy)]
 pub struct Options {
     /// If Some(_), we are allowed to use more than one thread. If Some(N), use no more than N threads. If Some(0)|None, use as many threads
     /// as there are logical cores.
@@ -50,12 +66,123 @@ pub struct Options {
     ///
     /// We will abort reading this file if it doesn't match.
     pub expected_checksum: Option<gix_hash::ObjectId>,
+    /// If true and if more than one thread may be used, guess where entries start if there is no IEOT extension to tell us
+    /// so entries can still be decoded in parallel. This only works for V2 and V3 indices.
+    ///
+    /// Guesses are validated while decoding, and we fall back to decoding entries serially if they turn out to be wrong.
+    pub guess_entry_offsets_for_threading: bool,
+    /// If true, hash all bytes of the index and compare the result to its trailing checksum, failing with
+    /// [`Error::ChecksumMismatch`] if it doesn't match. Indices with a null trailing checksum aren't verified.
+    ///
+    /// If more than one thread may be used, the hash is computed on its own thread while entries and extensions are decoded,
+    /// making verification nearly free.
+    pub verify_checksum: bool,
+    /// If true, [verify the cache tree][State::verify_tree()] against the decoded entries, failing with [`Error::Tree`]
+    /// if it's inconsistent. This is useful to detect corruptions that would otherwise cause wrong trees to be written.
+    pub verify_tree: bool,
+    /// If set, [verify the paths][State::verify_paths()] of all entries with the given protections, failing with
+    /// [`Error::Path`] if one of them is unsafe to check out. Use this for indices from untrusted sources.
+    pub verify_paths: Option<crate::verify::paths::Options>,
+    /// Determine which optional extensions to decode. Skipping them is useful if only entries are needed, as extensions like
+    /// the untracked cache can be costly to decode.
+    ///
+    /// Skipped extensions are still used to locate entries, and are [recorded][State::skipped_extensions()] to prevent
+    /// writing the index without them by accident.
+    pub extensions: Load,
+    /// Determine what to do if the index can't be decoded as a whole, for instance because an interrupted writer left it with
+    /// fewer entries than its header claims.
+    pub mode: Mode,
+}
+
+/// How to handle indices that can't be decoded as a whole, [when decoding][State::from_bytes()].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Fail if any entry or extension can't be decoded, or if the trailer isn't where it is expected.
+    #[default]
+    Strict,
+    /// If decoding fails like in [`Strict`][Mode::Strict] mode because of an entry, an extension or the trailer, decode all entries
+    /// up to the last one that can be decoded instead, and [describe][Outcome::recovery] what was salvaged.
+    ///
+    /// Extensions are decoded if they can still be found, either right after all entries the header claims or with the help of
+    /// the end-of-index-entry extension, and skipped entirely otherwise or if they fail to decode. The cache tree is dropped
+    /// if entries are missing as it would describe them. Indices with an [expected checksum][Options::expected_checksum]
+    /// are never recovered as they must be exactly what is expected.
+    Lenient,
+}
+
+/// Which optional extensions to load when [decoding an index][State::from_bytes()].
+///
+/// Mandatory extensions, like `link` or `sdir`, are always loaded as the index can't be understood without them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Load {
+    /// Load all extensions.
+    #[default]
+    All,
+    /// Load no optional extension.
+    None,
+    /// Load only the optional extensions with the given signatures.
+    Only(&'static [extension::Signature]),
+}
+
+impl Load {
+    /// Return `true` if the optional extension with `signature` should be loaded.
+    pub fn should_load(&self, signature: extension::Signature) -> bool {
+        match self {
+            Load::All => true,
+            Load::None => false,
+            Load::Only(signatures) => signatures.contains(&signature),
+        }
+    }
+}
+
+/// Information about how an index was decoded, as returned by [State::from_bytes()].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Outcome {
+    /// The checksum over all bytes of the index prior to it, as stored in its trailer, or `None` if the trailer is null.
+    ///
+    /// The latter happens if the index was written by tools that don't compute a checksum, or by git if `index.skipHash` is set.
+    pub checksum: Option<gix_hash::ObjectId>,
+    /// If `true`, the end-of-index-entry extension was present, but its hash didn't match the extensions it refers to or its offset
+    /// was invalid. It was ignored, and the extensions were found by decoding all entries sequentially instead, just like git does.
+    pub end_of_index_entry_mismatch: bool,
+    /// If the index couldn't be decoded as a whole and was recovered in [`Lenient`][Mode::Lenient] mode, this describes what
+    /// was salvaged. The trailing checksum isn't used then, so `checksum` is `None`.
+    pub recovery: Option<Recovery>,
+}
+
+/// A description of what was salvaged from an index that couldn't be decoded as a whole, as found in [Outcome::recovery].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Recovery {
+    /// The amount of entries the header claims the index has.
+    pub num_declared_entries: u32,
+    /// The amount of entries that could be decoded, which are all entries of the state.
+    pub num_recovered_entries: u32,
+    /// If `true`, the extensions couldn't be found or decoded, so none of them are present in the state.
+    pub extensions_unreachable: bool,
 }
 
 impl State {
     /// Decode an index state from `data` and store `timestamp` in the resulting instance for pass-through, assuming `object_hash`
     /// to be used through the file.
+    ///
+    /// Use [`Mode::Lenient`] in `options` to salvage what's possible from indices that can't be decoded as a whole.
     pub fn from_bytes(
+        data: &[u8],
+        timestamp: FileTime,
+        object_hash: gix_hash::Kind,
+        options: Options,
+    ) -> Result<(Self, Outcome), Error> {
+        match Self::from_bytes_strict(data, timestamp, object_hash, options) {
+            Err(Error::Entry { .. } | Error::Extension { .. } | Error::UnexpectedTrailerLength { .. })
+                if options.mode == Mode::Lenient && options.expected_checksum.is_none() =>
+            {
+                recover(data, timestamp, object_hash, options)
+            }
+            res => res,
+        }
+    }
+
+    fn from_bytes_strict(
         data: &[u8],
         timestamp: FileTime,
         object_hash: gix_hash::Kind,
@@ -63,12 +190,29 @@ impl State {
             thread_limit,
             min_extension_block_in_bytes_for_threading,
             expected_checksum,
+            guess_entry_offsets_for_threading,
+            verify_checksum,
+            verify_tree,
+            verify_paths,
+            extensions: load_extensions,
+            mode: _,
         }: Options,
-    ) -> Result<(Self, gix_hash::ObjectId), Error> {
+    ) -> Result<(Self, Outcome), Error> {
         let (version, num_entries, post_header_data) = header::decode(data, object_hash)?;
         let start_of_extensions = extension::end_of_index_entry::decode(data, object_hash);
+        let end_of_index_entry_mismatch =
+            start_of_extensions.is_none() && extension::end_of_index_entry::is_present(data, object_hash);
 
         let mut num_threads = gix_features::parallel::num_threads(thread_limit);
+        let hash_len = object_hash.len_in_bytes();
+        let verify_checksum = verify_checksum
+            && data
+                .get(data.len().saturating_sub(hash_len)..)
+                .map_or(false, |trailer| trailer.iter().any(|b| *b != 0));
+        let hash_in_parallel = verify_checksum && num_threads > 1;
+        if hash_in_parallel {
+            num_threads -= 1;
+        }
         let path_backing_buffer_size = entries::estimate_path_storage_requirements_in_bytes(
             num_entries,
             data.len(),
@@ -77,132 +221,200 @@ impl State {
             version,
         );
 
-        let (entries, ext, data) = match start_of_extensions {
-            Some(offset) if num_threads > 1 => {
-                let extensions_data = &data[offset..];
-                let index_offsets_table = extension::index_entry_offset_table::find(extensions_data, object_hash);
-                let (entries_res, ext_res) = gix_features::parallel::threads(|scope| {
-                    let extension_loading =
-                        (extensions_data.len() > min_extension_block_in_bytes_for_threading).then({
-                            num_threads -= 1;
-                            || {
-                                gix_features::parallel::build_thread()
-                                    .name("gix-index.from_bytes.load-extensions".into())
-                                    .spawn_scoped(scope, || extension::decode::all(extensions_data, object_hash))
-                                    .expect("valid name")
-                            }
+        let decode_entries_and_extensions = |mut num_threads: usize| {
+            Ok::<_, Error>(match start_of_extensions {
+                Some(offset) if num_threads > 1 => {
+                    let extensions_data = &data[offset..];
+                    let index_offsets_table = extension::index_entry_offset_table::find(extensions_data, object_hash)
+                        .filter(|offsets| {
+                            // Fall back to reading serially if the table doesn't describe exactly the entries we expect,
+                            // rather than failing or producing incorrect results.
+                            let start_of_entries = (data.len() - post_header_data.len()) as u64;
+                            offsets.iter().map(|o| u64::from(o.num_entries)).sum::<u64>() == u64::from(num_entries)
+                                && offsets.iter().all(|o| {
+                                    (start_of_entries..offset as u64).contains(&u64::from(o.from_beginning_of_file))
+                                })
                         });
-                    let entries_res = match index_offsets_table {
-                        Some(entry_offsets) => {
-                            let chunk_size = (entry_offsets.len() as f32 / num_threads as f32).ceil() as usize;
-                            let num_chunks = entry_offsets.chunks(chunk_size).count();
-                            let mut threads = Vec::with_capacity(num_chunks);
-                            for (id, chunks) in entry_offsets.chunks(chunk_size).enumerate() {
-                                let chunks = chunks.to_vec();
-                                threads.push(
+                    let (entries_res, ext_res) = gix_features::parallel::threads(|scope| {
+                        let extension_loading = (extensions_data.len() > min_extension_block_in_bytes_for_threading)
+                            .then({
+                                num_threads -= 1;
+                                || {
                                     gix_features::parallel::build_thread()
-                                        .name(format!("gix-index.from_bytes.read-entries.{id}"))
-                                        .spawn_scoped(scope, move || {
-                                            let num_entries_for_chunks =
-                                                chunks.iter().map(|c| c.num_entries).sum::<u32>() as usize;
-                                            let mut entries = Vec::with_capacity(num_entries_for_chunks);
-                                            let path_backing_buffer_size_for_chunks =
-                                                entries::estimate_path_storage_requirements_in_bytes(
+                                        .name("gix-index.from_bytes.load-extensions".into())
+                                        .spawn_scoped(scope, || {
+                                            extension::decode::all(
+                                                extensions_data,
+                                                offset,
+                                                object_hash,
+                                                load_extensions,
+                                            )
+                                        })
+                                        .expect("valid name")
+                                }
+                            });
+                        let entries_res = match index_offsets_table {
+                            Some(entry_offsets) => {
+                                let chunk_size = (entry_offsets.len() as f32 / num_threads as f32).ceil() as usize;
+                                let num_chunks = entry_offsets.chunks(chunk_size).count();
+                                let mut threads = Vec::with_capacity(num_chunks);
+                                let mut first_entry_index = 0;
+                                for (id, chunks) in entry_offsets.chunks(chunk_size).enumerate() {
+                                    let chunks = chunks.to_vec();
+                                    let mut entry_index = first_entry_index;
+                                    first_entry_index += chunks.iter().map(|c| c.num_entries).sum::<u32>();
+                                    threads.push(
+                                        gix_features::parallel::build_thread()
+                                            .name(format!("gix-index.from_bytes.read-entries.{id}"))
+                                            .spawn_scoped(scope, move || {
+                                                let num_entries_for_chunks =
+                                                    chunks.iter().map(|c| c.num_entries).sum::<u32>() as usize;
+                                                let mut entries = Vec::with_capacity(entries::capacity_for(
                                                     num_entries_for_chunks as u32,
-                                                    data.len() / num_chunks,
-                                                    start_of_extensions.map(|ofs| ofs / num_chunks),
+                                                    data.len(),
                                                     object_hash,
-                                                    version,
-                                                );
-                                            let mut path_backing =
-                                                Vec::with_capacity(path_backing_buffer_size_for_chunks);
-                                            let mut is_sparse = false;
-                                            for offset in chunks {
-                                                let (
-                                                    entries::Outcome {
-                                                        is_sparse: chunk_is_sparse,
+                                                ));
+                                                let path_backing_buffer_size_for_chunks =
+                                                    entries::estimate_path_storage_requirements_in_bytes(
+                                                        num_entries_for_chunks as u32,
+                                                        data.len() / num_chunks,
+                                                        start_of_extensions.map(|ofs| ofs / num_chunks),
+                                                        object_hash,
+                                                        version,
+                                                    );
+                                                let mut path_backing =
+                                                    Vec::with_capacity(path_backing_buffer_size_for_chunks);
+                                                let mut is_sparse = false;
+                                                for offset in chunks {
+                                                    let (
+                                                        entries::Outcome {
+                                                            is_sparse: chunk_is_sparse,
+                                                        },
+                                                        _data,
+                                                    ) = entries::chunk(
+                                                        &data[offset.from_beginning_of_file as usize..],
+                                                        &mut entries,
+                                                        &mut path_backing,
+                                                        offset.num_entries,
+                                                        entry_index,
+                                                        offset.from_beginning_of_file as usize,
+                                                        object_hash,
+                                                        version,
+                                                    )?;
+                                                    entry_index += offset.num_entries;
+                                                    is_sparse |= chunk_is_sparse;
+                                                }
+                                                Ok::<_, Error>((
+                                                    id,
+                                                    EntriesOutcome {
+                                                        entries,
+                                                        path_backing,
+                                                        is_sparse,
                                                     },
-                                                    _data,
-                                                ) = entries::chunk(
-                                                    &data[offset.from_beginning_of_file as usize..],
-                                                    &mut entries,
-                                                    &mut path_backing,
-                                                    offset.num_entries,
-                                                    object_hash,
-                                                    version,
-                                                )?;
-                                                is_sparse |= chunk_is_sparse;
+                                                ))
+                                            })
+                                            .expect("valid name"),
+                                    );
+                                }
+                                let mut results =
+                                    InOrderIter::from(threads.into_iter().map(|thread| thread.join().unwrap()));
+                                let mut acc = results.next().expect("have at least two results, one per thread");
+                                // We explicitly don't adjust the reserve in acc and rather allow for more copying
+                                // to happens as vectors grow to keep the peak memory size low.
+                                // NOTE: one day, we might use a memory pool for paths. We could encode the block of memory
+                                //       in some bytes in the path offset. That way there is more indirection/slower access
+                                //       to the path, but it would save time here.
+                                //       As it stands, `git` is definitely more efficient at this and probably uses less memory too.
+                                //       Maybe benchmarks can tell if that is noticeable later at 200/400GB/s memory bandwidth, or maybe just
+                                //       100GB/s on a single core.
+                                while let (Ok(lhs), Some(res)) = (acc.as_mut(), results.next()) {
+                                    match res {
+                                        Ok(rhs) => {
+                                            lhs.is_sparse |= rhs.is_sparse;
+                                            if lhs.append(rhs.entries, rhs.path_backing).is_none() {
+                                                acc = Err(Error::PathBackingTooLarge);
                                             }
-                                            Ok::<_, Error>((
-                                                id,
-                                                EntriesOutcome {
-                                                    entries,
-                                                    path_backing,
-                                                    is_sparse,
-                                                },
-                                            ))
-                                        })
-                                        .expect("valid name"),
-                                );
-                            }
-                            let mut results =
-                                InOrderIter::from(threads.into_iter().map(|thread| thread.join().unwrap()));
-                            let mut acc = results.next().expect("have at least two results, one per thread");
-                            // We explicitly don't adjust the reserve in acc and rather allow for more copying
-                            // to happens as vectors grow to keep the peak memory size low.
-                            // NOTE: one day, we might use a memory pool for paths. We could encode the block of memory
-                            //       in some bytes in the path offset. That way there is more indirection/slower access
-                            //       to the path, but it would save time here.
-                            //       As it stands, `git` is definitely more efficient at this and probably uses less memory too.
-                            //       Maybe benchmarks can tell if that is noticeable later at 200/400GB/s memory bandwidth, or maybe just
-                            //       100GB/s on a single core.
-                            while let (Ok(lhs), Some(res)) = (acc.as_mut(), results.next()) {
-                                match res {
-                                    Ok(rhs) => {
-                                        lhs.is_sparse |= rhs.is_sparse;
-                                        let ofs = lhs.path_backing.len();
-                                        lhs.path_backing.extend(rhs.path_backing);
-                                        lhs.entries.extend(rhs.entries.into_iter().map(|mut e| {
-                                            e.path.start += ofs;
-                                            e.path.end += ofs;
-                                            e
-                                        }));
-                                    }
-                                    Err(err) => {
-                                        acc = Err(err);
+                                        }
+                                        Err(err) => {
+                                            acc = Err(err);
+                                        }
                                     }
                                 }
+                                acc.map(|acc| (acc, &data[data.len() - object_hash.len_in_bytes()..]))
                             }
-                            acc.map(|acc| (acc, &data[data.len() - object_hash.len_in_bytes()..]))
-                        }
+                            None => guess_entry_offsets_for_threading
+                                .then(|| {
+                                    entries_at_guessed_offsets(
+                                        &data[header::SIZE..offset],
+                                        num_entries,
+                                        num_threads,
+                                        object_hash,
+                                        version,
+                                    )
+                                })
+                                .flatten()
+                                .map(|(entries, _consumed)| Ok((entries, &data[offset..])))
+                                .unwrap_or_else(|| {
+                                    entries(
+                                        post_header_data,
+                                        path_backing_buffer_size,
+                                        num_entries,
+                                        object_hash,
+                                        version,
+                                    )
+                                }),
+                        };
+                        let ext_res = extension_loading
+                            .map(|thread| thread.join().unwrap())
+                            .unwrap_or_else(|| {
+                                extension::decode::all(extensions_data, offset, object_hash, load_extensions)
+                            });
+                        (entries_res, ext_res)
+                    });
+                    let (ext, data) = ext_res?;
+                    (entries_res?.0, ext, data)
+                }
+                None | Some(_) => {
+                    let guessed = guess_entry_offsets_for_threading
+                        .then(|| {
+                            entries_at_guessed_offsets(post_header_data, num_entries, num_threads, object_hash, version)
+                        })
+                        .flatten();
+                    let (entries, extensions_data) = match guessed {
+                        Some((entries, consumed)) => (entries, &post_header_data[consumed..]),
                         None => entries(
                             post_header_data,
                             path_backing_buffer_size,
                             num_entries,
                             object_hash,
                             version,
-                        ),
+                        )?,
                     };
-                    let ext_res = extension_loading
-                        .map(|thread| thread.join().unwrap())
-                        .unwrap_or_else(|| extension::decode::all(extensions_data, object_hash));
-                    (entries_res, ext_res)
-                });
-                let (ext, data) = ext_res?;
-                (entries_res?.0, ext, data)
-            }
-            None | Some(_) => {
-                let (entries, data) = entries(
-                    post_header_data,
-                    path_backing_buffer_size,
-                    num_entries,
-                    object_hash,
-                    version,
-                )?;
-                let (ext, data) = extension::decode::all(data, object_hash)?;
-                (entries, ext, data)
-            }
+                    let (ext, data) = extension::decode::all(
+                        extensions_data,
+                        data.len() - extensions_data.len(),
+                        object_hash,
+                        load_extensions,
+                    )?;
+                    (entries, ext, data)
+                }
+            })
         };
+        let (decoded, actual_checksum) = gix_features::parallel::threads(|scope| {
+            let hashing = hash_in_parallel.then(|| {
+                gix_features::parallel::build_thread()
+                    .name("gix-index.from_bytes.verify-checksum".into())
+                    .spawn_scoped(scope, || checksum_of(data, object_hash))
+                    .expect("valid name")
+            });
+            let decoded = decode_entries_and_extensions(num_threads);
+            let actual_checksum = match hashing {
+                Some(thread) => Some(thread.join().unwrap()),
+                None => (verify_checksum && decoded.is_ok()).then(|| checksum_of(data, object_hash)),
+            };
+            (decoded, actual_checksum)
+        });
+        let (entries, ext, data) = decoded?;
 
         if data.len() != object_hash.len_in_bytes() {
             return Err(Error::UnexpectedTrailerLength {
@@ -211,56 +423,181 @@ impl State {
             });
         }
 
-        let checksum = gix_hash::ObjectId::from(data);
-        if let Some(expected_checksum) = expected_checksum {
-            if checksum != expected_checksum {
-                return Err(Error::ChecksumMismatch {
-                    actual_checksum: checksum,
-                    expected_checksum,
-                });
-            }
-        }
-        let EntriesOutcome {
-            entries,
-            path_backing,
-            mut is_sparse,
-        } = entries;
-        let extension::decode::Outcome {
-            tree,
-            link,
-            resolve_undo,
-            untracked,
-            fs_monitor,
-            is_sparse: is_sparse_from_ext, // a marker is needed in case there are no directories
-        } = ext;
-        is_sparse |= is_sparse_from_ext;
-
+        let checksum = check_trailer(gix_hash::ObjectId::from(data), actual_checksum, expected_checksum)?;
+        let state = assemble(object_hash, timestamp, version, entries, ext, verify_tree, verify_paths)?;
         Ok((
-            State {
-                object_hash,
-                timestamp,
-                version,
-                entries,
-                path_backing,
-                is_sparse,
-
-                tree,
-                link,
-                resolve_undo,
-                untracked,
-                fs_monitor,
+            state,
+            Outcome {
+                checksum,
+                end_of_index_entry_mismatch,
+                recovery: None,
             },
-            checksum,
         ))
     }
 }
 
+/// Decode as much of the index in `data` as possible after decoding it as a whole failed, as described by [`Mode::Lenient`].
+fn recover(
+    data: &[u8],
+    timestamp: FileTime,
+    object_hash: gix_hash::Kind,
+    Options {
+        verify_tree,
+        verify_paths,
+        extensions: load_extensions,
+        ..
+    }: Options,
+) -> Result<(State, Outcome), Error> {
+    let (version, num_entries, post_header_data) = header::decode(data, object_hash)?;
+    let mut entries = Vec::with_capacity(entries::capacity_for(num_entries, post_header_data.len(), object_hash));
+    let mut path_backing = Vec::new();
+    let (entries::Outcome { is_sparse }, consumed) = entries::chunk_lenient(
+        post_header_data,
+        &mut entries,
+        &mut path_backing,
+        num_entries,
+        object_hash,
+        version,
+    );
+    let num_recovered_entries = entries.len() as u32;
+    let has_all_entries = num_recovered_entries == num_entries;
+
+    let start_of_extensions = extension::end_of_index_entry::decode(data, object_hash);
+    let end_of_index_entry_mismatch =
+        start_of_extensions.is_none() && extension::end_of_index_entry::is_present(data, object_hash);
+    let ext = start_of_extensions
+        .or(has_all_entries.then_some(header::SIZE + consumed))
+        .and_then(|offset| extension::decode::all(&data[offset..], offset, object_hash, load_extensions).ok())
+        .and_then(|(ext, trailer)| (trailer.len() == object_hash.len_in_bytes()).then_some(ext));
+    let extensions_unreachable = ext.is_none();
+    let mut ext = ext.unwrap_or_default();
+    if !has_all_entries {
+        ext.tree = None;
+    }
+
+    entries.shrink_to_fit();
+    path_backing.shrink_to_fit();
+    let entries = EntriesOutcome {
+        entries,
+        path_backing,
+        is_sparse,
+    };
+    let state = assemble(object_hash, timestamp, version, entries, ext, verify_tree, verify_paths)?;
+    Ok((
+        state,
+        Outcome {
+            checksum: None,
+            end_of_index_entry_mismatch,
+            recovery: Some(Recovery {
+                num_declared_entries: num_entries,
+                num_recovered_entries,
+                extensions_unreachable,
+            }),
+        },
+    ))
+}
+
+/// Compare the `checksum` in the trailer of an index to the `actual_checksum` of its content if it was computed, and to the
+/// `expected_checksum` if there is one, and return it unless it's null.
+fn check_trailer(
+    checksum: gix_hash::ObjectId,
+    actual_checksum: Option<gix_hash::ObjectId>,
+    expected_checksum: Option<gix_hash::ObjectId>,
+) -> Result<Option<gix_hash::ObjectId>, Error> {
+    if let Some(actual_checksum) = actual_checksum {
+        if actual_checksum != checksum {
+            return Err(Error::ChecksumMismatch {
+                actual_checksum,
+                expected_checksum: checksum,
+            });
+        }
+    }
+    if let Some(expected_checksum) = expected_checksum {
+        if checksum != expected_checksum {
+            return Err(Error::ChecksumMismatch {
+                actual_checksum: checksum,
+                expected_checksum,
+            });
+        }
+    }
+    Ok((!checksum.is_null()).then_some(checksum))
+}
+
+/// Put the decoded `entries` and extensions `ext` together into a state, and verify its cache tree and paths if configured.
+fn assemble(
+    object_hash: gix_hash::Kind,
+    timestamp: FileTime,
+    version: Version,
+    entries: EntriesOutcome,
+    ext: extension::decode::Outcome,
+    verify_tree: bool,
+    verify_paths: Option<crate::verify::paths::Options>,
+) -> Result<State, Error> {
+    let EntriesOutcome {
+        entries,
+        path_backing,
+        mut is_sparse,
+    } = entries;
+    let extension::decode::Outcome {
+        tree,
+        link,
+        resolve_undo,
+        untracked,
+        fs_monitor,
+        is_sparse: is_sparse_from_ext, // a marker is needed in case there are no directories
+        unknown: unknown_extensions,
+        skipped: skipped_extensions,
+    } = ext;
+    is_sparse |= is_sparse_from_ext;
+
+    let state = State {
+        object_hash,
+        timestamp,
+        version,
+        entries,
+        path_backing,
+        is_sparse,
+
+        tree,
+        link,
+        resolve_undo,
+        untracked,
+        fs_monitor,
+        unknown_extensions,
+        skipped_extensions,
+    };
+    if verify_tree {
+        state.verify_tree()?;
+    }
+    if let Some(options) = verify_paths {
+        state.verify_paths(options)?;
+    }
+    Ok(state)
+}
+
 struct EntriesOutcome {
     pub entries: Vec<Entry>,
     pub path_backing: Vec<u8>,
     pub is_sparse: bool,
 }
 
+impl EntriesOutcome {
+    /// Append `entries` whose paths are in `path_backing`, or return `None` if the combined path backing would be
+    /// too large for the ranges stored in entries.
+    fn append(&mut self, entries: Vec<Entry>, path_backing: Vec<u8>) -> Option<()> {
+        let ofs = self.path_backing.len();
+        entry::try_stored_path_range(ofs..ofs + path_backing.len())?;
+        let ofs = ofs as u32;
+        self.path_backing.extend(path_backing);
+        self.entries.extend(entries.into_iter().map(|mut e| {
+            e.path.start += ofs;
+            e.path.end += ofs;
+            e
+        }));
+        Some(())
+    }
+}
+
 fn entries(
     post_header_data: &[u8],
     path_backing_buffer_size: usize,
@@ -268,13 +605,15 @@ fn entries(
     object_hash: gix_hash::Kind,
     version: Version,
 ) -> Result<(EntriesOutcome, &[u8]), Error> {
-    let mut entries = Vec::with_capacity(num_entries as usize);
+    let mut entries = Vec::with_capacity(entries::capacity_for(num_entries, post_header_data.len(), object_hash));
     let mut path_backing = Vec::with_capacity(path_backing_buffer_size);
     entries::chunk(
         post_header_data,
         &mut entries,
         &mut path_backing,
         num_entries,
+        0,
+        header::SIZE,
         object_hash,
         version,
     )
@@ -290,6 +629,90 @@ fn entries(
     })
 }
 
+/// Decode entries in `entries_data`, which starts at the first entry, with up to `num_threads` threads after guessing where
+/// to split them, returning the decoded entries along with the amount of consumed bytes.
+/// Return `None` if a guess was wrong so entries should be decoded serially instead.
+fn entries_at_guessed_offsets(
+    entries_data: &[u8],
+    num_entries: u32,
+    num_threads: usize,
+    object_hash: gix_hash::Kind,
+    version: Version,
+) -> Option<(EntriesOutcome, usize)> {
+    if num_threads < 2 || version == Version::V4 {
+        return None;
+    }
+    let offsets = entries::guess_offsets(entries_data, num_threads, object_hash, version)?;
+    let (&last_offset, _) = offsets.split_last()?;
+    gix_features::parallel::threads(|scope| {
+        let threads = offsets
+            .windows(2)
+            .enumerate()
+            .map(|(id, range)| {
+                let chunk = &entries_data[range[0]..range[1]];
+                gix_features::parallel::build_thread()
+                    .name(format!("gix-index.from_bytes.read-entries-at-guessed-offsets.{id}"))
+                    .spawn_scoped(scope, move || {
+                        let mut entries = Vec::new();
+                        let mut path_backing = Vec::new();
+                        entries::chunk_exact(chunk, &mut entries, &mut path_backing, object_hash, version).map(
+                            |entries::Outcome { is_sparse }| EntriesOutcome {
+                                entries,
+                                path_backing,
+                                is_sparse,
+                            },
+                        )
+                    })
+                    .expect("valid name")
+            })
+            .collect::<Vec<_>>();
+
+        // The last chunk is decoded on this thread, and as we don't necessarily know where it ends,
+        // we decode as much as we can and keep only the entries we are missing once all other chunks are done.
+        let mut last_entries = Vec::new();
+        let mut last_path_backing = Vec::new();
+        let consumed_after_entry = entries::chunk_greedy(
+            &entries_data[last_offset..],
+            &mut last_entries,
+            &mut last_path_backing,
+            num_entries,
+            object_hash,
+            version,
+        );
+
+        let mut acc = EntriesOutcome {
+            entries: Vec::with_capacity(entries::capacity_for(num_entries, entries_data.len(), object_hash)),
+            path_backing: Vec::new(),
+            is_sparse: false,
+        };
+        let mut is_valid = true;
+        for thread in threads {
+            match thread.join().unwrap() {
+                Some(chunk) if is_valid => {
+                    acc.is_sparse |= chunk.is_sparse;
+                    is_valid = acc.append(chunk.entries, chunk.path_backing).is_some();
+                }
+                _ => is_valid = false,
+            }
+        }
+        if !is_valid {
+            return None;
+        }
+
+        let num_missing = (num_entries as usize).checked_sub(acc.entries.len())?;
+        if num_missing > last_entries.len() {
+            return None;
+        }
+        last_entries.truncate(num_missing);
+        last_path_backing.truncate(last_entries.last().map_or(0, |e| e.path_range().end));
+        acc.is_sparse |= last_entries.iter().any(|e| e.mode.is_sparse());
+        acc.append(last_entries, last_path_backing)?;
+
+        let consumed = last_offset + num_missing.checked_sub(1).map_or(0, |idx| consumed_after_entry[idx]);
+        Some((acc, consumed))
+    })
+}
+
 pub(crate) fn stat(data: &[u8]) -> Option<(entry::Stat, &[u8])> {
     let (ctime_secs, data) = read_u32(data)?;
     let (ctime_nsecs, data) = read_u32(data)?;
@@ -303,13 +726,13 @@ pub(crate) fn stat(data: &[u8]) -> Option<(entry::Stat, &[u8])> {
     Some((
         entry::Stat {
             mtime: entry::Time {
-                secs: ctime_secs,
-                nsecs: ctime_nsecs,
-            },
-            ctime: entry::Time {
                 secs: mtime_secs,
                 nsecs: mtime_nsecs,
             },
+            ctime: entry::Time {
+                secs: ctime_secs,
+                nsecs: ctime_nsecs,
+            },
             dev,
             ino,
             uid,
@@ -319,3 +742,10 @@ pub(crate) fn stat(data: &[u8]) -> Option<(entry::Stat, &[u8])> {
         data,
     ))
 }
+
+/// Hash all bytes of the index in `data` up to its trailing checksum.
+fn checksum_of(data: &[u8], object_hash: gix_hash::Kind) -> gix_hash::ObjectId {
+    let mut hasher = gix_features::hash::hasher(object_hash);
+    hasher.update(&data[..data.len() - object_hash.len_in_bytes()]);
+    gix_hash::ObjectId::from(hasher.digest())
+}
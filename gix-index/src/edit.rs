@@ -0,0 +1,312 @@
+use bstr::{BStr, ByteSlice, ByteVec};
+
+use crate::{entry, extension, Entry, State};
+
+///
+pub mod upsert {
+    use bstr::BString;
+
+    /// The error returned by [State::upsert_entry()][crate::State::upsert_entry()].
+    #[derive(Debug, thiserror::Error)]
+    #[allow(missing_docs)]
+    pub enum Error {
+        #[error("Entry paths must not be empty")]
+        EmptyPath,
+        #[error("Entry path '{path}' must be relative to the repository")]
+        AbsolutePath { path: BString },
+        #[error("Entry path '{path}' must not contain the component '{component}'")]
+        InvalidComponent { path: BString, component: BString },
+        #[error("Entry path '{path}' must not end with a slash unless it's a sparse directory")]
+        TrailingSlash { path: BString },
+        #[error("The path '{path}' of a sparse directory must end with a slash")]
+        MissingTrailingSlash { path: BString },
+        #[error("Entry path '{path}' is inside of sparse directory '{sparse_dir}', which has to be expanded first")]
+        SparseIndexNotExpanded { path: BString, sparse_dir: BString },
+    }
+}
+
+///
+pub mod remove {
+    use bstr::BString;
+
+    /// The error returned by [State::remove_entries_by_prefix()][crate::State::remove_entries_by_prefix()].
+    #[derive(Debug, thiserror::Error)]
+    #[allow(missing_docs)]
+    pub enum Error {
+        #[error("Prefix '{prefix}' is inside of sparse directory '{sparse_dir}', which has to be expanded first")]
+        SparseIndexNotExpanded { prefix: BString, sparse_dir: BString },
+    }
+}
+
+/// Editing entries while keeping them sorted
+impl State {
+    /// Insert an entry at `path` with `mode`, `id` and `stat` at its sorted position, or replace the entry at `path` in stage 0
+    /// if it exists, returning the index of the entry in [`entries()`][State::entries()].
+    ///
+    /// Like in git, a stage-0 entry resolves a conflict, which is why all entries of other stages at `path` are removed.
+    /// Sparse directories can be added with [`Mode::DIR`][entry::Mode::DIR], in which case `path` must end with a slash
+    /// and the entry is [excluded from the worktree][entry::Flags::SKIP_WORKTREE]. Other paths must not end with a slash,
+    /// and no path may contain empty components, `.`, `..` or `.git` in any case.
+    ///
+    /// Note that `path` isn't checked for conflicts with directories or files of the same name, and that the cache tree is
+    /// invalidated along `path`. If this index [is sparse][State::is_sparse()], `path` must not be inside of a sparse directory
+    /// as the entry would be hidden by it. [Expand][State::ensure_full()] the index first in that case.
+    pub fn upsert_entry(
+        &mut self,
+        path: &BStr,
+        mode: entry::Mode,
+        id: gix_hash::ObjectId,
+        stat: entry::Stat,
+    ) -> Result<usize, upsert::Error> {
+        validate_path(path, mode, Default::default())?;
+        if let Some(sparse_dir) = self.sparse_dir_containing(path) {
+            return Err(upsert::Error::SparseIndexNotExpanded {
+                path: path.into(),
+                sparse_dir: sparse_dir.into(),
+            });
+        }
+        let flags = if mode.is_sparse() {
+            entry::Flags::SKIP_WORKTREE | entry::Flags::EXTENDED
+        } else {
+            entry::Flags::empty()
+        };
+
+        let start = self.entry_index_by_path(path).unwrap_or_else(|idx| idx);
+        let num_stages = self.entries[start..].partition_point(|e| e.path(self) == path);
+        let path_range = match num_stages {
+            0 => {
+                let path_start = self.path_backing.len();
+                self.path_backing.push_str(path);
+                entry::stored_path_range(path_start..self.path_backing.len())
+            }
+            _ => self.entries[start].path.clone(),
+        };
+        let new_entry = Entry {
+            stat,
+            id,
+            flags,
+            mode,
+            path: path_range,
+        };
+        if num_stages == 0 {
+            self.entries.insert(start, new_entry);
+        } else {
+            self.entries[start] = new_entry;
+            self.entries.drain(start + 1..start + num_stages);
+        }
+        if num_stages != 1 {
+            self.drop_fs_monitor_as_entries_moved();
+        }
+
+        self.is_sparse |= mode.is_sparse();
+        self.invalidate_tree_at(path);
+        Ok(start)
+    }
+
+    /// Remove the entry at `path` and `stage`, and return it if it existed.
+    ///
+    /// The path of the removed entry remains in the [path backing][State::path_backing()] and can still be read from there.
+    pub fn remove_entry(&mut self, path: &BStr, stage: entry::Stage) -> Option<Entry> {
+        let idx = self.entry_index_by_path_and_stage(path, stage)?;
+        let entry = self.entries.remove(idx);
+        if entry.is_sparse_dir() {
+            self.is_sparse = self.entries.iter().any(Entry::is_sparse_dir);
+        }
+        self.drop_fs_monitor_as_entries_moved();
+        self.invalidate_tree_at(path);
+        Some(entry)
+    }
+
+    /// Remove all entries whose path starts with `prefix` at once, like `git rm -r --cached` does, and return the amount of
+    /// removed entries, which is 0 if no entry matched. See [`prefixed_entries_range()`][State::prefixed_entries_range()]
+    /// for how `prefix` is matched, and use `dir/` to only remove the entries inside of a directory.
+    ///
+    /// The stages of removed conflicts are recorded in the [resolve-undo extension][State::resolve_undo()], like git does,
+    /// and all trees that contained removed entries are invalidated. The paths of removed entries remain in the
+    /// [path backing][State::path_backing()] until it's [compacted][State::compact_path_backing()].
+    ///
+    /// Sparse directories are removed like any other entry if `prefix` matches them, but if `prefix` is inside of one, only
+    /// some of its entries might be affected, which is why an error is returned and the index needs to be
+    /// [expanded][State::ensure_full()] first.
+    pub fn remove_entries_by_prefix(&mut self, prefix: &BStr) -> Result<usize, remove::Error> {
+        if let Some(sparse_dir) = self.sparse_dir_containing(prefix) {
+            return Err(remove::Error::SparseIndexNotExpanded {
+                prefix: prefix.into(),
+                sparse_dir: sparse_dir.into(),
+            });
+        }
+        let range = self.prefixed_entries_range(prefix);
+        if range.is_empty() {
+            return Ok(0);
+        }
+        let removed = &self.entries[range.clone()];
+        let undo: Vec<_> = self
+            .conflicts_in(removed)
+            .map(|(path, stages)| crate::conflict::resolve_path(path, stages))
+            .collect();
+        let mut previous_dir = None;
+        for entry in removed {
+            let path = entry.path_in(&self.path_backing);
+            let dir = path.rfind_byte(b'/').map(|pos| &path[..pos]);
+            if previous_dir != Some(dir) {
+                invalidate_tree_at(self.tree.as_mut(), path);
+                previous_dir = Some(dir);
+            }
+        }
+        let has_sparse_dir = removed.iter().any(Entry::is_sparse_dir);
+
+        self.entries.drain(range.clone());
+        if has_sparse_dir {
+            self.is_sparse = self.entries.iter().any(Entry::is_sparse_dir);
+        }
+        for undo in undo {
+            self.record_resolve_undo(undo);
+        }
+        self.drop_fs_monitor_as_entries_moved();
+        Ok(range.len())
+    }
+
+    /// Set the `stat` and `id` of the entry at `path` in stage 0, for instance after the file was changed on disk, and return it,
+    /// or return `None` if there is no such entry.
+    ///
+    /// The cache tree is invalidated along `path` if `id` differs from the one of the entry.
+    pub fn touch_entry(&mut self, path: &BStr, stat: entry::Stat, id: gix_hash::ObjectId) -> Option<&mut Entry> {
+        let idx = self.entry_index_by_path_and_stage(path, 0)?;
+        if self.entries[idx].id != id {
+            self.invalidate_tree_at(path);
+        }
+        let entry = &mut self.entries[idx];
+        entry.stat = stat;
+        entry.id = id;
+        Some(entry)
+    }
+
+    /// Set the stat information of the entries at `paths` in stage 0 to the one of their files in the worktree, as obtained
+    /// by `metadata(path)` without following symlinks, typically after they were checked out. Paths without entry are ignored.
+    /// `options` are the ones used to compare stat information later, which determine what is [recorded][Entry::set_stat_from_metadata()].
+    ///
+    /// This allows the next status to consider these entries unchanged without comparing their content, but note that
+    /// entries modified in the same second as the index was read are still [racily clean][State::is_entry_racy()].
+    pub fn refresh_stats<'a, E>(
+        &mut self,
+        paths: impl IntoIterator<Item = &'a BStr>,
+        options: entry::stat::Options,
+        mut metadata: impl FnMut(&BStr) -> Result<std::fs::Metadata, E>,
+    ) -> Result<(), E> {
+        for path in paths {
+            if let Some(idx) = self.entry_index_by_path_and_stage(path, 0) {
+                let metadata = metadata(path)?;
+                self.entries[idx].set_stat_from_metadata(&metadata, options);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl State {
+    /// Mark the root of the cache tree and all trees leading to `path` as invalid, similar to `cache_tree_invalidate_path()`
+    /// in git. The tree of the last component of `path` is only invalidated if `path` ends with a slash.
+    pub(crate) fn invalidate_tree_at(&mut self, path: &BStr) {
+        invalidate_tree_at(self.tree.as_mut(), path);
+    }
+
+    /// The fsmonitor extension refers to entries by index, which doesn't hold anymore if entries were inserted or removed.
+    /// Without it all entries will be checked for changes, as if there was no fsmonitor.
+    pub(crate) fn drop_fs_monitor_as_entries_moved(&mut self) {
+        if self.fs_monitor.take().is_some() {
+            for entry in &mut self.entries {
+                entry.flags.remove(entry::Flags::FSMONITOR_VALID);
+            }
+        }
+    }
+}
+
+/// Like [`State::invalidate_tree_at()`], but for the root `tree` directly.
+fn invalidate_tree_at(tree: Option<&mut extension::Tree>, path: &BStr) {
+    let mut tree = match tree {
+        Some(tree) => tree,
+        None => return,
+    };
+    tree.num_entries = None;
+    let mut components = path.split_str("/").peekable();
+    while let Some(name) = components.next() {
+        if components.peek().is_none() {
+            break;
+        }
+        tree = match tree.children.iter_mut().find(|child| child.name.as_slice() == name) {
+            Some(child) => child,
+            None => break,
+        };
+        tree.num_entries = None;
+    }
+}
+
+/// Check that `path` is valid for an entry of `mode`, like `verify_path()` in git, with the filesystem specific
+/// protections of `protect`.
+pub(crate) fn validate_path(
+    path: &BStr,
+    mode: entry::Mode,
+    protect: crate::verify::paths::Options,
+) -> Result<(), upsert::Error> {
+    if path.is_empty() {
+        return Err(upsert::Error::EmptyPath);
+    }
+    if path.starts_with(b"/") {
+        return Err(upsert::Error::AbsolutePath { path: path.into() });
+    }
+    let components = match (path.strip_suffix(b"/"), mode.is_sparse()) {
+        (Some(dir), true) => dir,
+        (None, false) => path.as_bytes(),
+        (Some(_), false) => return Err(upsert::Error::TrailingSlash { path: path.into() }),
+        (None, true) => return Err(upsert::Error::MissingTrailingSlash { path: path.into() }),
+    };
+    let is_symlink = mode == entry::Mode::SYMLINK;
+    for component in components.split_str("/") {
+        if component.is_empty()
+            || component == b"."
+            || component == b".."
+            || component.eq_ignore_ascii_case(b".git")
+            || (is_symlink && component.eq_ignore_ascii_case(b".gitmodules"))
+            || (protect.protect_ntfs
+                && component.split_str("\\").any(|name| {
+                    is_ntfs_dot(name, b"git", &[(b"git", b'1')])
+                        || (is_symlink && is_ntfs_dot(name, b"gitmodules", &[(b"gitmod", b'4'), (b"gi7eba", b'9')]))
+                }))
+            || (protect.protect_hfs
+                && (is_hfs_dot(component, b"git") || (is_symlink && is_hfs_dot(component, b"gitmodules"))))
+        {
+            return Err(upsert::Error::InvalidComponent {
+                path: path.into(),
+                component: component.into(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Return true if NTFS considers `name` the same as `.{dot_name}`, as it ignores case, trailing dots and spaces as well as
+/// alternate data streams after a `:`, or if `name` is one of its 8.3 short names, which start with one of the
+/// `short_name_prefixes`, followed by `~` and a digit up to the given one, similar to `is_ntfs_dot_generic()` in git.
+fn is_ntfs_dot(name: &[u8], dot_name: &[u8], short_name_prefixes: &[(&[u8], u8)]) -> bool {
+    let name = name.split_str(":").next().unwrap_or_default();
+    let name = name.trim_end_with(|c| c == '.' || c == ' ');
+    let is_short_name = |(prefix, max_digit): &(&[u8], u8)| {
+        name.len() == prefix.len() + 2
+            && name[..prefix.len()].eq_ignore_ascii_case(prefix)
+            && name[prefix.len()] == b'~'
+            && (b'1'..=*max_digit).contains(&name[prefix.len() + 1])
+    };
+    matches!(name.strip_prefix(b"."), Some(name) if name.eq_ignore_ascii_case(dot_name))
+        || short_name_prefixes.iter().any(is_short_name)
+}
+
+/// Return true if HFS+ considers `name` the same as `.{dot_name}`, as it ignores case and certain Unicode code points,
+/// similar to `is_hfs_dot_generic()` in git.
+fn is_hfs_dot(name: &[u8], dot_name: &[u8]) -> bool {
+    let is_ignored = |c: &char| matches!(c, '\u{200c}'..='\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{206a}'..='\u{206f}' | '\u{feff}');
+    let mut chars = name.chars().filter(|c| !is_ignored(c));
+    chars.next() == Some('.')
+        && chars
+            .map(|c| c.to_ascii_lowercase())
+            .eq(dot_name.iter().map(|b| char::from(*b)))
+}
@@ -62,6 +62,11 @@ impl Flags {
         (*self & Flags::STAGE_MASK).bits >> 12
     }
 
+    /// Return `true` if extended flags have to be written along with the storage representation, which requires index V3 or later.
+    pub(crate) fn needs_extended_storage(&self) -> bool {
+        self.intersects(Flags::EXTENDED | Flags::INTENT_TO_ADD | Flags::SKIP_WORKTREE)
+    }
+
     /// Transform ourselves to a storage representation to keep all flags which are to be persisted,
     /// skipping all extended flags. Note that the caller has to check for the `EXTENDED` bit to be present
     /// and write extended flags as well if so.
@@ -10,6 +10,32 @@ pub use flags::Flags;
 
 mod write;
 
+///
+pub mod stat;
+
+/// Return `range` in the path backing in the form stored by entries, or `None` if it ends beyond 4GiB.
+pub(crate) fn try_stored_path_range(range: std::ops::Range<usize>) -> Option<std::ops::Range<u32>> {
+    Some(range.start.try_into().ok()?..range.end.try_into().ok()?)
+}
+
+/// Like [`try_stored_path_range()`], but panics if `range` ends beyond 4GiB, which is far more than even the largest
+/// indices need.
+pub(crate) fn stored_path_range(range: std::ops::Range<usize>) -> std::ops::Range<u32> {
+    try_stored_path_range(range).expect("the path backing is smaller than 4GiB")
+}
+
+/// Compare the entry with `path_a` in `stage_a` to the one with `path_b` in `stage_b` to determine their order in the index,
+/// just like `cache_name_stage_compare()` in git.
+///
+/// Paths are compared byte by byte without any interpretation, so non-ASCII bytes sort after all ASCII bytes, and
+/// a path sorts before all paths it's a prefix of. This makes `a` sort before `a-b`, `a-b` before `a/b` and `a/b` before `a0`,
+/// as `-`, `/` and `0` are compared as bytes. Entries with the same path are ordered by stage.
+///
+/// All lookups, insertions and verifications of entries rely on this order.
+pub fn compare(path_a: &bstr::BStr, stage_a: Stage, path_b: &bstr::BStr, stage_b: Stage) -> std::cmp::Ordering {
+    crate::Entry::cmp_filepaths(path_a, path_b).then_with(|| stage_a.cmp(&stage_b))
+}
+
 /// The time component in a [`Stat`] struct.
 #[derive(Debug, Default, PartialEq, Eq, Hash, Ord, PartialOrd, Clone, Copy)]
 #[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
@@ -48,18 +74,72 @@ mod access {
     impl Entry {
         /// Return an entry's path, relative to the repository, which is extracted from its owning `state`.
         pub fn path<'a>(&self, state: &'a State) -> &'a BStr {
-            state.path_backing[self.path.clone()].as_bstr()
+            state.path_backing[self.path_range()].as_bstr()
         }
 
         /// Return an entry's path using the given `backing`.
         pub fn path_in<'backing>(&self, backing: &'backing crate::PathStorageRef) -> &'backing BStr {
-            backing[self.path.clone()].as_bstr()
+            backing[self.path_range()].as_bstr()
+        }
+
+        /// Return the range of our path in the path backing.
+        pub(crate) fn path_range(&self) -> std::ops::Range<usize> {
+            self.path.start as usize..self.path.end as usize
         }
 
         /// Return an entry's stage.
         pub fn stage(&self) -> entry::Stage {
             self.flags.stage()
         }
+
+        /// Return true if this entry is a sparse directory of a sparse index, standing in for all files below it which are
+        /// excluded from the worktree. Unlike other entries, its id is the one of a tree, not of a blob.
+        pub fn is_sparse_dir(&self) -> bool {
+            self.mode.is_sparse()
+        }
+
+        /// Return true if the entry is assumed to match the worktree, so `lstat()` checks are skipped, as set by
+        /// `git update-index --assume-unchanged`.
+        pub fn is_assume_valid(&self) -> bool {
+            self.flags.contains(entry::Flags::ASSUME_VALID)
+        }
+
+        /// Return true if the entry isn't expected to be present in the worktree, typically due to a sparse checkout.
+        pub fn is_skip_worktree(&self) -> bool {
+            self.flags.contains(entry::Flags::SKIP_WORKTREE)
+        }
+
+        /// Return true if the entry was added with `git add --intent-to-add`, so only its path is known but not its content.
+        pub fn is_intent_to_add(&self) -> bool {
+            self.flags.contains(entry::Flags::INTENT_TO_ADD)
+        }
+
+        /// Set or clear the [assume-valid][Entry::is_assume_valid()] flag.
+        pub fn set_assume_valid(&mut self, value: bool) {
+            self.flags.set(entry::Flags::ASSUME_VALID, value);
+        }
+
+        /// Set or clear the [skip-worktree][Entry::is_skip_worktree()] flag.
+        ///
+        /// As it's stored in the extended flags, the index will be written as V3 or later if it is set on any entry.
+        pub fn set_skip_worktree(&mut self, value: bool) {
+            self.set_extended_flag(entry::Flags::SKIP_WORKTREE, value);
+        }
+
+        /// Set or clear the [intent-to-add][Entry::is_intent_to_add()] flag.
+        ///
+        /// As it's stored in the extended flags, the index will be written as V3 or later if it is set on any entry.
+        pub fn set_intent_to_add(&mut self, value: bool) {
+            self.set_extended_flag(entry::Flags::INTENT_TO_ADD, value);
+        }
+
+        fn set_extended_flag(&mut self, flag: entry::Flags, value: bool) {
+            self.flags.set(flag, value);
+            let needs_extended_flags = self
+                .flags
+                .intersects(entry::Flags::SKIP_WORKTREE | entry::Flags::INTENT_TO_ADD);
+            self.flags.set(entry::Flags::EXTENDED, needs_extended_flags);
+        }
     }
 }
 
@@ -68,7 +148,10 @@ mod _impls {
 
     use bstr::BStr;
 
-    use crate::{entry::Time, Entry, State};
+    use crate::{
+        entry::{self, Time},
+        Entry, State,
+    };
 
     impl From<SystemTime> for Time {
         fn from(s: SystemTime) -> Self {
@@ -89,16 +172,14 @@ mod _impls {
     }
 
     impl Entry {
-        /// Compare one entry to another by their path, by comparing only their common path portion byte by byte, then resorting to
-        /// entry length and stage.
+        /// Compare one entry to another by their path and stage as found in `state`, to determine their order in the index
+        /// just like [`entry::compare()`] does.
         pub fn cmp(&self, other: &Self, state: &State) -> Ordering {
-            let lhs = self.path(state);
-            let rhs = other.path(state);
-            Entry::cmp_filepaths(lhs, rhs).then_with(|| self.stage().cmp(&other.stage()))
+            entry::compare(self.path(state), self.stage(), other.path(state), other.stage())
         }
 
         /// Compare one entry to another by their path, by comparing only their common path portion byte by byte, then resorting to
-        /// entry length.
+        /// entry length. This is the order of paths in the index, and of [entries][entry::compare()] if their stage is ignored.
         pub fn cmp_filepaths(a: &BStr, b: &BStr) -> Ordering {
             let common_len = a.len().min(b.len());
             a[..common_len]
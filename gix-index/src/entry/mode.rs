@@ -21,4 +21,18 @@ impl Mode {
     pub fn is_sparse(&self) -> bool {
         *self == Self::DIR
     }
+
+    /// Convert this instance to the mode of a tree entry, which is a proper enumeration of all valid modes, or return `None`
+    /// if our bits don't represent a valid mode.
+    pub fn to_tree_entry_mode(&self) -> Option<gix_object::tree::EntryMode> {
+        use gix_object::tree::EntryMode;
+        Some(match *self {
+            Self::DIR => EntryMode::Tree,
+            Self::FILE => EntryMode::Blob,
+            Self::FILE_EXECUTABLE => EntryMode::BlobExecutable,
+            Self::SYMLINK => EntryMode::Link,
+            Self::COMMIT => EntryMode::Commit,
+            _ => return None,
+        })
+    }
 }
@@ -0,0 +1,250 @@
+use crate::{
+    entry::{Mode, Stat, Time},
+    Entry,
+};
+
+/// The amount of stat information to compare, like `core.checkStat` in git.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStat {
+    /// Only compare the modification time in seconds, the size and the mode.
+    Minimal,
+    /// Compare all available fields, including nanoseconds, the creation time, inode and ownership.
+    #[default]
+    Default,
+}
+
+/// Configure how to [compare stat information][Entry::stat_matches()], similar to the configuration that affects
+/// `ce_match_stat()` in git.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Options {
+    /// If true, a change of the executable bit of a file is a change, like `core.fileMode`. Filesystems that can't store
+    /// it should set this to false.
+    pub trust_executable_bit: bool,
+    /// If true, the filesystem supports symbolic links, like `core.symlinks`. If false, symlinks are checked out as plain
+    /// files, which aren't considered a change of type.
+    pub has_symlinks: bool,
+    /// If true, the creation time is compared as well, like `core.trustCTime`.
+    pub trust_ctime: bool,
+    /// If true, compare the nanoseconds of timestamps as well, like git built with `USE_NSEC`.
+    ///
+    /// Nanoseconds are ignored if they are 0 on either side, as some filesystems truncate timestamps to seconds, and git built
+    /// with `NO_NSEC` doesn't record them. This is off by default as it's off in most builds of git, which may have written
+    /// the index with nanoseconds that don't match the ones we see, for instance if they were obtained from a cache.
+    pub use_nsec: bool,
+    /// If false, the filesystem doesn't record the nanoseconds of timestamps reliably, so they are neither
+    /// [recorded][Entry::set_stat_from_metadata()] nor compared, even if `use_nsec` is set. This is the case for
+    /// filesystems with a precision of seconds or worse, like HFS+ or FAT, and should be set from the probed
+    /// capabilities of the worktree.
+    pub trust_nsec: bool,
+    /// If true, compare the device number as well, like git built with `USE_STDEV`.
+    pub use_stdev: bool,
+    /// The amount of fields to compare.
+    pub check_stat: CheckStat,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            trust_executable_bit: true,
+            has_symlinks: true,
+            trust_ctime: true,
+            use_nsec: false,
+            trust_nsec: true,
+            use_stdev: false,
+            check_stat: Default::default(),
+        }
+    }
+}
+
+impl Stat {
+    /// Obtain the stat information of a file from its `metadata`, which should be obtained without following symlinks,
+    /// just like git records it.
+    ///
+    /// Like git, all values are truncated to 32 bits, except for the size which is never truncated to 0 if it isn't.
+    /// On Windows, the creation time is used as `ctime`, while the device, inode and ownership information is 0 as it's not
+    /// available, which is also what git for Windows does. Its timestamps are converted from ticks of 100 nanoseconds since
+    /// 1601, and times before the unix epoch are 0 on all platforms but unix.
+    pub fn from_fs(metadata: &std::fs::Metadata) -> Stat {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            Stat {
+                mtime: Time {
+                    secs: metadata.mtime() as u32,
+                    nsecs: metadata.mtime_nsec() as u32,
+                },
+                ctime: Time {
+                    secs: metadata.ctime() as u32,
+                    nsecs: metadata.ctime_nsec() as u32,
+                },
+                dev: metadata.dev() as u32,
+                ino: metadata.ino() as u32,
+                uid: metadata.uid(),
+                gid: metadata.gid(),
+                size: truncated_size(metadata.len()),
+            }
+        }
+        #[cfg(windows)]
+        {
+            use std::os::windows::fs::MetadataExt;
+            Stat {
+                mtime: time_from_windows_ticks(metadata.last_write_time()),
+                ctime: time_from_windows_ticks(metadata.creation_time()),
+                dev: 0,
+                ino: 0,
+                uid: 0,
+                gid: 0,
+                size: truncated_size(metadata.len()),
+            }
+        }
+        #[cfg(not(any(unix, windows)))]
+        {
+            let time = |time: std::io::Result<std::time::SystemTime>| {
+                time.ok()
+                    .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map(|d| Time {
+                        secs: d.as_secs() as u32,
+                        nsecs: d.subsec_nanos(),
+                    })
+                    .unwrap_or_default()
+            };
+            Stat {
+                mtime: time(metadata.modified()),
+                ctime: time(metadata.created()),
+                dev: 0,
+                ino: 0,
+                uid: 0,
+                gid: 0,
+                size: truncated_size(metadata.len()),
+            }
+        }
+    }
+
+    /// Return true if `other` stat information is the same as ours according to `options`, which is the case if the
+    /// file they were obtained from is unchanged.
+    ///
+    /// Note that [racily clean][crate::State::is_entry_racy()] entries may match even though their file was changed.
+    pub fn matches(&self, other: &Stat, options: Options) -> bool {
+        let check_stat = options.check_stat == CheckStat::Default;
+        let compare_nsec = options.use_nsec && options.trust_nsec && check_stat;
+        let time_matches = |a: Time, b: Time| {
+            a.secs == b.secs && (!compare_nsec || a.nsecs == 0 || b.nsecs == 0 || a.nsecs == b.nsecs)
+        };
+        time_matches(self.mtime, other.mtime)
+            && (!(options.trust_ctime && check_stat) || time_matches(self.ctime, other.ctime))
+            && (!check_stat || (self.uid == other.uid && self.gid == other.gid && self.ino == other.ino))
+            && (!(options.use_stdev && check_stat) || self.dev == other.dev)
+            && self.size == other.size
+    }
+}
+
+impl Entry {
+    /// Set our stat information to the one of the file described by `metadata`, which must be obtained without following
+    /// symlinks, typically after the file was written to the worktree from our object, so it will
+    /// [match][Entry::stat_matches()] as long as the file stays unchanged.
+    ///
+    /// Nanoseconds are recorded as 0 if they can't be [trusted][Options::trust_nsec] according to `options`, so they
+    /// won't be compared later.
+    pub fn set_stat_from_metadata(&mut self, metadata: &std::fs::Metadata, options: Options) {
+        let mut stat = Stat::from_fs(metadata);
+        if !options.trust_nsec {
+            stat.mtime.nsecs = 0;
+            stat.ctime.nsecs = 0;
+        }
+        self.stat = stat;
+    }
+
+    /// Return true if the file described by `metadata`, which must be obtained without following symlinks, still matches
+    /// this entry according to `options`, so its content doesn't have to be compared. This is what `ce_match_stat()` does
+    /// in git.
+    ///
+    /// A change of the file type is always a change. Submodules only have to be directories, as their stat information isn't
+    /// used.
+    pub fn stat_matches(&self, metadata: &std::fs::Metadata, options: Options) -> bool {
+        let file_type = metadata.file_type();
+        match self.mode {
+            Mode::FILE | Mode::FILE_EXECUTABLE => {
+                if !file_type.is_file() {
+                    return false;
+                }
+                if options.trust_executable_bit && (self.mode == Mode::FILE_EXECUTABLE) != is_executable(metadata) {
+                    return false;
+                }
+            }
+            Mode::SYMLINK => {
+                if !file_type.is_symlink() && (options.has_symlinks || !file_type.is_file()) {
+                    return false;
+                }
+            }
+            Mode::COMMIT => return file_type.is_dir(),
+            _ => return false,
+        }
+        self.stat.matches(&Stat::from_fs(metadata), options)
+    }
+}
+
+/// Git only stores the lower 32 bits of the size, and makes sure sizes that are a multiple of 4GB aren't stored as 0,
+/// which is reserved for [racily clean][crate::State::is_entry_racy()] entries.
+fn truncated_size(size: u64) -> u32 {
+    match size as u32 {
+        0 if size != 0 => 0x8000_0000,
+        size => size,
+    }
+}
+
+/// Convert Windows `ticks` of 100 nanoseconds since 1601-01-01 to a time relative to the unix epoch, like git for Windows
+/// does, or return the epoch itself for earlier times.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn time_from_windows_ticks(ticks: u64) -> Time {
+    const TICKS_PER_SEC: u64 = 10_000_000;
+    const SECS_FROM_1601_TO_UNIX_EPOCH: u64 = 11_644_473_600;
+    match (ticks / TICKS_PER_SEC).checked_sub(SECS_FROM_1601_TO_UNIX_EPOCH) {
+        Some(secs) => Time {
+            secs: secs as u32,
+            nsecs: (ticks % TICKS_PER_SEC) as u32 * 100,
+        },
+        None => Time::default(),
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    metadata.mode() & 0o100 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &std::fs::Metadata) -> bool {
+    false
+}
+
+#[test]
+fn sizes_are_truncated_like_git_does() {
+    assert_eq!(truncated_size(0), 0);
+    assert_eq!(truncated_size(42), 42);
+    assert_eq!(truncated_size(u64::from(u32::MAX) + 43), 42);
+    assert_eq!(
+        truncated_size(1 << 32),
+        0x8000_0000,
+        "0 means racily clean, so it's avoided for non-empty files"
+    );
+}
+
+#[test]
+fn windows_ticks_are_converted_to_times_since_the_unix_epoch() {
+    let epoch_in_ticks = 11_644_473_600 * 10_000_000;
+    assert_eq!(time_from_windows_ticks(epoch_in_ticks), Time::default());
+    assert_eq!(
+        time_from_windows_ticks(epoch_in_ticks + 42 * 10_000_000 + 1_234_567),
+        Time {
+            secs: 42,
+            nsecs: 123_456_700
+        },
+        "each tick is 100 nanoseconds"
+    );
+    assert_eq!(
+        time_from_windows_ticks(epoch_in_ticks - 1),
+        Time::default(),
+        "times before the epoch can't be represented"
+    );
+}
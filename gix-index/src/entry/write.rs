@@ -1,10 +1,39 @@
 use std::convert::TryInto;
 
-use crate::{entry, Entry, State};
+use bstr::BStr;
+
+use crate::{entry, util::write_var_int, Entry, State};
 
 impl Entry {
     /// Serialize ourselves to `out` with path access via `state`, without padding.
     pub fn write_to(&self, mut out: impl std::io::Write, state: &State) -> std::io::Result<()> {
+        let path = self.path(state);
+        self.write_all_but_path(&mut out, path)?;
+        out.write_all(path)?;
+        out.write_all(b"\0")
+    }
+
+    /// Serialize ourselves to `out` with path access via `state` as used by index V4, which stores our path relative to
+    /// `previous_path`, the path of the entry written before us, or the empty path if we are the first entry.
+    pub fn write_v4_to(
+        &self,
+        mut out: impl std::io::Write,
+        state: &State,
+        previous_path: &BStr,
+    ) -> std::io::Result<()> {
+        let path = self.path(state);
+        self.write_all_but_path(&mut out, path)?;
+        let common_prefix_len = path
+            .iter()
+            .zip(previous_path.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        write_var_int(&mut out, (previous_path.len() - common_prefix_len) as u64)?;
+        out.write_all(&path[common_prefix_len..])?;
+        out.write_all(b"\0")
+    }
+
+    fn write_all_but_path(&self, mut out: impl std::io::Write, path: &BStr) -> std::io::Result<()> {
         let stat = self.stat;
         out.write_all(&stat.ctime.secs.to_be_bytes())?;
         out.write_all(&stat.ctime.nsecs.to_be_bytes())?;
@@ -17,7 +46,6 @@ impl Entry {
         out.write_all(&stat.gid.to_be_bytes())?;
         out.write_all(&stat.size.to_be_bytes())?;
         out.write_all(self.id.as_bytes())?;
-        let path = self.path(state);
         let path_len: u16 = if path.len() >= entry::Flags::PATH_LEN.bits() as usize {
             entry::Flags::PATH_LEN.bits() as u16
         } else {
@@ -25,15 +53,16 @@ impl Entry {
                 .try_into()
                 .expect("we just checked that the length is smaller than 0xfff")
         };
-        out.write_all(&(self.flags.to_storage().bits() | path_len).to_be_bytes())?;
-        if self.flags.contains(entry::Flags::EXTENDED) {
+        let mut flags = self.flags;
+        flags.set(entry::Flags::EXTENDED, flags.needs_extended_storage());
+        out.write_all(&(flags.to_storage().bits() | path_len).to_be_bytes())?;
+        if flags.contains(entry::Flags::EXTENDED) {
             out.write_all(
                 &entry::at_rest::FlagsExtended::from_flags(self.flags)
                     .bits()
                     .to_be_bytes(),
             )?;
         }
-        out.write_all(path)?;
-        out.write_all(b"\0")
+        Ok(())
     }
 }
@@ -1,6 +1,6 @@
 use std::convert::TryInto;
 
-use crate::{extension, extension::Signature, util::from_be_u32};
+use crate::{decode, extension, extension::Signature, util::from_be_u32};
 
 pub(crate) fn header(data: &[u8]) -> (Signature, u32, &[u8]) {
     let (signature, data) = data.split_at(4);
@@ -11,62 +11,108 @@ pub(crate) fn header(data: &[u8]) -> (Signature, u32, &[u8]) {
 mod error {
     use crate::extension;
 
-    /// The error returned when decoding extensions.
+    /// The error returned when decoding an extension, as part of [`decode::Error::Extension`][crate::decode::Error::Extension]
+    /// which knows its signature and position.
     #[derive(Debug, thiserror::Error)]
     #[allow(missing_docs)]
     pub enum Error {
-        #[error(
-            "Encountered mandatory extension '{}' which isn't implemented yet",
-            String::from_utf8_lossy(signature)
-        )]
-        MandatoryUnimplemented { signature: extension::Signature },
+        #[error("The extension is mandatory but isn't implemented yet")]
+        MandatoryUnimplemented,
+        #[error("The extension claims to be {size} bytes large, but only {available} bytes are left")]
+        SizeExceedsData { size: u32, available: usize },
         #[error("Could not parse mandatory link extension")]
         Link(#[from] extension::link::decode::Error),
     }
 }
 pub use error::Error;
 
+/// Decode all extensions in `maybe_beginning_of_extensions`, which is at `offset_in_file` for use in errors, skipping
+/// optional extensions that `load` doesn't ask for.
 pub(crate) fn all(
     maybe_beginning_of_extensions: &[u8],
+    offset_in_file: usize,
     object_hash: gix_hash::Kind,
-) -> Result<(Outcome, &[u8]), Error> {
+    load: decode::Load,
+) -> Result<(Outcome, &[u8]), decode::Error> {
     let mut ext_iter = match extension::Iter::new_without_checksum(maybe_beginning_of_extensions, object_hash) {
         Some(iter) => iter,
         None => return Ok((Outcome::default(), maybe_beginning_of_extensions)),
     };
 
     let mut ext = Outcome::default();
+    let mut offset = offset_in_file;
     for (signature, ext_data) in ext_iter.by_ref() {
-        match signature {
-            extension::tree::SIGNATURE => {
-                ext.tree = extension::tree::decode(ext_data, object_hash);
-            }
-            extension::resolve_undo::SIGNATURE => {
-                ext.resolve_undo = extension::resolve_undo::decode(ext_data, object_hash);
-            }
-            extension::untracked_cache::SIGNATURE => {
-                ext.untracked = extension::untracked_cache::decode(ext_data, object_hash);
-            }
-            extension::fs_monitor::SIGNATURE => {
-                ext.fs_monitor = extension::fs_monitor::decode(ext_data);
+        one(&mut ext, signature, ext_data, offset, object_hash, load)?;
+        offset += 4 + 4 + ext_data.len();
+    }
+
+    if let Some(extension::iter::Malformed::SizeExceedsData {
+        signature,
+        size,
+        available,
+    }) = ext_iter.malformed()
+    {
+        return Err(decode::Error::Extension {
+            signature,
+            offset,
+            source: Error::SizeExceedsData { size, available },
+        });
+    }
+    Ok((ext, &maybe_beginning_of_extensions[ext_iter.consumed..]))
+}
+
+/// Decode the extension with `signature` and `ext_data`, which is at `offset_in_file` for use in errors, into `ext`,
+/// or skip it if it's optional and `load` doesn't ask for it.
+pub(crate) fn one(
+    ext: &mut Outcome,
+    signature: Signature,
+    ext_data: &[u8],
+    offset_in_file: usize,
+    object_hash: gix_hash::Kind,
+    load: decode::Load,
+) -> Result<(), decode::Error> {
+    let err = |source| decode::Error::Extension {
+        signature,
+        offset: offset_in_file,
+        source,
+    };
+    match signature {
+        extension::end_of_index_entry::SIGNATURE => {}       // skip already done
+        extension::index_entry_offset_table::SIGNATURE => {} // not relevant/obtained already
+        optional if optional[0].is_ascii_uppercase() && !load.should_load(optional) => ext.skipped.push(optional),
+        extension::tree::SIGNATURE => {
+            ext.tree = extension::tree::decode(ext_data, object_hash);
+        }
+        extension::resolve_undo::SIGNATURE => {
+            ext.resolve_undo = extension::resolve_undo::decode(ext_data, object_hash);
+        }
+        extension::untracked_cache::SIGNATURE => {
+            ext.untracked = extension::untracked_cache::decode(ext_data, object_hash);
+        }
+        extension::fs_monitor::SIGNATURE => {
+            ext.fs_monitor = extension::fs_monitor::decode(ext_data);
+        }
+        optional if optional[0].is_ascii_uppercase() => ext.unknown.push(extension::Unknown {
+            signature: optional,
+            data: ext_data.to_owned(),
+        }),
+        mandatory => match mandatory {
+            extension::link::SIGNATURE => {
+                ext.link = extension::link::decode(ext_data, object_hash)
+                    .map_err(|e| err(e.into()))?
+                    .into()
             }
-            extension::end_of_index_entry::SIGNATURE => {} // skip already done
-            extension::index_entry_offset_table::SIGNATURE => {} // not relevant/obtained already
-            mandatory if mandatory[0].is_ascii_lowercase() => match mandatory {
-                extension::link::SIGNATURE => ext.link = extension::link::decode(ext_data, object_hash)?.into(),
-                extension::sparse::SIGNATURE => {
-                    if !ext_data.is_empty() {
-                        // only used as a marker, if this changes we need this implementation.
-                        return Err(Error::MandatoryUnimplemented { signature: mandatory });
-                    }
-                    ext.is_sparse = true
+            extension::sparse::SIGNATURE => {
+                if !ext_data.is_empty() {
+                    // only used as a marker, if this changes we need this implementation.
+                    return Err(err(Error::MandatoryUnimplemented));
                 }
-                unknown => return Err(Error::MandatoryUnimplemented { signature: unknown }),
-            },
-            _unknown => {} // skip unknown extensions, too
-        }
+                ext.is_sparse = true
+            }
+            _ => return Err(err(Error::MandatoryUnimplemented)),
+        },
     }
-    Ok((ext, &maybe_beginning_of_extensions[ext_iter.consumed..]))
+    Ok(())
 }
 
 #[derive(Default)]
@@ -77,4 +123,6 @@ pub(crate) struct Outcome {
     pub untracked: Option<extension::UntrackedCache>,
     pub fs_monitor: Option<extension::FsMonitor>,
     pub is_sparse: bool,
+    pub unknown: Vec<extension::Unknown>,
+    pub skipped: Vec<extension::Signature>,
 }
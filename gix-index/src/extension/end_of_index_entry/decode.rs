@@ -14,18 +14,8 @@ use crate::{
 ///
 /// If the checksum wasn't matched, we will ignore this extension entirely.
 pub fn decode(data: &[u8], object_hash: gix_hash::Kind) -> Option<usize> {
+    let (start_of_eoie, ext_data) = locate(data, object_hash)?;
     let hash_len = object_hash.len_in_bytes();
-    if data.len() < MIN_SIZE_WITH_HEADER + hash_len {
-        return None;
-    }
-
-    let start_of_eoie = data.len() - MIN_SIZE_WITH_HEADER - hash_len;
-    let ext_data = &data[start_of_eoie..data.len() - hash_len];
-
-    let (signature, ext_size, ext_data) = extension::decode::header(ext_data);
-    if signature != SIGNATURE || ext_size as usize != MIN_SIZE {
-        return None;
-    }
 
     let (offset, checksum) = ext_data.split_at(4);
     let offset = from_be_u32(offset) as usize;
@@ -35,13 +25,14 @@ pub fn decode(data: &[u8], object_hash: gix_hash::Kind) -> Option<usize> {
 
     let mut hasher = gix_features::hash::hasher(gix_hash::Kind::Sha1);
     let mut last_chunk = None;
-    for (signature, chunk) in extension::Iter::new(&data[offset..data.len() - MIN_SIZE_WITH_HEADER - hash_len]) {
+    let mut iter = extension::Iter::new(&data[offset..data.len() - MIN_SIZE_WITH_HEADER - hash_len]);
+    for (signature, chunk) in iter.by_ref() {
         hasher.update(&signature);
         hasher.update(&(chunk.len() as u32).to_be_bytes());
         last_chunk = Some(chunk);
     }
 
-    if hasher.digest() != checksum {
+    if iter.malformed().is_some() || hasher.digest() != checksum {
         return None;
     }
     // The last-to-this chunk ends where ours starts
@@ -54,3 +45,24 @@ pub fn decode(data: &[u8], object_hash: gix_hash::Kind) -> Option<usize> {
 
     Some(offset)
 }
+
+/// Return `true` if `data` ends in an end of index entry extension, whether or not it is valid.
+///
+/// Use this to determine if a failure to [`decode()`] is due to a corrupt extension.
+pub fn is_present(data: &[u8], object_hash: gix_hash::Kind) -> bool {
+    locate(data, object_hash).is_some()
+}
+
+/// Return the offset at which the extension starts along with its data, without its header.
+fn locate(data: &[u8], object_hash: gix_hash::Kind) -> Option<(usize, &[u8])> {
+    let hash_len = object_hash.len_in_bytes();
+    if data.len() < MIN_SIZE_WITH_HEADER + hash_len {
+        return None;
+    }
+
+    let start_of_eoie = data.len() - MIN_SIZE_WITH_HEADER - hash_len;
+    let ext_data = &data[start_of_eoie..data.len() - hash_len];
+
+    let (signature, ext_size, ext_data) = extension::decode::header(ext_data);
+    (signature == SIGNATURE && ext_size as usize == MIN_SIZE).then_some((start_of_eoie, ext_data))
+}
@@ -8,7 +8,7 @@ pub const MIN_SIZE: usize = 4 /* offset to extensions */ + gix_hash::Kind::short
 pub const MIN_SIZE_WITH_HEADER: usize = extension::MIN_SIZE + MIN_SIZE;
 
 mod decode;
-pub use decode::decode;
+pub use decode::{decode, is_present};
 
 mod write;
 pub use write::write_to;
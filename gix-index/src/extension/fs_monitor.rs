@@ -2,17 +2,44 @@ use bstr::BString;
 
 use crate::{
     extension::{FsMonitor, Signature},
-    util::{read_u32, read_u64, split_at_byte_exclusive},
+    util::{read_u32, read_u64, split_at_byte_exclusive, split_at_pos},
 };
 
-#[derive(Clone)]
+/// The token the filesystem monitor used for its last query, to be used to ask for changes since then.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Token {
-    V1 { nanos_since_1970: u64 },
-    V2 { token: BString },
+    /// The time of the last query, as used by version 1 of the hook protocol.
+    V1 {
+        /// The amount of nanoseconds since the unix epoch.
+        nanos_since_1970: u64,
+    },
+    /// An opaque token provided by the filesystem monitor, as used by version 2 of the hook protocol.
+    V2 {
+        /// The token to pass back to the filesystem monitor.
+        token: BString,
+    },
 }
 
+/// The signature of the filesystem monitor extension.
 pub const SIGNATURE: Signature = *b"FSMN";
 
+/// Access
+impl FsMonitor {
+    /// The token to use for the next query to the filesystem monitor.
+    pub fn token(&self) -> &Token {
+        &self.token
+    }
+    /// Set the `token` to use for the next query to the filesystem monitor.
+    pub fn set_token(&mut self, token: Token) {
+        self.token = token;
+    }
+    /// A bitmap with a set bit for each entry which the filesystem monitor considers changed, making its stat data untrustworthy.
+    pub fn entry_dirty(&self) -> &gix_bitmap::ewah::Vec {
+        &self.entry_dirty
+    }
+}
+
+/// Decode the filesystem monitor extension from `data`.
 pub fn decode(data: &[u8]) -> Option<FsMonitor> {
     let (version, data) = read_u32(data)?;
     let (token, data) = match version {
@@ -28,11 +55,37 @@ pub fn decode(data: &[u8]) -> Option<FsMonitor> {
     };
 
     let (ewah_size, data) = read_u32(data)?;
-    let (entry_dirty, data) = gix_bitmap::ewah::decode(&data[..ewah_size as usize]).ok()?;
+    let (ewah_data, data) = split_at_pos(data, ewah_size as usize)?;
+    let (entry_dirty, ewah_data) = gix_bitmap::ewah::decode(ewah_data).ok()?;
 
-    if !data.is_empty() {
+    if !ewah_data.is_empty() || !data.is_empty() {
         return None;
     }
 
     FsMonitor { token, entry_dirty }.into()
 }
+
+/// Write the filesystem monitor extension `fs_monitor` to `out`.
+pub fn write_to(fs_monitor: &FsMonitor, mut out: impl std::io::Write) -> Result<(), std::io::Error> {
+    let mut data = Vec::new();
+    match &fs_monitor.token {
+        Token::V1 { nanos_since_1970 } => {
+            data.extend_from_slice(&1_u32.to_be_bytes());
+            data.extend_from_slice(&nanos_since_1970.to_be_bytes());
+        }
+        Token::V2 { token } => {
+            data.extend_from_slice(&2_u32.to_be_bytes());
+            data.extend_from_slice(token);
+            data.push(0);
+        }
+    }
+    let ewah_size_pos = data.len();
+    data.extend_from_slice(&0_u32.to_be_bytes());
+    let ewah_size = fs_monitor.entry_dirty.write_to(&mut data)?;
+    data[ewah_size_pos..][..4]
+        .copy_from_slice(&(u32::try_from(ewah_size).expect("less than 4GB bitmap")).to_be_bytes());
+
+    out.write_all(&SIGNATURE)?;
+    out.write_all(&(u32::try_from(data.len()).expect("less than 4GB fsmonitor extension")).to_be_bytes())?;
+    out.write_all(&data)
+}
@@ -21,7 +21,7 @@ pub fn decode(data: &[u8]) -> Option<Vec<Offset>> {
         return None;
     }
 
-    let mut out = Vec::with_capacity(entry_size);
+    let mut out = Vec::with_capacity(num_offsets);
     for _ in 0..num_offsets {
         let (offset, chunk) = read_u32(data)?;
         let (num_entries, chunk) = read_u32(chunk)?;
@@ -41,3 +41,15 @@ pub fn find(extensions: &[u8], object_hash: gix_hash::Kind) -> Option<Vec<Offset
         .find_map(|(sig, ext_data)| (sig == SIGNATURE).then_some(ext_data))
         .and_then(decode)
 }
+
+pub fn write_to(offsets: &[Offset], mut out: impl std::io::Write) -> Result<(), std::io::Error> {
+    out.write_all(&SIGNATURE)?;
+    let extension_size: u32 = 4 + 8 * offsets.len() as u32;
+    out.write_all(&extension_size.to_be_bytes())?;
+    out.write_all(&1_u32.to_be_bytes())?;
+    for offset in offsets {
+        out.write_all(&offset.from_beginning_of_file.to_be_bytes())?;
+        out.write_all(&offset.num_entries.to_be_bytes())?;
+    }
+    Ok(())
+}
@@ -2,12 +2,32 @@ use std::convert::TryInto;
 
 use crate::{extension, extension::Iter, util::from_be_u32};
 
+/// The reason an [`Iter`] stopped before consuming all of its data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Malformed {
+    /// The extension with `signature` claimed to be `size` bytes large, but only `available` bytes were left.
+    SizeExceedsData {
+        /// The signature of the extension.
+        signature: extension::Signature,
+        /// The size the extension claimed to have.
+        size: u32,
+        /// The amount of bytes following the extension header.
+        available: usize,
+    },
+    /// There were `len` bytes left, which are too few for an extension header.
+    TrailingBytes {
+        /// The amount of remaining bytes.
+        len: usize,
+    },
+}
+
 impl<'a> Iter<'a> {
     /// Create a new extension iterator at the entrypoint for extensions until the end of the extensions.
     pub fn new(data_at_beginning_of_extensions_and_truncated: &'a [u8]) -> Self {
         Iter {
             data: data_at_beginning_of_extensions_and_truncated,
             consumed: 0,
+            malformed: None,
         }
     }
 
@@ -23,33 +43,51 @@ impl<'a> Iter<'a> {
         Iter {
             data: &data_at_beginning_of_extensions[..end],
             consumed: 0,
+            malformed: None,
         }
         .into()
     }
 }
 
+impl<'a> Iter<'a> {
+    /// Return the reason the iteration stopped early, or `None` if all data was consumed so far.
+    ///
+    /// The extension that caused this starts at [`consumed`][Iter::consumed], and isn't part of it.
+    pub fn malformed(&self) -> Option<Malformed> {
+        self.malformed
+    }
+}
+
 impl<'a> Iterator for Iter<'a> {
     type Item = (extension::Signature, &'a [u8]);
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.data.len() < 4 + 4 {
+            if !self.data.is_empty() {
+                self.malformed = Some(Malformed::TrailingBytes { len: self.data.len() });
+                self.data = &[];
+            }
             return None;
         }
 
         let (signature, data) = self.data.split_at(4);
         let (size, data) = data.split_at(4);
-        self.data = data;
-        self.consumed += 4 + 4;
-
-        let size = from_be_u32(size) as usize;
+        let signature = signature.try_into().unwrap();
+        let size = from_be_u32(size);
 
-        match data.get(..size) {
+        // Each extension consumes at least its header, so even a file full of empty extensions can't make us loop for long.
+        match data.get(..size as usize) {
             Some(ext_data) => {
-                self.data = &data[size..];
-                self.consumed += size;
-                Some((signature.try_into().unwrap(), ext_data))
+                self.data = &data[ext_data.len()..];
+                self.consumed += 4 + 4 + ext_data.len();
+                Some((signature, ext_data))
             }
             None => {
+                self.malformed = Some(Malformed::SizeExceedsData {
+                    signature,
+                    size,
+                    available: data.len(),
+                });
                 self.data = &[];
                 None
             }
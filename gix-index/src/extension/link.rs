@@ -68,109 +68,104 @@ pub(crate) fn decode(data: &[u8], object_hash: gix_hash::Kind) -> Result<Link, d
 }
 
 impl Link {
+    /// Serialize this instance to `out`.
+    pub fn write_to(&self, mut out: impl std::io::Write) -> Result<(), std::io::Error> {
+        let mut data = Vec::new();
+        data.extend_from_slice(self.shared_index_checksum.as_bytes());
+        if let Some(bitmaps) = &self.bitmaps {
+            bitmaps.delete.write_to(&mut data)?;
+            bitmaps.replace.write_to(&mut data)?;
+        }
+
+        out.write_all(&SIGNATURE)?;
+        out.write_all(&(u32::try_from(data.len()).expect("less than 4GB link extension")).to_be_bytes())?;
+        out.write_all(&data)
+    }
+
+    /// Load the shared index this link points to from the directory containing `split_index` and merge its entries with the ones
+    /// in `split_index` according to our bitmaps, leaving the merged entries in `split_index`.
+    ///
+    /// A link without bitmaps is treated as if all entries of the split index are new.
     pub(crate) fn dissolve_into(
         self,
         split_index: &mut crate::File,
         object_hash: gix_hash::Kind,
         options: crate::decode::Options,
     ) -> Result<(), crate::file::init::Error> {
-        let shared_index_path = split_index
-            .path
-            .parent()
-            .expect("split index file in .git folder")
-            .join(format!("sharedindex.{}", self.shared_index_checksum));
-        let mut shared_index = crate::File::at(
-            &shared_index_path,
-            object_hash,
-            crate::decode::Options {
-                expected_checksum: self.shared_index_checksum.into(),
-                ..options
-            },
-        )?;
-
-        if let Some(bitmaps) = self.bitmaps {
-            let mut split_entry_index = 0;
-
-            let mut err = None;
-            bitmaps.replace.for_each_set_bit(|replace_index| {
-                let shared_entry = match shared_index.entries.get_mut(replace_index) {
-                    Some(e) => e,
-                    None => {
-                        err = decode::Error::Corrupt("replace bitmap length exceeds shared index length - more entries in bitmap than found in shared index").into();
-                        return None
-                    }
-                };
-
-                if shared_entry.flags.contains(crate::entry::Flags::REMOVE) {
-                    err = decode::Error::Corrupt("entry is marked as both replace and delete").into();
-                    return None
-                }
-
-                let split_entry = match split_index.entries.get(split_entry_index) {
-                    Some(e) => e,
-                    None => {
-                        err = decode::Error::Corrupt("replace bitmap length exceeds split index length - more entries in bitmap than found in split index").into();
-                        return None
-                    }
-                };
-                if !split_entry.path.is_empty() {
-                    err = decode::Error::Corrupt("paths in split index entries that are for replacement should be empty").into();
-                    return None
-                }
-                if shared_entry.path.is_empty() {
-                    err = decode::Error::Corrupt("paths in shared index entries that are replaced should not be empty").into();
-                    return None
-                }
-                shared_entry.stat = split_entry.stat;
-                shared_entry.id = split_entry.id;
-                shared_entry.flags = split_entry.flags;
-                shared_entry.mode = split_entry.mode;
-
-                split_entry_index += 1;
-                Some(())
-            });
-            if let Some(err) = err {
-                return Err(err.into());
+        let mut shared_index =
+            crate::file::split::open_shared_index(&split_index.path, self.shared_index_checksum, object_hash, options)?;
+
+        let mut split_entry_index = 0;
+        for replace_index in self.bitmaps.iter().flat_map(|bitmaps| bitmaps.replace.iter()) {
+            let shared_entry = shared_index
+                .entries
+                .get_mut(replace_index)
+                .ok_or(decode::Error::Corrupt(
+                "replace bitmap length exceeds shared index length - more entries in bitmap than found in shared index",
+            ))?;
+            if shared_entry.flags.contains(crate::entry::Flags::REMOVE) {
+                return Err(decode::Error::Corrupt("entry is marked as both replace and delete").into());
             }
 
-            let split_index_path_backing = std::mem::take(&mut split_index.path_backing);
-            for mut split_entry in split_index.entries.drain(split_entry_index..) {
-                let start = shared_index.path_backing.len();
-                let split_index_path = split_entry.path.clone();
+            let split_entry = split_index
+                .entries
+                .get(split_entry_index)
+                .ok_or(decode::Error::Corrupt(
+                "replace bitmap length exceeds split index length - more entries in bitmap than found in split index",
+            ))?;
+            if !split_entry.path.is_empty() {
+                return Err(decode::Error::Corrupt(
+                    "paths in split index entries that are for replacement should be empty",
+                )
+                .into());
+            }
+            if shared_entry.path.is_empty() {
+                return Err(decode::Error::Corrupt(
+                    "paths in shared index entries that are replaced should not be empty",
+                )
+                .into());
+            }
+            shared_entry.stat = split_entry.stat;
+            shared_entry.id = split_entry.id;
+            shared_entry.flags = split_entry.flags;
+            shared_entry.mode = split_entry.mode;
 
-                split_entry.path = start..start + split_entry.path.len();
-                shared_index.entries.push(split_entry);
+            split_entry_index += 1;
+        }
 
-                shared_index
-                    .path_backing
-                    .extend_from_slice(&split_index_path_backing[split_index_path]);
-            }
+        let split_index_path_backing = std::mem::take(&mut split_index.path_backing);
+        for mut split_entry in split_index.entries.drain(split_entry_index..) {
+            let start = shared_index.path_backing.len();
+            let split_index_path = split_entry.path_range();
 
-            bitmaps.delete.for_each_set_bit(|delete_index| {
-                let shared_entry = match shared_index.entries.get_mut(delete_index) {
-                    Some(e) => e,
-                    None => {
-                        err = decode::Error::Corrupt("delete bitmap length exceeds shared index length - more entries in bitmap than found in shared index").into();
-                        return None
-                    }
-                };
-                shared_entry.flags.insert(crate::entry::Flags::REMOVE);
-                Some(())
-            });
-            if let Some(err) = err {
-                return Err(err.into());
-            }
+            split_entry.path = crate::entry::try_stored_path_range(start..start + split_index_path.len())
+                .ok_or(crate::decode::Error::PathBackingTooLarge)?;
+            shared_index.entries.push(split_entry);
 
             shared_index
+                .path_backing
+                .extend_from_slice(&split_index_path_backing[split_index_path]);
+        }
+
+        for delete_index in self.bitmaps.iter().flat_map(|bitmaps| bitmaps.delete.iter()) {
+            let shared_entry = shared_index
                 .entries
-                .retain(|e| !e.flags.contains(crate::entry::Flags::REMOVE));
+                .get_mut(delete_index)
+                .ok_or(decode::Error::Corrupt(
+                "delete bitmap length exceeds shared index length - more entries in bitmap than found in shared index",
+            ))?;
+            shared_entry.flags.insert(crate::entry::Flags::REMOVE);
+        }
 
-            let mut shared_entries = std::mem::take(&mut shared_index.entries);
-            shared_entries.sort_by(|a, b| a.cmp(b, &shared_index.state));
+        shared_index
+            .entries
+            .retain(|e| !e.flags.contains(crate::entry::Flags::REMOVE));
 
-            split_index.entries = shared_entries;
-            split_index.path_backing = std::mem::take(&mut shared_index.path_backing);
-        }
+        let mut shared_entries = std::mem::take(&mut shared_index.entries);
+        shared_entries.sort_by(|a, b| a.cmp(b, &shared_index.state));
+
+        split_index.entries = shared_entries;
+        split_index.path_backing = std::mem::take(&mut shared_index.path_backing);
 
         Ok(())
     }
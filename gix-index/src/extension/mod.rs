@@ -8,10 +8,14 @@ pub const MIN_SIZE: usize = 4 /* signature */ + 4 /* size */;
 pub type Signature = [u8; 4];
 
 /// An iterator over the data of index extensions.
+///
+/// It stops at the first extension that claims to be larger than the remaining data, which can be observed with
+/// [`malformed()`][Iter::malformed()].
 pub struct Iter<'a> {
     data: &'a [u8],
     /// The amount of consumed bytes as seen from our internal data pointer. Useful to continue where the iterator left off.
     pub consumed: usize,
+    malformed: Option<iter::Malformed>,
 }
 
 /// A structure to associate object ids of a tree with sections in the index entries list.
@@ -42,18 +46,19 @@ pub struct Link {
 }
 
 /// The extension for untracked files.
-#[allow(dead_code)]
 #[derive(Clone)]
 pub struct UntrackedCache {
     /// Something identifying the location and machine that this cache is for.
     /// Should the repository be copied to a different machine, the entire cache can immediately be invalidated.
     identifier: BString,
-    /// Stat for the .git/info/exclude file
-    info_exclude: Option<untracked_cache::OidStat>,
-    /// Stat for the `core.excludesfile`
-    excludes_file: Option<untracked_cache::OidStat>,
+    /// Stat for the .git/info/exclude file, with a null id if there is no such file, but possibly with stat information
+    /// that we keep to write it back.
+    info_exclude: untracked_cache::OidStat,
+    /// Stat for the `core.excludesfile`, with a null id if there is no such file.
+    excludes_file: untracked_cache::OidStat,
     /// Usually `.gitignore`
     exclude_filename_per_dir: BString,
+    /// The `dir_struct` flags git used when the cache was created, which affect what's listed as untracked.
     dir_flags: u32,
 
     /// A list of directories and sub-directories, with `directories[0]` being the root.
@@ -61,17 +66,27 @@ pub struct UntrackedCache {
 }
 
 /// The extension for keeping state on recent information provided by the filesystem monitor.
-#[allow(dead_code)]
 #[derive(Clone)]
 pub struct FsMonitor {
-    token: fs_monitor::Token,
+    pub(crate) token: fs_monitor::Token,
     /// if a bit is true, the respective entry is NOT valid as per the fs monitor.
-    entry_dirty: gix_bitmap::ewah::Vec,
+    pub(crate) entry_dirty: gix_bitmap::ewah::Vec,
 }
 
-mod iter;
+/// An optional extension that isn't understood, kept verbatim so it survives a read/write round trip.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Unknown {
+    /// The signature of the extension, which always starts with an upper-case letter.
+    pub signature: Signature,
+    /// The data of the extension, excluding its header.
+    pub data: Vec<u8>,
+}
 
-pub(crate) mod fs_monitor;
+///
+pub mod iter;
+
+///
+pub mod fs_monitor;
 
 ///
 pub mod decode;
@@ -87,7 +102,8 @@ pub(crate) mod index_entry_offset_table;
 ///
 pub mod link;
 
-pub(crate) mod resolve_undo;
+///
+pub mod resolve_undo;
 
 ///
 pub mod untracked_cache;
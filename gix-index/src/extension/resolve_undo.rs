@@ -1,33 +1,63 @@
-use bstr::BString;
+use std::io::Write;
+
+use bstr::{BStr, BString};
 use gix_hash::ObjectId;
 
 use crate::{
+    entry,
     extension::Signature,
     util::{split_at_byte_exclusive, split_at_pos},
 };
 
+/// All paths whose conflicts were resolved, sorted by path.
 pub type Paths = Vec<ResolvePath>;
 
-#[allow(dead_code)]
-#[derive(Clone)]
+/// The stages of a path before its conflict was resolved, allowing the resolution to be undone.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ResolvePath {
     /// relative to the root of the repository, or what would be stored in the index
-    name: BString,
+    pub(crate) name: BString,
 
     /// 0 = ancestor/common, 1 = ours, 2 = theirs
-    stages: [Option<Stage>; 3],
+    pub(crate) stages: [Option<Stage>; 3],
 }
 
-#[allow(dead_code)]
-#[derive(Clone, Copy)]
+/// A single stage of a [`ResolvePath`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Stage {
-    mode: u32,
-    id: ObjectId,
+    pub(crate) mode: u32,
+    pub(crate) id: ObjectId,
 }
 
+impl ResolvePath {
+    /// The path relative to the root of the repository.
+    pub fn name(&self) -> &BStr {
+        self.name.as_ref()
+    }
+
+    /// The base, ours and theirs stages of the path, in that order, with missing stages being `None`.
+    pub fn stages(&self) -> &[Option<Stage>; 3] {
+        &self.stages
+    }
+}
+
+impl Stage {
+    /// The mode of the entry at this stage.
+    pub fn mode(&self) -> entry::Mode {
+        entry::Mode::from_bits_truncate(self.mode)
+    }
+
+    /// The id of the entry's object at this stage.
+    pub fn id(&self) -> &gix_hash::oid {
+        &self.id
+    }
+}
+
+/// The signature of the resolve-undo extension.
 pub const SIGNATURE: Signature = *b"REUC";
 
-pub fn decode(mut data: &[u8], object_hash: gix_hash::Kind) -> Option<Paths> {
+/// Decode the resolve-undo extension from `data`, with ids of the given `object_hash`.
+pub(crate) fn decode(mut data: &[u8], object_hash: gix_hash::Kind) -> Option<Paths> {
     let hash_len = object_hash.len_in_bytes();
     let mut out = Vec::new();
 
@@ -62,3 +92,21 @@ pub fn decode(mut data: &[u8], object_hash: gix_hash::Kind) -> Option<Paths> {
     }
     out.into()
 }
+
+pub(crate) fn write_to(paths: &[ResolvePath], mut out: impl std::io::Write) -> Result<(), std::io::Error> {
+    let mut data = Vec::new();
+    for path in paths {
+        data.extend_from_slice(&path.name);
+        data.push(0);
+        for stage in &path.stages {
+            write!(data, "{:o}\0", stage.map_or(0, |stage| stage.mode))?;
+        }
+        for stage in path.stages.iter().flatten() {
+            data.extend_from_slice(stage.id.as_bytes());
+        }
+    }
+
+    out.write_all(&SIGNATURE)?;
+    out.write_all(&(u32::try_from(data.len()).expect("less than 4GB resolve-undo extension")).to_be_bytes())?;
+    out.write_all(&data)
+}
@@ -7,15 +7,11 @@ use crate::{
     util::{split_at_byte_exclusive, split_at_pos},
 };
 
-/// A recursive data structure
+/// Decode the tree extension from `data`, a recursive data structure, or return `None` if it is malformed.
 pub fn decode(data: &[u8], object_hash: gix_hash::Kind) -> Option<Tree> {
     let (tree, data) = one_recursive(data, object_hash.len_in_bytes())?;
-    assert!(
-        data.is_empty(),
-        "BUG: should fully consume the entire tree extension chunk, got {} left",
-        data.len()
-    );
-    Some(tree)
+    // The entire tree extension chunk must be consumed, otherwise it's malformed.
+    data.is_empty().then_some(tree)
 }
 
 fn one_recursive(data: &[u8], hash_len: usize) -> Option<(Tree, &[u8])> {
@@ -37,7 +33,8 @@ fn one_recursive(data: &[u8], hash_len: usize) -> Option<(Tree, &[u8])> {
         )
     };
 
-    let mut subtrees = Vec::with_capacity(subtree_count);
+    // Don't trust the subtree count for allocations, each subtree needs at least a few bytes of data.
+    let mut subtrees = Vec::with_capacity(subtree_count.min(data.len()));
     for _ in 0..subtree_count {
         let (tree, rest) = one_recursive(data, hash_len)?;
         subtrees.push(tree);
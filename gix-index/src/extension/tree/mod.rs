@@ -11,6 +11,9 @@ pub use decode::decode;
 
 mod write;
 
+mod status;
+pub use status::Status;
+
 #[cfg(test)]
 mod tests {
 
@@ -0,0 +1,96 @@
+use bstr::{BStr, ByteSlice};
+
+use crate::{extension::Tree, Entry, State};
+
+/// The state of the cache-tree node of the directory that contains an entry, as returned by
+/// [`State::entries_with_tree_status()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status<'a> {
+    /// The node is valid, and the tree object with the given id represents the directory of the entry.
+    Valid(&'a gix_hash::oid),
+    /// The node exists, but was invalidated by changes to its entries, so its tree object has to be recomputed.
+    Invalidated,
+    /// There is no node for the directory, for instance because it was added after the cache tree was computed,
+    /// or because there is no tree extension at all.
+    Absent,
+}
+
+/// Cache tree
+impl State {
+    /// Return an iterator over all entries along with the [status][Status] of the cache-tree node of the directory
+    /// containing them, which allows to only recompute the tree objects of directories that changed.
+    ///
+    /// The status of the root tree applies to entries at the top-level, and sparse directories belong to their parent
+    /// directory. Nodes are found by moving a cursor through the cache tree as entries are traversed, so only
+    /// changes of directory cause a lookup.
+    pub fn entries_with_tree_status(&self) -> impl Iterator<Item = (&Entry, Status<'_>)> + '_ {
+        let mut cursor = Cursor::new(self.tree.as_ref());
+        self.entries.iter().map(move |entry| {
+            let path = entry.path(self);
+            let path = path.strip_suffix(b"/").unwrap_or(path);
+            let dir = path.rfind_byte(b'/').map_or(&path[..0], |pos| &path[..pos]);
+            (entry, cursor.status_of(dir.as_bstr()))
+        })
+    }
+}
+
+/// The path from the root of the cache tree to the directory of the most recent entry.
+struct Cursor<'a> {
+    /// The directory of the most recent entry, empty for the root.
+    dir: &'a BStr,
+    /// The nodes of the root and each component of `dir`, which end at the first component that has no node.
+    nodes: Vec<&'a Tree>,
+    /// The status of the node of `dir`.
+    status: Status<'a>,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(root: Option<&'a Tree>) -> Self {
+        Cursor {
+            dir: "".into(),
+            nodes: root.into_iter().collect(),
+            status: status(root),
+        }
+    }
+
+    fn status_of(&mut self, dir: &'a BStr) -> Status<'a> {
+        if dir == self.dir {
+            return self.status;
+        }
+        let num_common = components(self.dir)
+            .zip(components(dir))
+            .take_while(|(previous, current)| previous == current)
+            .count();
+        let has_all_common_nodes = self.nodes.len() > num_common;
+        self.nodes.truncate(num_common + 1);
+        let mut node = has_all_common_nodes.then(|| self.nodes[num_common]);
+        for name in components(dir).skip(num_common) {
+            node = node.and_then(|parent| {
+                parent
+                    .children
+                    .binary_search_by(|child| child.name.as_slice().cmp(name))
+                    .ok()
+                    .map(|idx| &parent.children[idx])
+            });
+            match node {
+                Some(node) => self.nodes.push(node),
+                None => break,
+            }
+        }
+        self.dir = dir;
+        self.status = status(node);
+        self.status
+    }
+}
+
+fn status(node: Option<&Tree>) -> Status<'_> {
+    match node {
+        Some(node) if node.num_entries.is_some() => Status::Valid(&node.id),
+        Some(_) => Status::Invalidated,
+        None => Status::Absent,
+    }
+}
+
+fn components(dir: &BStr) -> impl Iterator<Item = &[u8]> {
+    (!dir.is_empty()).then(|| dir.split_str("/")).into_iter().flatten()
+}
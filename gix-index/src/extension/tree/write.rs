@@ -23,7 +23,10 @@ impl Tree {
                 out.write_all(tree.id.as_bytes())?;
             }
 
-            for child in &tree.children {
+            // Children are kept sorted by name, but git orders them by the length of their name first.
+            let mut children: Vec<_> = tree.children.iter().collect();
+            children.sort_by(|a, b| a.name.len().cmp(&b.name.len()).then_with(|| a.name.cmp(&b.name)));
+            for child in children {
                 tree_entry(out, child)?;
             }
 
@@ -32,8 +35,11 @@ impl Tree {
 
         let signature = tree::SIGNATURE;
 
-        let estimated_size = self.num_entries.unwrap_or(0) * (300 + 3 + 1 + 3 + 1 + 20);
-        let mut entries: Vec<u8> = Vec::with_capacity(estimated_size as usize);
+        fn num_nodes(tree: &Tree) -> usize {
+            1 + tree.children.iter().map(num_nodes).sum::<usize>()
+        }
+        let estimated_size = num_nodes(self) * (24 + 1 + 3 + 1 + 3 + 1 + 20);
+        let mut entries: Vec<u8> = Vec::with_capacity(estimated_size);
         tree_entry(&mut entries, self)?;
 
         out.write_all(&signature)?;
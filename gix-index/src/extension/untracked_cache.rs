@@ -1,12 +1,12 @@
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 
-use bstr::BString;
+use bstr::{BStr, BString};
 use gix_hash::ObjectId;
 
 use crate::{
     entry,
     extension::{Signature, UntrackedCache},
-    util::{read_u32, split_at_byte_exclusive, split_at_pos, var_int},
+    util::{read_u32, split_at_byte_exclusive, split_at_pos, var_int, write_var_int},
 };
 
 /// A structure to track filesystem stat information along with an object id, linking a worktree file with what's in our ODB.
@@ -25,20 +25,133 @@ pub struct Directory {
     pub name: BString,
     /// Untracked files and directory names
     pub untracked_entries: Vec<BString>,
-    /// indices for sub-directories similar to this one.
+    /// Indices into [`UntrackedCache::directories()`] for sub-directories similar to this one.
     pub sub_directories: Vec<usize>,
 
-    /// The directories stat data, if available or valid // TODO: or is it the exclude file?
+    /// The directories stat data, if available and valid.
     pub stat: Option<entry::Stat>,
     /// The oid of a .gitignore file, if it exists
     pub exclude_file_oid: Option<ObjectId>,
-    /// TODO: figure out what this really does
+    /// If true, the directory was only checked for containing untracked files, without listing all of them.
     pub check_only: bool,
 }
 
 /// Only used as an indicator
 pub const SIGNATURE: Signature = *b"UNTR";
 
+/// Access
+impl UntrackedCache {
+    /// Something identifying the location and machine that this cache is for, typically the kernel name and the worktree path.
+    pub fn identifier(&self) -> &BStr {
+        self.identifier.as_ref()
+    }
+    /// The stat and id of the `.git/info/exclude` file, if it was present.
+    pub fn info_exclude(&self) -> Option<&OidStat> {
+        (!self.info_exclude.id.is_null()).then_some(&self.info_exclude)
+    }
+    /// The stat and id of the `core.excludesFile`, if it was present.
+    pub fn excludes_file(&self) -> Option<&OidStat> {
+        (!self.excludes_file.id.is_null()).then_some(&self.excludes_file)
+    }
+    /// The name of the per-directory exclude file, usually `.gitignore`.
+    pub fn exclude_filename_per_dir(&self) -> &BStr {
+        self.exclude_filename_per_dir.as_ref()
+    }
+    /// The flags git used to collect untracked files when creating this cache.
+    pub fn dir_flags(&self) -> u32 {
+        self.dir_flags
+    }
+    /// All directories, with the root being the first one if there are any.
+    /// Use [`Directory::sub_directories`] to traverse the hierarchy.
+    pub fn directories(&self) -> &[Directory] {
+        &self.directories
+    }
+}
+
+/// Serialization
+impl UntrackedCache {
+    /// Serialize this instance to `out`.
+    ///
+    /// Directories are written depth-first starting at the root, like git does.
+    pub fn write_to(&self, mut out: impl std::io::Write) -> Result<(), std::io::Error> {
+        let mut data = Vec::new();
+        write_var_int(&mut data, self.identifier.len() as u64)?;
+        data.extend_from_slice(&self.identifier);
+        for oid_stat in [&self.info_exclude, &self.excludes_file] {
+            write_stat(&mut data, &oid_stat.stat);
+            data.extend_from_slice(oid_stat.id.as_bytes());
+        }
+        data.extend_from_slice(&self.dir_flags.to_be_bytes());
+        data.extend_from_slice(&self.exclude_filename_per_dir);
+        data.push(0);
+
+        let mut blocks = Vec::new();
+        let mut valid = gix_bitmap::ewah::Vec::default();
+        let mut check_only = gix_bitmap::ewah::Vec::default();
+        let mut hash_valid = gix_bitmap::ewah::Vec::default();
+        let mut stats = Vec::new();
+        let mut hashes = Vec::new();
+        let mut num_directories = 0;
+        let mut stack: Vec<_> = self.directories.first().into_iter().collect();
+        while let Some(dir) = stack.pop() {
+            let index = num_directories;
+            num_directories += 1;
+            if dir.check_only {
+                check_only.set(index);
+            }
+            if let Some(stat) = &dir.stat {
+                valid.set(index);
+                write_stat(&mut stats, stat);
+            }
+            if let Some(id) = &dir.exclude_file_oid {
+                hash_valid.set(index);
+                hashes.extend_from_slice(id.as_bytes());
+            }
+
+            write_var_int(&mut blocks, dir.untracked_entries.len() as u64)?;
+            write_var_int(&mut blocks, dir.sub_directories.len() as u64)?;
+            blocks.extend_from_slice(&dir.name);
+            blocks.push(0);
+            for name in &dir.untracked_entries {
+                blocks.extend_from_slice(name);
+                blocks.push(0);
+            }
+            stack.extend(dir.sub_directories.iter().rev().map(|idx| &self.directories[*idx]));
+        }
+
+        write_var_int(&mut data, num_directories as u64)?;
+        if num_directories != 0 {
+            data.extend_from_slice(&blocks);
+            valid.write_to(&mut data)?;
+            check_only.write_to(&mut data)?;
+            hash_valid.write_to(&mut data)?;
+            data.extend_from_slice(&stats);
+            data.extend_from_slice(&hashes);
+            data.push(0);
+        }
+
+        out.write_all(&SIGNATURE)?;
+        out.write_all(&(u32::try_from(data.len()).expect("less than 4GB untracked extension")).to_be_bytes())?;
+        out.write_all(&data)
+    }
+}
+
+fn write_stat(out: &mut Vec<u8>, stat: &entry::Stat) {
+    for field in [
+        stat.ctime.secs,
+        stat.ctime.nsecs,
+        stat.mtime.secs,
+        stat.mtime.nsecs,
+        stat.dev,
+        stat.ino,
+        stat.uid,
+        stat.gid,
+        stat.size,
+    ] {
+        out.extend_from_slice(&field.to_be_bytes());
+    }
+}
+
 // #[allow(unused)]
 /// Decode an untracked cache extension from `data`, assuming object hashes are of type `object_hash`.
 pub fn decode(data: &[u8], object_hash: gix_hash::Kind) -> Option<UntrackedCache> {
@@ -58,8 +171,8 @@ pub fn decode(data: &[u8], object_hash: gix_hash::Kind) -> Option<UntrackedCache
 
     let mut res = UntrackedCache {
         identifier: identifier.into(),
-        info_exclude: (!info_exclude.id.is_null()).then_some(info_exclude),
-        excludes_file: (!excludes_file.id.is_null()).then_some(excludes_file),
+        info_exclude,
+        excludes_file,
         exclude_filename_per_dir: exclude_filename_per_dir.into(),
         dir_flags,
         directories: Vec::new(),
@@ -68,11 +181,12 @@ pub fn decode(data: &[u8], object_hash: gix_hash::Kind) -> Option<UntrackedCache
         return data.is_empty().then_some(res);
     }
 
-    let num_directory_blocks = num_directory_blocks.try_into().ok()?;
+    let num_directory_blocks: usize = num_directory_blocks.try_into().ok()?;
     let directories = &mut res.directories;
-    directories.reserve(num_directory_blocks);
+    // each directory block needs at least 3 bytes, don't let the count make us allocate more than that.
+    directories.reserve(num_directory_blocks.min(data.len() / 3));
 
-    let data = decode_directory_block(data, directories)?;
+    let data = decode_directory_blocks(data, directories, num_directory_blocks)?;
     if directories.len() != num_directory_blocks {
         return None;
     }
@@ -88,21 +202,21 @@ pub fn decode(data: &[u8], object_hash: gix_hash::Kind) -> Option<UntrackedCache
     }
 
     check_only.for_each_set_bit(|index| {
-        directories[index].check_only = true;
+        directories.get_mut(index)?.check_only = true;
         Some(())
     })?;
     valid.for_each_set_bit(|index| {
         let (stat, rest) = crate::decode::stat(data)?;
-        directories[index].stat = stat.into();
+        directories.get_mut(index)?.stat = stat.into();
         data = rest;
         Some(())
-    });
+    })?;
     hash_valid.for_each_set_bit(|index| {
         let (hash, rest) = split_at_pos(data, hash_len)?;
         data = rest;
-        directories[index].exclude_file_oid = ObjectId::from(hash).into();
+        directories.get_mut(index)?.exclude_file_oid = ObjectId::from(hash).into();
         Some(())
-    });
+    })?;
 
     // null-byte checked in the beginning
     if data.len() != 1 {
@@ -111,36 +225,53 @@ pub fn decode(data: &[u8], object_hash: gix_hash::Kind) -> Option<UntrackedCache
     res.into()
 }
 
-fn decode_directory_block<'a>(data: &'a [u8], directories: &mut Vec<Directory>) -> Option<&'a [u8]> {
-    let (num_untracked, data) = var_int(data)?;
-    let (num_dirs, data) = var_int(data)?;
-    let (name, mut data) = split_at_byte_exclusive(data, 0)?;
-    let mut untracked_entries = Vec::<BString>::with_capacity(num_untracked.try_into().ok()?);
-    for _ in 0..num_untracked {
-        let (name, rest) = split_at_byte_exclusive(data, 0)?;
+/// Decode directory blocks, which are stored depth-first, into `directories` without recursion to be safe against
+/// deeply nested, malicious input. Fail if there are more than `max_directories`.
+fn decode_directory_blocks<'a>(
+    mut data: &'a [u8],
+    directories: &mut Vec<Directory>,
+    max_directories: usize,
+) -> Option<&'a [u8]> {
+    // The parent directories along with the amount of sub-directories they are yet to receive.
+    let mut parents = Vec::<(usize, u64)>::new();
+    loop {
+        if directories.len() == max_directories {
+            return None;
+        }
+        let (num_untracked, rest) = var_int(data)?;
+        let (num_dirs, rest) = var_int(rest)?;
+        let (name, mut rest) = split_at_byte_exclusive(rest, 0)?;
+        let mut untracked_entries = Vec::<BString>::with_capacity(num_untracked.min(rest.len() as u64) as usize);
+        for _ in 0..num_untracked {
+            let (name, remaining) = split_at_byte_exclusive(rest, 0)?;
+            rest = remaining;
+            untracked_entries.push(name.into());
+        }
         data = rest;
-        untracked_entries.push(name.into());
-    }
 
-    let index = directories.len();
-    directories.push(Directory {
-        name: name.into(),
-        untracked_entries,
-        sub_directories: Vec::with_capacity(num_dirs.try_into().ok()?),
-        // the following are set later through their bitmaps
-        stat: None,
-        exclude_file_oid: None,
-        check_only: false,
-    });
-
-    for _ in 0..num_dirs {
-        let subdir_index = directories.len();
-        let rest = decode_directory_block(data, directories)?;
-        data = rest;
-        directories[index].sub_directories.push(subdir_index);
-    }
+        let index = directories.len();
+        if let Some((parent, num_dirs_left)) = parents.last_mut() {
+            directories[*parent].sub_directories.push(index);
+            *num_dirs_left -= 1;
+        }
+        directories.push(Directory {
+            name: name.into(),
+            untracked_entries,
+            sub_directories: Vec::with_capacity(num_dirs.min(data.len() as u64) as usize),
+            // the following are set later through their bitmaps
+            stat: None,
+            exclude_file_oid: None,
+            check_only: false,
+        });
+        parents.push((index, num_dirs));
 
-    data.into()
+        while parents.last().map_or(false, |(_, num_dirs_left)| *num_dirs_left == 0) {
+            parents.pop();
+        }
+        if parents.is_empty() {
+            return Some(data);
+        }
+    }
 }
 
 fn decode_oid_stat(data: &[u8], hash_len: usize) -> Option<(OidStat, &[u8])> {
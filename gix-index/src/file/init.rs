@@ -1,6 +1,9 @@
 #![allow(unused)]
 
-use std::path::{Path, PathBuf};
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+};
 
 use memmap2::Mmap;
 
@@ -14,10 +17,17 @@ mod error {
     pub enum Error {
         #[error("An IO error occurred while opening the index")]
         Io(#[from] std::io::Error),
+        #[error("The index file at '{}' doesn't exist", path.display())]
+        NotFound { path: std::path::PathBuf },
         #[error(transparent)]
         Decode(#[from] crate::decode::Error),
         #[error(transparent)]
         LinkExtension(#[from] crate::extension::link::decode::Error),
+        #[error("The shared index at '{}' with checksum {expected_checksum} referred to by the link extension doesn't exist", path.display())]
+        MissingSharedIndex {
+            path: std::path::PathBuf,
+            expected_checksum: gix_hash::ObjectId,
+        },
     }
 }
 
@@ -28,7 +38,8 @@ impl File {
     /// Try to open the index file at `path` with `options`, assuming `object_hash` is used throughout the file, or create a new
     /// index that merely exists in memory and is empty.
     ///
-    /// Note that the `path` will not be written if it doesn't exist.
+    /// Note that the `path` will not be written if it doesn't exist, but is kept so that [`File::write()`] creates it.
+    /// All other errors, like IO errors or a corrupt index, are returned as is.
     pub fn at_or_default(
         path: impl Into<PathBuf>,
         object_hash: gix_hash::Kind,
@@ -37,31 +48,45 @@ impl File {
         let path = path.into();
         Ok(match Self::at(&path, object_hash, options) {
             Ok(f) => f,
-            Err(Error::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => {
-                File::from_state(State::new(object_hash), path)
-            }
+            Err(Error::NotFound { .. }) => File::from_state(State::new(object_hash), path),
             Err(err) => return Err(err),
         })
     }
 
     /// Open an index file at `path` with `options`, assuming `object_hash` is used throughout the file.
+    ///
+    /// Small files are read into memory, larger ones are memory-mapped. If there is no file at `path`,
+    /// [`Error::NotFound`] is returned.
     pub fn at(path: impl Into<PathBuf>, object_hash: gix_hash::Kind, options: decode::Options) -> Result<Self, Error> {
         let path = path.into();
         let (data, mtime) = {
-            // SAFETY: we have to take the risk of somebody changing the file underneath. Git never writes into the same file.
-            let file = std::fs::File::open(&path)?;
-            #[allow(unsafe_code)]
-            let data = unsafe { Mmap::map(&file)? };
-            (data, filetime::FileTime::from_last_modification_time(&file.metadata()?))
+            let file = std::fs::File::open(&path).map_err(|err| match err.kind() {
+                std::io::ErrorKind::NotFound => Error::NotFound { path: path.clone() },
+                _ => Error::Io(err),
+            })?;
+            let metadata = file.metadata()?;
+            let need_more_than_this_many_bytes_to_use_mmap = 32 * 1024;
+            let data = if metadata.len() <= need_more_than_this_many_bytes_to_use_mmap {
+                let mut buf = Vec::with_capacity(metadata.len() as usize);
+                (&file).read_to_end(&mut buf)?;
+                Data::InMemory(buf)
+            } else {
+                // SAFETY: we have to take the risk of somebody changing the file underneath. Git never writes into the same file.
+                #[allow(unsafe_code)]
+                Data::Mapped(unsafe { Mmap::map(&file)? })
+            };
+            (data, filetime::FileTime::from_last_modification_time(&metadata))
         };
 
-        let (state, checksum) = State::from_bytes(&data, mtime, object_hash, options)?;
+        let (state, decode::Outcome { checksum, .. }) = State::from_bytes(data.as_ref(), mtime, object_hash, options)?;
         let mut file = File {
             state,
             path,
-            checksum: Some(checksum),
+            checksum,
+            shared_index_checksum: None,
         };
-        if let Some(mut link) = file.link.take() {
+        if let Some(link) = file.link.take() {
+            file.shared_index_checksum = Some(link.shared_index_checksum);
             link.dissolve_into(&mut file, object_hash, options)?;
         }
 
@@ -76,6 +101,22 @@ impl File {
             state,
             path: path.into(),
             checksum: None,
+            shared_index_checksum: None,
+        }
+    }
+}
+
+/// The bytes of an index file, either read into memory or memory-mapped.
+enum Data {
+    InMemory(Vec<u8>),
+    Mapped(Mmap),
+}
+
+impl AsRef<[u8]> for Data {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            Data::InMemory(data) => data,
+            Data::Mapped(map) => map,
         }
     }
 }
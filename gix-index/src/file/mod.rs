@@ -57,7 +57,8 @@ mod access {
             &self.path
         }
 
-        /// The checksum over the file that was read or written to disk, or `None` if the state in memory was never serialized.
+        /// The checksum over the file that was read or written to disk, or `None` if the state in memory was never serialized
+        /// or if the file that was read had a null checksum.
         ///
         /// Note that even if `Some`, it will only represent the state in memory right after reading or [writing][File::write()].
         pub fn checksum(&self) -> Option<gix_hash::ObjectId> {
@@ -85,6 +86,8 @@ mod mutation {
 ///
 pub mod init;
 ///
+pub mod split;
+///
 pub mod verify;
 ///
 pub mod write;
@@ -0,0 +1,286 @@
+use std::path::{Path, PathBuf};
+
+use crate::{decode, entry, extension, file, write, Entry, File, State};
+
+/// The options for [writing a split index][File::write_split()].
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    /// The percentage of our entries that may be missing in the shared index before a new shared index is written,
+    /// like `splitIndex.maxPercentChange` in git, which defaults to 20.
+    ///
+    /// With 0, a new shared index is written each time, and with 100 or more only if there is no shared index yet.
+    pub max_percent_change: u8,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options { max_percent_change: 20 }
+    }
+}
+
+/// The outcome of [writing a split index][File::write_split()].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Outcome {
+    /// The checksum of the shared index the split index links to, which is stored in `sharedindex.<checksum>` next to it.
+    pub shared_index_checksum: gix_hash::ObjectId,
+    /// If `true`, a new shared index was written as the previous one was missing or didn't share enough entries with us.
+    pub shared_index_written: bool,
+}
+
+/// Split indices
+impl File {
+    /// Return the checksum of the shared index if this is a split index, which was merged with it when it was
+    /// [read][File::at()] or which was [written][File::write_split()] against it.
+    pub fn shared_index_checksum(&self) -> Option<gix_hash::ObjectId> {
+        self.shared_index_checksum
+    }
+
+    /// Read our shared index with `options` if this is a split index, or return `None` otherwise.
+    ///
+    /// It's the base to pass to [`write_split()`][File::write_split()] for writing only the entries that changed.
+    pub fn shared_index(&self, options: decode::Options) -> Result<Option<File>, file::init::Error> {
+        self.shared_index_checksum
+            .map(|checksum| open_shared_index(&self.path, checksum, self.object_hash(), options))
+            .transpose()
+    }
+
+    /// Write ourselves as split index to our path, which contains only the entries that differ from the ones in `shared_index`
+    /// along with the link extension to it, like git does if `core.splitIndex` is enabled. `options` are used for writing
+    /// the split index, and have to include the [link extension][write::Extensions::should_write()].
+    ///
+    /// If there is no `shared_index`, typically obtained with [`shared_index()`][File::shared_index()], or if more than
+    /// [`max_percent_change`][Options::max_percent_change] of our entries aren't in it, all of our entries are written into
+    /// a new shared index `sharedindex.<checksum>` next to our path first, without any extension. Otherwise the modification
+    /// time of `shared_index` is updated so it isn't considered [stale][File::remove_stale_shared_indices()].
+    ///
+    /// Just like with [`write()`][File::write()], our checksum, version and timestamp are updated to the ones of the written
+    /// split index.
+    pub fn write_split(
+        &mut self,
+        shared_index: Option<&File>,
+        options: write::Options,
+        split_options: Options,
+    ) -> Result<Outcome, file::write::Error> {
+        let delta = shared_index
+            .filter(|shared| shared.object_hash() == self.object_hash() && shared.link().is_none())
+            .and_then(|shared| Some((shared, shared.checksum?)))
+            .and_then(|(shared, checksum)| {
+                Delta::compute(&self.state, shared, split_options.max_percent_change).map(|delta| (delta, checksum))
+            });
+        let shared_index_written = delta.is_none();
+        let (delta, shared_index_checksum) = match delta {
+            Some((delta, checksum)) => {
+                let path = shared_index_path(&self.path, &checksum);
+                filetime::set_file_mtime(path, filetime::FileTime::now())?;
+                (delta, checksum)
+            }
+            None => (Delta::default(), self.write_shared_index(options)?),
+        };
+
+        let (entries, path_backing) = delta.split_entries(&self.state);
+        let entries = std::mem::replace(&mut self.state.entries, entries);
+        let path_backing = std::mem::replace(&mut self.state.path_backing, path_backing);
+        // The fsmonitor bitmap refers to all entries, as git applies it after merging the shared index, which is why it can
+        // be written as is unless entries are removed.
+        let fs_monitor = entries
+            .iter()
+            .any(|e| e.flags.contains(entry::Flags::REMOVE))
+            .then(|| self.state.fs_monitor.take())
+            .flatten();
+        self.state.link = Some(extension::Link {
+            shared_index_checksum,
+            bitmaps: Some(extension::link::Bitmaps {
+                delete: delta.deleted,
+                replace: delta.replace,
+            }),
+        });
+        let res = file::write::write_locked(&self.state, &self.path, options, gix_lock::acquire::Fail::Immediately);
+        self.state.link = None;
+        self.state.fs_monitor = self.state.fs_monitor.take().or(fs_monitor);
+        self.state.entries = entries;
+        self.state.path_backing = path_backing;
+
+        let (version, digest, mtime) = res?;
+        self.state.timestamp = mtime;
+        self.state.version = version;
+        self.checksum = Some(digest);
+        self.shared_index_checksum = Some(shared_index_checksum);
+        Ok(Outcome {
+            shared_index_checksum,
+            shared_index_written,
+        })
+    }
+
+    /// Remove all `sharedindex.*` files next to our path that were last modified before `expiry`, except for our own
+    /// [shared index][File::shared_index_checksum()], and return the amount of removed files.
+    ///
+    /// Git does the same with shared indices older than `splitIndex.sharedIndexExpire`, two weeks by default, after writing
+    /// a new shared index. As split indices of other worktrees may still refer to old shared indices, their modification
+    /// time is updated each time they are used, which is why they must not be removed much sooner.
+    pub fn remove_stale_shared_indices(&self, expiry: std::time::SystemTime) -> std::io::Result<usize> {
+        let dir = match index_dir(&self.path) {
+            dir if dir.as_os_str().is_empty() => Path::new("."),
+            dir => dir,
+        };
+        let current = self.shared_index_checksum.map(|checksum| format!("{PREFIX}{checksum}"));
+        let mut num_removed = 0;
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            match name.to_str() {
+                Some(name) if name.starts_with(PREFIX) && Some(name) != current.as_deref() => {}
+                _ => continue,
+            }
+            let metadata = entry.metadata()?;
+            if metadata.is_file() && metadata.modified()? < expiry {
+                match std::fs::remove_file(entry.path()) {
+                    Ok(()) => num_removed += 1,
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+        Ok(num_removed)
+    }
+}
+
+impl File {
+    /// Write all of our entries without any extension into a new shared index next to us, and return its checksum.
+    fn write_shared_index(&self, options: write::Options) -> Result<gix_hash::ObjectId, file::write::Error> {
+        let mut buf = Vec::new();
+        let options = write::Options {
+            extensions: write::Extensions::None,
+            ..options
+        };
+        let (_version, checksum) = file::write::write_with_checksum(&self.state, &mut buf, options)?;
+        let mut lock = gix_lock::File::acquire_to_update_resource(
+            shared_index_path(&self.path, &checksum),
+            gix_lock::acquire::Fail::Immediately,
+            None,
+        )?;
+        std::io::Write::write_all(&mut lock, &buf)?;
+        lock.with_mut(|file| file.sync_all())?;
+        lock.commit()?;
+        Ok(checksum)
+    }
+}
+
+/// The entries of a split index, relative to the ones of its shared index.
+#[derive(Default)]
+struct Delta {
+    /// The indices of our entries that replace an entry in the shared index, in order.
+    replaced: Vec<usize>,
+    /// The indices of our entries that aren't in the shared index, in order.
+    added: Vec<usize>,
+    /// A bit for each shared entry that is replaced by one of ours.
+    replace: gix_bitmap::ewah::Vec,
+    /// A bit for each shared entry that we don't have anymore.
+    deleted: gix_bitmap::ewah::Vec,
+}
+
+impl Delta {
+    /// Compare the entries of `state` to the ones of `shared`, or return `None` if more than `max_percent_change` of
+    /// our entries aren't in `shared`, which is when a new shared index should be written instead, just like git does it.
+    fn compute(state: &State, shared: &State, max_percent_change: u8) -> Option<Self> {
+        if max_percent_change == 0 {
+            return None;
+        }
+        let mut delta = Delta::default();
+        let mut shared_entries = shared.entries.iter().enumerate().peekable();
+        let mut num_entries = 0;
+        for (idx, entry) in state.entries.iter().enumerate() {
+            if entry.flags.contains(entry::Flags::REMOVE) {
+                continue;
+            }
+            num_entries += 1;
+            let (path, stage) = (entry.path(state), entry.stage());
+            while let Some((shared_idx, _)) =
+                shared_entries.next_if(|(_, e)| entry::compare(e.path(shared), e.stage(), path, stage).is_lt())
+            {
+                delta.deleted.set(shared_idx);
+            }
+            match shared_entries.next_if(|(_, e)| e.path(shared) == path && e.stage() == stage) {
+                Some((shared_idx, shared_entry)) => {
+                    if !is_same_at_rest(entry, shared_entry) {
+                        delta.replace.set(shared_idx);
+                        delta.replaced.push(idx);
+                    }
+                }
+                None => delta.added.push(idx),
+            }
+        }
+        for (shared_idx, _) in shared_entries {
+            delta.deleted.set(shared_idx);
+        }
+
+        let max_percent_change = u64::from(max_percent_change.min(100));
+        (num_entries as u64 * max_percent_change >= delta.added.len() as u64 * 100).then_some(delta)
+    }
+
+    /// Return the entries to write into the split index along with their path backing. Replaced entries come first and have
+    /// empty paths as they are stored in the shared index, while added entries follow with their paths.
+    fn split_entries(&self, state: &State) -> (Vec<Entry>, crate::PathStorage) {
+        let mut path_backing = Vec::new();
+        let replaced = self.replaced.iter().map(|idx| Entry {
+            path: 0..0,
+            ..state.entries[*idx].clone()
+        });
+        let added = self.added.iter().map(|idx| {
+            let entry = &state.entries[*idx];
+            let start = path_backing.len();
+            path_backing.extend_from_slice(entry.path(state));
+            Entry {
+                path: entry::stored_path_range(start..path_backing.len()),
+                ..entry.clone()
+            }
+        });
+        let entries = replaced.collect::<Vec<_>>().into_iter().chain(added).collect();
+        (entries, path_backing)
+    }
+}
+
+/// Return `true` if `a` and `b` would be stored the same way apart from their path, which is what git compares to decide
+/// if an entry of the shared index has to be replaced.
+fn is_same_at_rest(a: &Entry, b: &Entry) -> bool {
+    let stored_flags = entry::Flags::STAGE_MASK
+        | entry::Flags::ASSUME_VALID
+        | entry::Flags::INTENT_TO_ADD
+        | entry::Flags::SKIP_WORKTREE;
+    a.stat == b.stat && a.id == b.id && a.mode == b.mode && a.flags & stored_flags == b.flags & stored_flags
+}
+
+const PREFIX: &str = "sharedindex.";
+
+/// Return the directory containing the index at `index_path`, which is where its shared indices are located.
+fn index_dir(index_path: &Path) -> &Path {
+    index_path.parent().expect("split index file in .git folder")
+}
+
+/// Return the path to the shared index with `checksum` for the split index at `index_path`.
+pub(crate) fn shared_index_path(index_path: &Path, checksum: &gix_hash::oid) -> PathBuf {
+    index_dir(index_path).join(format!("{PREFIX}{checksum}"))
+}
+
+/// Open the shared index with `checksum` of the split index at `index_path`, and fail with a dedicated error if it doesn't exist.
+pub(crate) fn open_shared_index(
+    index_path: &Path,
+    checksum: gix_hash::ObjectId,
+    object_hash: gix_hash::Kind,
+    options: decode::Options,
+) -> Result<File, file::init::Error> {
+    File::at(
+        shared_index_path(index_path, &checksum),
+        object_hash,
+        decode::Options {
+            expected_checksum: checksum.into(),
+            ..options
+        },
+    )
+    .map_err(|err| match err {
+        file::init::Error::NotFound { path } => file::init::Error::MissingSharedIndex {
+            path,
+            expected_checksum: checksum,
+        },
+        err => err,
+    })
+}
@@ -1,6 +1,6 @@
 use gix_features::hash;
 
-use crate::{write, File, Version};
+use crate::{write, File, State, Version};
 
 /// The error produced by [`File::write()`].
 #[derive(Debug, thiserror::Error)]
@@ -8,6 +8,8 @@ use crate::{write, File, Version};
 pub enum Error {
     #[error(transparent)]
     Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Write(#[from] write::Error),
     #[error("Could not acquire lock for index file")]
     AcquireLock(#[from] gix_lock::acquire::Error),
     #[error("Could not commit lock for index file")]
@@ -19,33 +21,78 @@ impl File {
     /// to retain all information of this index.
     pub fn write_to(
         &self,
-        mut out: impl std::io::Write,
+        out: impl std::io::Write,
         options: write::Options,
-    ) -> std::io::Result<(Version, gix_hash::ObjectId)> {
-        let mut hasher = hash::Write::new(&mut out, self.state.object_hash);
-        let version = self.state.write_to(&mut hasher, options)?;
-
-        let hash = hasher.hash.digest();
-        out.write_all(&hash)?;
-        Ok((version, gix_hash::ObjectId::from(hash)))
+    ) -> Result<(Version, gix_hash::ObjectId), write::Error> {
+        write_with_checksum(&self.state, out, options)
     }
 
-    /// Write ourselves to the path we were read from after acquiring a lock, using `options`.
+    /// Write ourselves to the path we were read from after acquiring a lock, using `options`, failing immediately if
+    /// the lock is held elsewhere.
     ///
-    /// Note that the hash produced will be stored which is why we need to be mutable.
+    /// Note that the hash produced will be stored which is why we need to be mutable, just like the version that
+    /// was actually written, which is available via [`version()`][crate::State::version()] afterwards.
+    /// The [timestamp][crate::State::timestamp()] is set to the modification time of the written file, just like it
+    /// would be when reading it.
     pub fn write(&mut self, options: write::Options) -> Result<(), Error> {
-        let mut lock = std::io::BufWriter::new(gix_lock::File::acquire_to_update_resource(
-            &self.path,
-            gix_lock::acquire::Fail::Immediately,
-            None,
-        )?);
-        let (version, digest) = self.write_to(&mut lock, options)?;
-        match lock.into_inner() {
-            Ok(lock) => lock.commit()?,
-            Err(err) => return Err(err.into_error().into()),
-        };
+        self.write_with_lock_mode(options, gix_lock::acquire::Fail::Immediately)
+    }
+
+    /// Like [`write()`][File::write()], but use `lock_mode` to determine how long to wait for the lock if it is held
+    /// elsewhere.
+    ///
+    /// The index is written into `index.lock` next to our path, which is synced to disk and then atomically renamed into place,
+    /// so readers see either the old or the new index. On error the lock file is removed, leaving the index unchanged. The same
+    /// happens on interrupts if the application installed the `gix-tempfile` signal handlers.
+    pub fn write_with_lock_mode(
+        &mut self,
+        options: write::Options,
+        lock_mode: gix_lock::acquire::Fail,
+    ) -> Result<(), Error> {
+        let (version, digest, mtime) = write_locked(&self.state, &self.path, options, lock_mode)?;
+        self.state.timestamp = mtime;
         self.state.version = version;
         self.checksum = Some(digest);
+        self.shared_index_checksum = None;
         Ok(())
     }
 }
+
+/// Write `state` to `out` with `options`, followed by the checksum over all written bytes, and return the version that
+/// was written along with the checksum.
+pub(crate) fn write_with_checksum(
+    state: &State,
+    mut out: impl std::io::Write,
+    options: write::Options,
+) -> Result<(Version, gix_hash::ObjectId), write::Error> {
+    let mut hasher = hash::Write::new(&mut out, state.object_hash);
+    let version = state.write_to(&mut hasher, options)?;
+
+    let hash = hasher.hash.digest();
+    out.write_all(&hash)?;
+    Ok((version, gix_hash::ObjectId::from(hash)))
+}
+
+/// Write `state` with `options` to `path` via a lock acquired with `lock_mode`, and return the version that was written,
+/// the checksum of the file and its modification time.
+pub(crate) fn write_locked(
+    state: &State,
+    path: &std::path::Path,
+    options: write::Options,
+    lock_mode: gix_lock::acquire::Fail,
+) -> Result<(Version, gix_hash::ObjectId, filetime::FileTime), Error> {
+    let mut lock = std::io::BufWriter::new(gix_lock::File::acquire_to_update_resource(path, lock_mode, None)?);
+    let (version, digest) = write_with_checksum(state, &mut lock, options)?;
+    let mtime = match lock.into_inner() {
+        Ok(mut lock) => {
+            let metadata = lock.with_mut(|file| {
+                file.sync_all()?;
+                file.metadata()
+            })?;
+            lock.commit()?;
+            filetime::FileTime::from_last_modification_time(&metadata)
+        }
+        Err(err) => return Err(err.into_error().into()),
+    };
+    Ok((version, digest, mtime))
+}
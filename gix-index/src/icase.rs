@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+
+use bstr::{BStr, BString, ByteSlice};
+
+use crate::{Entry, State};
+
+/// A structure to find entries and directories by path while ignoring the case of ASCII characters, similar to the
+/// `name-hash` and `dir-hash` tables of git.
+///
+/// It borrows the [`State`] it was [created from][State::prepare_icase_lookup()], which assures the state can't change
+/// while it's in use.
+pub struct Lookup<'a> {
+    state: &'a State,
+    /// Folded paths, mapping to the indices of all entries whose path folds to them, in ascending order.
+    entries: HashMap<BString, Vec<usize>>,
+    /// Folded directories without trailing slash, mapping to the index of the first entry in them along with the length of
+    /// the directory within the entry's path, to know how the directory is spelled in the index.
+    directories: HashMap<BString, (usize, usize)>,
+}
+
+/// Case-insensitive lookup
+impl State {
+    /// Prepare a lookup table to find entries and directories without considering the case of ASCII characters, which is
+    /// what case-insensitive filesystems need.
+    ///
+    /// Building it needs to visit all entries, so it should only be created if `core.ignoreCase` is set.
+    pub fn prepare_icase_lookup(&self) -> Lookup<'_> {
+        let mut entries = HashMap::<BString, Vec<usize>>::with_capacity(self.entries.len());
+        let mut directories = HashMap::new();
+        for (idx, entry) in self.entries.iter().enumerate() {
+            let path = entry.path(self);
+            entries.entry(fold(path)).or_default().push(idx);
+
+            let mut dir = path;
+            while let Some(slash) = dir.rfind_byte(b'/') {
+                dir = dir[..slash].as_bstr();
+                let folded = fold(dir);
+                if directories.contains_key(&folded) {
+                    // all parent directories are known as well.
+                    break;
+                }
+                directories.insert(folded, (idx, dir.len()));
+            }
+        }
+        Lookup {
+            state: self,
+            entries,
+            directories,
+        }
+    }
+}
+
+impl<'a> Lookup<'a> {
+    /// Find the entry with `path`, ignoring the case of ASCII characters if `ignore_case` is true, in which case an entry
+    /// whose path matches exactly is preferred. If there are multiple stages, the entry with the lowest one is returned.
+    ///
+    /// If `ignore_case` is false, this is the same as an exact lookup by path.
+    pub fn entry_by_path_icase(&self, path: &BStr, ignore_case: bool) -> Option<&'a Entry> {
+        let state = self.state;
+        if !ignore_case {
+            return state.entry_index_by_path(path).ok().map(|idx| &state.entries[idx]);
+        }
+        let candidates = self.entries.get(&fold(path))?;
+        let idx = candidates
+            .iter()
+            .find(|idx| state.entries[**idx].path(state) == path)
+            .or_else(|| candidates.first())?;
+        Some(&state.entries[*idx])
+    }
+
+    /// Return the directory at `path`, without trailing slash, as spelled in the index if there is at least one entry in it,
+    /// ignoring the case of ASCII characters if `ignore_case` is true.
+    ///
+    /// This is useful to adjust the case of the directory portion of new paths to what's already in the index.
+    pub fn directory_by_path_icase(&self, path: &BStr, ignore_case: bool) -> Option<&'a BStr> {
+        let state = self.state;
+        if !ignore_case {
+            let mut prefix = BString::from(path);
+            prefix.push(b'/');
+            return state
+                .prefixed_entries(prefix.as_ref())
+                .first()
+                .map(|entry| entry.path(state)[..path.len()].as_bstr());
+        }
+        let (idx, dir_len) = self.directories.get(&fold(path))?;
+        Some(state.entries[*idx].path(state)[..*dir_len].as_bstr())
+    }
+}
+
+/// Fold `path` like git does for case-insensitive comparisons, which only considers ASCII characters.
+fn fold(path: &BStr) -> BString {
+    path.to_ascii_uppercase().into()
+}
@@ -9,8 +9,8 @@ mod from_tree {
     use gix_traverse::tree::{breadthfirst, visit::Action, Visit};
 
     use crate::{
-        entry::{Flags, Mode, Stat},
-        Entry, PathStorage, State, Version,
+        entry::{self, Flags, Mode, Stat},
+        extension, Entry, PathStorage, State, Version,
     };
 
     /// Initialization
@@ -29,12 +29,16 @@ mod from_tree {
                 resolve_undo: None,
                 untracked: None,
                 fs_monitor: None,
+                unknown_extensions: Vec::new(),
+                skipped_extensions: Vec::new(),
             }
         }
         /// Create an index [`State`][crate::State] by traversing `tree` recursively, accessing sub-trees
-        /// with `find`.
+        /// with `find`, similar to `git read-tree`.
         ///
-        /// **No extension data is currently produced**.
+        /// All entries are in stage 0 and have no stat information, with submodules becoming entries of
+        /// [`COMMIT`][Mode::COMMIT] mode. As all trees are known, the cache tree extension is produced as well,
+        /// but no other extension data.
         pub fn from_tree<Find>(tree: &gix_hash::oid, mut find: Find) -> Result<Self, breadthfirst::Error>
         where
             Find: for<'a> FnMut(&gix_hash::oid, &'a mut Vec<u8>) -> Option<TreeRefIter<'a>>,
@@ -48,11 +52,16 @@ mod from_tree {
             let CollectEntries {
                 mut entries,
                 path_backing,
+                mut trees,
                 path: _,
                 path_deque: _,
             } = delegate;
 
-            entries.sort_by(|a, b| Entry::cmp_filepaths(a.path_in(&path_backing), b.path_in(&path_backing)));
+            entries.sort_by(|a, b| {
+                entry::compare(a.path_in(&path_backing), a.stage(), b.path_in(&path_backing), b.stage())
+            });
+            trees.sort_by(|a, b| a.0.cmp(&b.0));
+            let tree_cache = cache_tree(tree.to_owned(), &trees, &entries, &path_backing);
 
             Ok(State {
                 object_hash: tree.kind(),
@@ -61,18 +70,66 @@ mod from_tree {
                 entries,
                 path_backing,
                 is_sparse: false,
-                tree: None,
+                tree: Some(tree_cache),
                 link: None,
                 resolve_undo: None,
                 untracked: None,
                 fs_monitor: None,
+                unknown_extensions: Vec::new(),
+                skipped_extensions: Vec::new(),
             })
         }
     }
 
+    /// Build the cache tree with `root_id` from all `trees` below it, which are sorted by path, knowing that `entries` are
+    /// sorted as well.
+    fn cache_tree(
+        root_id: gix_hash::ObjectId,
+        trees: &[(BString, gix_hash::ObjectId)],
+        entries: &[Entry],
+        path_backing: &PathStorage,
+    ) -> extension::Tree {
+        let num_entries_in = |dir: &BStr| {
+            let mut prefix = BString::from(dir);
+            prefix.push(b'/');
+            let start =
+                entries.partition_point(|e| Entry::cmp_filepaths(e.path_in(path_backing), prefix.as_bstr()).is_lt());
+            entries[start..].partition_point(|e| e.path_in(path_backing).starts_with(&prefix)) as u32
+        };
+        let mut root = extension::Tree {
+            name: Default::default(),
+            id: root_id,
+            num_entries: Some(entries.len() as u32),
+            children: Vec::new(),
+        };
+        for (path, id) in trees {
+            let mut components = path.split_str("/").peekable();
+            let mut node = &mut root;
+            while let Some(name) = components.next() {
+                if components.peek().is_none() {
+                    node.children.push(extension::Tree {
+                        name: name.into(),
+                        id: *id,
+                        num_entries: Some(num_entries_in(path.as_bstr())),
+                        children: Vec::new(),
+                    });
+                    break;
+                }
+                node = node
+                    .children
+                    .iter_mut()
+                    .find(|child| child.name.as_slice() == name)
+                    .expect("parents are sorted before their children");
+            }
+        }
+        root
+    }
+
     struct CollectEntries {
         entries: Vec<Entry>,
         path_backing: PathStorage,
+        /// The paths and ids of all trees below the root.
+        trees: Vec<(BString, gix_hash::ObjectId)>,
         path: BString,
         path_deque: VecDeque<BString>,
     }
@@ -82,6 +139,7 @@ mod from_tree {
             CollectEntries {
                 entries: Vec::new(),
                 path_backing: Vec::new(),
+                trees: Vec::new(),
                 path: BString::default(),
                 path_deque: VecDeque::new(),
             }
@@ -111,7 +169,7 @@ mod from_tree {
                 id: entry.oid.into(),
                 flags: Flags::empty(),
                 mode,
-                path: path_start..self.path_backing.len(),
+                path: crate::entry::stored_path_range(path_start..self.path_backing.len()),
             };
 
             self.entries.push(new_entry);
@@ -143,7 +201,8 @@ mod from_tree {
             }
         }
 
-        fn visit_tree(&mut self, _entry: &gix_object::tree::EntryRef<'_>) -> gix_traverse::tree::visit::Action {
+        fn visit_tree(&mut self, entry: &gix_object::tree::EntryRef<'_>) -> gix_traverse::tree::visit::Action {
+            self.trees.push((self.path.clone(), entry.oid.to_owned()));
             Action::Continue
         }
 
@@ -33,6 +33,21 @@ pub mod verify;
 ///
 pub mod write;
 
+///
+pub mod sparse;
+
+///
+pub mod icase;
+
+///
+pub mod edit;
+
+///
+pub mod conflict;
+
+///
+pub mod changes;
+
 /// All known versions of a git index file.
 #[derive(PartialEq, Eq, Debug, Hash, Ord, PartialOrd, Clone, Copy)]
 #[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
@@ -58,9 +73,13 @@ pub struct Entry {
     pub mode: entry::Mode,
     /// The range to lookup in the path backing to obtain the entry path relative to the repository.
     /// This costs additional memory but is probably worth it given that paths can stay in one big allocation.
-    path: Range<usize>,
+    /// It's stored as `u32` to keep entries small, which limits the path backing to 4GiB.
+    path: Range<u32>,
 }
 
+// Entries dominate the memory usage of large indices, so their size must not grow unnoticed.
+const _: () = assert!(std::mem::size_of::<Entry>() <= 72);
+
 /// An index file whose state was read from a file on disk.
 #[derive(Clone)]
 pub struct File {
@@ -70,6 +89,8 @@ pub struct File {
     pub(crate) path: PathBuf,
     /// The checksum of all bytes prior to the checksum itself.
     pub(crate) checksum: Option<gix_hash::ObjectId>,
+    /// The checksum of the shared index if this is a split index, whose entries were merged into ours.
+    pub(crate) shared_index_checksum: Option<gix_hash::ObjectId>,
 }
 
 /// The type to use and store paths to all entries.
@@ -81,6 +102,8 @@ pub type PathStorageRef = [u8];
 ///
 /// As opposed to a snapshot, it's meant to be altered and eventually be written back to disk or converted into a tree.
 /// We treat index and its state synonymous.
+///
+/// The paths of all entries are limited to 4GiB in total, and adding entries beyond that panics.
 #[derive(Clone)]
 pub struct State {
     /// The kind of object hash used when storing the underlying file.
@@ -91,7 +114,6 @@ pub struct State {
     ///
     /// Note that on platforms that only have a precisions of a second for this time, we will treat all entries with the
     /// same timestamp as this as potentially changed, checking more thoroughly if a change actually happened.
-    #[allow(dead_code)]
     timestamp: FileTime,
     version: Version,
     entries: Vec<Entry>,
@@ -106,6 +128,10 @@ pub struct State {
     resolve_undo: Option<extension::resolve_undo::Paths>,
     untracked: Option<extension::UntrackedCache>,
     fs_monitor: Option<extension::FsMonitor>,
+    /// Optional extensions we don't understand, in the order they were encountered.
+    unknown_extensions: Vec<extension::Unknown>,
+    /// Optional extensions that were present but not decoded, which is why they can't be written back.
+    skipped_extensions: Vec<extension::Signature>,
 }
 
 mod impls {
@@ -143,11 +169,38 @@ mod impls {
 pub(crate) mod util {
     use std::convert::TryInto;
 
+    /// Decode an offset-encoded variable integer as used by git, returning `None` if `data` ends prematurely
+    /// or if the number doesn't fit into 64 bits.
     #[inline]
     pub fn var_int(data: &[u8]) -> Option<(u64, &[u8])> {
-        let (num, consumed) = gix_features::decode::leb64_from_read(data).ok()?;
-        let data = &data[consumed..];
-        (num, data).into()
+        let mut bytes = data.iter();
+        let mut byte = *bytes.next()?;
+        let mut value = u64::from(byte & 0x7f);
+        while byte & 0x80 != 0 {
+            byte = *bytes.next()?;
+            value = value.checked_add(1)?;
+            if value.leading_zeros() < 7 {
+                return None;
+            }
+            value = (value << 7) | u64::from(byte & 0x7f);
+        }
+        let consumed = data.len() - bytes.as_slice().len();
+        Some((value, &data[consumed..]))
+    }
+
+    /// Encode `value` as offset-encoded variable integer as used by git, the inverse of [`var_int()`].
+    pub fn write_var_int(mut out: impl std::io::Write, mut value: u64) -> std::io::Result<()> {
+        let mut buf = [0u8; 10];
+        let mut pos = buf.len() - 1;
+        buf[pos] = (value & 0x7f) as u8;
+        value >>= 7;
+        while value != 0 {
+            value -= 1;
+            pos -= 1;
+            buf[pos] = 0x80 | (value & 0x7f) as u8;
+            value >>= 7;
+        }
+        out.write_all(&buf[pos..])
     }
 
     #[inline]
@@ -193,7 +246,7 @@ pub(crate) mod util {
 
 #[test]
 fn size_of_entry() {
-    assert_eq!(std::mem::size_of::<crate::Entry>(), 80);
+    assert_eq!(std::mem::size_of::<crate::Entry>(), 72);
 
     // the reason we have our own time is half the size.
     assert_eq!(std::mem::size_of::<crate::entry::Time>(), 8);
@@ -0,0 +1,318 @@
+use std::ops::Range;
+
+use bstr::{BStr, BString, ByteSlice, ByteVec};
+use gix_object::{tree::EntryMode, TreeRefIter};
+
+use crate::{entry, extension::Tree, Entry, State};
+
+///
+pub mod expand {
+    use bstr::BString;
+
+    /// The error returned by [State::expand()][crate::State::expand()].
+    #[derive(Debug, thiserror::Error)]
+    #[allow(missing_docs)]
+    pub enum Error {
+        #[error("The tree {oid} of sparse directory '{path}' could not be found")]
+        TreeNotFound { oid: gix_hash::ObjectId, path: BString },
+        #[error("The tree {oid} of sparse directory '{path}' could not be decoded")]
+        TreeDecode {
+            oid: gix_hash::ObjectId,
+            path: BString,
+            source: gix_object::decode::Error,
+        },
+        #[error("Sparse directory '{path}' is in conflict with stage {stage}")]
+        Conflict { path: BString, stage: crate::entry::Stage },
+        #[error("Entry '{path}' is inside of sparse directory '{sparse_dir}'")]
+        NestedEntry { sparse_dir: BString, path: BString },
+    }
+}
+
+///
+pub mod collapse {
+    /// The error returned by [State::collapse()][crate::State::collapse()].
+    #[derive(Debug, thiserror::Error)]
+    #[allow(missing_docs)]
+    pub enum Error {
+        #[error("A cache tree is required to know the trees of directories to collapse")]
+        MissingTree,
+    }
+}
+
+/// Sparse indices
+impl State {
+    /// Replace all sparse directory entries with the entries of their trees, which are obtained with `find`, turning a sparse
+    /// index into a full one. Expanded entries are marked with [`SKIP_WORKTREE`][entry::Flags::SKIP_WORKTREE] as they are
+    /// outside of the sparse checkout, and the cache tree is extended with the expanded trees.
+    ///
+    /// Sparse directories must not be in conflict, and no other entry may be inside of them. Otherwise an error is returned
+    /// and the index remains unchanged.
+    pub fn expand<Find>(&mut self, mut find: Find) -> Result<(), expand::Error>
+    where
+        Find: for<'a> FnMut(&gix_hash::oid, &'a mut Vec<u8>) -> Option<TreeRefIter<'a>>,
+    {
+        for (idx, entry) in self.entries.iter().enumerate().filter(|(_, e)| e.is_sparse_dir()) {
+            let path = entry.path(self);
+            if entry.stage() != 0 {
+                return Err(expand::Error::Conflict {
+                    path: path.into(),
+                    stage: entry.stage(),
+                });
+            }
+            // Entries are sorted, so everything inside of the sparse directory would directly follow it.
+            if let Some(next) = self
+                .entries
+                .get(idx + 1)
+                .filter(|next| next.path(self).starts_with(path))
+            {
+                return Err(expand::Error::NestedEntry {
+                    sparse_dir: path.into(),
+                    path: next.path(self).into(),
+                });
+            }
+        }
+
+        let mut path_backing = std::mem::take(&mut self.path_backing);
+        let path_backing_len = path_backing.len();
+        let mut entries = Vec::with_capacity(self.entries.len());
+        // The cache tree is only changed once all trees were found, so it remains unchanged on error.
+        let mut expanded_dirs = Vec::new();
+        for entry in &self.entries {
+            if !entry.is_sparse_dir() {
+                entries.push(entry.clone());
+                continue;
+            }
+            let mut dir: BString = path_backing[entry.path_range()].into();
+            let res = expand_tree(&entry.id, &mut dir, &mut find, &mut entries, &mut path_backing);
+            let (num_entries, children) = match res {
+                Ok(res) => res,
+                Err(err) => {
+                    path_backing.truncate(path_backing_len);
+                    self.path_backing = path_backing;
+                    return Err(err);
+                }
+            };
+            dir.pop();
+            expanded_dirs.push((dir, entry.id, num_entries, children));
+        }
+
+        let mut tree = self.tree.take();
+        for (dir, id, num_entries, children) in expanded_dirs {
+            let dir = dir.as_bstr();
+            tree = tree.filter(|tree| find_node(tree, dir).map_or(false, |node| node.id == id));
+            if let Some(tree) = tree.as_mut() {
+                replace_node(tree, dir, num_entries, children);
+            }
+        }
+
+        entries
+            .sort_by(|a, b| entry::compare(a.path_in(&path_backing), a.stage(), b.path_in(&path_backing), b.stage()));
+        self.entries = entries;
+        self.path_backing = path_backing;
+        self.tree = tree;
+        self.is_sparse = false;
+        Ok(())
+    }
+
+    /// [Expand][State::expand()] this index if it [is sparse][State::is_sparse()], using `find` to obtain the trees of its
+    /// sparse directories, and do nothing otherwise.
+    ///
+    /// Call it before mutations that would otherwise fail with a `SparseIndexNotExpanded` error, like
+    /// [upserting][State::upsert_entry()] an entry inside of a sparse directory.
+    pub fn ensure_full<Find>(&mut self, find: Find) -> Result<(), expand::Error>
+    where
+        Find: for<'a> FnMut(&gix_hash::oid, &'a mut Vec<u8>) -> Option<TreeRefIter<'a>>,
+    {
+        if self.is_sparse {
+            self.expand(find)?;
+        }
+        Ok(())
+    }
+
+    /// Replace all directories whose entries are all marked with [`SKIP_WORKTREE`][entry::Flags::SKIP_WORKTREE], i.e. which are
+    /// entirely outside of the sparse checkout, with a single sparse directory entry, turning this index into a sparse one.
+    ///
+    /// The ids of the directories are taken from the cache tree, which is why directories whose cache tree entry is invalid or
+    /// missing remain expanded.
+    pub fn collapse(&mut self) -> Result<(), collapse::Error> {
+        let mut tree = self.tree.take().ok_or(collapse::Error::MissingTree)?;
+        let mut dirs = Vec::new();
+        collapsible_dirs(&mut tree, &mut BString::default(), self, &mut dirs);
+        dirs.sort_by_key(|(_, _, range)| range.start);
+
+        let mut entries = Vec::with_capacity(self.entries.len());
+        let mut dirs = dirs.into_iter().peekable();
+        let mut idx = 0;
+        while idx < self.entries.len() {
+            match dirs.next_if(|(_, _, range)| range.start == idx) {
+                Some((path, id, range)) => {
+                    let start = self.path_backing.len();
+                    self.path_backing.push_str(&path);
+                    entries.push(Entry {
+                        stat: entry::Stat::default(),
+                        id,
+                        flags: entry::Flags::SKIP_WORKTREE | entry::Flags::EXTENDED,
+                        mode: entry::Mode::DIR,
+                        path: entry::stored_path_range(start..self.path_backing.len()),
+                    });
+                    idx = range.end;
+                }
+                None => {
+                    entries.push(self.entries[idx].clone());
+                    idx += 1;
+                }
+            }
+        }
+
+        self.is_sparse |= entries.iter().any(Entry::is_sparse_dir);
+        self.entries = entries;
+        self.tree = Some(tree);
+        Ok(())
+    }
+}
+
+impl State {
+    /// Return the path of the sparse directory that `path` is inside of, which doesn't include a sparse directory at `path`
+    /// itself, or `None` if there is none or if this index isn't sparse.
+    pub(crate) fn sparse_dir_containing(&self, path: &BStr) -> Option<&BStr> {
+        if !self.is_sparse {
+            return None;
+        }
+        path.iter()
+            .enumerate()
+            .filter(|(pos, byte)| **byte == b'/' && pos + 1 < path.len())
+            .find_map(|(pos, _)| {
+                let idx = self.entry_index_by_path(path[..=pos].as_bstr()).ok()?;
+                let entry = &self.entries[idx];
+                entry.is_sparse_dir().then(|| entry.path(self))
+            })
+    }
+}
+
+/// Add all entries of the tree with `id` at `dir`, which ends in a slash, to `entries`, and return the amount of added entries
+/// along with cache tree nodes for all trees within it.
+fn expand_tree<Find>(
+    id: &gix_hash::oid,
+    dir: &mut BString,
+    find: &mut Find,
+    entries: &mut Vec<Entry>,
+    path_backing: &mut Vec<u8>,
+) -> Result<(u32, Vec<Tree>), expand::Error>
+where
+    Find: for<'a> FnMut(&gix_hash::oid, &'a mut Vec<u8>) -> Option<TreeRefIter<'a>>,
+{
+    let mut buf = Vec::new();
+    let tree_entries = find(id, &mut buf)
+        .ok_or_else(|| expand::Error::TreeNotFound {
+            oid: id.to_owned(),
+            path: dir.clone(),
+        })?
+        .map(|entry| entry.map(|e| (BString::from(e.filename), e.mode, e.oid.to_owned())))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| expand::Error::TreeDecode {
+            oid: id.to_owned(),
+            path: dir.clone(),
+            source: err,
+        })?;
+
+    let mut num_entries = 0;
+    let mut children = Vec::new();
+    for (name, mode, oid) in tree_entries {
+        let dir_len = dir.len();
+        dir.push_str(&name);
+        let mode = match mode {
+            EntryMode::Tree => {
+                dir.push(b'/');
+                let (num_child_entries, grand_children) = expand_tree(&oid, dir, find, entries, path_backing)?;
+                dir.truncate(dir_len);
+                num_entries += num_child_entries;
+                children.push(Tree {
+                    name: name.as_slice().into(),
+                    id: oid,
+                    num_entries: Some(num_child_entries),
+                    children: grand_children,
+                });
+                continue;
+            }
+            EntryMode::Blob => entry::Mode::FILE,
+            EntryMode::BlobExecutable => entry::Mode::FILE_EXECUTABLE,
+            EntryMode::Link => entry::Mode::SYMLINK,
+            EntryMode::Commit => entry::Mode::COMMIT,
+        };
+        let start = path_backing.len();
+        path_backing.extend_from_slice(dir);
+        dir.truncate(dir_len);
+        entries.push(Entry {
+            stat: entry::Stat::default(),
+            id: oid,
+            flags: entry::Flags::SKIP_WORKTREE | entry::Flags::EXTENDED,
+            mode,
+            path: entry::stored_path_range(start..path_backing.len()),
+        });
+        num_entries += 1;
+    }
+    children.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok((num_entries, children))
+}
+
+fn find_node<'a>(mut node: &'a Tree, path: &BStr) -> Option<&'a Tree> {
+    for component in path.split_str("/") {
+        node = node.children.iter().find(|child| child.name.as_slice() == component)?;
+    }
+    Some(node)
+}
+
+/// Replace the node at `path` with one that has `num_entries` and `children`, and update the entry count of all its ancestors
+/// accordingly, knowing that the node was counted as a single entry before.
+fn replace_node(mut node: &mut Tree, path: &BStr, num_entries: u32, children: Vec<Tree>) {
+    for component in path.split_str("/") {
+        if let Some(count) = node.num_entries.as_mut() {
+            *count = *count + num_entries - 1;
+        }
+        node = node
+            .children
+            .iter_mut()
+            .find(|child| child.name.as_slice() == component)
+            .expect("presence checked by caller");
+    }
+    node.num_entries = Some(num_entries);
+    node.children = children;
+}
+
+/// Collect the path, tree id and range of entries for all directories below `node` at `prefix` that can be collapsed into a
+/// single sparse directory entry, and adjust the entry counts in `node` accordingly. Return the amount of entries that are
+/// going away.
+fn collapsible_dirs(
+    node: &mut Tree,
+    prefix: &mut BString,
+    state: &State,
+    out: &mut Vec<(BString, gix_hash::ObjectId, Range<usize>)>,
+) -> u32 {
+    let mut num_removed = 0;
+    for child in &mut node.children {
+        let prefix_len = prefix.len();
+        prefix.push_str(&child.name);
+        prefix.push(b'/');
+
+        let range = state.prefixed_entries_range(prefix.as_bstr());
+        let entries = &state.entries[range.clone()];
+        let is_collapsible = !entries.is_empty()
+            && child.num_entries == Some(entries.len() as u32)
+            && entries
+                .iter()
+                .all(|e| e.flags.contains(entry::Flags::SKIP_WORKTREE) && e.stage() == 0);
+        if is_collapsible {
+            num_removed += entries.len() as u32 - 1;
+            child.num_entries = Some(1);
+            child.children.clear();
+            out.push((prefix.clone(), child.id, range));
+        } else {
+            num_removed += collapsible_dirs(child, prefix, state, out);
+        }
+        prefix.truncate(prefix_len);
+    }
+    if let Some(count) = node.num_entries.as_mut() {
+        *count -= num_removed;
+    }
+    num_removed
+}
@@ -1,15 +1,17 @@
 use std::cmp::Ordering;
 
+use bstr::{BString, ByteSlice};
+
 use crate::State;
 
 ///
 pub mod entries {
     use bstr::BString;
 
-    /// The error returned by [State::verify_entries()][crate::State::verify_entries()].
+    /// A single violation of the invariants of entries, along with the index of the offending entry.
     #[derive(Debug, thiserror::Error)]
     #[allow(missing_docs)]
-    pub enum Error {
+    pub enum Violation {
         #[error("Entry '{current_path}' (stage = {current_stage}) at index {current_index} should order after prior entry '{previous_path}' (stage = {previous_stage})")]
         OutOfOrder {
             current_index: usize,
@@ -18,6 +20,81 @@ pub mod entries {
             previous_path: BString,
             previous_stage: u8,
         },
+        #[error("Entry '{path}' at index {index} is in stage {stage}, but the same path is also in stage 0")]
+        ConflictWithStage0 {
+            index: usize,
+            path: BString,
+            stage: crate::entry::Stage,
+        },
+        #[error("Entry at index {index} has an invalid path")]
+        InvalidPath {
+            index: usize,
+            source: crate::edit::upsert::Error,
+        },
+        #[error("Entry '{path}' at index {index} has the invalid mode {mode:o}")]
+        InvalidMode { index: usize, path: BString, mode: u32 },
+    }
+
+    /// The error returned by [State::verify_entries()][crate::State::verify_entries()].
+    #[derive(Debug, thiserror::Error)]
+    #[error("Found {} violation(s) of the invariants of entries, the first one being: {}", violations.len(), violations[0])]
+    pub struct Error {
+        /// All violations in the order of the entries they were found in, with at least one of them.
+        pub violations: Vec<Violation>,
+    }
+}
+
+///
+pub mod paths {
+    use bstr::BString;
+
+    /// Protections against paths that are dangerous to check out on certain filesystems, as used by
+    /// [State::verify_paths()][crate::State::verify_paths()].
+    ///
+    /// Note that git enables `protect_ntfs` by default, and `protect_hfs` by default on macOS.
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub struct Options {
+        /// If true, reject paths with components that NTFS considers to be `.git`, like `git~1` or `.git. `, and symlinks
+        /// it considers to be `.gitmodules`, like `core.protectNTFS`. Backslashes separate components as well.
+        pub protect_ntfs: bool,
+        /// If true, reject paths with components that HFS+ considers to be `.git` as it ignores certain Unicode code points,
+        /// and symlinks it considers to be `.gitmodules`, like `core.protectHFS`.
+        pub protect_hfs: bool,
+    }
+
+    /// The error returned by [State::verify_paths()][crate::State::verify_paths()].
+    #[derive(Debug, thiserror::Error)]
+    #[error("Entry '{path}' at index {index} has a path that is unsafe to check out")]
+    pub struct Error {
+        /// The index of the offending entry.
+        pub index: usize,
+        /// The path of the offending entry.
+        pub path: BString,
+        /// The reason the path is unsafe.
+        pub source: crate::edit::upsert::Error,
+    }
+}
+
+///
+pub mod tree {
+    use bstr::BString;
+
+    /// The error returned by [State::verify_tree()][crate::State::verify_tree()].
+    #[derive(Debug, thiserror::Error)]
+    #[allow(missing_docs)]
+    pub enum Error {
+        #[error("The cache tree at '{path}' claims to contain {declared} entries, but there are {actual} entries in that directory")]
+        EntriesCount {
+            path: BString,
+            declared: u32,
+            actual: usize,
+        },
+        #[error("The cache tree at '{path}' has children out of order or duplicate children, with '{previous}' being followed by '{current}'")]
+        ChildrenOutOfOrder {
+            path: BString,
+            previous: BString,
+            current: BString,
+        },
     }
 }
 
@@ -40,26 +117,120 @@ pub mod extensions {
 }
 
 impl State {
-    /// Assure our entries are consistent.
+    /// Assure our entries are consistent, similar to what `git fsck` checks, and return all violations if they aren't.
+    ///
+    /// Entries must be sorted by path and stage without duplicates, paths in stage 0 must not have entries in other stages,
+    /// paths must be relative and must not contain empty components, `.`, `..` or `.git`, symlinks must not be named
+    /// `.gitmodules`, and modes must be valid for trees.
     pub fn verify_entries(&self) -> Result<(), entries::Error> {
+        let mut violations = Vec::new();
         let mut previous = None::<&crate::Entry>;
         for (idx, entry) in self.entries.iter().enumerate() {
+            let path = entry.path(self);
             if let Some(prev) = previous {
                 if prev.cmp(entry, self) != Ordering::Less {
-                    return Err(entries::Error::OutOfOrder {
+                    violations.push(entries::Violation::OutOfOrder {
                         current_index: idx,
-                        current_path: entry.path(self).into(),
+                        current_path: path.into(),
                         current_stage: entry.flags.stage() as u8,
                         previous_path: prev.path(self).into(),
                         previous_stage: prev.flags.stage() as u8,
                     });
+                } else if prev.stage() == 0 && entry.stage() != 0 && prev.path(self) == path {
+                    violations.push(entries::Violation::ConflictWithStage0 {
+                        index: idx,
+                        path: path.into(),
+                        stage: entry.stage(),
+                    });
                 }
             }
+            if let Err(err) = crate::edit::validate_path(path, entry.mode, Default::default()) {
+                violations.push(entries::Violation::InvalidPath {
+                    index: idx,
+                    source: err,
+                });
+            }
+            if entry.mode.to_tree_entry_mode().is_none() {
+                violations.push(entries::Violation::InvalidMode {
+                    index: idx,
+                    path: path.into(),
+                    mode: entry.mode.bits(),
+                });
+            }
             previous = Some(entry);
         }
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(entries::Error { violations })
+        }
+    }
+
+    /// Assure the paths of all entries are safe to check out on filesystems that need the protections of `options`,
+    /// in addition to the checks of [`verify_entries()`][State::verify_entries()], and return the first offending path
+    /// otherwise.
+    ///
+    /// This allows to check out an index from an untrusted source without validating each path while doing so.
+    pub fn verify_paths(&self, options: paths::Options) -> Result<(), paths::Error> {
+        for (index, entry) in self.entries.iter().enumerate() {
+            let path = entry.path(self);
+            crate::edit::validate_path(path, entry.mode, options).map_err(|source| paths::Error {
+                index,
+                path: path.into(),
+                source,
+            })?;
+        }
         Ok(())
     }
 
+    /// Assure the cache tree extension is consistent with our entries, if it is present, or return the first inconsistent
+    /// node in depth-first order.
+    ///
+    /// The entry count of each valid node must match the amount of entries in its directory, and the children of each node
+    /// must be sorted by name without duplicates. Invalidated nodes are exempt from the entry count check as they are expected
+    /// to be out of date.
+    pub fn verify_tree(&self) -> Result<(), tree::Error> {
+        fn verify_recursive(
+            state: &State,
+            node: &crate::extension::Tree,
+            prefix: &mut BString,
+        ) -> Result<(), tree::Error> {
+            let dir = || BString::from(prefix.strip_suffix(b"/").unwrap_or(prefix.as_slice()));
+            if let Some(declared) = node.num_entries {
+                let actual = state.prefixed_entries_range(prefix.as_bstr()).len();
+                if declared as usize != actual {
+                    return Err(tree::Error::EntriesCount {
+                        path: dir(),
+                        declared,
+                        actual,
+                    });
+                }
+            }
+            for (previous, current) in node.children.iter().zip(node.children.iter().skip(1)) {
+                if previous.name >= current.name {
+                    return Err(tree::Error::ChildrenOutOfOrder {
+                        path: dir(),
+                        previous: previous.name.as_bstr().into(),
+                        current: current.name.as_bstr().into(),
+                    });
+                }
+            }
+            for child in &node.children {
+                let prefix_len = prefix.len();
+                prefix.extend_from_slice(&child.name);
+                prefix.push(b'/');
+                verify_recursive(state, child, prefix)?;
+                prefix.truncate(prefix_len);
+            }
+            Ok(())
+        }
+
+        match self.tree() {
+            Some(root) => verify_recursive(self, root, &mut BString::default()),
+            None => Ok(()),
+        }
+    }
+
     /// Note: `find` cannot be `Option<F>` as we can't call it with a closure then due to the indirection through `Some`.
     pub fn verify_extensions<F>(&self, use_find: bool, find: F) -> Result<(), extensions::Error>
     where
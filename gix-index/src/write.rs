@@ -1,7 +1,30 @@
 use std::{convert::TryInto, io::Write};
 
+use bstr::{BStr, BString, ByteSlice};
+
 use crate::{entry, extension, write::util::CountBytes, State, Version};
 
+mod error {
+    use bstr::BString;
+
+    use crate::Version;
+
+    /// The error returned by [State::write_to()][crate::State::write_to()].
+    #[derive(Debug, thiserror::Error)]
+    #[allow(missing_docs)]
+    pub enum Error {
+        #[error(transparent)]
+        Io(#[from] std::io::Error),
+        #[error("Entry '{path}' has extended flags which can't be stored in an index of version {version:?}")]
+        ExtendedFlagsNotSupported { path: BString, version: Version },
+        #[error("The link extension can't be omitted as the index lacks the entries of the shared index it refers to")]
+        LinkExtensionRequired,
+        #[error("The '{signature}' extension was skipped when decoding the index and can't be written back")]
+        SkippedExtension { signature: BString },
+    }
+}
+pub use error::Error;
+
 /// A way to specify which of the optional extensions to write.
 #[derive(Debug, Copy, Clone)]
 pub enum Extensions {
@@ -11,15 +34,32 @@ pub enum Extensions {
     ///
     /// # Note: mandatory extensions
     ///
-    /// Mandatory extensions, like `sdir` or other lower-case ones, may not be configured here as they need to be present
-    /// or absent depending on the state of the index itself and for it to be valid.
+    /// Mandatory extensions, like `sdir`, may not be configured here as they need to be present or absent depending on
+    /// the state of the index itself and for it to be valid.
+    /// The `link` extension of a split index is the exception, but as the index doesn't contain the entries of the shared
+    /// index, it can only be omitted if there is none, i.e. if the index was [read from a file][crate::File::at()]
+    /// which merges the shared entries. Otherwise writing fails.
     Given {
+        /// Write the link extension, if present.
+        link: bool,
         /// Write the tree-cache extension, if present.
         tree_cache: bool,
+        /// Write the resolve-undo extension, if present.
+        resolve_undo: bool,
+        /// Write the untracked cache extension, if present.
+        untracked_cache: bool,
+        /// Write the filesystem monitor extension, if present.
+        fs_monitor: bool,
+        /// Write the index entry offset table extension, if there are enough entries for it to be useful.
+        entry_offset_table: bool,
         /// Write the end-of-index-entry extension.
         end_of_index_entry: bool,
+        /// Write all optional extensions that aren't understood as they were read, if present.
+        unknown: bool,
     },
-    /// Write no optional extension at all for what should be the smallest possible index
+    /// Write no optional extension at all for what should be the smallest possible index.
+    ///
+    /// Just like with [`Given`][Extensions::Given], this fails if there is a `link` extension.
     None,
 }
 
@@ -36,12 +76,23 @@ impl Extensions {
             Extensions::None => None,
             Extensions::All => Some(signature),
             Extensions::Given {
+                link,
                 tree_cache,
+                resolve_undo,
+                untracked_cache,
+                fs_monitor,
+                entry_offset_table,
                 end_of_index_entry,
+                unknown,
             } => match signature {
+                extension::link::SIGNATURE => link,
                 extension::tree::SIGNATURE => tree_cache,
+                extension::resolve_undo::SIGNATURE => resolve_undo,
+                extension::untracked_cache::SIGNATURE => untracked_cache,
+                extension::fs_monitor::SIGNATURE => fs_monitor,
+                extension::index_entry_offset_table::SIGNATURE => entry_offset_table,
                 extension::end_of_index_entry::SIGNATURE => end_of_index_entry,
-                _ => &false,
+                _ => unknown,
             }
             .then(|| signature),
         }
@@ -50,17 +101,70 @@ impl Extensions {
 
 /// The options for use when [writing an index][State::write_to()].
 ///
-/// Note that default options write either index V2 or V3 depending on the content of the entries.
+/// Note that default options write either index V2 or V3 depending on the content of the entries, unless the index
+/// was V4 before.
 #[derive(Debug, Default, Clone, Copy)]
 pub struct Options {
     /// Configures which extensions to write
     pub extensions: Extensions,
+    /// The version to write, or `None` to write V4 if the index was V4 before, or the lowest version that can represent
+    /// all entries otherwise, V2 or V3, just like git does.
+    ///
+    /// V4 compresses each path relative to the path of the previous entry, which makes indices with many deeply nested
+    /// paths much smaller.
+    pub version: Option<Version>,
+    /// The amount of blocks to split entries into for the index entry offset table extension, which allows readers to decode
+    /// blocks of entries in parallel, similar to `index.threads` in git.
+    ///
+    /// If `None`, use a block for every 10.000 entries, but no more blocks than there are cores minus one, just like git does.
+    /// The extension isn't written if there would be less than two blocks.
+    pub entry_offset_table_blocks: Option<usize>,
 }
 
 impl State {
-    /// Serialize this instance to `out` with [`options`][Options].
-    pub fn write_to(&self, out: impl std::io::Write, Options { extensions }: Options) -> std::io::Result<Version> {
-        let version = self.detect_required_version();
+    /// Serialize this instance to `out` with [`options`][Options], returning the version that was actually written.
+    ///
+    /// If the version to write is V2 but entries have extended flags, like [`SKIP_WORKTREE`][entry::Flags::SKIP_WORKTREE],
+    /// an error is returned instead of silently dropping them. The same is true if the `link` extension isn't supposed to be
+    /// written even though there is one, as the index would lack the entries of the shared index.
+    /// Extensions that were [skipped when decoding][State::skipped_extensions()] can't be written either, and have to be
+    /// excluded via [`Options::extensions`] to avoid losing them without notice.
+    pub fn write_to(
+        &self,
+        out: impl std::io::Write,
+        Options {
+            extensions,
+            version,
+            entry_offset_table_blocks,
+        }: Options,
+    ) -> Result<Version, Error> {
+        if self.link.is_some() && extensions.should_write(extension::link::SIGNATURE).is_none() {
+            return Err(Error::LinkExtensionRequired);
+        }
+        if let Some(signature) = self
+            .skipped_extensions
+            .iter()
+            .find(|signature| extensions.should_write(**signature).is_some())
+        {
+            return Err(Error::SkippedExtension {
+                signature: signature.as_bstr().to_owned(),
+            });
+        }
+        let version = match version {
+            Some(Version::V2) => {
+                if let Some(entry) = self.entries_with_extended_flags().next() {
+                    return Err(Error::ExtendedFlagsNotSupported {
+                        path: entry.path(self).to_owned(),
+                        version: Version::V2,
+                    });
+                }
+                Version::V2
+            }
+            Some(Version::V3) => Version::V3,
+            Some(Version::V4) => Version::V4,
+            None if self.version == Version::V4 => Version::V4,
+            None => self.detect_required_version(),
+        };
 
         let mut write = CountBytes::new(out);
         let num_entries: u32 = self
@@ -76,9 +180,21 @@ impl State {
             .try_into()
             .expect("definitely not too many entries");
 
+        let entries_per_block = extensions
+            .should_write(extension::index_entry_offset_table::SIGNATURE)
+            .and_then(|_| {
+                let num_entries = num_entries as usize;
+                let num_blocks = match entry_offset_table_blocks {
+                    Some(num_blocks) => num_blocks.min(num_entries),
+                    None => (num_entries / 10_000).min(gix_features::parallel::num_threads(None).saturating_sub(1)),
+                };
+                (num_blocks > 1).then(|| (num_entries + num_blocks - 1) / num_blocks)
+            });
+
         let offset_to_entries = header(&mut write, version, num_entries - removed_entries)?;
-        let offset_to_extensions = entries(&mut write, self, offset_to_entries)?;
-        let (extension_toc, out) = self.write_extensions(write, offset_to_extensions, extensions)?;
+        let (offset_to_extensions, entry_offsets) =
+            entries(&mut write, self, version, offset_to_entries, entries_per_block)?;
+        let (extension_toc, out) = self.write_extensions(write, offset_to_extensions, &entry_offsets, extensions)?;
 
         if num_entries > 0
             && extensions
@@ -96,18 +212,58 @@ impl State {
         &self,
         mut write: CountBytes<T>,
         offset_to_extensions: u32,
+        entry_offsets: &[extension::index_entry_offset_table::Offset],
         extensions: Extensions,
     ) -> std::io::Result<(Vec<(extension::Signature, u32)>, T)>
     where
         T: std::io::Write,
     {
         type WriteExtFn<'a> = &'a dyn Fn(&mut dyn std::io::Write) -> Option<std::io::Result<extension::Signature>>;
-        let extensions: &[WriteExtFn<'_>] = &[
+        let write_fns: &[WriteExtFn<'_>] = &[
+            &|write| {
+                (!entry_offsets.is_empty()).then(|| {
+                    extension::index_entry_offset_table::write_to(entry_offsets, write)
+                        .map(|_| extension::index_entry_offset_table::SIGNATURE)
+                })
+            },
+            &|write| {
+                extensions
+                    .should_write(extension::link::SIGNATURE)
+                    .and_then(|signature| self.link().map(|link| link.write_to(write).map(|_| signature)))
+            },
             &|write| {
                 extensions
                     .should_write(extension::tree::SIGNATURE)
                     .and_then(|signature| self.tree().map(|tree| tree.write_to(write).map(|_| signature)))
             },
+            &|write| {
+                extensions
+                    .should_write(extension::resolve_undo::SIGNATURE)
+                    .and_then(|signature| {
+                        self.resolve_undo
+                            .as_ref()
+                            .map(|paths| extension::resolve_undo::write_to(paths, write).map(|_| signature))
+                    })
+            },
+            &|write| {
+                extensions
+                    .should_write(extension::untracked_cache::SIGNATURE)
+                    .and_then(|signature| {
+                        self.untracked()
+                            .map(|untracked| untracked.write_to(write).map(|_| signature))
+                    })
+            },
+            &|write| {
+                // The bitmap refers to entries by index, which won't be valid anymore once entries are removed.
+                let entries_are_removed = self.entries.iter().any(|e| e.flags.contains(entry::Flags::REMOVE));
+                extensions
+                    .should_write(extension::fs_monitor::SIGNATURE)
+                    .filter(|_| !entries_are_removed)
+                    .and_then(|signature| {
+                        self.fs_monitor()
+                            .map(|fs_monitor| extension::fs_monitor::write_to(fs_monitor, write).map(|_| signature))
+                    })
+            },
             &|write| {
                 self.is_sparse()
                     .then(|| extension::sparse::write_to(write).map(|_| extension::sparse::SIGNATURE))
@@ -116,7 +272,7 @@ impl State {
 
         let mut offset_to_previous_ext = offset_to_extensions;
         let mut out = Vec::with_capacity(5);
-        for write_ext in extensions {
+        for write_ext in write_fns {
             if let Some(signature) = write_ext(&mut write).transpose()? {
                 let offset_past_ext = write.count;
                 let ext_size = offset_past_ext - offset_to_previous_ext - (extension::MIN_SIZE as u32);
@@ -124,16 +280,85 @@ impl State {
                 out.push((signature, ext_size));
             }
         }
+        for unknown in &self.unknown_extensions {
+            if extensions.should_write(unknown.signature).is_none() {
+                continue;
+            }
+            let ext_size: u32 = unknown.data.len().try_into().expect("extensions are smaller than 4GB");
+            write.write_all(&unknown.signature)?;
+            write.write_all(&ext_size.to_be_bytes())?;
+            write.write_all(&unknown.data)?;
+            out.push((unknown.signature, ext_size));
+        }
         Ok((out, write.inner))
     }
 }
 
+/// Racily clean entries
+impl State {
+    /// Return `true` if `entry` is racily clean, i.e. if its file was modified at or after the [time][State::timestamp()]
+    /// this state was read. Its stat information can then not tell if the file was modified again after it was recorded,
+    /// so its content needs to be compared to know if it changed.
+    ///
+    /// Like git built with `USE_NSEC`, nanoseconds are compared as well if both times have them. Otherwise, all entries
+    /// modified in the same second as the index are racy.
+    /// Submodules are never racy, and neither are entries of states without a timestamp.
+    pub fn is_entry_racy(&self, entry: &crate::Entry) -> bool {
+        let secs = self.timestamp.unix_seconds();
+        if secs == 0 || entry.mode == entry::Mode::COMMIT {
+            return false;
+        }
+        let mtime = &entry.stat.mtime;
+        let nsecs = self.timestamp.nanoseconds();
+        match secs.cmp(&i64::from(mtime.secs)) {
+            std::cmp::Ordering::Less => true,
+            std::cmp::Ordering::Equal => nsecs == 0 || mtime.nsecs == 0 || nsecs <= mtime.nsecs,
+            std::cmp::Ordering::Greater => false,
+        }
+    }
+
+    /// Compare the content of all [racily clean][State::is_entry_racy()] entries by obtaining the id of what's currently in the
+    /// worktree with `current_id(path, entry)`, which returns `None` if it can't be determined, for instance if the file
+    /// doesn't exist anymore.
+    ///
+    /// Entries whose content is unchanged are marked [up-to-date][entry::Flags::UPTODATE] and will be written as is, while the
+    /// size of all others is set to 0. Without calling this method, [`write_to()`][State::write_to()] sets the size of all
+    /// racily clean entries that aren't up-to-date to 0, so their content is compared the next time they are used.
+    pub fn check_racy_entries(
+        &mut self,
+        mut current_id: impl FnMut(&BStr, &crate::Entry) -> Option<gix_hash::ObjectId>,
+    ) {
+        let racy_entries: Vec<_> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, e)| (self.is_entry_racy(e) && !e.flags.contains(entry::Flags::UPTODATE)).then_some(idx))
+            .collect();
+        for idx in racy_entries {
+            let entry = &self.entries[idx];
+            let is_unchanged = current_id(entry.path(self), entry) == Some(entry.id);
+            let entry = &mut self.entries[idx];
+            if is_unchanged {
+                entry.flags.insert(entry::Flags::UPTODATE);
+            } else {
+                entry.stat.size = 0;
+            }
+        }
+    }
+}
+
 impl State {
     fn detect_required_version(&self) -> Version {
+        match self.entries_with_extended_flags().next() {
+            Some(_) => Version::V3,
+            None => Version::V2,
+        }
+    }
+
+    fn entries_with_extended_flags(&self) -> impl Iterator<Item = &crate::Entry> {
         self.entries
             .iter()
-            .find_map(|e| e.flags.contains(entry::Flags::EXTENDED).then_some(Version::V3))
-            .unwrap_or(Version::V2)
+            .filter(|e| !e.flags.contains(entry::Flags::REMOVE) && e.flags.needs_extended_storage())
     }
 }
 
@@ -155,11 +380,56 @@ fn header<T: std::io::Write>(
     Ok(out.count)
 }
 
-fn entries<T: std::io::Write>(out: &mut CountBytes<T>, state: &State, header_size: u32) -> Result<u32, std::io::Error> {
-    for entry in state.entries() {
+/// Write all entries and return the offset past them, along with the offsets of blocks of `entries_per_block` entries
+/// if set.
+fn entries<T: std::io::Write>(
+    out: &mut CountBytes<T>,
+    state: &State,
+    version: Version,
+    header_size: u32,
+    entries_per_block: Option<usize>,
+) -> Result<(u32, Vec<extension::index_entry_offset_table::Offset>), std::io::Error> {
+    let mut previous_path = BString::default();
+    let mut offsets = Vec::new();
+    let mut block = extension::index_entry_offset_table::Offset {
+        from_beginning_of_file: header_size,
+        num_entries: 0,
+    };
+    for (idx, entry) in state.entries().iter().enumerate() {
         if entry.flags.contains(entry::Flags::REMOVE) {
             continue;
         }
+        if entries_per_block.map_or(false, |n| idx != 0 && idx % n == 0) {
+            offsets.push(block);
+            block = extension::index_entry_offset_table::Offset {
+                from_beginning_of_file: out.count,
+                num_entries: 0,
+            };
+            // Like git, assure the first path of the block doesn't share a prefix with the previous one, so blocks can be
+            // decoded independently.
+            if let Some(first_byte) = previous_path.first_mut() {
+                *first_byte = 0;
+            }
+        }
+        block.num_entries += 1;
+        let smudged;
+        let entry = if state.is_entry_racy(entry) && !entry.flags.contains(entry::Flags::UPTODATE) {
+            // Like git, make sure the entry's content is compared next time as its stat information can't be trusted.
+            smudged = crate::Entry {
+                stat: entry::Stat { size: 0, ..entry.stat },
+                ..entry.clone()
+            };
+            &smudged
+        } else {
+            entry
+        };
+        if version == Version::V4 {
+            // Paths are compressed relative to the previously written path, and entries aren't padded.
+            entry.write_v4_to(&mut *out, state, previous_path.as_ref())?;
+            previous_path.clear();
+            previous_path.extend_from_slice(entry.path(state));
+            continue;
+        }
         entry.write_to(&mut *out, state)?;
         match (out.count - header_size) % 8 {
             0 => {}
@@ -169,8 +439,11 @@ fn entries<T: std::io::Write>(out: &mut CountBytes<T>, state: &State, header_siz
             }
         };
     }
+    if entries_per_block.is_some() && block.num_entries != 0 {
+        offsets.push(block);
+    }
 
-    Ok(out.count)
+    Ok((out.count, offsets))
 }
 
 mod util {
@@ -0,0 +1,14 @@
+#!/bin/bash
+set -eu -o pipefail
+
+export GIT_INDEX_VERSION=2
+git init -q
+git config index.threads 1
+
+for path in a-b/x a/x a/b/y a/b/c/z a/d/z c; do
+  mkdir -p "$(dirname "$path")"
+  echo content > "$path"
+done
+
+git add .
+git commit -q -m "commit writes the cache tree"
@@ -0,0 +1,29 @@
+#!/bin/bash
+set -eu -o pipefail
+
+git init -q
+
+echo base > both-modified
+echo base > deleted-by-them
+echo base > deleted-by-us
+echo base > unchanged
+git add .
+git commit -q -m "base"
+
+git checkout -q -b theirs
+echo theirs > both-modified
+git rm -q deleted-by-them
+echo theirs > deleted-by-us
+echo theirs > added-by-both
+git add .
+git commit -q -m "theirs"
+
+git checkout -q -b ours HEAD~1
+echo ours > both-modified
+echo ours > deleted-by-them
+git rm -q deleted-by-us
+echo ours > added-by-both
+git add .
+git commit -q -m "ours"
+
+git merge theirs || true
@@ -0,0 +1,20 @@
+#!/bin/bash
+set -eu -o pipefail
+
+export GIT_INDEX_VERSION=2
+git init -q
+git config index.threads 1
+
+echo base > file
+git add file
+git commit -q -m "base"
+
+git checkout -q -b theirs
+echo theirs > file
+git commit -q -am "theirs"
+
+git checkout -q main
+echo ours > file
+git commit -q -am "ours"
+
+git merge -q theirs || true
@@ -0,0 +1,16 @@
+#!/bin/bash
+set -eu -o pipefail
+
+export GIT_INDEX_VERSION=2
+git init -q
+git config index.threads 1
+
+touch a b
+mkdir -p d/e/f d/g h
+touch d/a d/e/a d/e/f/a d/e/f/b d/g/a h/a
+
+git add .
+git commit -q -m "initial"
+
+git rev-parse @^{tree} > tree-id
+git ls-tree -r -d @ > ls-tree-output
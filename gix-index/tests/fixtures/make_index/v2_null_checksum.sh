@@ -0,0 +1,18 @@
+#!/bin/bash
+set -eu -o pipefail
+
+git init -q
+
+touch a b
+mkdir d
+touch d/a
+
+git add .
+git commit -q -m "init"
+
+# Replace the trailing checksum with zeroes, like tools do that don't checksum the index.
+index=.git/index
+size=$(wc -c < $index)
+head -c $((size - 20)) $index > $index.tmp
+head -c 20 /dev/zero >> $index.tmp
+mv $index.tmp $index
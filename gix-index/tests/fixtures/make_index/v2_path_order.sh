@@ -0,0 +1,18 @@
+#!/bin/bash
+set -eu -o pipefail
+
+export GIT_INDEX_VERSION=2
+git init -q
+git config index.threads 1
+
+blob=$(printf content | git hash-object -w --stdin)
+# Paths with prefixes of each other and non-ASCII bytes, where single-letter paths are conflicted.
+for path in a a-b a.b a/b a/b-c a/b.c a/bc a0 ab b z $'\xc3\xa4' $'\xc3\xa4b' $'\xc3\xb6'; do
+  case "$path" in
+    ?) stages="1 2 3";;
+    *) stages="0";;
+  esac
+  for stage in $stages; do
+    printf '100644 %s %s\t%s\n' "$blob" "$stage" "$path"
+  done
+done | git update-index --index-info
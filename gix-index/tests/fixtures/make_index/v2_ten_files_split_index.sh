@@ -0,0 +1,19 @@
+#!/bin/bash
+set -eu -o pipefail
+
+export GIT_INDEX_VERSION=2
+git init -q
+git config index.threads 1
+
+mkdir d
+for idx in 0 1 2 3 4; do
+  printf "content $idx" > "f$idx"
+done
+for idx in 5 6 7 8 9; do
+  printf "content $idx" > "d/f$idx"
+done
+
+git add .
+git commit -q -m "files"
+
+git update-index --split-index
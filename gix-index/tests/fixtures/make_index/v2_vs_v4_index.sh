@@ -0,0 +1,20 @@
+#!/bin/bash
+
+# Creates an index with entries whose paths share varying prefixes with their
+# predecessor, and writes it once as V2 and once as V4.
+
+set -eu -o pipefail
+
+export GIT_INDEX_VERSION=2
+git init -q
+
+mkdir -p dir/sub other/deeply/nested
+touch a aa ab dir/a dir/sub/file other/deeply/nested/file zzz
+echo content > other/deeply/file
+
+git add .
+git commit -q -m "initial"
+
+cp .git/index v2-index
+git update-index --index-version 4
+cp .git/index v4-index
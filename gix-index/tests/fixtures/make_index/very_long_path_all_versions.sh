@@ -0,0 +1,23 @@
+#!/bin/bash
+
+# Creates indices with an entry whose path is longer than the 0xfff bytes the entry flags can represent,
+# once as V2, V3 and V4. The path is too long for the filesystem, so it's only added to the index.
+
+set -eu -o pipefail
+
+git init -q
+
+blob=$(echo content | git hash-object -w --stdin)
+long_path="$(printf 'd%.0s/' {1..100})$(printf 'f%.0s' {1..4800})"
+
+git update-index --index-version 2 --add --cacheinfo 100644,"$blob",a
+git update-index --add --cacheinfo 100644,"$blob","$long_path"
+git update-index --add --cacheinfo 100644,"$blob",z
+cp .git/index v2-index
+
+git update-index --index-version 3 --skip-worktree z
+cp .git/index v3-index
+
+git update-index --no-skip-worktree z
+git update-index --index-version 4
+cp .git/index v4-index
@@ -14,6 +14,153 @@ fn entry_by_path_and_stage() {
     }
 }
 
+#[test]
+fn entry_index_by_path() {
+    let file = Fixture::Loose("conflicting-file").open();
+    let idx = file.entry_index_by_path("file".into()).expect("present");
+    assert_eq!(
+        file.entries()[idx..].iter().map(|e| e.stage()).collect::<Vec<_>>(),
+        [1, 2, 3],
+        "the index of the first stage is returned, with all other stages following it"
+    );
+
+    let file = Fixture::Generated("v4_more_files_IEOT").open();
+    for (idx, entry) in file.entries().iter().enumerate() {
+        assert_eq!(file.entry_index_by_path(entry.path(&file)), Ok(idx));
+    }
+    for (path, insertion_idx) in [
+        ("0", 0),
+        ("aa", 1),
+        ("d/last", 6),
+        ("d/last/", 6),
+        ("y", file.entries().len()),
+    ] {
+        assert_eq!(
+            file.entry_index_by_path(path.into()),
+            Err(insertion_idx),
+            "{path} would be inserted at {insertion_idx}"
+        );
+    }
+}
+
+#[test]
+fn prefixed_entries() {
+    let file = Fixture::Generated("v4_more_files_IEOT").open();
+    let paths = |prefix: &str| {
+        file.prefixed_entries(prefix.into())
+            .iter()
+            .map(|e| e.path(&file).to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        paths("d/"),
+        ["d/a", "d/b", "d/c", "d/last/123", "d/last/34", "d/last/6"]
+    );
+    assert_eq!(paths("d/last/"), ["d/last/123", "d/last/34", "d/last/6"]);
+    assert_eq!(paths("d/last/3"), ["d/last/34"]);
+    assert_eq!(paths("x"), ["x"], "prefixes may match entire paths");
+    assert_eq!(
+        paths("").len(),
+        file.entries().len(),
+        "the empty prefix matches everything"
+    );
+    assert!(paths("d/e").is_empty());
+    assert!(paths("y").is_empty(), "prefixes past the last entry match nothing");
+}
+
+#[test]
+fn prefixed_entries_range() {
+    let mut state = gix_index::State::new(gix_hash::Kind::Sha1);
+    for (path, stage) in [
+        ("src!a", 0),
+        ("src", 0),
+        ("src-other/x", 0),
+        ("src/a", 0),
+        ("src/b", 1),
+        ("src/b", 3),
+        ("src/b", 2),
+        ("src/c/d", 0),
+        ("z", 0),
+    ] {
+        state.dangerously_push_entry(
+            gix_index::entry::Stat::default(),
+            gix_hash::Kind::Sha1.null(),
+            gix_index::entry::Flags::from_bits_truncate(stage << 12),
+            gix_index::entry::Mode::FILE,
+            path.into(),
+        );
+    }
+    state.sort_entries();
+    let paths = |prefix: &str| {
+        state
+            .prefixed_entries_with_paths(prefix.into())
+            .map(|(path, entry)| format!("{path}:{}", entry.stage()))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(state.prefixed_entries_range("src/".into()), 3..8);
+    assert_eq!(
+        paths("src/"),
+        ["src/a:0", "src/b:1", "src/b:2", "src/b:3", "src/c/d:0"],
+        "all stages are contained, and similarly named siblings are excluded"
+    );
+    assert_eq!(
+        paths("src"),
+        [
+            "src:0",
+            "src!a:0",
+            "src-other/x:0",
+            "src/a:0",
+            "src/b:1",
+            "src/b:2",
+            "src/b:3",
+            "src/c/d:0"
+        ],
+        "without slash, it's a plain prefix which also matches the entry itself"
+    );
+    assert_eq!(paths("src/b"), ["src/b:1", "src/b:2", "src/b:3"]);
+    assert_eq!(state.prefixed_entries_range("".into()), 0..state.entries().len());
+    assert_eq!(state.prefixed_entries_range("zz".into()), 9..9, "past the last entry");
+    assert_eq!(state.prefixed_entries_range("a".into()), 0..0, "before the first entry");
+    assert_eq!(state.prefixed_entries_range("src/ba".into()), 7..7);
+}
+
+#[test]
+fn compact_path_backing() {
+    let mut file = Fixture::Generated("v4_more_files_IEOT").open();
+    let paths = |file: &gix_index::File| {
+        file.entries()
+            .iter()
+            .map(|e| e.path(file).to_string())
+            .collect::<Vec<_>>()
+    };
+    for path in ["a", "d/last/123", "d/last/34", "x"] {
+        file.remove_entry(path.into(), 0).expect("present");
+    }
+    let expected = paths(&file);
+    let num_path_bytes = expected.iter().map(String::len).sum::<usize>();
+    assert!(
+        file.path_backing().len() > num_path_bytes,
+        "removed paths are still stored"
+    );
+
+    file.compact_path_backing();
+    assert_eq!(paths(&file), expected);
+    assert_eq!(file.path_backing().len(), num_path_bytes);
+    assert_eq!(
+        file.path_backing(),
+        expected.concat().as_bytes(),
+        "paths are stored in order"
+    );
+    assert!(file.entry_by_path_and_stage("d/last/6".into(), 0).is_some());
+
+    let mut file = Fixture::Loose("conflicting-file").open();
+    file.compact_path_backing();
+    assert_eq!(file.path_backing(), b"file", "stages of a conflict share their path");
+    assert_eq!(file.entries().len(), 3);
+    assert!(file.entries().iter().all(|e| e.path(&file) == "file"));
+}
+
 #[test]
 fn sort_entries() {
     let mut file = Fixture::Generated("v4_more_files_IEOT").open();
@@ -49,3 +196,428 @@ fn sort_entries() {
         "we can find the correct entry now"
     );
 }
+
+mod sparse {
+    use bstr::ByteSlice;
+    use gix::prelude::FindExt;
+    use gix_index::{entry, verify::extensions::no_find};
+
+    use crate::index::{fixture_worktree_path, Fixture};
+
+    fn open_with_repo(name: &'static str) -> (gix_index::File, gix::Repository) {
+        (
+            Fixture::Generated(name).open(),
+            gix::open(fixture_worktree_path(name)).expect("valid repo"),
+        )
+    }
+
+    fn paths_and_flags(state: &gix_index::State) -> Vec<(String, gix_hash::ObjectId, entry::Mode, bool)> {
+        state
+            .entries()
+            .iter()
+            .map(|e| {
+                (
+                    e.path(state).to_str_lossy().into_owned(),
+                    e.id,
+                    e.mode,
+                    e.flags.contains(entry::Flags::SKIP_WORKTREE),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn expand_turns_a_sparse_index_into_a_full_one() {
+        let (mut file, repo) = open_with_repo("v3_sparse_index");
+        assert!(file.is_sparse());
+        file.expand(|oid, buf| repo.objects.find_tree_iter(oid, buf).ok())
+            .expect("all trees are available");
+
+        let expected = Fixture::Generated("v3_skip_worktree").open();
+        assert!(!file.is_sparse());
+        assert_eq!(paths_and_flags(&file), paths_and_flags(&expected));
+        assert_eq!(file.tree(), expected.tree(), "the cache tree is expanded as well");
+        file.verify_entries().expect("entries are sorted");
+        file.verify_extensions(true, |oid, buf| repo.objects.find_tree_iter(oid, buf).ok())
+            .expect("the cache tree is valid");
+    }
+
+    #[test]
+    fn collapse_turns_a_full_index_into_a_sparse_one() {
+        let mut file = Fixture::Generated("v3_skip_worktree").open();
+        assert!(!file.is_sparse());
+        file.collapse().expect("tree extension is present");
+
+        let expected = Fixture::Generated("v3_sparse_index").open();
+        assert!(file.is_sparse());
+        assert_eq!(paths_and_flags(&file), paths_and_flags(&expected));
+        assert_eq!(file.tree(), expected.tree());
+        file.verify_entries().expect("entries are sorted");
+        file.verify_extensions(false, no_find).expect("the cache tree is valid");
+    }
+
+    #[test]
+    fn expand_rejects_conflicting_sparse_directories() {
+        let (mut file, repo) = open_with_repo("v3_sparse_index");
+        let idx = file
+            .entries()
+            .iter()
+            .position(|e| e.is_sparse_dir())
+            .expect("sparse dir present");
+        file.entries_mut()[idx]
+            .flags
+            .insert(entry::Flags::from_bits_truncate(2 << 12));
+
+        let err = file
+            .expand(|oid, buf| repo.objects.find_tree_iter(oid, buf).ok())
+            .unwrap_err();
+        assert!(
+            matches!(err, gix_index::sparse::expand::Error::Conflict { ref path, stage: 2 } if path == "c1/c3/"),
+            "{err:?}"
+        );
+        assert!(file.is_sparse(), "nothing changed");
+    }
+
+    #[test]
+    fn expand_keeps_the_cache_tree_if_a_later_tree_is_missing() {
+        let (mut file, repo) = open_with_repo("v3_sparse_index");
+        let d_id = file.entry_by_path_and_stage("d/".into(), 0).expect("present").id;
+        let tree = file.tree().cloned();
+        assert!(tree.is_some(), "the cache tree is present");
+        let entries = paths_and_flags(&file);
+
+        let err = file
+            .expand(|oid, buf| {
+                if oid == d_id {
+                    None
+                } else {
+                    repo.objects.find_tree_iter(oid, buf).ok()
+                }
+            })
+            .unwrap_err();
+        assert!(
+            matches!(err, gix_index::sparse::expand::Error::TreeNotFound { ref path, .. } if path == "d/"),
+            "the sparse directory 'c1/c3/' was expanded before: {err:?}"
+        );
+        assert!(file.is_sparse());
+        assert_eq!(file.tree(), tree.as_ref(), "the cache tree remains unchanged");
+        assert_eq!(paths_and_flags(&file), entries);
+    }
+
+    #[test]
+    fn expand_rejects_entries_inside_of_sparse_directories() {
+        let (mut file, repo) = open_with_repo("v3_sparse_index");
+        let entry = file.entry(0).clone();
+        file.dangerously_push_entry(entry.stat, entry.id, entry.flags, entry.mode, "d/nested".into());
+        file.sort_entries();
+
+        let err = file
+            .expand(|oid, buf| repo.objects.find_tree_iter(oid, buf).ok())
+            .unwrap_err();
+        assert!(
+            matches!(err, gix_index::sparse::expand::Error::NestedEntry { ref sparse_dir, ref path } if sparse_dir == "d/" && path == "d/nested"),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn upserting_inside_of_a_sparse_directory_requires_expansion() -> crate::Result {
+        let (mut file, repo) = open_with_repo("v3_sparse_index");
+        let id = file.entry(0).id;
+        for path in ["d/new", "c1/c3/deeper/new", "c1/c3/sparse/"] {
+            let mode = if path.ends_with('/') {
+                entry::Mode::DIR
+            } else {
+                entry::Mode::FILE
+            };
+            let err = file
+                .upsert_entry(path.into(), mode, id, entry::Stat::default())
+                .unwrap_err();
+            assert!(
+                matches!(err, gix_index::edit::upsert::Error::SparseIndexNotExpanded { path: ref p, ref sparse_dir }
+                    if p == path && (sparse_dir == "d/" || sparse_dir == "c1/c3/")),
+                "{err:?}"
+            );
+        }
+        assert_eq!(file.entries().len(), 8, "nothing was inserted");
+
+        file.upsert_entry("c1/new".into(), entry::Mode::FILE, id, entry::Stat::default())?;
+        let d_id = file.entry_by_path_and_stage("d/".into(), 0).expect("present").id;
+        file.upsert_entry("d/".into(), entry::Mode::DIR, d_id, entry::Stat::default())?;
+        assert_eq!(
+            file.entries().len(),
+            9,
+            "paths next to and at sparse directories can be changed"
+        );
+
+        file.ensure_full(|oid, buf| repo.objects.find_tree_iter(oid, buf).ok())?;
+        assert!(!file.is_sparse());
+        let idx = file.upsert_entry("d/new".into(), entry::Mode::FILE, id, entry::Stat::default())?;
+        assert_eq!(file.entry(idx).path(&file), "d/new");
+        assert!(
+            !file.entry(idx).flags.contains(entry::Flags::SKIP_WORKTREE),
+            "the new entry is part of the checkout"
+        );
+        file.verify_entries()?;
+
+        file.ensure_full(|_, _| unreachable!("full indices aren't expanded again"))?;
+        Ok(())
+    }
+
+    #[test]
+    fn removing_inside_of_a_sparse_directory_requires_expansion() -> crate::Result {
+        let (mut file, repo) = open_with_repo("v3_sparse_index");
+        for prefix in ["c1/c3/a", "c1/c3/deeper/", "d/x"] {
+            let err = file.remove_entries_by_prefix(prefix.into()).unwrap_err();
+            assert!(
+                matches!(err, gix_index::edit::remove::Error::SparseIndexNotExpanded { prefix: ref p, .. } if p == prefix),
+                "{err:?}"
+            );
+        }
+        assert_eq!(file.entries().len(), 8, "nothing was removed");
+
+        assert_eq!(
+            file.remove_entries_by_prefix("c1/c3/".into())?,
+            1,
+            "the sparse directory itself can be removed"
+        );
+        assert_eq!(file.remove_entries_by_prefix("c1/c".into())?, 2);
+        assert!(file.is_sparse(), "'d/' is still present");
+
+        file.ensure_full(|oid, buf| repo.objects.find_tree_iter(oid, buf).ok())?;
+        let num_files_in_d = file.prefixed_entries("d/".into()).len();
+        assert!(num_files_in_d > 0);
+        assert_eq!(file.remove_entries_by_prefix("d/".into())?, num_files_in_d);
+        Ok(())
+    }
+}
+
+mod icase {
+    use gix_index::{entry, State};
+
+    fn state_with(paths: &[&str]) -> State {
+        let mut state = State::new(gix_hash::Kind::Sha1);
+        for path in paths {
+            state.dangerously_push_entry(
+                entry::Stat::default(),
+                gix_hash::Kind::Sha1.null(),
+                entry::Flags::empty(),
+                entry::Mode::FILE,
+                (*path).into(),
+            );
+        }
+        state.sort_entries();
+        state
+    }
+
+    #[test]
+    fn entries_are_found_ignoring_the_case_of_ascii_characters() {
+        let state = state_with(&["Dir/File.txt", "a", "A", "Ä"]);
+        let lookup = state.prepare_icase_lookup();
+        let path_of = |path: &str, ignore_case: bool| {
+            lookup
+                .entry_by_path_icase(path.into(), ignore_case)
+                .map(|e| e.path(&state).to_string())
+        };
+
+        assert_eq!(path_of("dir/file.TXT", true).as_deref(), Some("Dir/File.txt"));
+        assert_eq!(
+            path_of("dir/file.TXT", false),
+            None,
+            "without ignoring case, paths must match"
+        );
+        assert_eq!(path_of("Dir/File.txt", false).as_deref(), Some("Dir/File.txt"));
+
+        assert_eq!(path_of("a", true).as_deref(), Some("a"), "exact matches are preferred");
+        assert_eq!(path_of("A", true).as_deref(), Some("A"));
+        assert_eq!(path_of("ä", true), None, "like git, only ASCII characters are folded");
+    }
+
+    #[test]
+    fn directories_are_found_as_spelled_in_the_index() {
+        let state = state_with(&["Dir/Sub/a", "dir/b", "top"]);
+        let lookup = state.prepare_icase_lookup();
+        let dir = |path: &str, ignore_case: bool| {
+            lookup
+                .directory_by_path_icase(path.into(), ignore_case)
+                .map(ToString::to_string)
+        };
+
+        assert_eq!(dir("DIR", true).as_deref(), Some("Dir"), "the first spelling wins");
+        assert_eq!(dir("dir/sub", true).as_deref(), Some("Dir/Sub"));
+        assert_eq!(dir("dir", false).as_deref(), Some("dir"));
+        assert_eq!(dir("dir/sub", false), None);
+        assert_eq!(dir("top", true), None, "files aren't directories");
+        assert_eq!(dir("Dir/Sub/a", true), None);
+    }
+}
+
+mod tree_status {
+    use bstr::{BStr, ByteSlice};
+    use gix_index::{entry, extension::tree::Status, State};
+
+    use crate::index::{fixture_worktree_path, Fixture};
+
+    fn statuses(state: &State) -> Vec<(&BStr, Status<'_>)> {
+        state
+            .entries_with_tree_status()
+            .map(|(entry, status)| (entry.path(state), status))
+            .collect()
+    }
+
+    #[test]
+    fn entries_refer_to_the_tree_of_their_directory() -> crate::Result {
+        let fixture = "v2_cache_tree_dirs";
+        let mut file = Fixture::Generated(fixture).open();
+        let repo = gix::open(fixture_worktree_path(fixture))?;
+        let tree_id = |dir: &str| -> crate::Result<gix_hash::ObjectId> {
+            Ok(repo.rev_parse_single(format!("HEAD:{dir}").as_str())?.detach())
+        };
+        for (path, status) in statuses(&file) {
+            let dir = path
+                .rfind_byte(b'/')
+                .map_or("", |pos| path[..pos].to_str().expect("UTF-8"));
+            assert_eq!(status, Status::Valid(&tree_id(dir)?), "{path}");
+        }
+
+        file.upsert_entry(
+            "a/new/file".into(),
+            entry::Mode::FILE,
+            gix_hash::Kind::Sha1.null(),
+            Default::default(),
+        )?;
+        let status_by_path = statuses(&file)
+            .into_iter()
+            .map(|(path, status)| {
+                let status = match status {
+                    Status::Valid(_) => "valid",
+                    Status::Invalidated => "invalidated",
+                    Status::Absent => "absent",
+                };
+                (path.to_string(), status)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            status_by_path,
+            [
+                ("a-b/x", "valid"),
+                ("a/b/c/z", "valid"),
+                ("a/b/y", "valid"),
+                ("a/d/z", "valid"),
+                ("a/new/file", "absent"),
+                ("a/x", "invalidated"),
+                ("c", "invalidated"),
+            ]
+            .map(|(path, status)| (path.to_owned(), status)),
+            "new directories have no node, and changes invalidate all trees leading to them"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn entries_without_tree_extension_are_absent() {
+        let mut state = State::new(gix_hash::Kind::Sha1);
+        for path in ["a", "d/b", "d/last/c"] {
+            state
+                .upsert_entry(
+                    path.into(),
+                    entry::Mode::FILE,
+                    gix_hash::Kind::Sha1.null(),
+                    Default::default(),
+                )
+                .expect("valid path");
+        }
+        assert!(state.tree().is_none());
+        assert!(statuses(&state).iter().all(|(_, status)| *status == Status::Absent));
+    }
+}
+
+mod fs_monitor {
+    use bstr::BStr;
+    use gix_index::{entry::Flags, extension::fs_monitor::Token, State};
+
+    use crate::index::Fixture;
+
+    fn valid(state: &State) -> Vec<bool> {
+        state
+            .entries()
+            .iter()
+            .map(|e| e.flags.contains(Flags::FSMONITOR_VALID))
+            .collect()
+    }
+
+    fn dirty(state: &State) -> Vec<usize> {
+        state.fs_monitor().expect("present").entry_dirty().iter().collect()
+    }
+
+    /// Open the index with the fsmonitor extension as if all of its entries were refreshed since.
+    fn all_valid() -> gix_index::File {
+        let mut file = Fixture::Loose("FSMN").open();
+        assert!(file.apply_fs_monitor());
+        for entry in file.entries_mut() {
+            entry.flags.insert(Flags::FSMONITOR_VALID);
+        }
+        file
+    }
+
+    #[test]
+    fn changes_mark_entries_dirty_and_untracked_paths_are_returned() {
+        let mut file = all_valid();
+        let paths: [&BStr; 5] = [
+            "tracked".into(),
+            "dir1/".into(),
+            "dir2".into(),
+            "untracked".into(),
+            "dir3/".into(),
+        ];
+        let untracked = file.apply_fs_monitor_changes(paths);
+        assert_eq!(untracked, ["untracked", "dir3/"]);
+        assert_eq!(
+            valid(&file),
+            [false, false, false, false, true, false],
+            "directories are marked with and without trailing slash"
+        );
+        assert_eq!(dirty(&file), [0, 1, 2, 3, 5], "the bitmap is updated as well");
+
+        let untracked = file.apply_fs_monitor_changes(["dir1/modified".into()]);
+        assert!(untracked.is_empty(), "dirty entries are still in the index");
+        assert_eq!(dirty(&file), [0, 1, 2, 3, 5]);
+    }
+
+    #[test]
+    fn tokens_are_written_along_with_the_dirty_entries() -> crate::Result {
+        let mut file = all_valid();
+        assert!(file.apply_fs_monitor_changes(["modified".into()]).is_empty());
+        let token = Token::V1 { nanos_since_1970: 42 };
+        file.set_fs_monitor_token(token.clone());
+
+        let mut buf = Vec::new();
+        file.write_to(&mut buf, Default::default())?;
+        let (mut state, _) = State::from_bytes(&buf, file.timestamp(), gix_hash::Kind::Sha1, Default::default())?;
+        assert_eq!(state.fs_monitor().expect("written").token(), &token);
+        assert_eq!(dirty(&state), [4]);
+        assert!(state.apply_fs_monitor());
+        assert_eq!(
+            valid(&state),
+            valid(&file),
+            "the next reader sees the same entries as dirty"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn setting_a_token_creates_the_extension_with_all_unrefreshed_entries_being_dirty() {
+        let mut file = Fixture::Generated("v2_more_files").open();
+        assert!(file.fs_monitor().is_none());
+        let token = Token::V2 { token: "token".into() };
+        file.set_fs_monitor_token(token.clone());
+        assert_eq!(file.fs_monitor().expect("created").token(), &token);
+        assert_eq!(dirty(&file), (0..file.entries().len()).collect::<Vec<_>>());
+
+        assert_eq!(
+            file.apply_fs_monitor_changes(["d/a".into(), "e".into()]),
+            ["e"],
+            "a path unknown to the index"
+        );
+    }
+}
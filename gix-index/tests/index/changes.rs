@@ -0,0 +1,239 @@
+use bstr::{BString, ByteSlice};
+use gix_index::{changes::Change, entry, Entry, State};
+
+use crate::index::{hex_to_id, Fixture, Fixture::*};
+
+/// The parts of each entry that make it unchanged, in order.
+type Model = Vec<(BString, entry::Stage, gix_hash::ObjectId, entry::Mode)>;
+
+fn model(state: &State) -> Model {
+    state
+        .entries()
+        .iter()
+        .map(|e| (e.path(state).to_owned(), e.stage(), e.id, e.mode))
+        .collect()
+}
+
+/// Apply the `changes` of `new` against `old` to the model of `old`, taking all entries of added or modified paths from `new`.
+fn apply(old: &State, new: &State, changes: &[Change<'_>]) -> Model {
+    let mut out = model(old);
+    for change in changes {
+        let path = change.path();
+        out.retain(|(p, ..)| p != path);
+        if !matches!(change, Change::Remove { .. }) {
+            out.extend(
+                new.entries()
+                    .iter()
+                    .filter(|e| e.path(new) == path)
+                    .map(|e| (e.path(new).to_owned(), e.stage(), e.id, e.mode)),
+            );
+        }
+    }
+    out.sort_by(|a, b| Entry::cmp_filepaths(a.0.as_bstr(), b.0.as_bstr()).then(a.1.cmp(&b.1)));
+    out
+}
+
+fn assert_changes_reproduce(old: &State, new: &State) -> usize {
+    let changes: Vec<_> = new.changes_against(old, None).collect();
+    assert!(
+        changes
+            .windows(2)
+            .all(|w| Entry::cmp_filepaths(w[0].path(), w[1].path()).is_lt()),
+        "changes are sorted by path and each path is mentioned once"
+    );
+    assert_eq!(
+        apply(old, new, &changes),
+        model(new),
+        "applying the changes reproduces the new state"
+    );
+    changes.len()
+}
+
+const FIXTURES: &[Fixture] = &[
+    Generated("V2_empty"),
+    Generated("v2"),
+    Generated("v2_more_files"),
+    Generated("v2_all_file_kinds"),
+    Generated("v2_deeper_tree"),
+    Generated("v2_conflicted_merge"),
+    Generated("v3_skip_worktree"),
+    Generated("v4_more_files_IEOT"),
+    Loose("conflicting-file"),
+    Loose("extended-flags"),
+    Loose("very-long-path"),
+];
+
+#[test]
+fn states_have_no_changes_against_themselves() {
+    for fixture in FIXTURES {
+        let state = fixture.open();
+        assert_eq!(
+            state.changes_against(&state, Some(Default::default())).count(),
+            0,
+            "{}",
+            fixture.to_name()
+        );
+    }
+}
+
+#[test]
+fn applying_changes_between_fixtures_reproduces_the_new_state() {
+    let states: Vec<_> = FIXTURES.iter().map(Fixture::open).collect();
+    for old in &states {
+        for new in &states {
+            assert_changes_reproduce(old, new);
+        }
+    }
+}
+
+#[test]
+fn applying_changes_of_randomly_edited_states_reproduces_them() {
+    let base = Generated("v2_deeper_tree").open();
+    let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+    let mut num_changes = 0;
+    for round in 0..200 {
+        let mut state = State::clone(&base);
+        for edit in 0..rng.below(8) {
+            if state.entries().is_empty() {
+                break;
+            }
+            let idx = rng.below(state.entries().len());
+            let (path, stage) = {
+                let e = &state.entries()[idx];
+                (e.path(&state).to_owned(), e.stage())
+            };
+            let id = gix_hash::ObjectId::from([rng.below(256) as u8; 20]);
+            match rng.below(5) {
+                0 => {
+                    state.remove_entry(path.as_ref(), stage).expect("entry exists");
+                }
+                1 => state.entries_mut()[idx].id = id,
+                2 => {
+                    let mode = &mut state.entries_mut()[idx].mode;
+                    *mode = if *mode == entry::Mode::FILE {
+                        entry::Mode::FILE_EXECUTABLE
+                    } else {
+                        entry::Mode::FILE
+                    };
+                }
+                3 => {
+                    let path = if rng.below(2) == 0 {
+                        path
+                    } else {
+                        format!("new/{round}-{edit}").into()
+                    };
+                    state
+                        .upsert_entry(path.as_ref(), entry::Mode::FILE, id, Default::default())
+                        .expect("valid path");
+                }
+                _ => {
+                    let stage = 1 + rng.below(3) as entry::Stage;
+                    if state.entry_by_path_and_stage(path.as_ref(), stage).is_none() {
+                        state.dangerously_push_entry(
+                            Default::default(),
+                            id,
+                            entry::Flags::from_bits_truncate(stage << 12),
+                            entry::Mode::FILE,
+                            path.as_ref(),
+                        );
+                        state.sort_entries();
+                    }
+                }
+            }
+        }
+        num_changes += assert_changes_reproduce(&base, &state);
+        num_changes += assert_changes_reproduce(&state, &base);
+    }
+    assert_ne!(num_changes, 0, "the edits are actually changing the state");
+}
+
+#[test]
+fn stat_changes_are_only_considered_if_options_are_given() {
+    let old = Generated("v2_more_files").open();
+    let mut new = State::clone(&old);
+    new.entries_mut()[0].stat.mtime.secs += 1;
+    assert_eq!(new.changes_against(&old, None).count(), 0, "stat is ignored by default");
+
+    let changes: Vec<_> = new.changes_against(&old, Some(Default::default())).collect();
+    assert_eq!(
+        changes,
+        [Change::Modify {
+            path: new.entries()[0].path(&new),
+            old: &old.entries()[0],
+            new: &new.entries()[0],
+        }]
+    );
+}
+
+#[test]
+fn conflicted_paths_are_reported_as_modified_with_their_lowest_stage() {
+    let conflicted = Generated("v2_conflicted_merge").open();
+    let mut resolved = State::clone(&conflicted);
+    let (path, ours) = {
+        let e = resolved
+            .entries()
+            .iter()
+            .find(|e| e.stage() == 2)
+            .expect("fixture is conflicted");
+        (e.path(&resolved).to_owned(), e.id)
+    };
+    resolved
+        .upsert_entry(path.as_ref(), entry::Mode::FILE, ours, Default::default())
+        .expect("valid path");
+
+    let lowest_stage = conflicted
+        .entries()
+        .iter()
+        .find(|e| e.path(&conflicted) == path)
+        .expect("present")
+        .stage();
+    assert_ne!(lowest_stage, 0);
+
+    let changes: Vec<_> = resolved.changes_against(&conflicted, None).collect();
+    assert_eq!(changes.len(), 1);
+    match changes[0] {
+        Change::Modify { path: p, old, new } => {
+            assert_eq!(p, path);
+            assert_eq!(
+                old.stage(),
+                lowest_stage,
+                "conflicts are represented by their lowest stage"
+            );
+            assert_eq!(new.stage(), 0);
+            assert_eq!(new.id, ours);
+        }
+        other => unreachable!("unexpected change: {other:?}"),
+    }
+
+    let changes: Vec<_> = conflicted.changes_against(&resolved, None).collect();
+    assert!(
+        matches!(changes.as_slice(), [Change::Modify { old, new, .. }] if old.stage() == 0 && new.stage() == lowest_stage),
+        "reintroducing a conflict is a modification as well"
+    );
+}
+
+#[test]
+fn additions_and_removals_refer_to_their_entry() {
+    let old = Generated("v2").open();
+    let mut new = State::clone(&old);
+    let id = hex_to_id("0000000000000000000000000000000000000001");
+    new.upsert_entry("added".into(), entry::Mode::FILE, id, Default::default())
+        .expect("valid path");
+
+    let changes: Vec<_> = new.changes_against(&old, None).collect();
+    assert!(matches!(changes.as_slice(), [Change::Add { path, entry }] if *path == "added" && entry.id == id));
+    let changes: Vec<_> = old.changes_against(&new, None).collect();
+    assert!(matches!(changes.as_slice(), [Change::Remove { path, entry }] if *path == "added" && entry.id == id));
+}
+
+/// A deterministic source of pseudo-random numbers to drive the edits.
+struct XorShift(u64);
+
+impl XorShift {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
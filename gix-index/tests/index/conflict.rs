@@ -0,0 +1,198 @@
+use bstr::BStr;
+use filetime::FileTime;
+use gix_index::{conflict::Resolution, entry, State};
+
+use crate::index::{hex_to_id, Fixture};
+
+const BASE: &str = "df967b96a579e45a18b8251732d16804b2e56a55";
+const OURS: &str = "b19a1e93bec1317dc6097229e12afaffbfa74dc2";
+const THEIRS: &str = "950b81b7eee953d050aa05a641f8e056c85dd1bd";
+
+fn conflicted_merge() -> gix_index::File {
+    Fixture::Generated("v2_conflicted_merge").open()
+}
+
+fn conflicts(state: &State) -> Vec<(&BStr, [Option<gix_hash::ObjectId>; 3])> {
+    state
+        .conflicts()
+        .map(|(path, stages)| (path, stages.map(|e| e.map(|e| e.id))))
+        .collect()
+}
+
+fn ids(ids: [Option<&str>; 3]) -> [Option<gix_hash::ObjectId>; 3] {
+    ids.map(|id| id.map(hex_to_id))
+}
+
+#[test]
+fn conflicts_are_grouped_by_path_with_missing_stages() {
+    let file = conflicted_merge();
+    assert_eq!(
+        conflicts(&file),
+        [
+            ("added-by-both".into(), ids([None, Some(OURS), Some(THEIRS)])),
+            ("both-modified".into(), ids([Some(BASE), Some(OURS), Some(THEIRS)])),
+            ("deleted-by-them".into(), ids([Some(BASE), Some(OURS), None])),
+            ("deleted-by-us".into(), ids([Some(BASE), None, Some(THEIRS)])),
+        ]
+    );
+    for (_path, stages) in file.conflicts() {
+        for (idx, entry) in stages.iter().enumerate() {
+            if let Some(entry) = entry {
+                assert_eq!(entry.stage() as usize, idx + 1);
+            }
+        }
+    }
+
+    let file = Fixture::Generated("v4_more_files_IEOT").open();
+    assert_eq!(file.conflicts().count(), 0);
+}
+
+#[test]
+fn resolve_conflict_installs_a_stage_0_entry_and_records_resolve_undo_data() -> crate::Result {
+    let mut file = conflicted_merge();
+    assert!(file.resolve_undo().is_none());
+
+    let idx = file
+        .resolve_conflict("both-modified".into(), Resolution::Ours)?
+        .expect("entry was added");
+    let entry = file.entry(idx);
+    assert_eq!(entry.path(&file), "both-modified");
+    assert_eq!(entry.stage(), 0);
+    assert_eq!(entry.id, hex_to_id(OURS));
+
+    let idx = file
+        .resolve_conflict("deleted-by-us".into(), Resolution::Theirs)?
+        .expect("entry was added");
+    assert_eq!(file.entry(idx).id, hex_to_id(THEIRS));
+
+    assert_eq!(
+        file.resolve_conflict("deleted-by-them".into(), Resolution::Remove)?,
+        None
+    );
+    assert!(file.entry_index_by_path("deleted-by-them".into()).is_err());
+
+    let merged = hex_to_id("0000000000000000000000000000000000000001");
+    file.resolve_conflict(
+        "added-by-both".into(),
+        Resolution::Entry {
+            mode: entry::Mode::FILE_EXECUTABLE,
+            id: merged,
+        },
+    )?;
+    assert_eq!(file.conflicts().count(), 0);
+    file.verify_entries()?;
+    assert_eq!(
+        file.entries().iter().map(|e| e.path(&file)).collect::<Vec<_>>(),
+        ["added-by-both", "both-modified", "deleted-by-us", "unchanged"]
+    );
+
+    let mut buf = Vec::new();
+    file.write_to(&mut buf, Default::default())?;
+    let (state, _) = State::from_bytes(&buf, FileTime::now(), gix_hash::Kind::Sha1, Default::default())?;
+    let undo = state.resolve_undo().expect("written and read back");
+    assert_eq!(
+        undo.iter()
+            .map(|path| (
+                path.name(),
+                path.stages().map(|stage| stage.map(|stage| stage.id().to_owned()))
+            ))
+            .collect::<Vec<_>>(),
+        [
+            ("added-by-both".into(), ids([None, Some(OURS), Some(THEIRS)])),
+            ("both-modified".into(), ids([Some(BASE), Some(OURS), Some(THEIRS)])),
+            ("deleted-by-them".into(), ids([Some(BASE), Some(OURS), None])),
+            ("deleted-by-us".into(), ids([Some(BASE), None, Some(THEIRS)])),
+        ],
+        "all conflicts are recorded, sorted by path"
+    );
+    assert!(undo
+        .iter()
+        .flat_map(|path| path.stages().iter().flatten())
+        .all(|stage| stage.mode() == entry::Mode::FILE));
+    Ok(())
+}
+
+#[test]
+fn resolve_conflict_fails_without_conflict_or_chosen_stage() {
+    let mut file = conflicted_merge();
+    for path in ["unchanged", "missing"] {
+        assert!(matches!(
+            file.resolve_conflict(path.into(), Resolution::Ours),
+            Err(gix_index::conflict::resolve::Error::NotConflicted { .. })
+        ));
+    }
+
+    let num_entries = file.entries().len();
+    assert!(matches!(
+        file.resolve_conflict("added-by-both".into(), Resolution::Base),
+        Err(gix_index::conflict::resolve::Error::MissingStage { stage: 1, .. })
+    ));
+    assert_eq!(file.entries().len(), num_entries, "nothing changes on error");
+    assert!(file.resolve_undo().is_none());
+}
+
+#[test]
+fn resolve_undo_records_can_be_looked_up_and_cleared() -> crate::Result {
+    let mut file = conflicted_merge();
+    for path in ["both-modified", "deleted-by-them"] {
+        file.resolve_conflict(path.into(), Resolution::Ours)?;
+    }
+    let record = file.resolve_undo_for("both-modified".into()).expect("recorded");
+    assert_eq!(
+        record.stages().map(|stage| stage.map(|stage| stage.id().to_owned())),
+        ids([Some(BASE), Some(OURS), Some(THEIRS)])
+    );
+    assert!(file.resolve_undo_for("unchanged".into()).is_none());
+
+    let removed = file.remove_resolve_undo("both-modified".into()).expect("present");
+    assert_eq!(removed.name(), "both-modified");
+    assert!(file.remove_resolve_undo("both-modified".into()).is_none());
+    assert_eq!(file.resolve_undo().map(Vec::len), Some(1));
+
+    let mut clone = State::clone(&file);
+    assert!(clone.remove_resolve_undo("deleted-by-them".into()).is_some());
+    assert!(
+        clone.resolve_undo().is_none(),
+        "the extension is dropped once it's empty"
+    );
+
+    assert_eq!(file.take_resolve_undo().map(|paths| paths.len()), Some(1));
+    let mut buf = Vec::new();
+    file.write_to(&mut buf, Default::default())?;
+    let (state, _) = State::from_bytes(&buf, FileTime::now(), gix_hash::Kind::Sha1, Default::default())?;
+    assert!(state.resolve_undo().is_none(), "nothing is written once cleared");
+    Ok(())
+}
+
+#[test]
+fn resolved_conflicts_can_be_recreated_by_git_checkout_merge() -> crate::Result {
+    let tmp = gix_testtools::scripted_fixture_writable_standalone("make_index/v2_conflicting_file.sh")?;
+    let git = |args: &[&str]| crate::index::git(tmp.path()).args(args).output();
+
+    let index_path = tmp.path().join(".git").join("index");
+    let mut file = gix_index::File::at(&index_path, gix_hash::Kind::Sha1, Default::default())?;
+    let conflicted = conflicts(&file)
+        .into_iter()
+        .map(|(path, ids)| (path.to_owned(), ids))
+        .collect::<Vec<_>>();
+    assert_eq!(conflicted.len(), 1);
+    file.resolve_conflict("file".into(), Resolution::Ours)?;
+    file.write(Default::default())?;
+
+    let output = git(&["ls-files", "--unmerged"])?;
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty(), "git sees the conflict as resolved");
+
+    assert!(git(&["checkout", "--merge", "--", "file"])?.status.success());
+    let file = gix_index::File::at(&index_path, gix_hash::Kind::Sha1, Default::default())?;
+    assert_eq!(
+        conflicts(&file)
+            .into_iter()
+            .map(|(path, ids)| (path.to_owned(), ids))
+            .collect::<Vec<_>>(),
+        conflicted,
+        "git recreated all stages of the conflict from the resolve-undo extension"
+    );
+    assert!(std::fs::read_to_string(tmp.path().join("file"))?.starts_with("<<<<<<<"));
+    Ok(())
+}
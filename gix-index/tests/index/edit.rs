@@ -0,0 +1,252 @@
+use bstr::ByteSlice;
+use gix_index::{entry, State};
+
+use crate::index::{hex_to_id, Fixture};
+
+fn paths(state: &State) -> Vec<String> {
+    state.entries().iter().map(|e| e.path(state).to_string()).collect()
+}
+
+fn tree_validity(state: &State, dirs: &[&str]) -> Vec<bool> {
+    dirs.iter()
+        .map(|dir| {
+            let mut tree = state.tree().expect("present");
+            for name in dir.split('/').filter(|c| !c.is_empty()) {
+                tree = tree
+                    .children
+                    .iter()
+                    .find(|t| t.name.as_bstr() == name)
+                    .expect("child exists");
+            }
+            tree.num_entries.is_some()
+        })
+        .collect()
+}
+
+#[test]
+fn upsert_entry_inserts_at_sorted_position_or_replaces() -> crate::Result {
+    let mut file = Fixture::Generated("v4_more_files_IEOT").open();
+    let id = hex_to_id("0000000000000000000000000000000000000001");
+    let num_entries = file.entries().len();
+
+    let idx = file.upsert_entry("d/a".into(), entry::Mode::FILE_EXECUTABLE, id, entry::Stat::default())?;
+    assert_eq!(file.entries().len(), num_entries, "existing entries are replaced");
+    assert_eq!(file.entry(idx).path(&file), "d/a");
+    assert_eq!(file.entry(idx).mode, entry::Mode::FILE_EXECUTABLE);
+    assert_eq!(file.entry(idx).id, id);
+
+    for path in ["d/last/5", "0", "z", "d-x", "d/last0"] {
+        let idx = file.upsert_entry(path.into(), entry::Mode::FILE, id, entry::Stat::default())?;
+        assert_eq!(file.entry(idx).path(&file), path);
+    }
+    file.verify_entries()?;
+    assert_eq!(
+        paths(&file),
+        [
+            "0",
+            "a",
+            "b",
+            "c",
+            "d-x",
+            "d/a",
+            "d/b",
+            "d/c",
+            "d/last/123",
+            "d/last/34",
+            "d/last/5",
+            "d/last/6",
+            "d/last0",
+            "x",
+            "z"
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn upsert_entry_invalidates_cache_tree_along_path() -> crate::Result {
+    let mut file = Fixture::Generated("v4_more_files_IEOT").open();
+    let dirs = ["", "d", "d/last"];
+    assert_eq!(tree_validity(&file, &dirs), [true, true, true]);
+
+    let id = file.entry(0).id;
+    file.upsert_entry("d/new".into(), entry::Mode::FILE, id, entry::Stat::default())?;
+    assert_eq!(
+        tree_validity(&file, &dirs),
+        [false, false, true],
+        "only trees containing the path are affected"
+    );
+    Ok(())
+}
+
+#[test]
+fn upsert_entry_resolves_conflicts() -> crate::Result {
+    let mut file = Fixture::Loose("conflicting-file").open();
+    let id = file.entry(0).id;
+    let idx = file.upsert_entry("file".into(), entry::Mode::FILE, id, entry::Stat::default())?;
+    assert_eq!(idx, 0);
+    assert_eq!(file.entries().len(), 1, "all other stages are removed");
+    assert_eq!(file.entry(0).stage(), 0);
+    file.verify_entries()?;
+    Ok(())
+}
+
+#[test]
+fn upsert_entry_rejects_invalid_paths() {
+    let mut file = Fixture::Generated("v4_more_files_IEOT").open();
+    let id = file.entry(0).id;
+    for (path, mode) in [
+        ("", entry::Mode::FILE),
+        (".git/config", entry::Mode::FILE),
+        ("d/.GIT/config", entry::Mode::FILE),
+        ("d//a", entry::Mode::FILE),
+        ("d/./a", entry::Mode::FILE),
+        ("../a", entry::Mode::FILE),
+        ("/a", entry::Mode::FILE),
+        ("d/", entry::Mode::FILE),
+        ("dir", entry::Mode::DIR),
+        ("dir//", entry::Mode::DIR),
+    ] {
+        assert!(
+            file.upsert_entry(path.into(), mode, id, entry::Stat::default())
+                .is_err(),
+            "{path:?} with mode {mode:?} is invalid"
+        );
+    }
+    assert_eq!(file.entries().len(), 10, "nothing was added");
+
+    assert!(!file.is_sparse());
+    let idx = file
+        .upsert_entry("sparse/".into(), entry::Mode::DIR, id, entry::Stat::default())
+        .expect("trailing slashes are required for sparse directories");
+    assert!(file.entry(idx).flags.contains(entry::Flags::SKIP_WORKTREE));
+    assert!(file.is_sparse());
+}
+
+#[test]
+fn remove_entry() {
+    let mut file = Fixture::Loose("conflicting-file").open();
+    let removed = file.remove_entry("file".into(), 2).expect("present");
+    assert_eq!(removed.stage(), 2);
+    assert_eq!(removed.path(&file), "file", "the path can still be read");
+    assert_eq!(file.entries().iter().map(|e| e.stage()).collect::<Vec<_>>(), [1, 3]);
+    assert!(file.remove_entry("file".into(), 2).is_none());
+    assert!(file.remove_entry("missing".into(), 0).is_none());
+}
+
+#[test]
+fn remove_entries_by_prefix_removes_the_matching_range_and_invalidates_its_trees() -> crate::Result {
+    let mut file = Fixture::Generated("v4_more_files_IEOT").open();
+    let dirs = ["", "d", "d/last"];
+    assert_eq!(file.remove_entries_by_prefix("missing/".into())?, 0);
+    assert_eq!(tree_validity(&file, &dirs), [true, true, true], "nothing changes");
+
+    assert_eq!(file.remove_entries_by_prefix("d/la".into())?, 3);
+    assert_eq!(paths(&file), ["a", "b", "c", "d/a", "d/b", "d/c", "x"]);
+    assert_eq!(tree_validity(&file, &dirs), [false, false, false]);
+
+    assert_eq!(file.remove_entries_by_prefix("d/".into())?, 3);
+    assert_eq!(paths(&file), ["a", "b", "c", "x"]);
+    assert!(file.resolve_undo().is_none(), "there were no conflicts");
+    assert_eq!(file.remove_entries_by_prefix("d/".into())?, 0);
+    Ok(())
+}
+
+#[test]
+fn remove_entries_by_prefix_is_like_git_rm_cached() -> crate::Result {
+    for (fixture, prefix, pathspec) in [
+        ("v4_more_files_IEOT", "d/", "d"),
+        ("v2_conflicted_merge", "deleted-by-", "deleted-by-*"),
+    ] {
+        let fixture = Fixture::Generated(fixture);
+        let worktree = crate::index::fixture_worktree_path(fixture.to_name());
+        let tmp = gix_testtools::tempfile::TempDir::new()?;
+        let expected_path = tmp.path().join("expected");
+        std::fs::copy(fixture.to_path(), &expected_path)?;
+        let status = crate::index::git(&worktree)
+            .args(["rm", "-r", "--cached", "--quiet", pathspec])
+            .env("GIT_INDEX_FILE", &expected_path)
+            .status()?;
+        assert!(status.success());
+        let expected = gix_index::File::at(&expected_path, gix_hash::Kind::Sha1, Default::default())?;
+
+        let mut actual = fixture.open();
+        let num_entries = actual.entries().len();
+        let num_removed = actual.remove_entries_by_prefix(prefix.into())?;
+        assert_eq!(num_removed, num_entries - expected.entries().len());
+        let entries = |state: &State| {
+            state
+                .entries()
+                .iter()
+                .map(|e| (e.path(state).to_owned(), e.stage(), e.id, e.mode))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(entries(&actual), entries(&expected));
+        assert_eq!(
+            actual.resolve_undo(),
+            expected.resolve_undo(),
+            "the stages of removed conflicts are recorded"
+        );
+        actual.compact_path_backing();
+        actual.verify_entries()?;
+    }
+    Ok(())
+}
+
+#[test]
+fn touch_entry_invalidates_cache_tree_only_if_the_id_changes() {
+    let mut file = Fixture::Generated("v4_more_files_IEOT").open();
+    let dirs = ["", "d", "d/last"];
+    let stat = entry::Stat {
+        size: 42,
+        ..Default::default()
+    };
+
+    let id = file.entry_by_path_and_stage("d/last/6".into(), 0).expect("present").id;
+    let entry = file.touch_entry("d/last/6".into(), stat, id).expect("present");
+    assert_eq!(entry.stat, stat);
+    assert_eq!(tree_validity(&file, &dirs), [true, true, true]);
+
+    let new_id = hex_to_id("0000000000000000000000000000000000000001");
+    let entry = file.touch_entry("d/last/6".into(), stat, new_id).expect("present");
+    assert_eq!(entry.id, new_id);
+    assert_eq!(tree_validity(&file, &dirs), [false, false, false]);
+
+    assert!(file.touch_entry("missing".into(), stat, new_id).is_none());
+}
+
+#[test]
+fn refresh_stats_sets_stat_information_of_checked_out_files() -> crate::Result {
+    let dir = gix_testtools::tempfile::tempdir()?;
+    let mut state = State::new(gix_hash::Kind::Sha1);
+    for path in ["a", "b"] {
+        state.upsert_entry(
+            path.into(),
+            entry::Mode::FILE,
+            gix_hash::Kind::Sha1.null(),
+            Default::default(),
+        )?;
+        std::fs::write(dir.path().join(path), path)?;
+    }
+    let metadata = |path: &bstr::BStr| std::fs::symlink_metadata(dir.path().join(path.to_str().expect("valid UTF-8")));
+    assert!(state
+        .entries()
+        .iter()
+        .all(|e| !e.stat_matches(&metadata(e.path(&state)).unwrap(), Default::default())));
+
+    state.refresh_stats(["b".into(), "missing".into()], Default::default(), metadata)?;
+    assert!(!state.entry(0).stat_matches(&metadata("a".into())?, Default::default()));
+    assert!(
+        state.entry(1).stat_matches(&metadata("b".into())?, Default::default()),
+        "only the given paths are refreshed, and unknown ones are ignored"
+    );
+    assert_eq!(state.entry(1).stat.size, 1);
+
+    let err = state
+        .refresh_stats(["a".into()], Default::default(), |_| {
+            Err(std::io::Error::new(std::io::ErrorKind::Other, "boom"))
+        })
+        .unwrap_err();
+    assert_eq!(err.to_string(), "boom", "errors are passed through");
+    Ok(())
+}
@@ -15,3 +15,537 @@ mod time {
         }
     }
 }
+
+mod compare {
+    use std::cmp::Ordering;
+
+    use bstr::{BStr, ByteSlice};
+    use gix_index::{entry, State};
+
+    use crate::index::Fixture;
+
+    /// Paths in the order git sorts them, with prefixes of each other and non-ASCII bytes, as listed in `v2_path_order.sh`.
+    const SORTED: &[&[u8]] = &[
+        b"a",
+        b"a-b",
+        b"a.b",
+        b"a/b",
+        b"a/b-c",
+        b"a/b.c",
+        b"a/bc",
+        b"a0",
+        b"ab",
+        b"b",
+        b"z",
+        "\u{e4}".as_bytes(),
+        "\u{e4}b".as_bytes(),
+        "\u{f6}".as_bytes(),
+    ];
+
+    /// `cache_name_compare()` in git, comparing the common portion with `memcmp()` and then the lengths.
+    fn cache_name_compare(a: &[u8], b: &[u8]) -> Ordering {
+        let len = a.len().min(b.len());
+        a[..len].cmp(&b[..len]).then(a.len().cmp(&b.len()))
+    }
+
+    #[test]
+    fn paths_are_compared_bytewise_like_git_does() {
+        for (i, a) in SORTED.iter().enumerate() {
+            for (j, b) in SORTED.iter().enumerate() {
+                let expected = i.cmp(&j);
+                assert_eq!(cache_name_compare(a, b), expected, "the test data is sorted");
+                assert_eq!(
+                    entry::compare(a.as_bstr(), 0, b.as_bstr(), 0),
+                    expected,
+                    "{:?} vs {:?}",
+                    a.as_bstr(),
+                    b.as_bstr()
+                );
+                assert_eq!(gix_index::Entry::cmp_filepaths(a.as_bstr(), b.as_bstr()), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn stages_only_matter_for_the_same_path() {
+        let (a, ab) = ("a".into(), "a-b".into());
+        assert_eq!(entry::compare(a, 1, a, 2), Ordering::Less);
+        assert_eq!(entry::compare(a, 3, a, 0), Ordering::Greater);
+        assert_eq!(entry::compare(a, 2, a, 2), Ordering::Equal);
+        assert_eq!(
+            entry::compare(a, 3, ab, 0),
+            Ordering::Less,
+            "the path is compared first, even if it's a prefix"
+        );
+        assert_eq!(entry::compare(ab, 0, a, 3), Ordering::Greater);
+    }
+
+    fn paths_and_stages(state: &State) -> Vec<(&BStr, entry::Stage)> {
+        state.entries().iter().map(|e| (e.path(state), e.stage())).collect()
+    }
+
+    #[test]
+    fn lookups_and_insertions_agree_with_the_order_of_git() -> crate::Result {
+        let expected = Fixture::Generated("v2_path_order").open();
+        let blob = expected.entry(0).id;
+
+        // Add entries in reverse order, with conflicts where paths are prefixes of other paths, like the fixture does.
+        let mut state = State::new(gix_hash::Kind::Sha1);
+        for path in SORTED.iter().rev() {
+            let stages: &[entry::Stage] = if path.len() == 1 { &[3, 1, 2] } else { &[0] };
+            for &stage in stages {
+                if stage == 0 {
+                    state.upsert_entry(path.as_bstr(), entry::Mode::FILE, blob, Default::default())?;
+                } else {
+                    state.dangerously_push_entry(
+                        Default::default(),
+                        blob,
+                        entry::Flags::from_bits_truncate(stage << 12),
+                        entry::Mode::FILE,
+                        path.as_bstr(),
+                    );
+                    state.sort_entries();
+                }
+            }
+        }
+
+        assert_eq!(
+            paths_and_stages(&state),
+            paths_and_stages(&expected),
+            "insertions and sorting produce the order of git"
+        );
+        state.verify_entries()?;
+        for (idx, entry) in expected.entries().iter().enumerate() {
+            let path = entry.path(&expected);
+            assert_eq!(
+                state.entry_index_by_path_and_stage(path, entry.stage()),
+                Some(idx),
+                "lookups find each entry where git put it"
+            );
+            assert_eq!(
+                state.entry_index_by_path(path).map(|first| first <= idx),
+                Ok(true),
+                "the first entry of a path is found"
+            );
+            if let Some(next) = expected.entries().get(idx + 1) {
+                assert_eq!(entry.cmp(next, &expected), Ordering::Less);
+            }
+        }
+        Ok(())
+    }
+}
+
+mod flags {
+    use gix_index::{entry::Flags, State};
+
+    #[test]
+    fn setters_maintain_the_extended_bit() {
+        let mut state = State::new(gix_hash::Kind::Sha1);
+        state.dangerously_push_entry(
+            Default::default(),
+            gix_hash::Kind::Sha1.null(),
+            Flags::empty(),
+            gix_index::entry::Mode::FILE,
+            "a".into(),
+        );
+        let entry = &mut state.entries_mut()[0];
+
+        entry.set_assume_valid(true);
+        assert!(entry.is_assume_valid());
+        assert!(
+            !entry.flags.contains(Flags::EXTENDED),
+            "assume-valid is stored in the basic flags"
+        );
+
+        entry.set_skip_worktree(true);
+        entry.set_intent_to_add(true);
+        assert!(entry.is_skip_worktree() && entry.is_intent_to_add());
+        assert!(entry.flags.contains(Flags::EXTENDED));
+
+        entry.set_skip_worktree(false);
+        assert!(entry.flags.contains(Flags::EXTENDED), "intent-to-add still needs it");
+        entry.set_intent_to_add(false);
+        assert!(!entry.is_skip_worktree() && !entry.is_intent_to_add());
+        assert!(
+            !entry.flags.contains(Flags::EXTENDED),
+            "without extended flags, V2 is sufficient again"
+        );
+        assert!(entry.is_assume_valid(), "other flags are untouched");
+    }
+}
+
+mod mode {
+    use gix::objs::tree::EntryMode;
+    use gix_index::entry::Mode;
+
+    #[test]
+    fn to_tree_entry_mode() {
+        for (mode, expected) in [
+            (Mode::DIR, EntryMode::Tree),
+            (Mode::FILE, EntryMode::Blob),
+            (Mode::FILE_EXECUTABLE, EntryMode::BlobExecutable),
+            (Mode::SYMLINK, EntryMode::Link),
+            (Mode::COMMIT, EntryMode::Commit),
+        ] {
+            assert_eq!(mode.to_tree_entry_mode(), Some(expected), "{mode:?}");
+        }
+        assert_eq!(
+            Mode::empty().to_tree_entry_mode(),
+            None,
+            "modes that can't be written into trees aren't valid"
+        );
+    }
+}
+
+mod stat {
+    use gix_index::entry::{
+        stat::{CheckStat, Options},
+        Stat, Time,
+    };
+
+    fn sample() -> Stat {
+        Stat {
+            mtime: Time { secs: 10, nsecs: 500 },
+            ctime: Time { secs: 5, nsecs: 100 },
+            dev: 1,
+            ino: 2,
+            uid: 3,
+            gid: 4,
+            size: 42,
+        }
+    }
+
+    fn minimal() -> Options {
+        Options {
+            check_stat: CheckStat::Minimal,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn size_and_seconds_of_modification_time_always_matter() {
+        let stat = sample();
+        assert!(stat.matches(&stat, Default::default()));
+        for other in [
+            Stat { size: 41, ..stat },
+            Stat {
+                mtime: Time { secs: 11, nsecs: 500 },
+                ..stat
+            },
+        ] {
+            assert!(!stat.matches(&other, Default::default()));
+            assert!(!stat.matches(&other, minimal()));
+        }
+    }
+
+    #[test]
+    fn nanoseconds_are_only_compared_if_both_sides_have_them() {
+        let stat = sample();
+        let other = Stat {
+            mtime: Time { secs: 10, nsecs: 501 },
+            ..stat
+        };
+        let use_nsec = Options {
+            use_nsec: true,
+            ..Default::default()
+        };
+        assert!(
+            stat.matches(&other, Default::default()),
+            "nanoseconds are ignored by default"
+        );
+        assert!(!stat.matches(&other, use_nsec));
+        assert!(
+            !stat.matches(
+                &Stat {
+                    ctime: Time { secs: 5, nsecs: 101 },
+                    ..stat
+                },
+                use_nsec
+            ),
+            "the ctime nanoseconds are compared as well"
+        );
+        assert!(
+            stat.matches(
+                &other,
+                Options {
+                    use_nsec: true,
+                    ..minimal()
+                }
+            ),
+            "minimal checks ignore nanoseconds"
+        );
+
+        let truncated = Stat {
+            mtime: Time { secs: 10, nsecs: 0 },
+            ctime: Time { secs: 5, nsecs: 0 },
+            ..stat
+        };
+        assert!(
+            stat.matches(&truncated, use_nsec),
+            "filesystems that truncate to seconds don't cause mismatches"
+        );
+        assert!(truncated.matches(&stat, use_nsec));
+    }
+
+    #[test]
+    fn untrusted_nanoseconds_are_never_compared() {
+        let stat = sample();
+        let other = Stat {
+            mtime: Time { secs: 10, nsecs: 501 },
+            ctime: Time { secs: 5, nsecs: 101 },
+            ..stat
+        };
+        assert!(stat.matches(
+            &other,
+            Options {
+                use_nsec: true,
+                trust_nsec: false,
+                ..Default::default()
+            }
+        ));
+    }
+
+    #[test]
+    fn ctime_is_only_compared_if_trusted() {
+        let stat = sample();
+        let other = Stat {
+            ctime: Time { secs: 6, nsecs: 100 },
+            ..stat
+        };
+        assert!(!stat.matches(&other, Default::default()));
+        assert!(stat.matches(
+            &other,
+            Options {
+                trust_ctime: false,
+                ..Default::default()
+            }
+        ));
+        assert!(stat.matches(&other, minimal()));
+    }
+
+    #[test]
+    fn inode_and_ownership_are_ignored_by_minimal_checks() {
+        let stat = sample();
+        for other in [
+            Stat { ino: 20, ..stat },
+            Stat { uid: 30, ..stat },
+            Stat { gid: 40, ..stat },
+        ] {
+            assert!(!stat.matches(&other, Default::default()));
+            assert!(stat.matches(&other, minimal()));
+        }
+    }
+
+    #[test]
+    fn device_is_only_compared_if_configured() {
+        let stat = sample();
+        let other = Stat { dev: 10, ..stat };
+        assert!(stat.matches(&other, Default::default()));
+        assert!(!stat.matches(
+            &other,
+            Options {
+                use_stdev: true,
+                ..Default::default()
+            }
+        ));
+        assert!(stat.matches(
+            &other,
+            Options {
+                use_stdev: true,
+                ..minimal()
+            }
+        ));
+    }
+
+    mod from_fs {
+        use std::path::Path;
+
+        use filetime::FileTime;
+        use gix_index::{
+            entry::{stat::Options, Mode, Stat},
+            State,
+        };
+
+        fn state_with_file(path: &Path, mode: Mode) -> crate::Result<State> {
+            let mut state = State::new(gix_hash::Kind::Sha1);
+            let stat = Stat::from_fs(&std::fs::symlink_metadata(path)?);
+            state.upsert_entry("file".into(), mode, gix_hash::Kind::Sha1.null(), stat)?;
+            Ok(state)
+        }
+
+        fn matches(state: &State, path: &Path, options: Options) -> crate::Result<bool> {
+            Ok(state.entry(0).stat_matches(&std::fs::symlink_metadata(path)?, options))
+        }
+
+        #[test]
+        fn files_changed_in_various_ways_do_not_match() -> crate::Result {
+            let dir = gix_testtools::tempfile::tempdir()?;
+            let path = dir.path().join("file");
+            std::fs::write(&path, "content")?;
+            let state = state_with_file(&path, Mode::FILE)?;
+            assert!(matches(&state, &path, Default::default())?, "nothing changed");
+            assert!(
+                !state
+                    .entry(0)
+                    .stat_matches(&std::fs::metadata(dir.path())?, Default::default()),
+                "a directory is a different type"
+            );
+
+            std::fs::write(&path, "other content")?;
+            assert!(!matches(&state, &path, Default::default())?, "the size changed");
+
+            std::fs::write(&path, "content")?;
+            let mtime = FileTime::from_unix_time(1_000_000_000, 0);
+            filetime::set_file_mtime(&path, mtime)?;
+            assert!(!matches(&state, &path, Default::default())?, "the mtime changed");
+
+            let state = state_with_file(&path, Mode::FILE)?;
+            assert!(
+                matches(&state, &path, Default::default())?,
+                "files with old modification times match as well"
+            );
+            Ok(())
+        }
+
+        #[test]
+        fn set_stat_from_metadata_makes_changed_files_match() -> crate::Result {
+            let dir = gix_testtools::tempfile::tempdir()?;
+            let path = dir.path().join("file");
+            std::fs::write(&path, "content")?;
+            let mut state = state_with_file(&path, Mode::FILE)?;
+
+            std::fs::write(&path, "new content")?;
+            assert!(!matches(&state, &path, Default::default())?);
+
+            let metadata = std::fs::symlink_metadata(&path)?;
+            state.entries_mut()[0].set_stat_from_metadata(&metadata, Default::default());
+            assert_eq!(state.entry(0).stat, Stat::from_fs(&metadata));
+            assert_eq!(state.entry(0).stat.size, 11);
+            assert!(matches(&state, &path, Default::default())?);
+            Ok(())
+        }
+
+        #[test]
+        #[cfg(unix)]
+        fn nanoseconds_are_recorded_unless_untrusted() -> crate::Result {
+            use std::os::unix::fs::MetadataExt;
+
+            let dir = gix_testtools::tempfile::tempdir()?;
+            let path = dir.path().join("file");
+            std::fs::write(&path, "content")?;
+            filetime::set_file_mtime(&path, FileTime::from_unix_time(1_000_000_000, 123_456_789))?;
+            let metadata = std::fs::symlink_metadata(&path)?;
+            let stat = Stat::from_fs(&metadata);
+            assert_eq!(stat.mtime.secs, 1_000_000_000);
+            assert_eq!(stat.mtime.nsecs as i64, metadata.mtime_nsec());
+            assert_eq!(stat.ctime.nsecs as i64, metadata.ctime_nsec());
+
+            let mut state = state_with_file(&path, Mode::FILE)?;
+            let untrusted = Options {
+                use_nsec: true,
+                trust_nsec: false,
+                ..Default::default()
+            };
+            state.entries_mut()[0].set_stat_from_metadata(&metadata, untrusted);
+            assert_eq!(state.entry(0).stat.mtime.nsecs, 0);
+            assert_eq!(state.entry(0).stat.ctime.nsecs, 0);
+            assert_eq!(state.entry(0).stat.mtime.secs, 1_000_000_000);
+
+            filetime::set_file_mtime(&path, FileTime::from_unix_time(1_000_000_000, 987_654_321))?;
+            assert!(
+                matches(&state, &path, untrusted)?,
+                "only the nanoseconds changed, which aren't trusted"
+            );
+            let state = state_with_file(&path, Mode::FILE)?;
+            filetime::set_file_mtime(&path, FileTime::from_unix_time(1_000_000_000, 123_456_789))?;
+            if metadata.mtime_nsec() != 0 {
+                assert!(
+                    !matches(
+                        &state,
+                        &path,
+                        Options {
+                            use_nsec: true,
+                            trust_ctime: false,
+                            ..Default::default()
+                        }
+                    )?,
+                    "trusted nanoseconds are compared"
+                );
+            }
+            Ok(())
+        }
+
+        #[test]
+        #[cfg(windows)]
+        fn timestamps_are_converted_from_ticks_like_the_standard_library_does() -> crate::Result {
+            let dir = gix_testtools::tempfile::tempdir()?;
+            let path = dir.path().join("file");
+            std::fs::write(&path, "content")?;
+            filetime::set_file_mtime(&path, FileTime::from_unix_time(1_000_000_000, 123_456_700))?;
+            let metadata = std::fs::symlink_metadata(&path)?;
+            let stat = Stat::from_fs(&metadata);
+            for (time, system_time) in [(stat.mtime, metadata.modified()?), (stat.ctime, metadata.created()?)] {
+                let expected = system_time.duration_since(std::time::UNIX_EPOCH)?;
+                assert_eq!(time.secs as u64, expected.as_secs());
+                assert_eq!(time.nsecs, expected.subsec_nanos());
+            }
+            assert_eq!(stat.mtime.nsecs, 123_456_700, "NTFS keeps 100ns precision");
+            Ok(())
+        }
+
+        #[test]
+        #[cfg(unix)]
+        fn executable_bit_is_only_compared_if_trusted() -> crate::Result {
+            use std::os::unix::fs::PermissionsExt;
+
+            let dir = gix_testtools::tempfile::tempdir()?;
+            let path = dir.path().join("file");
+            std::fs::write(&path, "content")?;
+            let state = state_with_file(&path, Mode::FILE_EXECUTABLE)?;
+            assert!(
+                !matches(&state, &path, Default::default())?,
+                "the file isn't executable"
+            );
+
+            let options = Options {
+                trust_executable_bit: false,
+                trust_ctime: false,
+                ..Default::default()
+            };
+            assert!(matches(&state, &path, options)?);
+
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+            assert!(
+                matches(
+                    &state,
+                    &path,
+                    Options {
+                        trust_ctime: false,
+                        ..Default::default()
+                    }
+                )?,
+                "now the file is executable, changing only the ctime"
+            );
+            Ok(())
+        }
+
+        #[test]
+        #[cfg(unix)]
+        fn symlinks_may_be_files_if_unsupported() -> crate::Result {
+            let dir = gix_testtools::tempfile::tempdir()?;
+            let path = dir.path().join("file");
+            std::fs::write(&path, "content")?;
+            let state = state_with_file(&path, Mode::SYMLINK)?;
+            assert!(!matches(&state, &path, Default::default())?, "a file isn't a symlink");
+            assert!(matches(
+                &state,
+                &path,
+                Options {
+                    has_symlinks: false,
+                    ..Default::default()
+                }
+            )?);
+            Ok(())
+        }
+    }
+}
@@ -1,3 +1,44 @@
+mod at {
+    use crate::index::Fixture::Generated;
+
+    #[test]
+    fn missing_files_are_a_distinct_error() {
+        let err = gix_index::File::at(
+            "__definitely no file that exists ever__",
+            gix_hash::Kind::Sha1,
+            Default::default(),
+        )
+        .expect_err("there is no such file");
+        assert!(
+            matches!(&err, gix_index::file::init::Error::NotFound { path } if path.to_str() == Some("__definitely no file that exists ever__")),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn large_files_are_read_just_like_small_ones() -> gix_testtools::Result {
+        let small_path = Generated("v4_more_files_IEOT").to_path();
+        let data = std::fs::read(&small_path)?;
+        let (before_checksum, _checksum) = data.split_at(data.len() - 20);
+        let payload = vec![42; 64 * 1024];
+        let mut large = before_checksum.to_vec();
+        large.extend_from_slice(b"ZZZZ");
+        large.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        large.extend_from_slice(&payload);
+        large.extend_from_slice(&[0; 20]);
+
+        let tmp = gix_testtools::tempfile::TempDir::new()?;
+        let large_path = tmp.path().join("index");
+        std::fs::write(&large_path, &large)?;
+
+        let small = gix_index::File::at(small_path, gix_hash::Kind::Sha1, Default::default())?;
+        let large = gix_index::File::at(large_path, gix_hash::Kind::Sha1, Default::default())?;
+        assert_eq!(large.entries(), small.entries());
+        assert_eq!(large.unknown_extensions()[0].data, payload);
+        Ok(())
+    }
+}
+
 mod at_or_new {
     use crate::index::Fixture::Generated;
 
@@ -23,10 +64,51 @@ mod at_or_new {
         assert_eq!(index.object_hash(), gix_hash::Kind::Sha1, "object hash is respected");
         assert_eq!(index.entries().len(), 0, "index is empty");
     }
+
+    #[test]
+    fn errors_other_than_a_missing_file_are_propagated() -> gix_testtools::Result {
+        let tmp = gix_testtools::tempfile::TempDir::new()?;
+        let corrupt_path = tmp.path().join("index");
+        std::fs::write(&corrupt_path, b"DIRC")?;
+        let err = gix_index::File::at_or_default(&corrupt_path, gix_hash::Kind::Sha1, Default::default())
+            .expect_err("corrupt files are not replaced with a default");
+        assert!(matches!(err, gix_index::file::init::Error::Decode(_)), "{err:?}");
+
+        let err = gix_index::File::at_or_default(tmp.path(), gix_hash::Kind::Sha1, Default::default())
+            .expect_err("directories can't be read as index");
+        assert!(matches!(err, gix_index::file::init::Error::Io(_)), "{err:?}");
+        Ok(())
+    }
+
+    #[test]
+    fn written_default_is_an_empty_index_to_git() -> gix_testtools::Result {
+        let tmp = gix_testtools::scripted_fixture_writable_standalone("make_index/V2_empty.sh")?;
+        let index_path = tmp.path().join(".git").join("index");
+        std::fs::remove_file(&index_path)?;
+        let mut index = gix_index::File::at_or_default(&index_path, gix_hash::Kind::Sha1, Default::default())?;
+        assert_eq!(index.path(), index_path, "the path is kept for writing");
+        index.write(Default::default())?;
+        assert!(index_path.is_file());
+        assert_eq!(index.version(), gix_index::Version::V2);
+        index.verify_integrity()?;
+
+        let output = crate::index::git(tmp.path()).args(["ls-files", "--stage"]).output()?;
+        assert!(output.status.success(), "git can read the index");
+        assert!(output.stdout.is_empty(), "there are no entries");
+
+        let output = crate::index::git(tmp.path()).arg("write-tree").output()?;
+        assert!(output.status.success());
+        assert_eq!(
+            output.stdout.as_slice(),
+            format!("{}\n", gix_hash::ObjectId::empty_tree(gix_hash::Kind::Sha1)).as_bytes(),
+            "the index represents the empty tree"
+        );
+        Ok(())
+    }
 }
 
 mod from_state {
-    use gix_index::Version::{V2, V3};
+    use gix_index::Version::{V2, V3, V4};
 
     use crate::index::Fixture::*;
 
@@ -38,7 +120,7 @@ mod from_state {
             (Generated("V2_empty"), V2),
             (Generated("v2_more_files"), V2),
             (Generated("v2_all_file_kinds"), V2),
-            (Generated("v4_more_files_IEOT"), V2),
+            (Generated("v4_more_files_IEOT"), V4),
         ];
 
         for (fixture, expected_version) in fixtures {
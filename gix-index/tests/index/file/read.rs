@@ -3,6 +3,7 @@ use std::path::{Path, PathBuf};
 use bstr::ByteSlice;
 use gix_index::{
     entry::{self, Flags, Mode},
+    extension::fs_monitor,
     Version,
 };
 
@@ -103,6 +104,58 @@ fn v2_with_multiple_entries_without_eoie_ext() {
     assert_eq!(tree.name.as_bstr(), "d");
 }
 
+#[test]
+fn v2_with_nested_trees_matching_ls_tree() {
+    let base = gix_testtools::scripted_fixture_read_only_standalone(Path::new("make_index").join("v2_deeper_tree.sh"))
+        .unwrap();
+    let file = verify(gix_index::File::at(base.join(".git/index"), gix_hash::Kind::Sha1, Default::default()).unwrap());
+    let root = file.tree().expect("cache tree is present");
+    assert!(root.name.is_empty());
+    assert_eq!(root.num_entries, Some(file.entries().len() as u32));
+    assert_eq!(
+        root.id,
+        hex_to_id(std::fs::read_to_string(base.join("tree-id")).unwrap().trim())
+    );
+
+    fn collect(tree: &gix_index::extension::Tree, prefix: &str, out: &mut Vec<(String, gix_hash::ObjectId)>) {
+        for child in &tree.children {
+            let path = format!("{prefix}{}", child.name.as_bstr());
+            out.push((path.clone(), child.id));
+            collect(child, &format!("{path}/"), out);
+        }
+    }
+    let mut actual = Vec::new();
+    collect(root, "", &mut actual);
+
+    let expected: Vec<_> = std::fs::read_to_string(base.join("ls-tree-output"))
+        .unwrap()
+        .lines()
+        .map(|line| {
+            let (info, path) = line.split_once('\t').expect("tab separated");
+            (path.to_owned(), hex_to_id(info.rsplit(' ').next().expect("id")))
+        })
+        .collect();
+    assert_eq!(
+        actual, expected,
+        "the cache tree hierarchy is the same as the one of the committed tree"
+    );
+}
+
+#[test]
+fn tree_extension_with_trailing_data_is_ignored() {
+    let data = b"\x001 0\n\xe6\x9d\xe2\x9b\xb2\xd1\xd6\x43\x4b\x8b\x29\xae\x77\x5a\xd8\xc2\xe4\x8c\x53\x91";
+    let tree = gix_index::extension::tree::decode(data, gix_hash::Kind::Sha1).expect("valid");
+    assert_eq!(tree.num_entries, Some(1));
+    assert_eq!(tree.id, hex_to_id("e69de29bb2d1d6434b8b29ae775ad8c2e48c5391"));
+
+    let mut data = data.to_vec();
+    data.push(b'x');
+    assert!(
+        gix_index::extension::tree::decode(&data, gix_hash::Kind::Sha1).is_none(),
+        "trailing data means the extension is malformed"
+    );
+}
+
 fn find_shared_index_for(index: impl AsRef<Path>) -> PathBuf {
     let mut matches = std::fs::read_dir(index.as_ref().parent().unwrap())
         .unwrap()
@@ -130,6 +183,26 @@ fn v3_extended_flags() {
     assert_eq!(file.version(), Version::V3);
 }
 
+#[test]
+fn v2_with_extended_flag_is_rejected() {
+    let mut data = std::fs::read(loose_file_path("very-long-path")).unwrap();
+    let (header_len, stat_and_mode_len, hash_len) = (12, 40, 20);
+    let first_entry_flags = header_len + stat_and_mode_len + hash_len;
+    data[first_entry_flags] |= 0x40;
+
+    let err = gix_index::State::from_bytes(
+        &data,
+        filetime::FileTime::now(),
+        gix_hash::Kind::Sha1,
+        Default::default(),
+    )
+    .expect_err("extended flags are invalid in V2");
+    assert!(
+        matches!(err, gix_index::decode::Error::Entry { index: 0, offset: 12 }),
+        "the first entry is the one with the extended flag set, got {err:?}"
+    );
+}
+
 #[test]
 fn v2_very_long_path() {
     let file = loose_file("very-long-path");
@@ -154,6 +227,67 @@ fn v2_very_long_path() {
     assert!(tree.id.is_null(), "there is no id for the root")
 }
 
+#[test]
+fn paths_longer_than_the_length_in_flags_roundtrip_in_all_versions() {
+    let dir = gix_testtools::scripted_fixture_read_only_standalone(
+        Path::new("make_index").join("very_long_path_all_versions.sh"),
+    )
+    .unwrap();
+    let long_path = format!("{}{}", "d/".repeat(100), "f".repeat(4800));
+    for (name, version) in [
+        ("v2-index", Version::V2),
+        ("v3-index", Version::V3),
+        ("v4-index", Version::V4),
+    ] {
+        let data = std::fs::read(dir.join(name)).unwrap();
+        let file = verify(gix_index::File::at(dir.join(name), gix_hash::Kind::Sha1, Default::default()).unwrap());
+        assert_eq!(file.version(), version);
+        assert_eq!(
+            file.entries().iter().map(|e| e.path(&file)).collect::<Vec<_>>(),
+            ["a", long_path.as_str(), "z"]
+        );
+
+        let mut buf = Vec::new();
+        file.write_to(&mut buf, Default::default()).unwrap();
+        assert_eq!(buf, data, "{name}: we write long paths exactly like git does");
+    }
+}
+
+#[test]
+fn v2_entries_with_full_stat_data() {
+    let file = loose_file("very-long-path");
+    assert_eq!(file.version(), Version::V2);
+
+    let entry = &file.entries()[3];
+    assert_eq!(entry.path(&file), "path2");
+    assert_eq!(entry.id, hex_to_id("f87290f8eb2cbbea7857214459a0739927eab154"));
+    assert_eq!(entry.mode, entry::Mode::FILE);
+    assert!(entry.flags.is_empty());
+    assert_eq!(
+        entry.stat,
+        entry::Stat {
+            ctime: entry::Time {
+                secs: 1642582231,
+                nsecs: 601588614
+            },
+            mtime: entry::Time {
+                secs: 1642582231,
+                nsecs: 262818863
+            },
+            dev: 16777230,
+            ino: 44337761,
+            uid: 501,
+            gid: 20,
+            size: 12,
+        },
+        "values match what `git ls-files --debug` reports"
+    );
+
+    let symlink = &file.entries()[2];
+    assert_eq!(symlink.path(&file), "path0sym");
+    assert_eq!(symlink.mode, entry::Mode::SYMLINK);
+}
+
 #[test]
 fn reuc_extension() {
     let file = loose_file("REUC");
@@ -167,7 +301,26 @@ fn untr_extension() {
     let file = loose_file("UNTR");
     assert_eq!(file.version(), Version::V2);
 
-    assert!(file.untracked().is_some());
+    let untracked = file.untracked().expect("present");
+    assert!(untracked.identifier().starts_with(b"Location ") && untracked.identifier().contains_str("system Darwin"));
+    assert_eq!(untracked.exclude_filename_per_dir(), ".gitignore");
+    assert!(untracked.info_exclude().is_none(), "there is no such file");
+    assert!(untracked.excludes_file().is_some());
+
+    let dirs = untracked.directories();
+    assert_eq!(
+        dirs.iter().map(|d| d.name.as_bstr()).collect::<Vec<_>>(),
+        ["", "done", "dthree", "dtwo"]
+    );
+    assert_eq!(dirs[0].untracked_entries, ["three", "dtwo/", "dthree/"]);
+    assert_eq!(dirs[0].sub_directories, [1, 2, 3]);
+    assert!(dirs[1].untracked_entries.is_empty());
+    assert_eq!(dirs[2].untracked_entries, ["three"]);
+    assert_eq!(dirs[3].untracked_entries, ["two"]);
+    assert!(
+        dirs.iter().all(|d| d.stat.is_some() && d.sub_directories.len() <= 3),
+        "all directories have valid stat information"
+    );
 }
 
 #[test]
@@ -178,12 +331,111 @@ fn untr_extension_with_oids() {
     assert!(file.untracked().is_some());
 }
 
+#[test]
+fn optional_extensions_can_be_skipped_and_are_recorded() {
+    use gix_index::{decode::Load, extension};
+    let open = |path: &Path, extensions: Load, thread_limit: Option<usize>| {
+        gix_index::File::at(
+            path,
+            gix_hash::Kind::Sha1,
+            gix_index::decode::Options {
+                extensions,
+                thread_limit,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+
+    let path = &loose_file_path("REUC");
+    let all = open(path, Load::All, None);
+    assert!(all.tree().is_some() && all.resolve_undo().is_some());
+    assert!(all.skipped_extensions().is_empty());
+
+    let none = open(path, Load::None, None);
+    assert_eq!(none.entries(), all.entries());
+    assert!(none.tree().is_none() && none.resolve_undo().is_none());
+    assert_eq!(
+        none.skipped_extensions(),
+        [extension::tree::SIGNATURE, extension::resolve_undo::SIGNATURE]
+    );
+
+    let only_tree = open(path, Load::Only(&[extension::tree::SIGNATURE]), None);
+    assert!(only_tree.tree().is_some() && only_tree.resolve_undo().is_none());
+    assert_eq!(only_tree.skipped_extensions(), [extension::resolve_undo::SIGNATURE]);
+
+    let path = &crate::fixture_index_path("v4_more_files_IEOT");
+    let all = open(path, Load::All, Some(1));
+    for thread_limit in [1, 4] {
+        let none = open(path, Load::None, Some(thread_limit));
+        assert_eq!(
+            none.entries(),
+            all.entries(),
+            "EOIE and IEOT are still used to find entries"
+        );
+        assert!(none.tree().is_none());
+        assert_eq!(
+            none.skipped_extensions(),
+            [extension::tree::SIGNATURE],
+            "EOIE and IEOT are not recorded as they are recreated when writing"
+        );
+    }
+}
+
 #[test]
 fn fsmn_v1() {
-    let file = loose_file("FSMN");
+    let mut file = loose_file("FSMN");
     assert_eq!(file.version(), Version::V2);
 
-    assert!(file.fs_monitor().is_some());
+    let fs_monitor = file.fs_monitor().expect("present");
+    assert_eq!(
+        fs_monitor.token(),
+        &fs_monitor::Token::V2 {
+            token: "1642331326943378000".into()
+        }
+    );
+    assert_eq!(fs_monitor.entry_dirty().iter().collect::<Vec<_>>(), [0, 1, 2, 3, 4, 5]);
+
+    assert!(file.entries().iter().all(|e| !e.flags.contains(Flags::FSMONITOR_VALID)));
+    assert!(file.apply_fs_monitor());
+    assert_eq!(
+        file.entries()
+            .iter()
+            .map(|e| e.flags.contains(Flags::FSMONITOR_VALID))
+            .collect::<Vec<_>>(),
+        (0..file.entries().len()).map(|idx| idx > 5).collect::<Vec<_>>(),
+        "only entries not marked dirty are valid"
+    );
+
+    let token = fs_monitor::Token::V1 { nanos_since_1970: 42 };
+    file.fs_monitor_mut().expect("present").set_token(token.clone());
+    assert_eq!(file.fs_monitor().expect("present").token(), &token);
+}
+
+#[test]
+fn fsmn_v1_token() {
+    let mut data = Vec::new();
+    data.extend_from_slice(&1u32.to_be_bytes());
+    data.extend_from_slice(&1642331326943378000u64.to_be_bytes());
+    let ewah = b"\0\0\0\x02\0\0\0\x02\0\0\0\x02\0\0\0\0\0\0\0\0\0\0\0\x02\0\0\0\0";
+    data.extend_from_slice(&(ewah.len() as u32).to_be_bytes());
+    data.extend_from_slice(ewah);
+
+    let fs_monitor = fs_monitor::decode(&data).expect("valid");
+    assert_eq!(
+        fs_monitor.token(),
+        &fs_monitor::Token::V1 {
+            nanos_since_1970: 1642331326943378000
+        }
+    );
+    assert_eq!(fs_monitor.entry_dirty().iter().collect::<Vec<_>>(), [1]);
+
+    data.push(0);
+    assert!(fs_monitor::decode(&data).is_none(), "trailing bytes are rejected");
+    assert!(
+        fs_monitor::decode(&data[..data.len() - 4]).is_none(),
+        "truncated bitmaps are rejected"
+    );
 }
 
 #[test]
@@ -222,12 +474,633 @@ fn v4_with_delta_paths_and_ieot_ext() {
     }
 }
 
+#[test]
+fn v4_with_ieot_ext_decodes_the_same_with_any_amount_of_threads() {
+    let data = std::fs::read(crate::fixture_index_path("v4_more_files_IEOT")).unwrap();
+    let decode = |data: &[u8], thread_limit: usize| {
+        gix_index::State::from_bytes(
+            data,
+            filetime::FileTime::now(),
+            gix_hash::Kind::Sha1,
+            gix_index::decode::Options {
+                thread_limit: Some(thread_limit),
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .0
+    };
+    let serial = decode(&data, 1);
+    assert_eq!(serial.entries().len(), 10);
+    for thread_limit in [2, 3, 10] {
+        let parallel = decode(&data, thread_limit);
+        assert_eq!(parallel.entries(), serial.entries());
+        assert_eq!(parallel.path_backing(), serial.path_backing());
+    }
+
+    let mut corrupt_ieot = data.clone();
+    let ieot_ext = corrupt_ieot
+        .windows(4)
+        .rposition(|w| w == b"IEOT")
+        .expect("IEOT extension is present");
+    let (signature_and_size_len, version_len) = (8, 4);
+    let first_offset = ieot_ext + signature_and_size_len + version_len;
+    corrupt_ieot[first_offset..][..4].copy_from_slice(&u32::MAX.to_be_bytes());
+    let parallel = decode(&corrupt_ieot, 2);
+    assert_eq!(
+        parallel.entries(),
+        serial.entries(),
+        "an invalid offset table makes us fall back to reading entries serially"
+    );
+}
+
+#[test]
+fn eoie_with_mismatching_hash_is_ignored_and_reported() {
+    let data = std::fs::read(crate::fixture_index_path("v4_more_files_IEOT")).unwrap();
+    let decode = |data: &[u8], thread_limit: usize| {
+        gix_index::State::from_bytes(
+            data,
+            filetime::FileTime::now(),
+            gix_hash::Kind::Sha1,
+            gix_index::decode::Options {
+                thread_limit: Some(thread_limit),
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    let (expected, outcome) = decode(&data, 1);
+    assert!(!outcome.end_of_index_entry_mismatch, "the fixture is valid");
+
+    let mut corrupt_eoie = data.clone();
+    let hash_len = gix_hash::Kind::Sha1.len_in_bytes();
+    let last_byte_of_eoie_hash = corrupt_eoie.len() - hash_len - 1;
+    corrupt_eoie[last_byte_of_eoie_hash] ^= 0xff;
+    for thread_limit in [1, 2] {
+        let (actual, outcome) = decode(&corrupt_eoie, thread_limit);
+        assert!(outcome.end_of_index_entry_mismatch);
+        assert_eq!(
+            actual.entries(),
+            expected.entries(),
+            "extensions are found by reading all entries instead"
+        );
+        assert_eq!(actual.tree(), expected.tree());
+    }
+}
+
+#[test]
+fn trailing_checksum_is_verified_on_request() {
+    let decode = |data: &[u8], thread_limit: usize, verify_checksum: bool| {
+        gix_index::State::from_bytes(
+            data,
+            filetime::FileTime::now(),
+            gix_hash::Kind::Sha1,
+            gix_index::decode::Options {
+                thread_limit: Some(thread_limit),
+                verify_checksum,
+                ..Default::default()
+            },
+        )
+    };
+    let data = std::fs::read(crate::fixture_index_path("v4_more_files_IEOT")).unwrap();
+    for thread_limit in [1, 4] {
+        let (_state, outcome) = decode(&data, thread_limit, true).expect("fixtures have valid checksums");
+        assert_eq!(outcome.checksum.expect("not null").as_bytes(), &data[data.len() - 20..]);
+    }
+
+    let mut corrupt = data.clone();
+    let first_byte_of_first_entry_ctime = 12;
+    corrupt[first_byte_of_first_entry_ctime] ^= 0xff;
+    decode(&corrupt, 1, false).expect("without verification, the corruption goes unnoticed");
+    for thread_limit in [1, 4] {
+        let err = decode(&corrupt, thread_limit, true).unwrap_err();
+        assert!(
+            matches!(err, gix_index::decode::Error::ChecksumMismatch { expected_checksum, .. } if expected_checksum.as_bytes() == &data[data.len() - 20..]),
+            "{err:?}"
+        );
+    }
+}
+
+#[test]
+fn null_trailing_checksum_is_accepted_but_not_reported() {
+    let path = crate::fixture_index_path("v2_null_checksum");
+    let data = std::fs::read(&path).unwrap();
+    assert!(
+        data[data.len() - 20..].iter().all(|b| *b == 0),
+        "the fixture is prepared"
+    );
+    for (thread_limit, verify_checksum) in [(1, false), (1, true), (4, true)] {
+        let (state, outcome) = gix_index::State::from_bytes(
+            &data,
+            filetime::FileTime::now(),
+            gix_hash::Kind::Sha1,
+            gix_index::decode::Options {
+                thread_limit: Some(thread_limit),
+                verify_checksum,
+                ..Default::default()
+            },
+        )
+        .expect("null checksums are never a mismatch");
+        assert_eq!(outcome.checksum, None);
+        assert_eq!(state.entries().len(), 3);
+    }
+
+    let file = gix_index::File::at(path, gix_hash::Kind::Sha1, Default::default()).unwrap();
+    assert_eq!(file.checksum(), None, "there is no checksum to compare to");
+    file.verify_entries().unwrap();
+}
+
+#[test]
+fn guessed_entry_offsets_decode_the_same_as_serial_decoding() {
+    let decode = |data: &[u8], thread_limit: usize, guess_entry_offsets_for_threading: bool| {
+        gix_index::State::from_bytes(
+            data,
+            filetime::FileTime::now(),
+            gix_hash::Kind::Sha1,
+            gix_index::decode::Options {
+                thread_limit: Some(thread_limit),
+                guess_entry_offsets_for_threading,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .0
+    };
+    for path in [
+        crate::fixture_index_path("v2"),
+        crate::fixture_index_path("v2_more_files"),
+        crate::fixture_index_path("v3_skip_worktree"),
+        crate::fixture_index_path("v3_sparse_index"),
+        crate::fixture_index_path("v4_more_files_IEOT"),
+        loose_file_path("very-long-path"),
+        loose_file_path("extended-flags"),
+        loose_file_path("conflicting-file"),
+        loose_file_path("UNTR"),
+    ] {
+        let data = std::fs::read(&path).unwrap();
+        let serial = decode(&data, 1, false);
+        for thread_limit in [2, 3, 4, 8] {
+            let guessed = decode(&data, thread_limit, true);
+            assert_eq!(guessed.entries(), serial.entries(), "{path:?}");
+            assert_eq!(guessed.path_backing(), serial.path_backing(), "{path:?}");
+            assert_eq!(guessed.is_sparse(), serial.is_sparse(), "{path:?}");
+            assert_eq!(guessed.tree(), serial.tree(), "{path:?}");
+        }
+    }
+}
+
+#[test]
+fn reading_from_a_stream_is_the_same_as_decoding_all_bytes() {
+    let decode = |data: &[u8], buf_size: Option<usize>, verify_checksum: bool| {
+        let options = gix_index::decode::Options {
+            thread_limit: Some(1),
+            verify_checksum,
+            ..Default::default()
+        };
+        let timestamp = filetime::FileTime::from_unix_time(42, 0);
+        match buf_size {
+            None => gix_index::State::from_bytes(data, timestamp, gix_hash::Kind::Sha1, options)
+                .map_err(|err| format!("{err:?}")),
+            Some(size) => gix_index::State::from_read(
+                std::io::BufReader::with_capacity(size, data),
+                timestamp,
+                gix_hash::Kind::Sha1,
+                options,
+            )
+            .map_err(|err| match err {
+                gix_index::decode::from_read::Error::Decode(err) => format!("{err:?}"),
+                gix_index::decode::from_read::Error::Io(err) => unreachable!("reading from memory can't fail: {err}"),
+            }),
+        }
+        .map(|(state, outcome)| {
+            let mut buf = Vec::new();
+            state.write_to(&mut buf, Default::default()).expect("valid");
+            (
+                state.entries().to_owned(),
+                state.path_backing().to_owned(),
+                buf,
+                outcome,
+            )
+        })
+    };
+
+    let mut datas = Vec::new();
+    for path in [
+        crate::fixture_index_path("V2_empty"),
+        crate::fixture_index_path("v2"),
+        crate::fixture_index_path("v2_more_files"),
+        crate::fixture_index_path("v2_null_checksum"),
+        crate::fixture_index_path("v2_split_index"),
+        crate::fixture_index_path("v3_sparse_index"),
+        crate::fixture_index_path("v4_more_files_IEOT"),
+        loose_file_path("very-long-path"),
+        loose_file_path("extended-flags"),
+        loose_file_path("conflicting-file"),
+        loose_file_path("REUC"),
+        loose_file_path("UNTR-with-oids"),
+        loose_file_path("FSMN"),
+    ] {
+        datas.push(std::fs::read(path).unwrap());
+    }
+
+    for data in &datas {
+        let expected = decode(data, None, true);
+        assert!(expected.is_ok(), "{:?}", expected.as_ref().err());
+        for buf_size in [1, 7, 8 * 1024] {
+            assert_eq!(decode(data, Some(buf_size), true), expected);
+        }
+    }
+
+    let data = &datas[6];
+    let mut corrupt_eoie = data.clone();
+    let last_byte_of_eoie_hash = corrupt_eoie.len() - 20 - 1;
+    corrupt_eoie[last_byte_of_eoie_hash] ^= 0xff;
+    let expected = decode(&corrupt_eoie, None, false);
+    assert!(expected
+        .as_ref()
+        .map_or(false, |(.., outcome)| outcome.end_of_index_entry_mismatch));
+    assert_eq!(decode(&corrupt_eoie, Some(1), false), expected);
+
+    let mut corrupt = data.clone();
+    corrupt[12] ^= 0xff;
+    let expected = decode(&corrupt, None, true);
+    assert!(expected.is_err());
+    assert_eq!(decode(&corrupt, Some(1), true), expected, "checksums are verified");
+
+    for len in 0..data.len() {
+        let truncated = &data[..len];
+        assert_eq!(
+            decode(truncated, Some(3), false),
+            decode(truncated, None, false),
+            "truncated to {len} bytes, the same errors are produced"
+        );
+    }
+}
+
+#[test]
+fn v4_delta_paths_match_v2_paths_of_same_content() {
+    let base = gix_testtools::scripted_fixture_read_only_standalone(Path::new("make_index").join("v2_vs_v4_index.sh"))
+        .unwrap();
+    let v2 = verify(gix_index::File::at(base.join("v2-index"), gix_hash::Kind::Sha1, Default::default()).unwrap());
+    let v4 = verify(gix_index::File::at(base.join("v4-index"), gix_hash::Kind::Sha1, Default::default()).unwrap());
+    assert_eq!(v2.version(), Version::V2);
+    assert_eq!(v4.version(), Version::V4);
+
+    assert_eq!(v2.entries().len(), 8);
+    assert_eq!(v2.entries(), v4.entries(), "stat, id, flags and mode are the same");
+    assert_eq!(
+        v2.entries().iter().map(|e| e.path(&v2)).collect::<Vec<_>>(),
+        v4.entries().iter().map(|e| e.path(&v4)).collect::<Vec<_>>(),
+        "paths are reconstructed correctly, including ones that replace the previous path entirely"
+    );
+}
+
+#[test]
+fn v4_with_malformed_delta_paths_is_rejected() {
+    let (header_len, stat_and_mode_len, hash_len, flags_len) = (12, 40, 20, 2);
+    let start_of_path = header_len + stat_and_mode_len + hash_len + flags_len;
+    let data = std::fs::read(
+        gix_testtools::scripted_fixture_read_only_standalone(Path::new("make_index").join("v2_vs_v4_index.sh"))
+            .unwrap()
+            .join("v4-index"),
+    )
+    .unwrap();
+
+    let mut truncated_varint = data[..start_of_path].to_vec();
+    truncated_varint.push(0xff);
+    let mut overflowing_varint = data[..start_of_path].to_vec();
+    overflowing_varint.extend(std::iter::repeat(0xff).take(11));
+    overflowing_varint.extend_from_slice(&data[start_of_path + 1..]);
+
+    for (data, case) in [
+        (truncated_varint, "truncated varint"),
+        (overflowing_varint, "varint overflows 64 bits"),
+    ] {
+        let err = gix_index::State::from_bytes(
+            &data,
+            filetime::FileTime::now(),
+            gix_hash::Kind::Sha1,
+            Default::default(),
+        )
+        .expect_err(case);
+        assert!(
+            matches!(err, gix_index::decode::Error::Entry { index: 0, offset: 12 }),
+            "{case}: got {err:?}"
+        );
+    }
+}
+
+mod corrupt {
+    use bstr::ByteSlice;
+    use gix_index::{decode, extension};
+
+    fn decode(data: &[u8]) -> Result<gix_index::State, decode::Error> {
+        gix_index::State::from_bytes(
+            data,
+            filetime::FileTime::now(),
+            gix_hash::Kind::Sha1,
+            Default::default(),
+        )
+        .map(|(state, _)| state)
+    }
+
+    fn v2_more_files() -> Vec<u8> {
+        std::fs::read(crate::fixture_index_path("v2_more_files")).unwrap()
+    }
+
+    #[test]
+    fn truncated_entries_report_the_index_and_offset_of_the_failing_entry() {
+        let data = v2_more_files();
+        let state = decode(&data).unwrap();
+        let (header_len, entry_len_sans_path, hash_len) = (12, 62, 20);
+        let offsets: Vec<_> = state
+            .entries()
+            .iter()
+            .scan(header_len, |offset, e| {
+                let entry_offset = *offset;
+                *offset += (entry_len_sans_path + e.path(&state).len() + 8) & !7;
+                Some(entry_offset)
+            })
+            .collect();
+        let last = offsets.len() - 1;
+
+        let mut truncated = data[..offsets[last] + 30].to_vec();
+        truncated.extend(std::iter::repeat(0).take(hash_len));
+        let err = decode(&truncated).unwrap_err();
+        assert!(
+            matches!(err, decode::Error::Entry { index, offset } if index as usize == last && offset == offsets[last]),
+            "{err:?}"
+        );
+        assert_eq!(
+            err.to_string(),
+            format!("Could not parse entry at index {last} at byte offset {}", offsets[last])
+        );
+    }
+
+    #[test]
+    fn extensions_exceeding_the_file_report_their_signature_and_offset() {
+        let mut data = v2_more_files();
+        let tree_offset = data.find(extension::tree::SIGNATURE).expect("tree extension present");
+        data[tree_offset + 4..][..4].copy_from_slice(&u32::MAX.to_be_bytes());
+
+        let err = decode(&data).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                decode::Error::Extension {
+                    signature,
+                    offset,
+                    source: extension::decode::Error::SizeExceedsData { size: u32::MAX, .. }
+                } if signature == extension::tree::SIGNATURE && offset == tree_offset
+            ),
+            "{err:?}"
+        );
+        assert_eq!(
+            err.to_string(),
+            format!("Could not decode extension 'TREE' at byte offset {tree_offset}")
+        );
+    }
+
+    fn index_with_extensions(extensions: &[u8]) -> Vec<u8> {
+        let mut data = b"DIRC\0\0\0\x02\0\0\0\0".to_vec();
+        data.extend_from_slice(extensions);
+        data.extend(std::iter::repeat(0).take(20));
+        data
+    }
+
+    #[test]
+    fn extension_iter_stops_at_sizes_exceeding_the_data_and_tells_why() {
+        let mut iter = extension::Iter::new(b"ABCD\0\0\0\x01xTREE\0\0\0\x09abc");
+        assert_eq!(iter.next(), Some((*b"ABCD", &b"x"[..])));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next(), None, "it's fused");
+        assert_eq!(iter.consumed, 9, "the malformed extension isn't consumed");
+        assert_eq!(
+            iter.malformed(),
+            Some(extension::iter::Malformed::SizeExceedsData {
+                signature: *b"TREE",
+                size: 9,
+                available: 3
+            })
+        );
+
+        let mut iter = extension::Iter::new(b"ABCD\0\0\0\0TRE");
+        assert_eq!(iter.by_ref().count(), 1);
+        assert_eq!(
+            iter.malformed(),
+            Some(extension::iter::Malformed::TrailingBytes { len: 3 })
+        );
+
+        let mut iter = extension::Iter::new(b"ABCD\0\0\0\0");
+        assert_eq!(iter.by_ref().count(), 1);
+        assert_eq!(iter.malformed(), None);
+    }
+
+    #[test]
+    fn extension_sizes_exceeding_the_data_are_an_error_at_the_offset_of_the_extension() {
+        for (extensions, expected_size, expected_available) in [
+            (&b"TREE\xff\xff\xff\xff"[..], u32::MAX, 0),
+            (b"TREE\0\0\0\x05abcd", 5, 4),
+            (b"ABCD\0\0\0\0REUC\x80\0\0\0", 0x8000_0000, 0),
+        ] {
+            let err = decode(&index_with_extensions(extensions)).unwrap_err();
+            let expected_offset = 12 + extensions.len() - 8 - expected_available;
+            assert!(
+                matches!(
+                    err,
+                    decode::Error::Extension {
+                        offset,
+                        source: extension::decode::Error::SizeExceedsData { size, available },
+                        ..
+                    } if offset == expected_offset && size == expected_size && available == expected_available
+                ),
+                "{err:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn many_empty_extensions_are_decoded_and_trailing_bytes_are_rejected() {
+        let state = decode(&index_with_extensions(&b"ABCD\0\0\0\0".repeat(1000))).unwrap();
+        assert_eq!(state.unknown_extensions().len(), 1000);
+
+        let err = decode(&index_with_extensions(b"ABCD\0\0\0\0TRE")).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                decode::Error::UnexpectedTrailerLength {
+                    expected: 20,
+                    actual: 23
+                }
+            ),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn entry_counts_exceeding_the_file_fail_at_the_first_missing_entry() {
+        let data = v2_more_files();
+        let state = decode(&data).unwrap();
+        let end_of_entries = data
+            .find(extension::tree::SIGNATURE)
+            .expect("tree extension follows entries");
+        let mut data = data[..end_of_entries].to_vec();
+        data.extend(std::iter::repeat(0).take(20));
+        data[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+
+        let err = decode(&data).unwrap_err();
+        assert!(
+            matches!(err, decode::Error::Entry { index, offset } if index as usize == state.entries().len() && offset == end_of_entries),
+            "{err:?}"
+        );
+    }
+
+    fn decode_leniently(data: &[u8]) -> Result<(gix_index::State, decode::Outcome), decode::Error> {
+        gix_index::State::from_bytes(
+            data,
+            filetime::FileTime::now(),
+            gix_hash::Kind::Sha1,
+            decode::Options {
+                mode: decode::Mode::Lenient,
+                ..Default::default()
+            },
+        )
+    }
+
+    fn paths(state: &gix_index::State) -> Vec<&bstr::BStr> {
+        state.entries().iter().map(|e| e.path(state)).collect()
+    }
+
+    #[test]
+    fn lenient_mode_salvages_entries_if_more_are_declared_than_present() {
+        let data = v2_more_files();
+        let expected = decode(&data).unwrap();
+        let num_entries = expected.entries().len() as u32;
+
+        let mut too_many_declared = data.clone();
+        too_many_declared[8..12].copy_from_slice(&(num_entries + 3).to_be_bytes());
+        let end_of_entries = data
+            .find(extension::tree::SIGNATURE)
+            .expect("tree extension follows entries");
+        let mut interrupted = data[..end_of_entries].to_vec();
+        interrupted.truncate(interrupted.len() - 5);
+
+        for (data, num_recovered_entries) in [(too_many_declared, num_entries), (interrupted, num_entries - 1)] {
+            assert!(
+                matches!(decode(&data).unwrap_err(), decode::Error::Entry { .. }),
+                "strict mode keeps failing"
+            );
+            let (state, outcome) = decode_leniently(&data).unwrap();
+            assert_eq!(
+                outcome.recovery,
+                Some(decode::Recovery {
+                    num_declared_entries: u32::from_be_bytes(data[8..12].try_into().unwrap()),
+                    num_recovered_entries,
+                    extensions_unreachable: true,
+                })
+            );
+            assert_eq!(outcome.checksum, None, "the trailer isn't used when recovering");
+            assert_eq!(paths(&state), paths(&expected)[..num_recovered_entries as usize]);
+            assert!(state.tree().is_none(), "without EOIE, extensions can't be found");
+
+            let mut buf = Vec::new();
+            state.write_to(&mut buf, Default::default()).expect("valid");
+            buf.extend(std::iter::repeat(0).take(20));
+            let rewritten = decode(&buf).expect("recovered states are valid");
+            assert_eq!(paths(&rewritten), paths(&state));
+        }
+    }
+
+    #[test]
+    fn lenient_mode_finds_extensions_with_eoie_but_drops_the_tree_if_entries_are_missing() {
+        let mut data = std::fs::read(crate::fixture_index_path("v4_more_files_IEOT")).unwrap();
+        let expected = decode(&data).unwrap();
+        assert!(expected.tree().is_some());
+        let num_entries = expected.entries().len() as u32;
+        data[8..12].copy_from_slice(&(num_entries + 1).to_be_bytes());
+
+        let (state, outcome) = decode_leniently(&data).unwrap();
+        assert_eq!(
+            outcome.recovery,
+            Some(decode::Recovery {
+                num_declared_entries: num_entries + 1,
+                num_recovered_entries: num_entries,
+                extensions_unreachable: false,
+            })
+        );
+        assert_eq!(paths(&state), paths(&expected), "delta paths are recovered as well");
+        assert!(
+            state.tree().is_none(),
+            "the cache tree would claim more entries than there are"
+        );
+    }
+
+    #[test]
+    fn lenient_mode_skips_trailing_garbage_after_entries() {
+        let data = v2_more_files();
+        let expected = decode(&data).unwrap();
+        let end_of_entries = data
+            .find(extension::tree::SIGNATURE)
+            .expect("tree extension follows entries");
+
+        let mut instead_of_extensions = data[..end_of_entries].to_vec();
+        instead_of_extensions.extend_from_slice(b"trailing garbage");
+        instead_of_extensions.extend(std::iter::repeat(0).take(20));
+        let mut before_extensions = data[..end_of_entries].to_vec();
+        before_extensions.extend_from_slice(b"garbage");
+        before_extensions.extend_from_slice(&data[end_of_entries..]);
+
+        for data in [instead_of_extensions, before_extensions] {
+            decode(&data).expect_err("strict mode keeps failing");
+            let (state, outcome) = decode_leniently(&data).unwrap();
+            assert_eq!(
+                outcome.recovery,
+                Some(decode::Recovery {
+                    num_declared_entries: expected.entries().len() as u32,
+                    num_recovered_entries: expected.entries().len() as u32,
+                    extensions_unreachable: true,
+                })
+            );
+            assert_eq!(paths(&state), paths(&expected));
+            assert!(state.tree().is_none());
+        }
+    }
+
+    #[test]
+    fn lenient_mode_only_recovers_if_needed_and_never_with_an_expected_checksum() {
+        let data = v2_more_files();
+        let (state, outcome) = decode_leniently(&data).unwrap();
+        assert_eq!(outcome.recovery, None, "valid indices are decoded as a whole");
+        assert!(outcome.checksum.is_some());
+        assert!(state.tree().is_some());
+
+        let mut truncated = data[..data.len() - 30].to_vec();
+        truncated[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        let err = gix_index::State::from_bytes(
+            &truncated,
+            filetime::FileTime::now(),
+            gix_hash::Kind::Sha1,
+            decode::Options {
+                mode: decode::Mode::Lenient,
+                expected_checksum: outcome.checksum,
+                ..Default::default()
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(err, decode::Error::Entry { .. }), "{err:?}");
+    }
+}
+
 #[test]
 fn sparse_checkout_non_sparse_index() {
     let file = file("v3_skip_worktree");
 
     assert_eq!(file.version(), Version::V3);
     assert!(!file.is_sparse());
+    assert!(
+        file.entries().iter().any(|e| e.flags.contains(Flags::SKIP_WORKTREE)),
+        "the skip-worktree bit set by `git update-index --skip-worktree` is visible"
+    );
     file.entries().iter().for_each(|e| {
         assert_eq!(e.mode, Mode::FILE);
         let path = e.path(&file);
@@ -249,8 +1122,11 @@ fn sparse_checkout_cone_mode() {
         let path = e.path(&file);
         if path.starts_with("c1/c3".as_bytes()) || path.starts_with("d".as_bytes()) {
             assert_eq!(e.mode, Mode::DIR);
+            assert!(e.is_sparse_dir());
+            assert!(path.ends_with(b"/"), "sparse directories have a trailing slash");
             assert_eq!(e.flags, Flags::EXTENDED | Flags::SKIP_WORKTREE);
         } else {
+            assert!(!e.is_sparse_dir());
             assert_eq!(e.mode, Mode::FILE);
             assert_eq!(e.flags, Flags::empty());
         }
@@ -300,6 +1176,38 @@ fn v2_split_index_recursion_is_handled_gracefully() {
     ));
 }
 
+#[test]
+fn split_index_without_its_shared_index_fails_with_typed_error() -> crate::Result {
+    let split_index = crate::fixture_index_path("v2_split_index");
+    let shared_index = find_shared_index_for(&split_index);
+    let expected_checksum = gix_hash::ObjectId::from_hex(
+        &shared_index
+            .file_name()
+            .expect("present")
+            .to_str()
+            .expect("valid UTF-8")
+            .as_bytes()["sharedindex.".len()..],
+    )?;
+
+    let tmp = gix_testtools::tempfile::TempDir::new()?;
+    let index_path = tmp.path().join("index");
+    std::fs::copy(split_index, &index_path)?;
+
+    let err = gix_index::File::at(&index_path, gix_hash::Kind::Sha1, Default::default())
+        .expect_err("the shared index is missing");
+    match err {
+        gix_index::file::init::Error::MissingSharedIndex {
+            path,
+            expected_checksum: actual_checksum,
+        } => {
+            assert_eq!(actual_checksum, expected_checksum);
+            assert_eq!(path, tmp.path().join(shared_index.file_name().expect("present")));
+        }
+        err => panic!("unexpected error: {err:?}"),
+    }
+    Ok(())
+}
+
 #[test]
 fn split_index_and_regular_index_of_same_content_are_indeed_the_same() {
     let base = gix_testtools::scripted_fixture_read_only_standalone(
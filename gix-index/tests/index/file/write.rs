@@ -14,12 +14,34 @@ fn roundtrips() -> crate::Result {
             Generated("v2"),
             options_with(write::Extensions::Given {
                 tree_cache: true,
+                link: false,
+                resolve_undo: false,
+                untracked_cache: false,
+                fs_monitor: false,
+                entry_offset_table: false,
                 end_of_index_entry: true,
+                unknown: false,
             }),
         ),
         (Generated("V2_empty"), only_tree_ext()),
         (Generated("v2_more_files"), only_tree_ext()),
         (Generated("v2_all_file_kinds"), only_tree_ext()),
+        (Generated("v2_deeper_tree"), only_tree_ext()),
+        (Generated("v3_skip_worktree"), only_tree_ext()),
+        (Generated("v3_sparse_index_non_cone"), only_tree_ext()),
+        (Generated("v3_sparse_index"), only_tree_ext()),
+        (Generated("v2_sparse_index_no_dirs"), only_tree_ext()),
+        (Loose("REUC"), all_ext_but_eoie()),
+        (Loose("FSMN"), all_ext_but_eoie()),
+        (Loose("UNTR"), all_ext_but_eoie()),
+        (Loose("UNTR-with-oids"), all_ext_but_eoie()),
+        (
+            Generated("v4_more_files_IEOT"),
+            Options {
+                entry_offset_table_blocks: Some(2),
+                ..options_with(write::Extensions::All)
+            },
+        ),
     ];
 
     for (fixture, options) in input {
@@ -27,7 +49,7 @@ fn roundtrips() -> crate::Result {
         let expected_bytes = std::fs::read(fixture.to_path())?;
         let mut out_bytes = Vec::new();
 
-        let (actual_version, _digest) = expected.write_to(&mut out_bytes, options)?;
+        let (actual_version, _digest) = write_with_unchanged_worktree(&expected, &mut out_bytes, options)?;
         let (actual, _) = State::from_bytes(&out_bytes, FileTime::now(), gix_hash::Kind::Sha1, Default::default())?;
 
         let name = fixture.to_name();
@@ -38,41 +60,82 @@ fn roundtrips() -> crate::Result {
 }
 
 #[test]
-fn roundtrips_sparse_index() -> crate::Result {
-    // NOTE: I initially tried putting these fixtures into the main roundtrip test above,
-    // but the call to `compare_raw_bytes` panics. It seems like git is using a different
-    // ordering when it comes to writing the tree extension. Need to investigate more, hence
-    // the separate test for now.
-    //
-    //          git                     gitoxide
-    //
-    //          treeroot                treeroot
-    //            | d                     | c1
-    //            | d/c4                  | c1/c2
-    //            | c1                    | c1/c3
-    //            | c1/c2                 | d
-    //            | c1/c3                 | d/c4
-    //
-
-    let input = [
-        ("v3_skip_worktree", only_tree_ext()),
-        ("v3_sparse_index_non_cone", only_tree_ext()),
-        ("v3_sparse_index", only_tree_ext()),
-        ("v2_sparse_index_no_dirs", only_tree_ext()),
-    ];
-
-    for (fixture, options) in input {
-        let fixture = Generated(fixture);
-        let expected = fixture.open();
-        let _expected_bytes = std::fs::read(fixture.to_path())?;
+fn v4_paths_are_compressed_like_git_does() -> crate::Result {
+    let dir = gix_testtools::scripted_fixture_read_only_standalone("make_index/v2_vs_v4_index.sh")?;
+    let v4_bytes = std::fs::read(dir.join("v4-index"))?;
+    for (name, options) in [
+        ("v4-index", only_tree_ext()),
+        (
+            "v2-index",
+            Options {
+                version: Some(Version::V4),
+                ..only_tree_ext()
+            },
+        ),
+    ] {
+        let expected = gix_index::File::at(dir.join(name), gix_hash::Kind::Sha1, Default::default())?;
         let mut out_bytes = Vec::new();
+        let (actual_version, _digest) = write_with_unchanged_worktree(&expected, &mut out_bytes, options)?;
+        assert_eq!(actual_version, Version::V4);
 
-        let (actual_version, _) = expected.write_to(&mut out_bytes, options)?;
         let (actual, _) = State::from_bytes(&out_bytes, FileTime::now(), gix_hash::Kind::Sha1, Default::default())?;
+        assert_eq!(actual.entries(), expected.entries());
+        assert_eq!(actual.path_backing(), expected.path_backing());
+        compare_raw_bytes(&out_bytes, &v4_bytes, name);
+    }
+    Ok(())
+}
 
-        compare_states_against_baseline(&actual, actual_version, &expected, options, fixture.to_name());
-        // TODO: make this work and re-enable it, once this is done the fixtures can be merged into the main "roundtrip" test
-        // compare_raw_bytes(&out_bytes, &_expected_bytes, fixture);
+#[test]
+fn entry_offset_table_allows_decoding_entries_in_parallel() -> crate::Result {
+    let fixture = Generated("v2_more_files");
+    let expected = fixture.open();
+    let num_entries = expected.entries().len();
+    for (num_blocks, expected_blocks) in [
+        (None, 0),
+        (Some(1), 0),
+        (Some(3), 3),
+        (Some(num_entries * 2), num_entries),
+    ] {
+        let tmp = gix_testtools::tempfile::TempDir::new()?;
+        let index_path = tmp.path().join("index");
+        expected.write_to(
+            std::fs::File::create(&index_path)?,
+            Options {
+                entry_offset_table_blocks: num_blocks,
+                ..Default::default()
+            },
+        )?;
+
+        let out_bytes = std::fs::read(&index_path)?;
+        let ieot = out_bytes.windows(4).position(|w| w == b"IEOT");
+        match expected_blocks {
+            0 => assert!(ieot.is_none(), "too few blocks don't make the extension worth it"),
+            n => {
+                let ieot = ieot.expect("present");
+                let size = u32::from_be_bytes(out_bytes[ieot + 4..][..4].try_into()?) as usize;
+                assert_eq!(size, 4 + n * 8, "{num_blocks:?} blocks were requested");
+            }
+        }
+
+        let (actual, _) = State::from_bytes(
+            &out_bytes,
+            FileTime::now(),
+            gix_hash::Kind::Sha1,
+            gix_index::decode::Options {
+                thread_limit: Some(4),
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(actual.entries(), expected.entries());
+
+        let worktree = crate::index::fixture_worktree_path(fixture.to_name());
+        let output = crate::index::git(&worktree)
+            .args(["-c", "index.threads=true", "ls-files", "--stage"])
+            .env("GIT_INDEX_FILE", &index_path)
+            .output()?;
+        assert!(output.status.success(), "git can read the index");
+        assert_eq!(output.stdout.iter().filter(|b| **b == b'\n').count(), num_entries);
     }
     Ok(())
 }
@@ -92,32 +155,41 @@ fn state_comparisons_with_various_extension_configurations() {
         Generated("v2_more_files"),
         Generated("v2_all_file_kinds"),
         Generated("v2_split_index"),
-        // TODO: this fails because git allows to configure the index version while gitoxide doesn't
-        //       the fixture artificially sets the version to V4 and gitoxide writes it back out as the lowest required version, V2
-        // Generated("v4_more_files_IEOT"),
+        Generated("v4_more_files_IEOT"),
         Generated("v3_skip_worktree"),
         Generated("v3_sparse_index_non_cone"),
         Generated("v3_sparse_index"),
-        // TODO: this fails because git writes the sdir extension in this case while gitoxide doesn't
-        // Generated("v2_sparse_index_no_dirs"),
+        Generated("v2_sparse_index_no_dirs"),
     ] {
         for options in [
             options_with(write::Extensions::None),
             options_with(write::Extensions::All),
             options_with(write::Extensions::Given {
                 tree_cache: true,
+                link: true,
+                resolve_undo: true,
+                untracked_cache: true,
+                fs_monitor: true,
+                entry_offset_table: true,
                 end_of_index_entry: false,
+                unknown: false,
             }),
             options_with(write::Extensions::Given {
                 tree_cache: false,
+                link: false,
+                resolve_undo: false,
+                untracked_cache: false,
+                fs_monitor: false,
+                entry_offset_table: false,
                 end_of_index_entry: true,
+                unknown: false,
             }),
         ] {
             let expected = fixture.open();
             let fixture = fixture.to_name();
 
             let mut out = Vec::<u8>::new();
-            let (actual_version, _digest) = expected.write_to(&mut out, options).unwrap();
+            let (actual_version, _digest) = write_with_unchanged_worktree(&expected, &mut out, options).unwrap();
 
             let (actual, _) =
                 State::from_bytes(&out, FileTime::now(), gix_hash::Kind::Sha1, Default::default()).unwrap();
@@ -139,6 +211,207 @@ fn extended_flags_automatically_upgrade_the_version_to_avoid_data_loss() -> crat
     Ok(())
 }
 
+#[test]
+fn the_lowest_sufficient_version_is_chosen_unless_v4_was_used_or_requested() -> crate::Result {
+    let written_version = |index: &gix_index::File, version: Option<Version>| -> crate::Result<Version> {
+        let (actual_version, _digest) = index.write_to(
+            Vec::new(),
+            Options {
+                version,
+                ..Default::default()
+            },
+        )?;
+        Ok(actual_version)
+    };
+
+    let v2 = Generated("v2").open();
+    assert_eq!(
+        written_version(&v2, None)?,
+        Version::V2,
+        "nothing requires more than V2"
+    );
+    assert_eq!(
+        written_version(&v2, Some(Version::V4))?,
+        Version::V4,
+        "V4 is used when asked for"
+    );
+
+    let mut v3 = Loose("extended-flags").open();
+    assert_eq!(v3.version(), Version::V3);
+    assert_eq!(written_version(&v3, None)?, Version::V3, "extended flags require V3");
+    for entry in v3.entries_mut() {
+        entry
+            .flags
+            .remove(entry::Flags::EXTENDED | entry::Flags::INTENT_TO_ADD | entry::Flags::SKIP_WORKTREE);
+    }
+    assert_eq!(
+        written_version(&v3, None)?,
+        Version::V2,
+        "without extended flags, V2 is sufficient again"
+    );
+
+    let mut v4 = Generated("v4_more_files_IEOT").open();
+    assert_eq!(written_version(&v4, None)?, Version::V4, "V4 is retained once chosen");
+    v4.entries_mut()[0].flags.insert(entry::Flags::SKIP_WORKTREE);
+    assert_eq!(
+        written_version(&v4, None)?,
+        Version::V4,
+        "V4 can store extended flags as well"
+    );
+    Ok(())
+}
+
+#[test]
+fn extended_flags_are_written_even_if_the_extended_bit_is_not_set() -> crate::Result {
+    let fixture = Generated("v2");
+    let mut expected = fixture.open();
+    let first = &mut expected.entries_mut()[0];
+    assert!(!first.flags.contains(entry::Flags::EXTENDED));
+    first.flags.insert(entry::Flags::SKIP_WORKTREE);
+
+    let tmp = gix_testtools::tempfile::TempDir::new()?;
+    let index_path = tmp.path().join("index");
+    let (actual_version, _digest) = expected.write_to(std::fs::File::create(&index_path)?, Default::default())?;
+    assert_eq!(actual_version, Version::V3, "skip-worktree needs V3");
+
+    let actual = gix_index::File::at(&index_path, gix_hash::Kind::Sha1, Default::default())?;
+    assert!(actual.entries()[0].flags.contains(entry::Flags::SKIP_WORKTREE));
+
+    let worktree = crate::index::fixture_worktree_path(fixture.to_name());
+    let output = crate::index::git(&worktree)
+        .args(["ls-files", "-t"])
+        .env("GIT_INDEX_FILE", &index_path)
+        .output()?;
+    assert!(output.status.success());
+    assert!(
+        output.stdout.starts_with(b"S "),
+        "git sees the first entry as skip-worktree: {:?}",
+        bstr::BStr::new(&output.stdout)
+    );
+    Ok(())
+}
+
+#[test]
+fn entry_flags_set_via_accessors_are_understood_by_git() -> crate::Result {
+    let fixture = Generated("v2_more_files");
+    let mut index = fixture.open();
+    assert_eq!(
+        index.set_skip_worktree_by_pathspec("d/".into(), true),
+        3,
+        "all entries in the directory are affected"
+    );
+    assert_eq!(
+        index.set_skip_worktree_by_pathspec("d/".into(), true),
+        0,
+        "nothing changes the second time"
+    );
+    index
+        .entry_mut_by_path_and_stage("a".into(), 0)
+        .expect("present")
+        .set_assume_valid(true);
+    index
+        .entry_mut_by_path_and_stage("b".into(), 0)
+        .expect("present")
+        .set_intent_to_add(true);
+
+    let tmp = gix_testtools::tempfile::TempDir::new()?;
+    let index_path = tmp.path().join("index");
+    let (actual_version, _digest) = index.write_to(std::fs::File::create(&index_path)?, Default::default())?;
+    assert_eq!(actual_version, Version::V3, "extended flags need V3");
+
+    let actual = gix_index::File::at(&index_path, gix_hash::Kind::Sha1, Default::default())?;
+    assert_eq!(
+        actual
+            .entries()
+            .iter()
+            .map(|e| (e.is_assume_valid(), e.is_intent_to_add(), e.is_skip_worktree()))
+            .collect::<Vec<_>>(),
+        index
+            .entries()
+            .iter()
+            .map(|e| (e.is_assume_valid(), e.is_intent_to_add(), e.is_skip_worktree()))
+            .collect::<Vec<_>>()
+    );
+
+    let worktree = crate::index::fixture_worktree_path(fixture.to_name());
+    let git = |args: &[&str]| -> crate::Result<bstr::BString> {
+        let output = crate::index::git(&worktree)
+            .args(args)
+            .env("GIT_INDEX_FILE", &index_path)
+            .output()?;
+        assert!(output.status.success(), "git can read the index");
+        Ok(output.stdout.into())
+    };
+    assert_eq!(
+        git(&["ls-files", "-v"])?,
+        "h a\nH b\nH c\nS d/a\nS d/b\nS d/c\n",
+        "assume-valid entries are lower-case, skip-worktree entries are marked with 'S'"
+    );
+    assert_eq!(
+        git(&["diff", "--name-status", "--", "b"])?,
+        "A\tb\n",
+        "intent-to-add entries appear as added"
+    );
+
+    for entry in index.entries_mut() {
+        entry.set_intent_to_add(false);
+    }
+    assert_eq!(index.set_skip_worktree_by_pathspec("".into(), false), 3);
+    let (actual_version, _digest) = index.write_to(Vec::new(), Default::default())?;
+    assert_eq!(
+        actual_version,
+        Version::V2,
+        "clearing the flags makes V2 sufficient again"
+    );
+    Ok(())
+}
+
+#[test]
+fn forcing_v2_with_extended_flags_is_an_error() {
+    let mut expected = Generated("v2").open();
+    expected.entries_mut()[0].flags.insert(entry::Flags::INTENT_TO_ADD);
+    let expected_path = expected.entries()[0].path(&expected).to_owned();
+
+    let err = expected
+        .write_to(
+            Vec::new(),
+            Options {
+                version: Some(Version::V2),
+                ..Default::default()
+            },
+        )
+        .unwrap_err();
+    assert!(
+        matches!(err, write::Error::ExtendedFlagsNotSupported { ref path, version: Version::V2 } if *path == expected_path),
+        "{err:?}"
+    );
+
+    let (actual_version, _digest) = expected
+        .write_to(
+            Vec::new(),
+            Options {
+                version: Some(Version::V3),
+                ..Default::default()
+            },
+        )
+        .expect("V3 can store extended flags");
+    assert_eq!(actual_version, Version::V3);
+}
+
+#[test]
+fn tree_extension_is_skipped_if_there_is_no_tree() -> crate::Result {
+    let state = State::new(gix_hash::Kind::Sha1);
+    assert!(state.tree().is_none());
+
+    let mut buf = Vec::new();
+    state.write_to(&mut buf, options_with(write::Extensions::All))?;
+    assert!(
+        !buf.windows(4).any(|w| w == extension::tree::SIGNATURE),
+        "there is nothing to write"
+    );
+    Ok(())
+}
+
 #[test]
 fn remove_flag_is_respected() -> crate::Result {
     let mut index = Generated("v4_more_files_IEOT").open();
@@ -168,6 +441,588 @@ fn remove_flag_is_respected() -> crate::Result {
     Ok(())
 }
 
+#[test]
+fn fs_monitor_extension_is_dropped_if_entries_are_removed() -> crate::Result {
+    let mut index = Loose("FSMN").open();
+    assert!(index.fs_monitor().is_some());
+    index.entries_mut()[0].flags.insert(entry::Flags::REMOVE);
+
+    let mut buf = Vec::<u8>::new();
+    index.write_to(&mut buf, Default::default())?;
+    let (state, _checksum) = State::from_bytes(&buf, FileTime::now(), gix_hash::Kind::Sha1, Default::default())?;
+    assert!(
+        state.fs_monitor().is_none(),
+        "the dirty bitmap would refer to the wrong entries"
+    );
+    Ok(())
+}
+
+mod racy {
+    use filetime::FileTime;
+    use gix_index::{entry, State};
+
+    use crate::index::Fixture::*;
+
+    const TIMESTAMP: u32 = 1_700_000_000;
+
+    /// Return a state read at `TIMESTAMP` whose first entries were modified before, at and after it, in that order, followed by a
+    /// submodule modified at it and more entries modified before it, with all of them having a non-zero size.
+    fn state_with_racy_entries() -> crate::Result<State> {
+        let data = std::fs::read(Generated("v2_more_files").to_path())?;
+        let (mut state, _) = State::from_bytes(
+            &data,
+            FileTime::from_unix_time(TIMESTAMP.into(), 0),
+            gix_hash::Kind::Sha1,
+            Default::default(),
+        )?;
+        for (entry, mtime) in state.entries_mut().iter_mut().zip(
+            [TIMESTAMP - 1, TIMESTAMP, TIMESTAMP + 1, TIMESTAMP]
+                .into_iter()
+                .chain(std::iter::repeat(TIMESTAMP - 1)),
+        ) {
+            entry.stat.mtime.secs = mtime;
+            entry.stat.size = 42;
+        }
+        state.entries_mut()[3].mode = entry::Mode::COMMIT;
+        Ok(state)
+    }
+
+    fn written_sizes(state: &State) -> crate::Result<Vec<u32>> {
+        let mut buf = Vec::new();
+        state.write_to(&mut buf, Default::default())?;
+        buf.extend_from_slice(&[0; 20]);
+        let (state, _) = State::from_bytes(&buf, FileTime::now(), gix_hash::Kind::Sha1, Default::default())?;
+        Ok(state.entries().iter().map(|e| e.stat.size).collect())
+    }
+
+    #[test]
+    fn entries_modified_at_or_after_the_timestamp_are_racy() -> crate::Result {
+        let state = state_with_racy_entries()?;
+        assert_eq!(
+            state
+                .entries()
+                .iter()
+                .map(|e| state.is_entry_racy(e))
+                .collect::<Vec<_>>(),
+            [false, true, true, false, false, false],
+            "submodules are never racy"
+        );
+
+        let data = std::fs::read(Generated("v2_more_files").to_path())?;
+        let (state, _) = State::from_bytes(&data, FileTime::zero(), gix_hash::Kind::Sha1, Default::default())?;
+        assert!(
+            state.entries().iter().all(|e| !state.is_entry_racy(e)),
+            "without timestamp, nothing is racy"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn nanoseconds_are_compared_if_both_times_have_them() -> crate::Result {
+        let mut state = state_with_racy_entries()?;
+        state.set_timestamp(FileTime::from_unix_time(TIMESTAMP.into(), 500));
+        for (entry, nsecs) in state.entries_mut().iter_mut().zip([400, 500, 600, 0]) {
+            entry.stat.mtime.secs = TIMESTAMP;
+            entry.stat.mtime.nsecs = nsecs;
+            entry.mode = entry::Mode::FILE;
+        }
+        assert_eq!(
+            state.entries()[..4]
+                .iter()
+                .map(|e| state.is_entry_racy(e))
+                .collect::<Vec<_>>(),
+            [false, true, true, true],
+            "without nanoseconds, the same second is racy"
+        );
+
+        state.set_timestamp(FileTime::from_unix_time(TIMESTAMP.into(), 0));
+        assert!(
+            state.entries()[..4].iter().all(|e| state.is_entry_racy(e)),
+            "a timestamp without nanoseconds makes the whole second racy"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn writing_sets_the_timestamp_to_the_modification_time_of_the_index() -> crate::Result {
+        let tmp = gix_testtools::tempfile::TempDir::new()?;
+        let file_path = tmp.path().join("a");
+        std::fs::write(&file_path, b"content")?;
+        let mut state = State::new(gix_hash::Kind::Sha1);
+        state.dangerously_push_entry(
+            entry::Stat::from_fs(&std::fs::metadata(&file_path)?),
+            gix_hash::Kind::Sha1.null(),
+            entry::Flags::empty(),
+            entry::Mode::FILE,
+            "a".into(),
+        );
+
+        let index_path = tmp.path().join("index");
+        let mut index = gix_index::File::from_state(state, &index_path);
+        index.write(Default::default())?;
+        let index_mtime = FileTime::from_last_modification_time(&std::fs::metadata(&index_path)?);
+        assert_eq!(index.timestamp(), index_mtime);
+        assert_eq!(
+            gix_index::File::at(&index_path, gix_hash::Kind::Sha1, Default::default())?.timestamp(),
+            index_mtime,
+            "it's the same as if the index was read"
+        );
+
+        for (mtime, expected_racy) in [
+            (index_mtime, true),
+            (
+                FileTime::from_unix_time(index_mtime.unix_seconds(), index_mtime.nanoseconds() / 2),
+                index_mtime.nanoseconds() / 2 == 0,
+            ),
+            (FileTime::from_unix_time(index_mtime.unix_seconds() + 1, 0), true),
+            (FileTime::from_unix_time(index_mtime.unix_seconds() - 1, 0), false),
+        ] {
+            filetime::set_file_mtime(&file_path, mtime)?;
+            index.entries_mut()[0].set_stat_from_metadata(&std::fs::metadata(&file_path)?, Default::default());
+            assert_eq!(
+                index.is_entry_racy(&index.entries()[0]),
+                expected_racy,
+                "{mtime:?} compared to index at {index_mtime:?}"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn racy_entries_are_smudged_when_written() -> crate::Result {
+        let state = state_with_racy_entries()?;
+        assert_eq!(written_sizes(&state)?, [42, 0, 0, 42, 42, 42]);
+        assert!(
+            state.entries().iter().all(|e| e.stat.size == 42),
+            "the state itself isn't altered"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn racy_entries_with_unchanged_content_are_written_as_is() -> crate::Result {
+        let mut state = state_with_racy_entries()?;
+        let changed_path = state.entry(2).path(&state).to_owned();
+        let mut seen = Vec::new();
+        state.check_racy_entries(|path, entry| {
+            seen.push(path.to_owned());
+            (path != changed_path).then_some(entry.id)
+        });
+        assert_eq!(seen.len(), 2, "only racy entries are checked");
+        assert!(state.entry(1).flags.contains(entry::Flags::UPTODATE));
+        assert_eq!(state.entry(2).stat.size, 0, "changed entries are smudged right away");
+
+        assert_eq!(written_sizes(&state)?, [42, 42, 0, 42, 42, 42]);
+        Ok(())
+    }
+}
+
+#[test]
+fn unknown_optional_extensions_are_written_back_verbatim() -> crate::Result {
+    let with_extension = |signature: &[u8; 4], payload: &[u8]| {
+        let data = std::fs::read(crate::loose_file_path("very-long-path")).expect("fixture is present");
+        let (before_checksum, checksum) = data.split_at(data.len() - 20);
+        let mut data = before_checksum.to_vec();
+        data.extend_from_slice(signature);
+        data.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        data.extend_from_slice(payload);
+        data.extend_from_slice(checksum);
+        data
+    };
+
+    let data = with_extension(b"ZZZZ", b"opaque");
+    let (state, _outcome) = State::from_bytes(&data, FileTime::now(), gix_hash::Kind::Sha1, Default::default())?;
+    assert_eq!(
+        state.unknown_extensions(),
+        &[extension::Unknown {
+            signature: *b"ZZZZ",
+            data: b"opaque".to_vec()
+        }]
+    );
+
+    let mut buf = Vec::<u8>::new();
+    state.write_to(&mut buf, all_ext_but_eoie())?;
+    compare_raw_bytes(&buf, &data[..data.len() - 20], "very-long-path with ZZZZ");
+
+    buf.clear();
+    state.write_to(&mut buf, only_tree_ext())?;
+    buf.extend_from_slice(&[0; 20]);
+    let (state, _outcome) = State::from_bytes(&buf, FileTime::now(), gix_hash::Kind::Sha1, Default::default())?;
+    assert!(
+        state.unknown_extensions().is_empty(),
+        "unknown extensions can be dropped"
+    );
+
+    let data = with_extension(b"zzzz", b"opaque");
+    let err = State::from_bytes(&data, FileTime::now(), gix_hash::Kind::Sha1, Default::default()).unwrap_err();
+    assert!(
+        matches!(
+            err,
+            gix_index::decode::Error::Extension { signature, source: extension::decode::Error::MandatoryUnimplemented, .. } if &signature == b"zzzz"
+        ),
+        "unknown lower-case extensions are mandatory and can't be ignored, got {err:?}"
+    );
+    Ok(())
+}
+
+#[test]
+fn untracked_cache_extension_can_be_stripped() -> crate::Result {
+    let index = Loose("UNTR").open();
+    assert!(index.untracked().is_some());
+
+    let mut buf = Vec::<u8>::new();
+    index.write_to(&mut buf, only_tree_ext())?;
+    let (state, _outcome) = State::from_bytes(&buf, FileTime::now(), gix_hash::Kind::Sha1, Default::default())?;
+    assert!(state.untracked().is_none(), "it was dropped on request");
+    assert_eq!(state.entries().len(), index.entries().len());
+    Ok(())
+}
+
+#[test]
+fn link_extension_is_written_back_but_cannot_be_stripped_without_shared_entries() -> crate::Result {
+    let path = crate::fixture_index_path("v2_split_index");
+    let data = std::fs::read(&path)?;
+    let (state, _outcome) = State::from_bytes(&data, FileTime::now(), gix_hash::Kind::Sha1, Default::default())?;
+    assert!(
+        state.link().is_some(),
+        "reading from bytes doesn't merge the shared index"
+    );
+
+    let mut buf = Vec::<u8>::new();
+    state.write_to(&mut buf, all_ext_but_eoie())?;
+    compare_raw_bytes(&buf, &data[..data.len() - 20], "v2_split_index");
+
+    for extensions in [
+        write::Extensions::None,
+        write::Extensions::Given {
+            link: false,
+            tree_cache: true,
+            resolve_undo: true,
+            untracked_cache: true,
+            fs_monitor: true,
+            entry_offset_table: true,
+            end_of_index_entry: true,
+            unknown: true,
+        },
+    ] {
+        let err = state.write_to(Vec::new(), options_with(extensions)).unwrap_err();
+        assert!(
+            matches!(err, write::Error::LinkExtensionRequired),
+            "the shared entries would be lost, got {err:?}"
+        );
+    }
+
+    let index = gix_index::File::at(path, gix_hash::Kind::Sha1, Default::default())?;
+    assert!(index.link().is_none(), "the shared index was merged");
+    buf.clear();
+    index.write_to(&mut buf, options_with(write::Extensions::None))?;
+    let (state, _outcome) = State::from_bytes(&buf, FileTime::now(), gix_hash::Kind::Sha1, Default::default())?;
+    assert!(state.link().is_none());
+    assert_eq!(
+        state.entries().len(),
+        index.entries().len(),
+        "all entries are contained"
+    );
+    Ok(())
+}
+
+#[test]
+fn skipped_extensions_can_only_be_written_if_they_are_excluded() -> crate::Result {
+    let index = gix_index::File::at(
+        crate::loose_file_path("REUC"),
+        gix_hash::Kind::Sha1,
+        gix_index::decode::Options {
+            extensions: gix_index::decode::Load::Only(&[extension::tree::SIGNATURE]),
+            ..Default::default()
+        },
+    )?;
+    assert_eq!(index.skipped_extensions(), [extension::resolve_undo::SIGNATURE]);
+
+    let err = index.write_to(Vec::new(), Default::default()).unwrap_err();
+    assert!(
+        matches!(&err, write::Error::SkippedExtension { signature } if signature == "REUC"),
+        "the skipped extension would be lost, got {err:?}"
+    );
+
+    let mut buf = Vec::new();
+    index.write_to(
+        &mut buf,
+        options_with(write::Extensions::Given {
+            link: false,
+            tree_cache: true,
+            resolve_undo: false,
+            untracked_cache: true,
+            fs_monitor: true,
+            entry_offset_table: true,
+            end_of_index_entry: true,
+            unknown: true,
+        }),
+    )?;
+    let (state, _outcome) = State::from_bytes(&buf, FileTime::now(), gix_hash::Kind::Sha1, Default::default())?;
+    assert!(state.tree().is_some());
+    assert!(state.resolve_undo().is_none(), "it was excluded explicitly");
+    Ok(())
+}
+
+mod lock {
+    use gix_index::{write, Version};
+
+    use crate::index::Fixture::Generated;
+
+    fn copy_of_fixture() -> crate::Result<(gix_testtools::tempfile::TempDir, std::path::PathBuf)> {
+        let tmp = gix_testtools::tempfile::TempDir::new()?;
+        let path = tmp.path().join("index");
+        std::fs::copy(Generated("v2").to_path(), &path)?;
+        Ok((tmp, path))
+    }
+
+    #[test]
+    fn existing_lock_files_prevent_writing() -> crate::Result {
+        let (_tmp, path) = copy_of_fixture()?;
+        let lock_path = path.with_extension("lock");
+        std::fs::write(&lock_path, b"held elsewhere")?;
+        let mut index = gix_index::File::at(&path, gix_hash::Kind::Sha1, Default::default())?;
+        let before = std::fs::read(&path)?;
+
+        for lock_mode in [
+            gix_lock::acquire::Fail::Immediately,
+            gix_lock::acquire::Fail::AfterDurationWithBackoff(std::time::Duration::from_millis(50)),
+        ] {
+            let err = index
+                .write_with_lock_mode(Default::default(), lock_mode)
+                .expect_err("the lock can't be acquired");
+            assert!(
+                matches!(
+                    err,
+                    gix_index::file::write::Error::AcquireLock(gix_lock::acquire::Error::PermanentlyLocked { .. })
+                ),
+                "{err:?}"
+            );
+        }
+        assert_eq!(std::fs::read(&path)?, before, "the index is untouched");
+        assert_eq!(
+            std::fs::read(&lock_path)?,
+            b"held elsewhere",
+            "foreign locks are left alone"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn lock_files_are_removed_on_error() -> crate::Result {
+        let (_tmp, path) = copy_of_fixture()?;
+        let mut index = gix_index::File::at(&path, gix_hash::Kind::Sha1, Default::default())?;
+        let before = std::fs::read(&path)?;
+        index.entries_mut()[0]
+            .flags
+            .insert(gix_index::entry::Flags::SKIP_WORKTREE);
+
+        let err = index
+            .write(write::Options {
+                version: Some(Version::V2),
+                ..Default::default()
+            })
+            .expect_err("V2 can't store the extended flag");
+        assert!(matches!(err, gix_index::file::write::Error::Write(_)), "{err:?}");
+        assert!(!path.with_extension("lock").exists(), "the lock was rolled back");
+        assert_eq!(std::fs::read(&path)?, before, "the index is untouched");
+
+        index.write(Default::default())?;
+        assert!(!path.with_extension("lock").exists(), "the lock was committed");
+        let written = gix_index::File::at(&path, gix_hash::Kind::Sha1, Default::default())?;
+        assert_eq!(written.version(), Version::V3);
+        assert_eq!(written.checksum(), index.checksum());
+        Ok(())
+    }
+}
+
+/// Write `file` to `out` with `options` assuming that the files in its worktree are unchanged, so racily clean entries
+/// are written as they were read.
+mod split {
+    use bstr::ByteSlice;
+    use gix_index::{entry, file::split, File};
+
+    fn git(dir: &std::path::Path, args: &[&str]) -> std::io::Result<Vec<u8>> {
+        let output = crate::index::git(dir).args(args).output()?;
+        assert!(output.status.success(), "{args:?} failed: {}", output.stderr.as_bstr());
+        Ok(output.stdout)
+    }
+
+    /// A repository with ten files whose index is written by git, as split index if `split` is set.
+    fn repo(split: bool) -> crate::Result<(gix_testtools::tempfile::TempDir, File)> {
+        let tmp = gix_testtools::scripted_fixture_writable_standalone(if split {
+            "make_index/v2_ten_files_split_index.sh"
+        } else {
+            "make_index/v2_ten_files.sh"
+        })?;
+        let file = File::at(tmp.path().join(".git/index"), gix_hash::Kind::Sha1, Default::default())?;
+        Ok((tmp, file))
+    }
+
+    fn ls_files(state: &gix_index::State) -> String {
+        state
+            .entries()
+            .iter()
+            .map(|e| format!("{:06o} {} {}\t{}\n", e.mode.bits(), e.id, e.stage(), e.path(state)))
+            .collect()
+    }
+
+    fn shared_indices(dir: &std::path::Path) -> crate::Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(dir.join(".git"))? {
+            let name = entry?.file_name().into_string().expect("valid UTF-8");
+            if name.starts_with("sharedindex.") {
+                names.push(name);
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Replace, delete and add an entry.
+    fn modify(file: &mut File) -> crate::Result {
+        let id = file.entry_by_path_and_stage("f0".into(), 0).expect("present").id;
+        file.touch_entry("d/f5".into(), entry::Stat::default(), id)
+            .expect("present");
+        file.remove_entry("f1".into(), 0).expect("present");
+        file.upsert_entry("d/new".into(), entry::Mode::FILE, id, entry::Stat::default())?;
+        Ok(())
+    }
+
+    #[test]
+    fn only_changed_entries_are_written_to_the_split_index_which_git_can_read() -> crate::Result {
+        let (tmp, mut file) = repo(true)?;
+        let shared_index_checksum = file.shared_index_checksum().expect("git wrote a split index");
+        let shared = file.shared_index(Default::default())?.expect("split index");
+        assert_eq!(shared.checksum(), Some(shared_index_checksum));
+        assert_eq!(shared.entries().len(), 10);
+
+        modify(&mut file)?;
+        let outcome = file.write_split(Some(&shared), Default::default(), Default::default())?;
+        assert_eq!(
+            outcome,
+            split::Outcome {
+                shared_index_checksum,
+                shared_index_written: false
+            },
+            "one of ten entries is new, which is below the default of 20%"
+        );
+        assert_eq!(file.shared_index_checksum(), Some(shared_index_checksum));
+        assert_eq!(shared_indices(tmp.path())?.len(), 1);
+
+        let (split, _) = gix_index::State::from_bytes(
+            &std::fs::read(file.path())?,
+            file.timestamp(),
+            gix_hash::Kind::Sha1,
+            Default::default(),
+        )?;
+        assert_eq!(split.entries().len(), 2, "one replaced and one added entry");
+        assert_eq!(split.entry(0).path(&split), "", "replaced entries have no path");
+        assert_eq!(split.entry(1).path(&split), "d/new");
+        let bitmaps = split.link().expect("present").bitmaps.as_ref().expect("present");
+        assert_eq!(bitmaps.replace.iter().collect::<Vec<_>>(), [0], "d/f5");
+        assert_eq!(bitmaps.delete.iter().collect::<Vec<_>>(), [6], "f1");
+
+        assert_eq!(git(tmp.path(), &["ls-files", "-s"])?.as_bstr(), ls_files(&file));
+        let reread = File::at(file.path(), gix_hash::Kind::Sha1, Default::default())?;
+        assert_eq!(ls_files(&reread), ls_files(&file));
+        assert_eq!(reread.checksum(), file.checksum());
+        Ok(())
+    }
+
+    #[test]
+    fn a_new_shared_index_is_written_if_there_is_none_or_too_many_entries_changed() -> crate::Result {
+        let (tmp, mut file) = repo(false)?;
+        assert_eq!(file.shared_index_checksum(), None);
+        assert!(file.shared_index(Default::default())?.is_none());
+
+        let first = file.write_split(None, Default::default(), Default::default())?;
+        assert!(first.shared_index_written);
+        assert_eq!(
+            shared_indices(tmp.path())?,
+            [format!("sharedindex.{}", first.shared_index_checksum)]
+        );
+        assert_eq!(git(tmp.path(), &["ls-files", "-s"])?.as_bstr(), ls_files(&file));
+
+        modify(&mut file)?;
+        let shared = file.shared_index(Default::default())?.expect("split index");
+        let outcome = file.write_split(
+            Some(&shared),
+            Default::default(),
+            split::Options { max_percent_change: 5 },
+        )?;
+        assert!(
+            outcome.shared_index_written,
+            "one of ten entries is new, which is above 5%"
+        );
+        assert_ne!(outcome.shared_index_checksum, first.shared_index_checksum);
+        assert_eq!(shared_indices(tmp.path())?.len(), 2);
+        assert_eq!(git(tmp.path(), &["ls-files", "-s"])?.as_bstr(), ls_files(&file));
+        assert_eq!(
+            git(tmp.path(), &["--no-optional-locks", "status", "--porcelain"])?.as_bstr(),
+            "MM d/f5\nAD d/new\nD  f1\n?? f1\n",
+            "git understands the index written against the new shared index"
+        );
+
+        let shared = file.shared_index(Default::default())?.expect("split index");
+        let outcome = file.write_split(
+            Some(&shared),
+            Default::default(),
+            split::Options { max_percent_change: 0 },
+        )?;
+        assert!(outcome.shared_index_written, "0% always writes a new shared index");
+        assert_eq!(
+            outcome.shared_index_checksum,
+            shared.checksum().expect("written"),
+            "nothing changed, so the same shared index is written again"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn stale_shared_indices_can_be_removed() -> crate::Result {
+        let (tmp, mut file) = repo(true)?;
+        let previous = file.shared_index_checksum().expect("split index");
+        let outcome = file.write_split(None, Default::default(), Default::default())?;
+        assert_eq!(
+            outcome.shared_index_checksum, previous,
+            "the shared index is the same as the one written by git"
+        );
+
+        modify(&mut file)?;
+        let outcome = file.write_split(None, Default::default(), Default::default())?;
+        assert_ne!(outcome.shared_index_checksum, previous);
+        assert_eq!(shared_indices(tmp.path())?.len(), 2);
+
+        let two_weeks_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(14 * 24 * 60 * 60);
+        assert_eq!(
+            file.remove_stale_shared_indices(two_weeks_ago)?,
+            0,
+            "nothing is old enough"
+        );
+
+        let in_a_minute = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+        assert_eq!(file.remove_stale_shared_indices(in_a_minute)?, 1);
+        assert_eq!(
+            shared_indices(tmp.path())?,
+            [format!("sharedindex.{}", outcome.shared_index_checksum)],
+            "the shared index in use is kept"
+        );
+        assert_eq!(git(tmp.path(), &["ls-files", "-s"])?.as_bstr(), ls_files(&file));
+        Ok(())
+    }
+}
+
+fn write_with_unchanged_worktree(
+    file: &gix_index::File,
+    out: &mut Vec<u8>,
+    options: Options,
+) -> Result<(Version, gix_hash::ObjectId), write::Error> {
+    let mut file = file.clone();
+    file.check_racy_entries(|_, entry| Some(entry.id));
+    file.write_to(out, options)
+}
+
 fn compare_states_against_baseline(
     actual: &State,
     actual_version: Version,
@@ -262,11 +1117,37 @@ fn only_tree_ext() -> Options {
     Options {
         extensions: write::Extensions::Given {
             end_of_index_entry: false,
+            unknown: false,
+            link: false,
+            resolve_undo: false,
+            untracked_cache: false,
+            fs_monitor: false,
+            entry_offset_table: false,
             tree_cache: true,
         },
+        ..Default::default()
+    }
+}
+
+fn all_ext_but_eoie() -> Options {
+    Options {
+        extensions: write::Extensions::Given {
+            end_of_index_entry: false,
+            unknown: true,
+            link: true,
+            resolve_undo: true,
+            untracked_cache: true,
+            fs_monitor: true,
+            entry_offset_table: true,
+            tree_cache: true,
+        },
+        ..Default::default()
     }
 }
 
 fn options_with(extensions: write::Extensions) -> Options {
-    Options { extensions }
+    Options {
+        extensions,
+        ..Default::default()
+    }
 }
@@ -9,6 +9,7 @@ fn from_tree() -> crate::Result {
         "make_index/v2_more_files.sh",
         "make_index/v2_all_file_kinds.sh",
         "make_index/v4_more_files_IEOT.sh",
+        "make_index/v2_deeper_tree.sh",
     ];
 
     for fixture in fixtures {
@@ -25,6 +26,20 @@ fn from_tree() -> crate::Result {
     Ok(())
 }
 
+#[test]
+fn from_empty_tree() -> crate::Result {
+    let empty_tree = gix_hash::ObjectId::empty_tree(gix_hash::Kind::Sha1);
+    let state = State::from_tree(&empty_tree, |oid, _buf| {
+        (oid == empty_tree).then(|| gix::objs::TreeRefIter::from_bytes(&[]))
+    })?;
+    assert_eq!(state.entries().len(), 0);
+    let tree = state.tree().expect("always present");
+    assert_eq!(tree.id, empty_tree);
+    assert_eq!(tree.num_entries, Some(0));
+    assert!(tree.children.is_empty());
+    Ok(())
+}
+
 #[test]
 fn new() {
     let state = State::new(gix_hash::Kind::Sha1);
@@ -44,6 +59,13 @@ fn compare_states(actual: &State, expected: &State, fixture: &str) {
         fixture
     );
 
+    assert_eq!(
+        actual.tree(),
+        expected.tree(),
+        "tree extension mismatch in {:?}",
+        fixture
+    );
+
     for (a, e) in actual.entries().iter().zip(expected.entries()) {
         assert_eq!(a.id, e.id, "entry id mismatch in {:?}", fixture);
         assert_eq!(a.flags, e.flags, "entry flags mismatch in {:?}", fixture);
@@ -3,28 +3,50 @@ use std::path::{Path, PathBuf};
 use gix_hash::ObjectId;
 
 mod access;
+mod changes;
+mod conflict;
+mod edit;
 mod entry;
 mod file;
 mod init;
+mod verify;
 
 pub fn hex_to_id(hex: &str) -> ObjectId {
     ObjectId::from_hex(hex.as_bytes()).expect("40 bytes hex")
 }
 
+pub fn fixture_worktree_path(name: &str) -> PathBuf {
+    gix_testtools::scripted_fixture_read_only_standalone(Path::new("make_index").join(name).with_extension("sh"))
+        .expect("script works")
+}
+
 pub fn fixture_index_path(name: &str) -> PathBuf {
-    let dir =
-        gix_testtools::scripted_fixture_read_only_standalone(Path::new("make_index").join(name).with_extension("sh"))
-            .expect("script works");
-    dir.join(".git").join("index")
+    fixture_worktree_path(name).join(".git").join("index")
 }
 
 pub fn loose_file_path(name: &str) -> PathBuf {
     gix_testtools::fixture_path_standalone(Path::new("loose_index").join(name).with_extension("git-index"))
 }
 
+/// Return a `git` invocation in `dir` for checking how git sees what we wrote, isolated from the configuration of the system
+/// and the user and with a fixed author and committer, just like the scripts producing fixtures.
+pub fn git(dir: &Path) -> std::process::Command {
+    let mut cmd = std::process::Command::new("git");
+    cmd.current_dir(dir)
+        .env_remove("GIT_DIR")
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .env("GIT_CONFIG_GLOBAL", "/dev/null")
+        .env("HOME", dir)
+        .env("GIT_AUTHOR_NAME", "author")
+        .env("GIT_AUTHOR_EMAIL", "author@example.com")
+        .env("GIT_COMMITTER_NAME", "committer")
+        .env("GIT_COMMITTER_EMAIL", "committer@example.com");
+    cmd
+}
+
 #[test]
 fn size_of_entry() {
-    assert_eq!(std::mem::size_of::<gix_index::Entry>(), 80);
+    assert_eq!(std::mem::size_of::<gix_index::Entry>(), 72);
 
     // the reason we have our own time is half the size.
     assert_eq!(std::mem::size_of::<gix_index::entry::Time>(), 8);
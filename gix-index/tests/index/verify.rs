@@ -0,0 +1,282 @@
+mod entries {
+    use gix_index::{entry, verify::entries::Violation, State};
+
+    use crate::index::Fixture;
+
+    fn push(state: &mut State, path: &str, stage: entry::Stage, mode: entry::Mode) {
+        let flags = entry::Flags::from_bits(stage << 12).expect("valid stage");
+        state.dangerously_push_entry(
+            Default::default(),
+            gix_hash::Kind::Sha1.null(),
+            flags,
+            mode,
+            path.into(),
+        );
+    }
+
+    #[test]
+    fn fixtures_are_valid() {
+        for fixture in [
+            Fixture::Generated("v4_more_files_IEOT"),
+            Fixture::Generated("v3_sparse_index"),
+            Fixture::Generated("v2_conflicted_merge"),
+            Fixture::Loose("conflicting-file"),
+        ] {
+            let file = fixture.open();
+            file.verify_entries()
+                .unwrap_or_else(|err| panic!("{}: {err}", fixture.to_name()));
+        }
+    }
+
+    #[test]
+    fn all_violations_are_returned_with_entry_indices() {
+        let mut state = State::new(gix_hash::Kind::Sha1);
+        for (path, stage, mode) in [
+            ("a", 0, entry::Mode::FILE),
+            ("a", 2, entry::Mode::FILE),
+            ("b", 0, entry::Mode::FILE),
+            ("b", 0, entry::Mode::FILE),
+            ("c/../d", 0, entry::Mode::FILE),
+            ("c/.git/config", 0, entry::Mode::FILE),
+            ("d", 0, entry::Mode::empty()),
+            ("e/", 0, entry::Mode::DIR),
+            ("f/", 0, entry::Mode::FILE),
+            ("/g", 0, entry::Mode::FILE),
+        ] {
+            push(&mut state, path, stage, mode);
+        }
+
+        let err = state.verify_entries().expect_err("there are violations");
+        let violations: Vec<_> = err
+            .violations
+            .iter()
+            .map(|v| match v {
+                Violation::OutOfOrder { current_index, .. } => ("order", *current_index),
+                Violation::ConflictWithStage0 { index, .. } => ("stage", *index),
+                Violation::InvalidPath { index, .. } => ("path", *index),
+                Violation::InvalidMode { index, .. } => ("mode", *index),
+            })
+            .collect();
+        assert_eq!(
+            violations,
+            [
+                ("stage", 1),
+                ("order", 3),
+                ("path", 4),
+                ("path", 5),
+                ("mode", 6),
+                ("path", 8),
+                ("order", 9),
+                ("path", 9)
+            ]
+        );
+        assert!(err.to_string().starts_with("Found 8 violation(s)"), "{err}");
+    }
+}
+
+mod tree {
+    use gix_index::{decode, verify, State};
+
+    use crate::index::Fixture;
+
+    fn decode_with_verification(data: &[u8]) -> Result<State, decode::Error> {
+        State::from_bytes(
+            data,
+            filetime::FileTime::now(),
+            gix_hash::Kind::Sha1,
+            decode::Options {
+                verify_tree: true,
+                ..Default::default()
+            },
+        )
+        .map(|(state, _)| state)
+    }
+
+    #[test]
+    fn fixtures_are_valid() {
+        for fixture in [
+            Fixture::Generated("v4_more_files_IEOT"),
+            Fixture::Generated("v3_sparse_index"),
+            Fixture::Generated("v2_conflicted_merge"),
+        ] {
+            let file = fixture.open();
+            file.verify_tree()
+                .unwrap_or_else(|err| panic!("{}: {err}", fixture.to_name()));
+            let data = std::fs::read(fixture.to_path()).expect("readable");
+            decode_with_verification(&data).unwrap_or_else(|err| panic!("{}: {err}", fixture.to_name()));
+        }
+    }
+
+    #[test]
+    fn invalidated_nodes_are_exempt() {
+        let mut file = Fixture::Generated("v4_more_files_IEOT").open();
+        file.remove_entry("d/last/6".into(), 0).expect("present");
+        file.verify_tree().expect("all nodes along the path are invalid now");
+    }
+
+    #[test]
+    fn entry_count_mismatches_are_reported_with_the_path_of_the_node() -> crate::Result {
+        let file = Fixture::Generated("v4_more_files_IEOT").open();
+        let mut data = Vec::new();
+        file.write_to(&mut data, Default::default())?;
+
+        let node = b"last\x003 0\n";
+        let pos = data
+            .windows(node.len())
+            .position(|w| w == node)
+            .expect("tree extension contains the node");
+        data[pos + 5] = b'4';
+
+        let (state, _) = State::from_bytes(
+            &data,
+            filetime::FileTime::now(),
+            gix_hash::Kind::Sha1,
+            Default::default(),
+        )?;
+        let err = state.verify_tree().unwrap_err();
+        assert!(
+            matches!(&err, verify::tree::Error::EntriesCount { path, declared: 4, actual: 3 } if path == "d/last"),
+            "{err:?}"
+        );
+
+        let err = decode_with_verification(&data).unwrap_err();
+        assert!(matches!(err, decode::Error::Tree(_)), "{err:?}");
+        Ok(())
+    }
+}
+
+mod paths {
+    use gix_index::{decode, entry, verify::paths::Options, State};
+
+    use crate::index::Fixture;
+
+    const NTFS: Options = Options {
+        protect_ntfs: true,
+        protect_hfs: false,
+    };
+    const HFS: Options = Options {
+        protect_ntfs: false,
+        protect_hfs: true,
+    };
+
+    fn state_with(path: &str, mode: entry::Mode) -> State {
+        let mut state = State::new(gix_hash::Kind::Sha1);
+        state.dangerously_push_entry(
+            Default::default(),
+            gix_hash::Kind::Sha1.null(),
+            entry::Flags::empty(),
+            mode,
+            path.into(),
+        );
+        state
+    }
+
+    fn is_safe(path: &str, mode: entry::Mode, options: Options) -> bool {
+        state_with(path, mode).verify_paths(options).is_ok()
+    }
+
+    #[test]
+    fn generic_checks_apply_without_protections() {
+        for path in [".GIT/config", "a/../b", "/a", "a//b", "a/"] {
+            assert!(!is_safe(path, entry::Mode::FILE, Options::default()), "{path}");
+        }
+        assert!(!is_safe(".GitModules", entry::Mode::SYMLINK, Options::default()));
+        assert!(is_safe(".gitmodules", entry::Mode::FILE, Options::default()));
+        assert!(is_safe("git~1/config", entry::Mode::FILE, Options::default()));
+        assert!(is_safe("dir/", entry::Mode::DIR, Options::default()));
+    }
+
+    #[test]
+    fn ntfs_aliases_of_dot_git_are_rejected() {
+        for path in [
+            "git~1/config",
+            "GIT~1/config",
+            ".git./config",
+            ".git . /config",
+            ".git::$INDEX_ALLOCATION/config",
+            "a\\.git\\config",
+            "a/b\\GIT~1",
+        ] {
+            assert!(!is_safe(path, entry::Mode::FILE, NTFS), "{path}");
+            assert!(is_safe(path, entry::Mode::FILE, HFS), "{path} is fine on HFS");
+        }
+        for path in ["git~2/config", ".gitx/config", "git~1x", ".git.x"] {
+            assert!(is_safe(path, entry::Mode::FILE, NTFS), "{path}");
+        }
+        for path in [".gitmodules .", "gitmod~4", "GI7EBA~9", ".gitmodules:stream"] {
+            assert!(!is_safe(path, entry::Mode::SYMLINK, NTFS), "{path}");
+            assert!(
+                is_safe(path, entry::Mode::FILE, NTFS),
+                "{path} is only dangerous as symlink"
+            );
+        }
+        assert!(is_safe("gitmod~5", entry::Mode::SYMLINK, NTFS));
+    }
+
+    #[test]
+    fn hfs_aliases_of_dot_git_are_rejected() {
+        for path in [
+            ".g\u{200c}it/config",
+            "\u{feff}.GIT/config",
+            ".gi\u{206f}t",
+            "a/.git\u{200d}",
+        ] {
+            assert!(!is_safe(path, entry::Mode::FILE, HFS), "{path:?}");
+            assert!(is_safe(path, entry::Mode::FILE, NTFS), "{path:?} is fine on NTFS");
+        }
+        assert!(!is_safe(".git\u{202a}modules", entry::Mode::SYMLINK, HFS));
+        assert!(is_safe(".git\u{202a}modules", entry::Mode::FILE, HFS));
+        assert!(
+            is_safe(".g\u{2000}it", entry::Mode::FILE, HFS),
+            "only certain code points are ignored"
+        );
+    }
+
+    #[test]
+    fn the_first_offending_path_is_reported_and_decoding_can_verify_paths() -> crate::Result {
+        let mut state = state_with("a", entry::Mode::FILE);
+        for path in ["git~1/config", "z\\.git\\hooks"] {
+            state.dangerously_push_entry(
+                Default::default(),
+                gix_hash::Kind::Sha1.null(),
+                entry::Flags::empty(),
+                entry::Mode::FILE,
+                path.into(),
+            );
+        }
+        let err = state.verify_paths(NTFS).unwrap_err();
+        assert_eq!(err.index, 1);
+        assert_eq!(err.path, "git~1/config");
+
+        let mut buf = Vec::new();
+        state.write_to(&mut buf, Default::default())?;
+        buf.extend_from_slice(&[0; 20]);
+        let decode = |verify_paths| {
+            State::from_bytes(
+                &buf,
+                filetime::FileTime::now(),
+                gix_hash::Kind::Sha1,
+                decode::Options {
+                    verify_paths,
+                    ..Default::default()
+                },
+            )
+        };
+        decode(None)?;
+        decode(Some(HFS))?;
+        let err = decode(Some(NTFS)).unwrap_err();
+        assert!(matches!(&err, decode::Error::Path(err) if err.index == 1), "{err:?}");
+
+        for fixture in [
+            Fixture::Generated("v4_more_files_IEOT"),
+            Fixture::Generated("v3_sparse_index"),
+            Fixture::Loose("very-long-path"),
+        ] {
+            fixture.open().verify_paths(Options {
+                protect_ntfs: true,
+                protect_hfs: true,
+            })?;
+        }
+        Ok(())
+    }
+}
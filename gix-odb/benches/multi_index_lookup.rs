@@ -0,0 +1,145 @@
+//! Measure how long it takes to look up objects in a store with many synthetic packs, once by probing the index of each pack
+//! and once through a multi-index covering all of them, which only opens the packs actually needed for reading objects.
+//! The multi-index is used by the store as well as by a [multi-index bundle][gix_odb::pack::multi_index::Bundle] on its own.
+//!
+//! Run with `cargo bench -p gix-odb --bench multi_index_lookup`.
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::atomic::AtomicBool,
+    time::{Duration, Instant},
+};
+
+use gix_odb::Find;
+
+const NUM_PACKS: u64 = 64;
+const NUM_OBJECTS_PER_PACK: u64 = 100_000;
+const NUM_LOOKUPS: u64 = 1_000_000;
+
+/// Return a pseudo-random but stable object id for `seed`.
+fn id_for(seed: u64) -> [u8; 20] {
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+    let mut id = [0; 20];
+    for chunk in id.chunks_mut(8) {
+        chunk.copy_from_slice(&next().to_be_bytes()[..chunk.len()]);
+    }
+    id
+}
+
+/// Write a version 2 pack index with `ids` to `path` along with an empty pack, without any meaningful checksums as these
+/// aren't verified when looking up objects.
+fn write_index(path: &Path, mut ids: Vec<[u8; 20]>) -> std::io::Result<()> {
+    ids.sort_unstable();
+    let mut fan = [0u32; 256];
+    for id in &ids {
+        fan[id[0] as usize] += 1;
+    }
+    let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+    out.write_all(b"\xfftOc")?;
+    out.write_all(&2u32.to_be_bytes())?;
+    let mut num_ids = 0;
+    for count in fan {
+        num_ids += count;
+        out.write_all(&num_ids.to_be_bytes())?;
+    }
+    for id in &ids {
+        out.write_all(id)?;
+    }
+    for _crc32 in &ids {
+        out.write_all(&0u32.to_be_bytes())?;
+    }
+    for idx in 0..ids.len() as u32 {
+        out.write_all(&(12 + idx * 16).to_be_bytes())?;
+    }
+    out.write_all(&[0; 40])?;
+    out.flush()?;
+    std::fs::File::create(path.with_extension("pack"))?;
+    Ok(())
+}
+
+/// Write all synthetic packs into the `pack` directory of `objects_dir`.
+fn write_packs(objects_dir: &Path) -> std::io::Result<PathBuf> {
+    let pack_dir = objects_dir.join("pack");
+    std::fs::create_dir_all(&pack_dir)?;
+    for pack in 0..NUM_PACKS {
+        let first_seed = pack * NUM_OBJECTS_PER_PACK;
+        write_index(
+            &pack_dir.join(format!("pack-{pack:040x}.idx")),
+            (first_seed..first_seed + NUM_OBJECTS_PER_PACK).map(id_for).collect(),
+        )?;
+    }
+    Ok(pack_dir)
+}
+
+/// Look up existing objects of all packs in turn along with the same amount of missing objects in the store at `objects_dir`.
+fn lookup(objects_dir: &Path) -> Duration {
+    let mut handle = gix_odb::at(objects_dir).expect("valid store");
+    // Missing objects would otherwise cause the pack directory to be checked for changes each time.
+    handle.refresh_never();
+    let num_objects = NUM_PACKS * NUM_OBJECTS_PER_PACK;
+    let start = Instant::now();
+    for lookup in 0..NUM_LOOKUPS {
+        let seed = (lookup % NUM_PACKS) * NUM_OBJECTS_PER_PACK + lookup / NUM_PACKS % NUM_OBJECTS_PER_PACK;
+        assert!(handle.contains(gix_hash::ObjectId::from(id_for(seed))));
+        assert!(!handle.contains(gix_hash::ObjectId::from(id_for(num_objects + lookup))));
+    }
+    start.elapsed()
+}
+
+/// Like [`lookup()`], but with a multi-index bundle for the pack directory at `pack_dir`.
+fn lookup_in_bundle(pack_dir: &Path) -> Duration {
+    let multi_index =
+        gix_odb::pack::multi_index::File::at(pack_dir.join("multi-pack-index")).expect("valid multi-index");
+    let bundle = gix_odb::pack::multi_index::Bundle::new(multi_index, pack_dir, 1).expect("valid pack directory");
+    let num_objects = NUM_PACKS * NUM_OBJECTS_PER_PACK;
+    let start = Instant::now();
+    for lookup in 0..NUM_LOOKUPS {
+        let seed = (lookup % NUM_PACKS) * NUM_OBJECTS_PER_PACK + lookup / NUM_PACKS % NUM_OBJECTS_PER_PACK;
+        assert!(bundle.contains(gix_hash::ObjectId::from(id_for(seed))));
+        assert!(!bundle.contains(gix_hash::ObjectId::from(id_for(num_objects + lookup))));
+    }
+    start.elapsed()
+}
+
+fn main() -> std::io::Result<()> {
+    let dir = std::env::temp_dir().join(format!("gix-odb-bench-multi-index-lookup-{}", std::process::id()));
+    let objects_dir = dir.join("objects");
+    let multi_index_objects_dir = dir.join("multi-index-objects");
+    write_packs(&objects_dir)?;
+    let pack_dir = write_packs(&multi_index_objects_dir)?;
+    gix_odb::pack::multi_index::File::write_from_pack_dir(
+        pack_dir.clone(),
+        gix_features::progress::Discard,
+        &AtomicBool::new(false),
+        gix_odb::pack::multi_index::write::Options::default(),
+        Default::default(),
+    )
+    .expect("valid indices");
+
+    let with_indices = lookup(&objects_dir);
+    let with_multi_index = lookup(&multi_index_objects_dir);
+    let with_bundle = lookup_in_bundle(&pack_dir);
+    for (name, elapsed) in [
+        ("indices", with_indices),
+        ("multi-index", with_multi_index),
+        ("multi-index bundle", with_bundle),
+    ] {
+        println!(
+            "{name}: {:.2}s for {} lookups in {NUM_PACKS} packs",
+            elapsed.as_secs_f64(),
+            NUM_LOOKUPS * 2
+        );
+    }
+    println!(
+        "speedup: {:.1}x",
+        with_indices.as_secs_f64() / with_multi_index.as_secs_f64()
+    );
+    std::fs::remove_dir_all(dir)
+}
@@ -30,9 +30,7 @@ fn db_with_all_object_sources() -> crate::Result<(gix_odb::Handle, tempfile::Tem
         multi_pack_index,
         gix_features::progress::Discard,
         &std::sync::atomic::AtomicBool::default(),
-        gix_odb::pack::multi_index::write::Options {
-            object_hash: gix_hash::Kind::Sha1,
-        },
+        gix_odb::pack::multi_index::write::Options::default(),
     )?;
     Ok((gix_odb::at(objects_dir.path())?, objects_dir))
 }
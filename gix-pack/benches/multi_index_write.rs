@@ -0,0 +1,109 @@
+//! Measure how long it takes to write a multi-index for synthetic pack indices with fifty million objects in total, some of
+//! which are contained in more than one of them, once with a single thread and once with all logical cores.
+//!
+//! Run with `cargo bench -p gix-pack --bench multi_index_write --features gix-features/parallel`.
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::atomic::AtomicBool,
+    time::Instant,
+};
+
+const NUM_OBJECTS: u64 = 50_000_000;
+const NUM_INDICES: u64 = 16;
+/// The amount of objects each index shares with the next one.
+const NUM_SHARED_OBJECTS: u64 = NUM_OBJECTS / NUM_INDICES / 10;
+
+/// Return a pseudo-random but stable object id for `seed`.
+fn id_for(seed: u64) -> [u8; 20] {
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+    let mut id = [0; 20];
+    for chunk in id.chunks_mut(8) {
+        chunk.copy_from_slice(&next().to_be_bytes()[..chunk.len()]);
+    }
+    id
+}
+
+/// Write a version 2 pack index with `ids` to `path`, without any meaningful checksums as these aren't verified when opening it.
+fn write_index(path: &Path, mut ids: Vec<[u8; 20]>) -> std::io::Result<()> {
+    ids.sort_unstable();
+    let mut fan = [0u32; 256];
+    for id in &ids {
+        fan[id[0] as usize] += 1;
+    }
+    let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+    out.write_all(b"\xfftOc")?;
+    out.write_all(&2u32.to_be_bytes())?;
+    let mut num_ids = 0;
+    for count in fan {
+        num_ids += count;
+        out.write_all(&num_ids.to_be_bytes())?;
+    }
+    for id in &ids {
+        out.write_all(id)?;
+    }
+    for _crc32 in &ids {
+        out.write_all(&0u32.to_be_bytes())?;
+    }
+    for idx in 0..ids.len() as u32 {
+        out.write_all(&(12 + idx * 16).to_be_bytes())?;
+    }
+    out.write_all(&[0; 40])?;
+    out.flush()
+}
+
+fn main() -> std::io::Result<()> {
+    let dir = std::env::temp_dir().join(format!("gix-pack-bench-multi-index-write-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let objects_per_index = NUM_OBJECTS / NUM_INDICES;
+    let index_paths = (0..NUM_INDICES)
+        .map(|index| {
+            let path = dir.join(format!("pack-{index:040x}.idx"));
+            let first_seed = index * objects_per_index;
+            write_index(
+                &path,
+                (first_seed..first_seed + objects_per_index + NUM_SHARED_OBJECTS)
+                    .map(|seed| id_for(seed % NUM_OBJECTS))
+                    .collect(),
+            )?;
+            Ok(path)
+        })
+        .collect::<std::io::Result<Vec<PathBuf>>>()?;
+
+    let mut durations = Vec::new();
+    for thread_limit in [Some(1), None] {
+        let start = Instant::now();
+        let outcome = gix_pack::multi_index::File::write_from_index_paths(
+            index_paths.clone(),
+            std::io::sink(),
+            gix_features::progress::Discard,
+            &AtomicBool::new(false),
+            gix_pack::multi_index::write::Options {
+                thread_limit,
+                ..Default::default()
+            },
+        )
+        .expect("valid indices");
+        let elapsed = start.elapsed();
+        println!(
+            "{} threads: {:.2}s for {} objects, {} duplicates removed",
+            gix_features::parallel::num_threads(thread_limit),
+            elapsed.as_secs_f64(),
+            outcome.num_objects,
+            outcome.num_duplicate_objects_removed
+        );
+        durations.push(elapsed);
+    }
+    println!(
+        "speedup: {:.1}x",
+        durations[0].as_secs_f64() / durations[1].as_secs_f64()
+    );
+    std::fs::remove_dir_all(dir)
+}
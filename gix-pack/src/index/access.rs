@@ -114,6 +114,19 @@ impl index::File {
         }
     }
 
+    /// Returns the entry at the given `index`, with all of its fields read from this index.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is out of bounds.
+    pub fn entry_at_index(&self, index: EntryIndex) -> Entry {
+        Entry {
+            oid: self.oid_at_index(index).to_owned(),
+            pack_offset: self.pack_offset_at_index(index),
+            crc32: self.crc32_at_index(index),
+        }
+    }
+
     /// Returns the `index` of the given hash for use with the [`oid_at_index()`][index::File::oid_at_index()],
     /// [`pack_offset_at_index()`][index::File::pack_offset_at_index()] or [`crc32_at_index()`][index::File::crc32_at_index()].
     // NOTE: pretty much the same things as in `multi_index::File::lookup`, change things there
@@ -129,9 +142,6 @@ impl index::File {
     ///
     /// Pass `candidates` to obtain the set of entry-indices matching `prefix`, with the same return value as
     /// one would have received if it remained `None`. It will be empty if no object matched the `prefix`.
-    ///
-    // NOTE: pretty much the same things as in `index::File::lookup`, change things there
-    //       as well.
     pub fn lookup_prefix(
         &self,
         prefix: gix_hash::Prefix,
@@ -212,6 +222,8 @@ pub(crate) fn lookup_prefix<'a>(
     let first_byte = prefix.as_oid().first_byte() as usize;
     let mut upper_bound = fan[first_byte];
     let mut lower_bound = if first_byte != 0 { fan[first_byte - 1] } else { 0 };
+    // As prefixes are at least a byte long, all matching objects are in the fan-out bucket of their first byte.
+    let bucket = lower_bound..upper_bound.min(num_objects);
 
     // Bisect using indices
     while lower_bound < upper_bound {
@@ -223,11 +235,11 @@ pub(crate) fn lookup_prefix<'a>(
             Less => upper_bound = mid,
             Equal => match candidates {
                 Some(candidates) => {
-                    let first_past_entry = ((0..mid).rev())
+                    let first_past_entry = ((bucket.start..mid).rev())
                         .take_while(|prev| prefix.cmp_oid(oid_at_index(*prev)) == Equal)
                         .last();
 
-                    let last_future_entry = ((mid + 1)..num_objects)
+                    let last_future_entry = ((mid + 1)..bucket.end)
                         .take_while(|next| prefix.cmp_oid(oid_at_index(*next)) == Equal)
                         .last();
 
@@ -246,10 +258,10 @@ pub(crate) fn lookup_prefix<'a>(
                 }
                 None => {
                     let next = mid + 1;
-                    if next < num_objects && prefix.cmp_oid(oid_at_index(next)) == Equal {
+                    if next < bucket.end && prefix.cmp_oid(oid_at_index(next)) == Equal {
                         return Some(Err(()));
                     }
-                    if mid != 0 && prefix.cmp_oid(oid_at_index(mid - 1)) == Equal {
+                    if mid > bucket.start && prefix.cmp_oid(oid_at_index(mid - 1)) == Equal {
                         return Some(Err(()));
                     }
                     return Some(Ok(mid));
@@ -70,6 +70,23 @@ impl index::File {
 
             (kind, fan, num_objects)
         };
+        let min_idx_len = match kind {
+            Version::V2 => {
+                V2_SIGNATURE.len()
+                    + N32_SIZE
+                    + FAN_LEN * N32_SIZE
+                    + footer_size
+                    + num_objects as usize * (hash_len + N32_SIZE /* crc32 */ + N32_SIZE/* offset */)
+            }
+            Version::V1 => FAN_LEN * N32_SIZE + footer_size + num_objects as usize * (N32_SIZE /* offset */ + hash_len),
+        };
+        if idx_len < min_idx_len {
+            return Err(Error::Corrupt {
+                message: format!(
+                    "Pack index of size {idx_len} is too small for its {num_objects} objects, which need at least {min_idx_len} bytes"
+                ),
+            });
+        }
         Ok(index::File {
             data,
             path: path.to_owned(),
@@ -80,6 +80,7 @@ use memmap2::Mmap;
 #[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
 #[allow(missing_docs)]
 pub enum Version {
+    /// Offsets into the pack are stored in 32 bits, so packs can't be larger than 4GiB, and there are no CRC32 checksums.
     V1 = 1,
     V2 = 2,
 }
@@ -137,6 +138,12 @@ impl File {
     pub fn object_hash(&self) -> gix_hash::Kind {
         self.object_hash
     }
+    /// Return the fan-out table, whose entry at `n` is the amount of objects whose id starts with a byte of `n` or less.
+    ///
+    /// Thus its last entry is the same as [`num_objects()`][File::num_objects()].
+    pub fn fan(&self) -> &[u32; FAN_LEN] {
+        &self.fan
+    }
 }
 
 const V2_SIGNATURE: &[u8] = b"\xfftOc";
@@ -146,6 +153,9 @@ pub mod init;
 pub(crate) mod access;
 pub use access::Entry;
 
+///
+pub mod reverse;
+
 ///
 pub mod traverse;
 mod util;
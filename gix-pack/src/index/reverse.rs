@@ -0,0 +1,130 @@
+//! Reverse indices map the position of an object in its pack to its position in the pack index, and are stored in `.rev`
+//! files next to the pack.
+use std::io::{self, Write};
+
+use crate::{
+    data,
+    index::{Entry, EntryIndex},
+};
+
+/// The signature of reverse index files.
+pub const SIGNATURE: &[u8] = b"RIDX";
+/// The only known version of reverse index files.
+pub const VERSION: u32 = 1;
+
+mod error {
+    use crate::{data, index::EntryIndex};
+
+    /// The error returned by [`from_offsets()`][super::from_offsets()] and [`index::File::reverse_index()`][crate::index::File::reverse_index()].
+    #[derive(Debug, thiserror::Error)]
+    #[allow(missing_docs)]
+    pub enum Error {
+        #[error("The entries at {first} and {second} both claim to start at pack offset {offset}")]
+        DuplicateOffset {
+            offset: data::Offset,
+            first: EntryIndex,
+            second: EntryIndex,
+        },
+    }
+}
+pub use error::Error;
+
+/// Turn the pack `offsets` of all entries of a pack index, in the order of the index, into the positions of these entries
+/// ordered by their pack offset, which is the table stored in reverse index files.
+///
+/// Offsets must be unique as each object starts at its own offset in the pack.
+pub fn from_offsets(offsets: impl IntoIterator<Item = data::Offset>) -> Result<Vec<EntryIndex>, Error> {
+    let mut offsets_and_positions: Vec<_> = offsets
+        .into_iter()
+        .enumerate()
+        .map(|(position, offset)| (offset, position as EntryIndex))
+        .collect();
+    offsets_and_positions.sort_unstable();
+    for pair in offsets_and_positions.windows(2) {
+        let ((offset, first), (next_offset, second)) = (pair[0], pair[1]);
+        if offset == next_offset {
+            return Err(Error::DuplicateOffset { offset, first, second });
+        }
+    }
+    Ok(offsets_and_positions
+        .into_iter()
+        .map(|(_offset, position)| position)
+        .collect())
+}
+
+/// Write a reverse index with `positions` as obtained by [`from_offsets()`] to `out`, for the pack with `pack_checksum`
+/// whose objects are identified by `object_hash`, and return the checksum of the written file.
+///
+/// The result is the same as the `.rev` files written by `git index-pack --rev-index`.
+pub fn write(
+    positions: &[EntryIndex],
+    object_hash: gix_hash::Kind,
+    pack_checksum: &gix_hash::oid,
+    out: impl io::Write,
+) -> io::Result<gix_hash::ObjectId> {
+    let mut out = gix_features::hash::Write::new(io::BufWriter::new(out), object_hash);
+    out.write_all(SIGNATURE)?;
+    out.write_all(&VERSION.to_be_bytes())?;
+    let hash_id: u32 = match object_hash {
+        gix_hash::Kind::Sha1 => 1,
+    };
+    out.write_all(&hash_id.to_be_bytes())?;
+    for position in positions {
+        out.write_all(&position.to_be_bytes())?;
+    }
+    out.write_all(pack_checksum.as_bytes())?;
+
+    let checksum: gix_hash::ObjectId = out.hash.digest().into();
+    let mut out = out.inner;
+    out.write_all(checksum.as_slice())?;
+    out.flush()?;
+    Ok(checksum)
+}
+
+/// Reverse indices
+impl crate::index::File {
+    /// Return the positions of all entries ordered by their offset in the pack, as stored in reverse index files.
+    pub fn reverse_index(&self) -> Result<Vec<EntryIndex>, Error> {
+        let mut positions = Vec::new();
+        self.reverse_index_into(&mut positions)?;
+        Ok(positions)
+    }
+
+    /// Like [`reverse_index()`][crate::index::File::reverse_index()], but clear `positions` and write them into it instead,
+    /// which doesn't allocate if it is reused for indices with at most as many objects.
+    pub fn reverse_index_into(&self, positions: &mut Vec<EntryIndex>) -> Result<(), Error> {
+        positions.clear();
+        positions.extend(0..self.num_objects);
+        positions.sort_unstable_by_key(|position| (self.pack_offset_at_index(*position), *position));
+        for pair in positions.windows(2) {
+            let (first, second) = (pair[0], pair[1]);
+            let offset = self.pack_offset_at_index(first);
+            if offset == self.pack_offset_at_index(second) {
+                return Err(Error::DuplicateOffset { offset, first, second });
+            }
+        }
+        Ok(())
+    }
+
+    /// Return an iterator over all entries ordered by their offset in the pack, which is the order in which a pack is read
+    /// from start to end.
+    ///
+    /// The order is determined once by obtaining the [reverse index][crate::index::File::reverse_index()], while entries are
+    /// read as the iterator advances.
+    pub fn iter_by_pack_offset(&self) -> Result<impl ExactSizeIterator<Item = Entry> + '_, Error> {
+        Ok(self
+            .reverse_index()?
+            .into_iter()
+            .map(move |position| self.entry_at_index(position)))
+    }
+
+    /// Like [`iter_by_pack_offset()`][crate::index::File::iter_by_pack_offset()], but use `positions` to store the
+    /// [reverse index][crate::index::File::reverse_index_into()], to avoid allocating if it is reused.
+    pub fn iter_by_pack_offset_with_buffer<'a>(
+        &'a self,
+        positions: &'a mut Vec<EntryIndex>,
+    ) -> Result<impl ExactSizeIterator<Item = Entry> + 'a, Error> {
+        self.reverse_index_into(positions)?;
+        Ok(positions.iter().map(move |position| self.entry_at_index(*position)))
+    }
+}
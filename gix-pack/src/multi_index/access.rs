@@ -4,7 +4,7 @@ use std::{
 };
 
 use crate::{
-    data,
+    data, index,
     index::PrefixLookupResult,
     multi_index::{EntryIndex, File, PackIndex, Version},
 };
@@ -43,6 +43,12 @@ impl File {
     pub fn num_objects(&self) -> EntryIndex {
         self.num_objects
     }
+    /// Returns the amount of multi-index files this one declares to build upon as layer of a [chain][crate::multi_index::Chain].
+    ///
+    /// Note that git writes zero even for layers that have base layers, which are only known from the chain file listing them.
+    pub fn num_base_files(&self) -> u8 {
+        self.num_base_files
+    }
     /// Returns the kind of hash function used for object ids available in this index.
     pub fn object_hash(&self) -> gix_hash::Kind {
         self.object_hash
@@ -59,6 +65,32 @@ impl File {
     pub fn index_names(&self) -> &[PathBuf] {
         &self.index_names
     }
+    /// Return the ids of all chunks this implementation doesn't understand along with their range of bytes in the file at
+    /// [`path()`][File::path()], in the order they are listed in its chunk table.
+    ///
+    /// These are ignored when reading, but could be copied when rewriting the multi-index.
+    pub fn unknown_chunks(&self) -> &[(gix_chunk::Id, Range<gix_chunk::file::Offset>)] {
+        &self.unknown_chunks
+    }
+    /// Return the fan-out table, whose entry at `n` is the amount of objects whose id starts with a byte of `n` or less.
+    ///
+    /// Thus its last entry is the same as [`num_objects()`][File::num_objects()].
+    pub fn fan(&self) -> &[u32; 256] {
+        &self.fan
+    }
+    /// Return the name of the pack data file (`*.pack`) that `pack_index` refers to, or `None` if it is out of bounds.
+    ///
+    /// It's located in the same directory as this multi-index file.
+    pub fn pack_name(&self, pack_index: PackIndex) -> Option<PathBuf> {
+        self.index_names
+            .get(pack_index as usize)
+            .map(|index_name| index_name.with_extension("pack"))
+    }
+    /// Return the path to the pack data file that `pack_index` refers to within `pack_dir`, typically `objects/pack`,
+    /// or `None` if it is out of bounds.
+    pub fn pack_path(&self, pack_index: PackIndex, pack_dir: &Path) -> Option<PathBuf> {
+        self.pack_name(pack_index).map(|name| pack_dir.join(name))
+    }
 }
 
 impl File {
@@ -77,9 +109,6 @@ impl File {
     ///
     /// Pass `candidates` to obtain the set of entry-indices matching `prefix`, with the same return value as
     /// one would have received if it remained `None`. It will be empty if no object matched the `prefix`.
-    ///
-    // NOTE: pretty much the same things as in `index::File::lookup`, change things there
-    //       as well.
     pub fn lookup_prefix(
         &self,
         prefix: gix_hash::Prefix,
@@ -106,6 +135,7 @@ impl File {
     /// The pack-index refers to an entry in the [`index_names`][File::index_names()] list, from which the pack can be derived.
     pub fn pack_id_and_pack_offset_at_index(&self, index: EntryIndex) -> (PackIndex, data::Offset) {
         const OFFSET_ENTRY_SIZE: usize = 4 + 4;
+        const LARGE_OFFSET_ENTRY_SIZE: usize = 8;
         let index = index as usize;
         let start = self.offsets_ofs + index * OFFSET_ENTRY_SIZE;
 
@@ -117,11 +147,13 @@ impl File {
         let pack_offset = if (ofs32 & HIGH_BIT) == HIGH_BIT {
             // We determine if large offsets are actually larger than 4GB and if not, we don't use the high-bit to signal anything
             // but allow the presence of the large-offset chunk to signal what's happening.
-            if let Some(offsets_64) = self.large_offsets_ofs {
-                let from = offsets_64 + (ofs32 ^ HIGH_BIT) as usize * 8;
-                crate::read_u64(&self.data[from..][..8])
-            } else {
-                ofs32 as u64
+            match &self.large_offsets {
+                // All large offsets were validated to be in bounds when opening the file.
+                Some(large_offsets) => {
+                    let from = large_offsets.start + (ofs32 ^ HIGH_BIT) as usize * LARGE_OFFSET_ENTRY_SIZE;
+                    crate::read_u64(&self.data[from..][..LARGE_OFFSET_ENTRY_SIZE])
+                }
+                None => ofs32 as u64,
             }
         } else {
             ofs32 as u64
@@ -129,15 +161,84 @@ impl File {
         (pack_index, pack_offset)
     }
 
-    /// Return an iterator over all entries within this file.
-    pub fn iter(&self) -> impl Iterator<Item = Entry> + '_ {
-        (0..self.num_objects).map(move |idx| {
-            let (pack_index, pack_offset) = self.pack_id_and_pack_offset_at_index(idx);
-            Entry {
-                oid: self.oid_at_index(idx).to_owned(),
-                pack_offset,
-                pack_index,
+    /// Find the object with `id` and return the index of the pack containing it along with the absolute offset to its
+    /// header in the pack, or `None` if it wasn't found.
+    ///
+    /// This is a shortcut for [`File::lookup()`] followed by [`File::pack_id_and_pack_offset_at_index()`], and the pack
+    /// can be obtained with [`File::pack_name()`].
+    pub fn pack_id_and_pack_offset(&self, id: impl AsRef<gix_hash::oid>) -> Option<(PackIndex, data::Offset)> {
+        self.lookup(id).map(|idx| self.pack_id_and_pack_offset_at_index(idx))
+    }
+
+    /// Find the index ranging from 0 to [File::num_objects()] of the entry at `pack_offset` in the pack at `pack_index`, or
+    /// `None` if there is no such entry, which reverses [`File::pack_id_and_pack_offset_at_index()`].
+    ///
+    /// The entries of each pack are ordered by their pack offset once on first use, which takes `O(n log n)`, while each
+    /// lookup takes `O(log n)` afterwards. Use [`File::oid_at_index()`] to obtain the id of the object.
+    ///
+    /// Entries of corrupt files whose pack is unknown or has multiple entries at the same offset are never found.
+    pub fn lookup_offset(&self, pack_index: PackIndex, pack_offset: data::Offset) -> Option<EntryIndex> {
+        let entries = self
+            .entries_by_pack_offset
+            .get_or_init(|| self.sort_entries_by_pack_offset())
+            .get(pack_index as usize)?;
+        let position = entries
+            .binary_search_by_key(&pack_offset, |index| self.pack_id_and_pack_offset_at_index(*index).1)
+            .ok()?;
+        Some(entries[position])
+    }
+
+    fn sort_entries_by_pack_offset(&self) -> Vec<Vec<EntryIndex>> {
+        let mut entries_and_offsets_by_pack = vec![(Vec::new(), Vec::new()); self.num_indices as usize];
+        for index in 0..self.num_objects {
+            let (pack_index, pack_offset) = self.pack_id_and_pack_offset_at_index(index);
+            if let Some((entries, offsets)) = entries_and_offsets_by_pack.get_mut(pack_index as usize) {
+                entries.push(index);
+                offsets.push(pack_offset);
             }
-        })
+        }
+        entries_and_offsets_by_pack
+            .into_iter()
+            .map(|(entries, offsets)| {
+                index::reverse::from_offsets(offsets)
+                    .map(|positions| {
+                        positions
+                            .into_iter()
+                            .map(|position| entries[position as usize])
+                            .collect()
+                    })
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+
+    /// Return the entry at the given `index`, which ranges from 0 to [File::num_objects()].
+    pub fn entry_at_index(&self, index: EntryIndex) -> Entry {
+        let (pack_index, pack_offset) = self.pack_id_and_pack_offset_at_index(index);
+        Entry {
+            oid: self.oid_at_index(index).to_owned(),
+            pack_offset,
+            pack_index,
+        }
+    }
+
+    /// Return an iterator over all entries within this file, sorted by object id.
+    ///
+    /// Entries are decoded one at a time as the iterator advances.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = Entry> + '_ {
+        (0..self.num_objects).map(move |idx| self.entry_at_index(idx))
+    }
+
+    /// Return the reverse index of the pack at `pack_index`, that is the positions of its entries in its pack index ordered
+    /// by their offset in the pack, which can be written with [`index::reverse::write()`].
+    ///
+    /// Note that this is only the reverse index of the pack if this multi-index refers to all of its objects, which isn't
+    /// the case if some of them were taken from other packs while deduplicating.
+    pub fn reverse_index(&self, pack_index: PackIndex) -> Result<Vec<index::EntryIndex>, index::reverse::Error> {
+        index::reverse::from_offsets(
+            self.iter()
+                .filter(|entry| entry.pack_index == pack_index)
+                .map(|entry| entry.pack_offset),
+        )
     }
 }
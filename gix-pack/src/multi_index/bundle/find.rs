@@ -0,0 +1,119 @@
+use crate::{data, data::decode::entry::ResolvedBase, multi_index::Bundle};
+
+mod error {
+    /// The error returned by [Bundle::find()][super::Bundle::find()].
+    #[derive(Debug, thiserror::Error)]
+    #[allow(missing_docs)]
+    pub enum Error {
+        #[error(transparent)]
+        OpenPack(#[from] crate::data::header::decode::Error),
+        #[error(transparent)]
+        Decode(#[from] crate::data::decode::Error),
+        #[error("The base object {base_id} could not be found but is required to decode {id}")]
+        DeltaBaseMissing {
+            base_id: gix_hash::ObjectId,
+            id: gix_hash::ObjectId,
+        },
+        #[error("Reached recursion limit of {max_depth} while resolving ref delta bases for {id}")]
+        DeltaBaseRecursionLimit { max_depth: usize, id: gix_hash::ObjectId },
+    }
+}
+
+pub use error::Error;
+
+/// The amount of times in a row ref delta bases are looked up in other packs before giving up, just like the dynamic store
+/// of `gix-odb` does it.
+const MAX_DELTA_BASE_RECURSION_DEPTH: usize = 32;
+
+impl Bundle {
+    /// Find an object with the given [`ObjectId`][gix_hash::ObjectId] and place its data into `out`, mapping the pack containing
+    /// it if it isn't mapped yet.
+    ///
+    /// [`cache`][crate::cache::DecodeEntry] is used to accelerate the lookup.
+    ///
+    /// As the multi-index only knows one copy of each object, the base of a ref delta may be found in another pack, which is
+    /// mapped as well and may cause the pack of the object to be unmapped once it was decoded.
+    pub fn find<'a>(
+        &mut self,
+        id: impl AsRef<gix_hash::oid>,
+        out: &'a mut Vec<u8>,
+        cache: &mut impl crate::cache::DecodeEntry,
+    ) -> Result<Option<(gix_object::Data<'a>, data::entry::Location)>, Error> {
+        self.find_inner(id.as_ref(), out, cache, 0)
+    }
+
+    fn find_inner<'a>(
+        &mut self,
+        id: &gix_hash::oid,
+        out: &'a mut Vec<u8>,
+        cache: &mut impl crate::cache::DecodeEntry,
+        depth: usize,
+    ) -> Result<Option<(gix_object::Data<'a>, data::entry::Location)>, Error> {
+        let (pack_position, pack_offset) = match self.lookup(id) {
+            Some(pack_and_offset) => pack_and_offset,
+            None => return Ok(None),
+        };
+        let pack = self.pack_at(pack_position)?;
+        let entry = pack.entry(pack_offset);
+        let header_size = entry.header_size();
+        let outcome = match pack.decode_entry(
+            entry,
+            out,
+            |id, _out| {
+                self.pack_offset_in(pack_position, id)
+                    .map(|pack_offset| ResolvedBase::InPack(pack.entry(pack_offset)))
+            },
+            cache,
+        ) {
+            Ok(outcome) => outcome,
+            Err(data::decode::Error::DeltaBaseUnresolved(base_id)) => {
+                if depth >= MAX_DELTA_BASE_RECURSION_DEPTH {
+                    return Err(Error::DeltaBaseRecursionLimit {
+                        max_depth: MAX_DELTA_BASE_RECURSION_DEPTH,
+                        id: id.to_owned(),
+                    });
+                }
+                let mut base = Vec::new();
+                let base_kind = self
+                    .find_inner(&base_id, &mut base, cache, depth + 1)?
+                    .ok_or_else(|| Error::DeltaBaseMissing {
+                        base_id,
+                        id: id.to_owned(),
+                    })?
+                    .0
+                    .kind;
+                pack.decode_entry(
+                    pack.entry(pack_offset),
+                    out,
+                    |id, out| {
+                        self.pack_offset_in(pack_position, id)
+                            .map(|pack_offset| ResolvedBase::InPack(pack.entry(pack_offset)))
+                            .or_else(|| {
+                                (id == base_id).then(|| {
+                                    out.resize(base.len(), 0);
+                                    out.copy_from_slice(&base);
+                                    ResolvedBase::OutOfPack {
+                                        kind: base_kind,
+                                        end: out.len(),
+                                    }
+                                })
+                            })
+                    },
+                    cache,
+                )?
+            }
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Some((
+            gix_object::Data {
+                kind: outcome.kind,
+                data: out.as_slice(),
+            },
+            data::entry::Location {
+                pack_id: pack.id,
+                pack_offset,
+                entry_size: outcome.compressed_size + header_size,
+            },
+        )))
+    }
+}
@@ -0,0 +1,71 @@
+use std::path::{Path, PathBuf};
+
+use crate::multi_index::{Bundle, File};
+
+mod error {
+    /// The error returned by [Bundle::new()][super::Bundle::new()].
+    #[derive(Debug, thiserror::Error)]
+    #[allow(missing_docs)]
+    pub enum Error {
+        #[error("Could not read the pack directory at '{path}'")]
+        Io {
+            source: std::io::Error,
+            path: std::path::PathBuf,
+        },
+        #[error(transparent)]
+        Index(#[from] crate::index::init::Error),
+    }
+}
+
+pub use error::Error;
+
+/// Initialization
+impl Bundle {
+    /// Find objects with `multi_index` in the packs of `pack_dir`, typically the directory containing the multi-index, while
+    /// mapping at most `max_open_packs` packs at a time, or one if it is zero.
+    ///
+    /// All indices in `pack_dir` which aren't referred to by `multi_index` are opened right away to find the objects the
+    /// multi-index doesn't contain, while their packs are mapped on first use just like the ones of the multi-index.
+    pub fn new(multi_index: File, pack_dir: impl AsRef<Path>, max_open_packs: usize) -> Result<Self, Error> {
+        let pack_dir = pack_dir.as_ref();
+        let io_err = |source| Error::Io {
+            source,
+            path: pack_dir.to_owned(),
+        };
+        let mut fallback_index_paths = Vec::new();
+        for entry in std::fs::read_dir(pack_dir).map_err(io_err)? {
+            let path = entry.map_err(io_err)?.path();
+            let is_fallback_index = path.extension().map_or(false, |ext| ext == "idx")
+                && path.file_name().map_or(false, |name| {
+                    !multi_index
+                        .index_names()
+                        .iter()
+                        .any(|index_name| index_name.as_os_str() == name)
+                });
+            if is_fallback_index {
+                fallback_index_paths.push(path);
+            }
+        }
+        fallback_index_paths.sort();
+
+        let fallback_indices = fallback_index_paths
+            .iter()
+            .map(|path| crate::index::File::at(path, multi_index.object_hash()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let pack_paths: Vec<PathBuf> = multi_index
+            .index_names()
+            .iter()
+            .map(|index_name| pack_dir.join(index_name))
+            .chain(fallback_index_paths)
+            .map(|index_path| index_path.with_extension("pack"))
+            .collect();
+        Ok(Bundle {
+            packs: vec![None; pack_paths.len()],
+            recently_used_packs: Default::default(),
+            max_open_packs: max_open_packs.max(1),
+            multi_index,
+            fallback_indices,
+            pack_paths,
+        })
+    }
+}
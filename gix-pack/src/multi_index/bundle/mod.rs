@@ -0,0 +1,99 @@
+use std::sync::Arc;
+
+use crate::{
+    data,
+    multi_index::{Bundle, File},
+};
+
+///
+pub mod init;
+
+///
+pub mod find;
+
+/// Access
+impl Bundle {
+    /// Return the multi-index used to find objects.
+    pub fn multi_index(&self) -> &File {
+        &self.multi_index
+    }
+
+    /// Return the indices of packs next to the multi-index which it doesn't refer to, ordered by name.
+    pub fn fallback_indices(&self) -> &[crate::index::File] {
+        &self.fallback_indices
+    }
+
+    /// Return the amount of packs which are mapped at most at a time.
+    pub fn max_open_packs(&self) -> usize {
+        self.max_open_packs
+    }
+
+    /// Return the amount of packs which are currently mapped.
+    pub fn num_open_packs(&self) -> usize {
+        self.recently_used_packs.len()
+    }
+
+    /// Return `true` if the object with `id` is contained in the multi-index or in one of the
+    /// [fallback indices][Bundle::fallback_indices()], without mapping any pack.
+    pub fn contains(&self, id: impl AsRef<gix_hash::oid>) -> bool {
+        self.lookup(id.as_ref()).is_some()
+    }
+
+    /// Return the position of the pack containing the object with `id` along with the offset of its entry in the pack, looking
+    /// into the multi-index first.
+    fn lookup(&self, id: &gix_hash::oid) -> Option<(usize, data::Offset)> {
+        if let Some((pack_index, pack_offset)) = self.multi_index.pack_id_and_pack_offset(id) {
+            return Some((pack_index as usize, pack_offset));
+        }
+        let num_indices = self.multi_index.num_indices() as usize;
+        self.fallback_indices.iter().enumerate().find_map(|(position, index)| {
+            index
+                .lookup(id)
+                .map(|entry_index| (num_indices + position, index.pack_offset_at_index(entry_index)))
+        })
+    }
+
+    /// Return the offset of the entry of the object with `id` in the pack at position `pack`, if that pack contains it.
+    ///
+    /// Note that the multi-index only knows one copy of each object, so objects contained in multiple packs are only found in one of them.
+    fn pack_offset_in(&self, pack: usize, id: &gix_hash::oid) -> Option<data::Offset> {
+        match pack.checked_sub(self.multi_index.num_indices() as usize) {
+            None => self
+                .multi_index
+                .pack_id_and_pack_offset(id)
+                .filter(|(pack_index, _)| *pack_index as usize == pack)
+                .map(|(_, pack_offset)| pack_offset),
+            Some(position) => {
+                let index = &self.fallback_indices[position];
+                index
+                    .lookup(id)
+                    .map(|entry_index| index.pack_offset_at_index(entry_index))
+            }
+        }
+    }
+
+    /// Return the pack at position `pack`, mapping it if needed and unmapping the least recently used pack if there would be
+    /// more than [`max_open_packs`][Bundle::max_open_packs()] otherwise.
+    fn pack_at(&mut self, pack: usize) -> Result<Arc<data::File>, data::header::decode::Error> {
+        if let Some(file) = self.packs[pack].clone() {
+            let position = self
+                .recently_used_packs
+                .iter()
+                .position(|recently_used| *recently_used == pack)
+                .expect("all mapped packs are tracked");
+            self.recently_used_packs.remove(position);
+            self.recently_used_packs.push_back(pack);
+            return Ok(file);
+        }
+
+        let file = Arc::new(data::File::at(&self.pack_paths[pack], self.multi_index.object_hash())?);
+        if self.recently_used_packs.len() >= self.max_open_packs {
+            if let Some(least_recently_used) = self.recently_used_packs.pop_front() {
+                self.packs[least_recently_used] = None;
+            }
+        }
+        self.packs[pack] = Some(file.clone());
+        self.recently_used_packs.push_back(pack);
+        Ok(file)
+    }
+}
@@ -0,0 +1,103 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    data,
+    multi_index::{Chain, EntryIndex, File, PackIndex},
+};
+
+/// Access methods
+impl Chain {
+    /// Return all layers, starting with the base.
+    pub fn layers(&self) -> &[File] {
+        &self.layers
+    }
+    /// Returns the amount of indices in all layers, and returned as one past the highest known pack index.
+    pub fn num_indices(&self) -> PackIndex {
+        let last = self.layers.len() - 1;
+        self.num_indices_in_base[last] + self.layers[last].num_indices()
+    }
+    /// Returns the total amount of objects in all layers, and returned as one past the highest known entry index.
+    pub fn num_objects(&self) -> EntryIndex {
+        let last = self.layers.len() - 1;
+        self.num_objects_in_base[last] + self.layers[last].num_objects()
+    }
+    /// Returns the kind of hash function used for object ids in all layers.
+    pub fn object_hash(&self) -> gix_hash::Kind {
+        self.layers[0].object_hash()
+    }
+    /// Return the names of the index files (`*.idx`) of all layers in the order of their pack index.
+    pub fn index_names(&self) -> impl Iterator<Item = &PathBuf> {
+        self.layers.iter().flat_map(|layer| layer.index_names())
+    }
+    /// Return the name of the pack data file (`*.pack`) that `pack_index` refers to, or `None` if it is out of bounds.
+    ///
+    /// It's located in the directory containing the packs, which also contains the chain directory.
+    pub fn pack_name(&self, pack_index: PackIndex) -> Option<PathBuf> {
+        let layer = self.num_indices_in_base.partition_point(|base| *base <= pack_index) - 1;
+        self.layers[layer].pack_name(pack_index - self.num_indices_in_base[layer])
+    }
+    /// Return the path to the pack data file that `pack_index` refers to within `pack_dir`, typically `objects/pack`,
+    /// or `None` if it is out of bounds.
+    pub fn pack_path(&self, pack_index: PackIndex, pack_dir: &Path) -> Option<PathBuf> {
+        self.pack_name(pack_index).map(|name| pack_dir.join(name))
+    }
+}
+
+impl Chain {
+    /// Return the object id at the given `index`, which ranges from 0 to [Chain::num_objects()].
+    pub fn oid_at_index(&self, index: EntryIndex) -> &gix_hash::oid {
+        let (layer, index) = self.layer_at_index(index);
+        self.layers[layer].oid_at_index(index)
+    }
+
+    /// Find the index ranging from 0 to [Chain::num_objects()] that belongs to data associated with `id`, or `None` if it
+    /// wasn't found.
+    ///
+    /// Layers are searched starting with the most recent one, just like git does it.
+    pub fn lookup(&self, id: impl AsRef<gix_hash::oid>) -> Option<EntryIndex> {
+        let id = id.as_ref();
+        self.layers
+            .iter()
+            .zip(&self.num_objects_in_base)
+            .rev()
+            .find_map(|(layer, num_objects_in_base)| layer.lookup(id).map(|index| num_objects_in_base + index))
+    }
+
+    /// Given the `index` ranging from 0 to [Chain::num_objects()], return the pack index and its absolute offset into the pack.
+    ///
+    /// The pack-index refers to an entry in [`index_names()`][Chain::index_names()], from which the pack can be derived.
+    pub fn pack_id_and_pack_offset_at_index(&self, index: EntryIndex) -> (PackIndex, data::Offset) {
+        let (layer, index) = self.layer_at_index(index);
+        let (pack_index, pack_offset) = self.layers[layer].pack_id_and_pack_offset_at_index(index);
+        (self.num_indices_in_base[layer] + pack_index, pack_offset)
+    }
+
+    /// Find the object with `id` and return the index of the pack containing it along with the absolute offset to its
+    /// header in the pack, or `None` if it wasn't found.
+    pub fn pack_id_and_pack_offset(&self, id: impl AsRef<gix_hash::oid>) -> Option<(PackIndex, data::Offset)> {
+        self.lookup(id).map(|idx| self.pack_id_and_pack_offset_at_index(idx))
+    }
+
+    /// Return the entry at the given `index`, which ranges from 0 to [Chain::num_objects()].
+    pub fn entry_at_index(&self, index: EntryIndex) -> crate::multi_index::Entry {
+        let (pack_index, pack_offset) = self.pack_id_and_pack_offset_at_index(index);
+        crate::multi_index::Entry {
+            oid: self.oid_at_index(index).to_owned(),
+            pack_offset,
+            pack_index,
+        }
+    }
+
+    /// Return an iterator over all entries of all layers, starting with the base, with the entries of each layer sorted
+    /// by object id.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = crate::multi_index::Entry> + '_ {
+        (0..self.num_objects()).map(move |idx| self.entry_at_index(idx))
+    }
+
+    /// Return the position of the layer containing the entry at `index`, along with the index of the entry within it.
+    fn layer_at_index(&self, index: EntryIndex) -> (usize, EntryIndex) {
+        debug_assert!(index < self.num_objects(), "index out of bounds");
+        let layer = self.num_objects_in_base.partition_point(|base| *base <= index) - 1;
+        (layer, index - self.num_objects_in_base[layer])
+    }
+}
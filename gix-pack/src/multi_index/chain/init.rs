@@ -0,0 +1,177 @@
+use std::{
+    convert::TryFrom,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use crate::multi_index::{Chain, EntryIndex, File, PackIndex};
+
+mod error {
+    use crate::multi_index;
+
+    /// The error returned by [Chain::at()][super::Chain::at()] and its siblings.
+    #[derive(Debug, thiserror::Error)]
+    #[allow(missing_docs)]
+    pub enum Error {
+        #[error("Could not read the multi-index chain file at '{path}'")]
+        Io {
+            source: std::io::Error,
+            path: std::path::PathBuf,
+        },
+        #[error(transparent)]
+        Layer(#[from] multi_index::init::Error),
+        #[error("The line {line:?} of the multi-index chain file at '{path}' isn't the checksum of a layer")]
+        InvalidLayerChecksum { line: String, path: std::path::PathBuf },
+        #[error("The multi-index layer at '{path}' has checksum {actual}, but {expected} was expected")]
+        LayerChecksumMismatch {
+            path: std::path::PathBuf,
+            expected: gix_hash::ObjectId,
+            actual: gix_hash::ObjectId,
+        },
+        #[error(
+            "The multi-index layer at '{path}' builds upon {actual} base files, but {expected} layers come before it"
+        )]
+        BaseFilesMismatch {
+            path: std::path::PathBuf,
+            expected: usize,
+            actual: u8,
+        },
+        #[error(
+            "The multi-index layer at '{path}' uses object hash {actual}, but the layers before it use {expected}"
+        )]
+        ObjectHashMismatch {
+            path: std::path::PathBuf,
+            expected: gix_hash::Kind,
+            actual: gix_hash::Kind,
+        },
+        #[error("A multi-index chain needs at least one layer")]
+        NoLayers,
+        #[error(
+            "The multi-index layers contain {0} objects or indices altogether, which is more than can be referred to"
+        )]
+        TooManyEntries(u64),
+        #[error("Did not find a multi-index file or chain at '{}'", .0.display())]
+        InvalidPath(std::path::PathBuf),
+    }
+}
+
+pub use error::Error;
+
+/// The name of the directory next to the packs which contains all layers of a chain.
+pub const DIR_NAME: &str = "multi-pack-index.d";
+/// The name of the file within [`DIR_NAME`] that lists the checksums of all layers, one per line and starting with the base.
+pub const FILE_NAME: &str = "multi-pack-index-chain";
+
+/// Initialization
+impl Chain {
+    /// Open the multi-index file or chain at `path`, which may be a multi-index file, the directory with all layers of a
+    /// chain or the directory with the packs containing either of these.
+    pub fn at(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::try_from(path.as_ref())
+    }
+
+    /// Open the multi-index file at `path` as chain with a single layer.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::new(vec![File::at(path)?])
+    }
+
+    /// Open all layers listed in the chain file in `chain_dir`, typically `objects/pack/multi-pack-index.d`.
+    ///
+    /// The checksum of each layer is validated against the one in its file name.
+    pub fn from_chain_dir(chain_dir: impl AsRef<Path>) -> Result<Self, Error> {
+        let chain_dir = chain_dir.as_ref();
+        let chain_file_path = chain_dir.join(FILE_NAME);
+        let io_err = |source| Error::Io {
+            source,
+            path: chain_file_path.clone(),
+        };
+        let chain_file = std::fs::File::open(&chain_file_path).map_err(io_err)?;
+        let mut layers = Vec::new();
+        for line in BufReader::new(chain_file).lines() {
+            let line = line.map_err(io_err)?;
+            let expected = gix_hash::ObjectId::from_hex(line.as_bytes()).map_err(|_| Error::InvalidLayerChecksum {
+                line: line.clone(),
+                path: chain_file_path.clone(),
+            })?;
+            let layer_path = chain_dir.join(format!("multi-pack-index-{line}.midx"));
+            let layer = File::at(&layer_path)?;
+            let actual = layer.checksum();
+            if actual != expected {
+                return Err(Error::LayerChecksumMismatch {
+                    path: layer_path,
+                    expected,
+                    actual,
+                });
+            }
+            layers.push(layer);
+        }
+        Self::new(layers)
+    }
+
+    /// Open the `multi-pack-index` file in `pack_dir`, typically `objects/pack`, or the chain in its
+    /// [`multi-pack-index.d`][DIR_NAME] directory if there is no such file, just like git does.
+    pub fn from_pack_dir(pack_dir: impl AsRef<Path>) -> Result<Self, Error> {
+        let pack_dir = pack_dir.as_ref();
+        let path = pack_dir.join("multi-pack-index");
+        if path.is_file() {
+            Self::from_file(path)
+        } else {
+            Self::from_chain_dir(pack_dir.join(DIR_NAME))
+        }
+    }
+
+    /// Create a new chain from `layers`, starting with the base.
+    ///
+    /// All layers must use the same object hash, and if a layer declares [base files][File::num_base_files()], their
+    /// amount must match the amount of layers before it.
+    pub fn new(layers: Vec<File>) -> Result<Self, Error> {
+        let first = layers.first().ok_or(Error::NoLayers)?;
+        let mut num_objects_in_base = Vec::with_capacity(layers.len());
+        let mut num_indices_in_base = Vec::with_capacity(layers.len());
+        let (mut num_objects, mut num_indices) = (0u64, 0u64);
+        for (position, layer) in layers.iter().enumerate() {
+            if layer.object_hash() != first.object_hash() {
+                return Err(Error::ObjectHashMismatch {
+                    path: layer.path().to_owned(),
+                    expected: first.object_hash(),
+                    actual: layer.object_hash(),
+                });
+            }
+            if layer.num_base_files() != 0 && layer.num_base_files() as usize != position {
+                return Err(Error::BaseFilesMismatch {
+                    path: layer.path().to_owned(),
+                    expected: position,
+                    actual: layer.num_base_files(),
+                });
+            }
+            num_objects_in_base.push(num_objects as EntryIndex);
+            num_indices_in_base.push(num_indices as PackIndex);
+            num_objects += u64::from(layer.num_objects());
+            num_indices += u64::from(layer.num_indices());
+            if num_objects > u64::from(EntryIndex::MAX) || num_indices > u64::from(PackIndex::MAX) {
+                return Err(Error::TooManyEntries(num_objects.max(num_indices)));
+            }
+        }
+        Ok(Chain {
+            layers,
+            num_objects_in_base,
+            num_indices_in_base,
+        })
+    }
+}
+
+impl TryFrom<&Path> for Chain {
+    type Error = Error;
+
+    fn try_from(path: &Path) -> Result<Self, Self::Error> {
+        if path.is_file() {
+            Self::from_file(path)
+        } else if path.join(FILE_NAME).is_file() {
+            Self::from_chain_dir(path)
+        } else if path.is_dir() {
+            Self::from_pack_dir(path)
+        } else {
+            Err(Error::InvalidPath(path.to_owned()))
+        }
+    }
+}
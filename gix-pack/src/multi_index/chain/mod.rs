@@ -0,0 +1,4 @@
+///
+pub mod init;
+
+mod access;
@@ -1,3 +1,7 @@
+/// The ids of all chunks this implementation reads and writes.
+pub(crate) const KNOWN_IDS: [gix_chunk::Id; 5] =
+    [index_names::ID, fanout::ID, lookup::ID, offsets::ID, large_offsets::ID];
+
 /// Information for the chunk about index names
 pub mod index_names {
     use std::path::{Path, PathBuf};
@@ -15,23 +19,38 @@ pub mod index_names {
         #[derive(Debug, thiserror::Error)]
         #[allow(missing_docs)]
         pub enum Error {
-            #[error("The pack names were not ordered alphabetically.")]
-            NotOrderedAlphabetically,
-            #[error("Each pack path name must be terminated with a null byte")]
-            MissingNullByte,
+            #[error("The index name '{path}' was sorted after '{previous}', but names must be ordered alphabetically")]
+            NotOrderedAlphabetically {
+                path: std::path::PathBuf,
+                previous: std::path::PathBuf,
+            },
+            #[error("The index name '{path}' was contained more than once")]
+            DuplicateName { path: std::path::PathBuf },
+            #[error("The index name '{path}' isn't a file name ending in '.idx'")]
+            InvalidName { path: std::path::PathBuf },
+            #[error("The index name at position {index} isn't terminated with a null byte")]
+            MissingNullByte { index: u32 },
             #[error("Couldn't turn path '{path}' into OS path due to encoding issues")]
             PathEncoding { path: BString },
             #[error("non-padding bytes found after all paths were read.")]
             UnknownTrailerBytes,
+            #[error(
+                "The chunk of index names has {actual} bytes, but its names and their padding take {expected} bytes"
+            )]
+            SizeMismatch { expected: usize, actual: usize },
         }
     }
 
     /// Parse null-separated index names from the given `chunk` of bytes and the expected number of packs and indices.
-    /// Ignore padding bytes which are typically \0.
-    pub fn from_bytes(mut chunk: &[u8], num_packs: u32) -> Result<Vec<PathBuf>, decode::Error> {
-        let mut out = Vec::new();
-        for _ in 0..num_packs {
-            let null_byte_pos = chunk.find_byte(b'\0').ok_or(decode::Error::MissingNullByte)?;
+    ///
+    /// All names must be [valid][is_valid_name()], unique and sorted alphabetically, and the chunk must not contain
+    /// anything but null bytes after them to pad it to a multiple of 4 bytes.
+    pub fn from_bytes(chunk: &[u8], num_packs: u32) -> Result<Vec<PathBuf>, decode::Error> {
+        let chunk_len = chunk.len();
+        let mut chunk = chunk;
+        let mut out = Vec::<PathBuf>::new();
+        for index in 0..num_packs {
+            let null_byte_pos = chunk.find_byte(b'\0').ok_or(decode::Error::MissingNullByte { index })?;
 
             let path = &chunk[..null_byte_pos];
             let path = gix_path::try_from_byte_slice(path)
@@ -40,9 +59,18 @@ pub mod index_names {
                 })?
                 .to_owned();
 
+            if !is_valid_name(&path) {
+                return Err(decode::Error::InvalidName { path });
+            }
             if let Some(previous) = out.last() {
-                if previous >= &path {
-                    return Err(decode::Error::NotOrderedAlphabetically);
+                if previous == &path {
+                    return Err(decode::Error::DuplicateName { path });
+                }
+                if previous > &path {
+                    return Err(decode::Error::NotOrderedAlphabetically {
+                        previous: previous.clone(),
+                        path,
+                    });
                 }
             }
             out.push(path);
@@ -50,14 +78,37 @@ pub mod index_names {
             chunk = &chunk[null_byte_pos + 1..];
         }
 
-        if !chunk.is_empty() && !chunk.iter().all(|b| *b == 0) {
+        let names_len = chunk_len - chunk.len();
+        let expected =
+            names_len + (CHUNK_ALIGNMENT as usize - names_len % CHUNK_ALIGNMENT as usize) % CHUNK_ALIGNMENT as usize;
+        if chunk_len != expected {
+            return Err(decode::Error::SizeMismatch {
+                expected,
+                actual: chunk_len,
+            });
+        }
+        if !chunk.iter().all(|b| *b == 0) {
             return Err(decode::Error::UnknownTrailerBytes);
         }
-        // NOTE: git writes garbage into this chunk, usually extra \0 bytes, which we simply ignore. If we were strict
-        // about it we couldn't read this chunk data at all.
         Ok(out)
     }
 
+    /// Return `true` if `name` is a plain file name ending in `.idx`, which is the only kind of name that may be stored in
+    /// this chunk as it's resolved relative to the directory containing the multi-index.
+    ///
+    /// Anything else, like absolute paths or paths with `..` components, could make us access arbitrary files. Names with
+    /// backslashes or colons are rejected on all platforms as they could be paths or drive letters on Windows.
+    pub fn is_valid_name(name: &Path) -> bool {
+        let mut components = name.components();
+        let is_file_name = matches!(
+            (components.next(), components.next()),
+            (Some(std::path::Component::Normal(_)), None)
+        );
+        is_file_name
+            && name.extension() == Some("idx".as_ref())
+            && !gix_path::into_bstr(name).iter().any(|b| matches!(b, b'\\' | b':'))
+    }
+
     /// Calculate the size on disk for our chunk with the given index paths. Note that these are expected to have been processed already
     /// to actually be file names.
     pub fn storage_size(paths: impl IntoIterator<Item = impl AsRef<Path>>) -> u64 {
@@ -129,10 +180,18 @@ pub mod fanout {
 
     /// Write the fanout for the given entries, which must be sorted by oid
     pub(crate) fn write(
-        sorted_entries: &[multi_index::write::Entry],
+        sorted_entries: impl IntoIterator<Item = std::io::Result<multi_index::write::Entry>>,
         mut out: impl std::io::Write,
     ) -> std::io::Result<()> {
-        let fanout = crate::index::write::encode::fanout(sorted_entries.iter().map(|e| e.id.first_byte()));
+        let mut fanout = [0u32; 256];
+        for entry in sorted_entries {
+            fanout[entry?.id.first_byte() as usize] += 1;
+        }
+        let mut num_entries = 0;
+        for value in fanout.iter_mut() {
+            num_entries += *value;
+            *value = num_entries;
+        }
 
         for value in fanout.iter() {
             out.write_all(&value.to_be_bytes())?;
@@ -156,11 +215,11 @@ pub mod lookup {
     }
 
     pub(crate) fn write(
-        sorted_entries: &[multi_index::write::Entry],
+        sorted_entries: impl IntoIterator<Item = std::io::Result<multi_index::write::Entry>>,
         mut out: impl std::io::Write,
     ) -> std::io::Result<()> {
         for entry in sorted_entries {
-            out.write_all(entry.id.as_slice())?;
+            out.write_all(entry?.id.as_slice())?;
         }
         Ok(())
     }
@@ -186,7 +245,7 @@ pub mod offsets {
     }
 
     pub(crate) fn write(
-        sorted_entries: &[multi_index::write::Entry],
+        sorted_entries: impl IntoIterator<Item = std::io::Result<multi_index::write::Entry>>,
         large_offsets_needed: bool,
         mut out: impl std::io::Write,
     ) -> std::io::Result<()> {
@@ -194,6 +253,7 @@ pub mod offsets {
         let mut num_large_offsets = 0u32;
 
         for entry in sorted_entries {
+            let entry = entry?;
             out.write_all(&entry.pack_index.to_be_bytes())?;
 
             let offset: u32 = if large_offsets_needed {
@@ -218,7 +278,19 @@ pub mod offsets {
     /// Returns true if the `offset` range seems to match the size required for `num_objects`.
     pub fn is_valid(offset: &Range<usize>, num_objects: u32) -> bool {
         let entry_size = 4 /* pack-id */ + 4 /* pack-offset */;
-        ((offset.end - offset.start) / num_objects as usize) == entry_size
+        offset.end - offset.start == num_objects as usize * entry_size
+    }
+
+    /// Return the index of each entry in the offsets `chunk` that refers to a large offset, along with the index of that large offset.
+    pub(crate) fn large_offset_indices(chunk: &[u8]) -> impl Iterator<Item = (multi_index::EntryIndex, u32)> + '_ {
+        use crate::index::write::encode::HIGH_BIT;
+        chunk
+            .chunks_exact(4 /* pack-id */ + 4 /* pack-offset */)
+            .enumerate()
+            .filter_map(|(index, entry)| {
+                let offset = crate::read_u32(&entry[4..]);
+                (offset & HIGH_BIT == HIGH_BIT).then_some((index as multi_index::EntryIndex, offset ^ HIGH_BIT))
+            })
     }
 }
 
@@ -232,10 +304,13 @@ pub mod large_offsets {
     pub const ID: gix_chunk::Id = *b"LOFF";
 
     /// Returns Some(num-large-offset) if there are offsets larger than u32.
-    pub(crate) fn num_large_offsets(entries: &[multi_index::write::Entry]) -> Option<usize> {
+    pub(crate) fn num_large_offsets(
+        entries: impl IntoIterator<Item = std::io::Result<multi_index::write::Entry>>,
+    ) -> std::io::Result<Option<usize>> {
         let mut num_large_offsets = 0;
         let mut needs_large_offsets = false;
         for entry in entries {
+            let entry = entry?;
             if entry.pack_offset > LARGE_OFFSET_THRESHOLD {
                 num_large_offsets += 1;
             }
@@ -244,7 +319,7 @@ pub mod large_offsets {
             }
         }
 
-        needs_large_offsets.then_some(num_large_offsets)
+        Ok(needs_large_offsets.then_some(num_large_offsets))
     }
     /// Returns true if the `offsets` range seems to be properly aligned for the data we expect.
     pub fn is_valid(offset: &Range<usize>) -> bool {
@@ -252,14 +327,15 @@ pub mod large_offsets {
     }
 
     pub(crate) fn write(
-        sorted_entries: &[multi_index::write::Entry],
+        sorted_entries: impl IntoIterator<Item = std::io::Result<multi_index::write::Entry>>,
         mut num_large_offsets: usize,
         mut out: impl std::io::Write,
     ) -> std::io::Result<()> {
-        for offset in sorted_entries
-            .iter()
-            .filter_map(|e| (e.pack_offset > LARGE_OFFSET_THRESHOLD).then_some(e.pack_offset))
-        {
+        for entry in sorted_entries {
+            let offset = entry?.pack_offset;
+            if offset <= LARGE_OFFSET_THRESHOLD {
+                continue;
+            }
             out.write_all(&offset.to_be_bytes())?;
             num_large_offsets = num_large_offsets
                 .checked_sub(1)
@@ -1,9 +1,9 @@
-use std::{convert::TryFrom, path::Path};
+use std::{convert::TryFrom, path::Path, sync::atomic::AtomicBool};
 
 use crate::multi_index::{chunk, File, Version};
 
 mod error {
-    use crate::multi_index::chunk;
+    use crate::multi_index::{chunk, EntryIndex};
 
     /// The error returned by [File::at()][super::File::at()].
     #[derive(Debug, thiserror::Error)]
@@ -14,13 +14,15 @@ mod error {
             source: std::io::Error,
             path: std::path::PathBuf,
         },
+        #[error("The file at '{path}' is not a multi-index file as it doesn't start with the expected signature")]
+        NotAMultiIndex { path: std::path::PathBuf },
         #[error("{message}")]
         Corrupt { message: &'static str },
-        #[error("Unsupported multi-index version: {version})")]
+        #[error("Unsupported multi-index version: {version}")]
         UnsupportedVersion { version: u8 },
-        #[error("Unsupported hash kind: {kind})")]
+        #[error("Unsupported hash kind: {kind}")]
         UnsupportedObjectHash { kind: u8 },
-        #[error(transparent)]
+        #[error("The chunk table of the multi-index file is corrupt")]
         ChunkFileDecode(#[from] gix_chunk::file::decode::Error),
         #[error(transparent)]
         MissingChunk(#[from] gix_chunk::file::index::offset_by_kind::Error),
@@ -28,21 +30,63 @@ mod error {
         FileTooLarge(#[from] gix_chunk::file::index::data_by_kind::Error),
         #[error("The multi-pack fan doesn't have the correct size of 256 * 4 bytes")]
         MultiPackFanSize,
+        #[error("The multi-pack fan isn't sorted in ascending order")]
+        MultiPackFanOrder,
         #[error(transparent)]
         PackNames(#[from] chunk::index_names::decode::Error),
         #[error("multi-index chunk {:?} has invalid size: {message}", String::from_utf8_lossy(.id))]
         InvalidChunkSize { id: gix_chunk::Id, message: &'static str },
+        #[error("The entry at {index} refers to large offset {large_offset_index}, which is out of bounds of the {num_large_offsets} large offsets")]
+        LargeOffsetOutOfBounds {
+            index: EntryIndex,
+            large_offset_index: u32,
+            num_large_offsets: usize,
+        },
+        #[error("The checksum of the multi-index file at '{path}' could not be verified")]
+        Checksum {
+            source: crate::multi_index::verify::checksum::Error,
+            path: std::path::PathBuf,
+        },
     }
 }
 
 pub use error::Error;
 
+/// Options for use in [`File::at_opts()`].
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Options {
+    /// If `true`, the file is hashed after opening it to assure its [`checksum()`][File::checksum()] matches its content,
+    /// which is the same as calling [`File::verify_checksum()`] and costs a full read of the file, but none of the
+    /// structural checks of [`File::verify_integrity()`].
+    pub verify_checksum: bool,
+}
+
 /// Initialization
 impl File {
     /// Open the multi-index file at the given `path`.
+    ///
+    /// The chunk table as well as the presence and size of all mandatory chunks are validated, while unknown chunks are ignored
+    /// but available via [`File::unknown_chunks()`]. If there is a chunk with large offsets, all entries referring to it are
+    /// validated as well, so that accessing them can't fail later.
     pub fn at(path: impl AsRef<Path>) -> Result<Self, Error> {
         Self::try_from(path.as_ref())
     }
+
+    /// Like [`File::at()`], but configured with `options`.
+    ///
+    /// Use [`File::verify_checksum()`] instead of [`Options::verify_checksum`] to be able to interrupt the verification and
+    /// observe its progress.
+    pub fn at_opts(path: impl AsRef<Path>, options: Options) -> Result<Self, Error> {
+        let file = Self::at(path)?;
+        if options.verify_checksum {
+            file.verify_checksum(gix_features::progress::Discard, &AtomicBool::new(false))
+                .map_err(|source| Error::Checksum {
+                    source,
+                    path: file.path.clone(),
+                })?;
+        }
+        Ok(file)
+    }
 }
 
 impl TryFrom<&Path> for File {
@@ -54,6 +98,10 @@ impl TryFrom<&Path> for File {
             path: path.to_owned(),
         })?;
 
+        if !data.starts_with(Self::SIGNATURE) {
+            return Err(Error::NotAMultiIndex { path: path.to_owned() });
+        }
+
         const TRAILER_LEN: usize = gix_hash::Kind::shortest().len_in_bytes(); /* trailing hash */
         if data.len()
             < Self::HEADER_LEN
@@ -66,13 +114,8 @@ impl TryFrom<&Path> for File {
             });
         }
 
-        let (version, object_hash, num_chunks, num_indices) = {
-            let (signature, data) = data.split_at(4);
-            if signature != Self::SIGNATURE {
-                return Err(Error::Corrupt {
-                    message: "Invalid signature",
-                });
-            }
+        let (version, object_hash, num_chunks, num_base_files, num_indices) = {
+            let (_signature, data) = data.split_at(4);
             let (version, data) = data.split_at(1);
             let version = match version[0] {
                 1 => Version::V1,
@@ -85,12 +128,13 @@ impl TryFrom<&Path> for File {
             let (num_chunks, data) = data.split_at(1);
             let num_chunks = num_chunks[0];
 
-            let (_num_base_files, data) = data.split_at(1); // TODO: handle base files once it's clear what this does
+            let (num_base_files, data) = data.split_at(1);
+            let num_base_files = num_base_files[0];
 
             let (num_indices, _) = data.split_at(4);
             let num_indices = crate::read_u32(num_indices);
 
-            (version, object_hash, num_chunks, num_indices)
+            (version, object_hash, num_chunks, num_base_files, num_indices)
         };
 
         let chunks = gix_chunk::file::Index::from_bytes(&data, Self::HEADER_LEN, num_chunks as u32)?;
@@ -100,6 +144,9 @@ impl TryFrom<&Path> for File {
 
         let fan = chunks.data_by_id(&data, chunk::fanout::ID)?;
         let fan = chunk::fanout::from_bytes(fan).ok_or(Error::MultiPackFanSize)?;
+        if fan.windows(2).any(|w| w[0] > w[1]) {
+            return Err(Error::MultiPackFanOrder);
+        }
         let num_objects = fan[255];
 
         let lookup = chunks.validated_usize_offset_by_id(chunk::lookup::ID, |offset| {
@@ -129,6 +176,24 @@ impl TryFrom<&Path> for File {
             })
             .ok()
             .transpose()?;
+        if let Some(large_offsets) = &large_offsets {
+            let num_large_offsets = large_offsets.len() / 8;
+            if let Some((index, large_offset_index)) = chunk::offsets::large_offset_indices(&data[offsets.clone()])
+                .find(|(_, large_offset_index)| *large_offset_index as usize >= num_large_offsets)
+            {
+                return Err(Error::LargeOffsetOutOfBounds {
+                    index,
+                    large_offset_index,
+                    num_large_offsets,
+                });
+            }
+        }
+
+        let unknown_chunks = chunks
+            .iter()
+            .filter(|chunk| !chunk::KNOWN_IDS.contains(&chunk.kind))
+            .map(|chunk| (chunk.kind, chunk.offset.clone()))
+            .collect();
 
         let checksum_offset = chunks.highest_offset() as usize;
         let trailer = &data[checksum_offset..];
@@ -149,9 +214,12 @@ impl TryFrom<&Path> for File {
             index_names,
             lookup_ofs: lookup.start,
             offsets_ofs: offsets.start,
-            large_offsets_ofs: large_offsets.map(|r| r.start),
+            large_offsets,
+            unknown_chunks,
+            entries_by_pack_offset: Default::default(),
             num_objects,
             num_indices,
+            num_base_files,
         })
     }
 }
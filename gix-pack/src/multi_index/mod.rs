@@ -33,19 +33,66 @@ pub struct File {
     /// The amount of pack files contained within
     num_indices: u32,
     num_objects: u32,
+    num_base_files: u8,
 
     fan: [u32; 256],
     index_names: Vec<PathBuf>,
     lookup_ofs: usize,
     offsets_ofs: usize,
-    large_offsets_ofs: Option<usize>,
+    large_offsets: Option<std::ops::Range<usize>>,
+    unknown_chunks: Vec<(gix_chunk::Id, std::ops::Range<gix_chunk::file::Offset>)>,
+    /// For each pack, the indices of its entries ordered by their pack offset, built on first use by [`File::lookup_offset()`].
+    entries_by_pack_offset: once_cell::sync::OnceCell<Vec<Vec<EntryIndex>>>,
 }
 
+/// A chain of multi-index files, typically stored in `multi-pack-index.d` and listed in its `multi-pack-index-chain` file,
+/// with each layer containing the objects of packs that were added after the ones of the layers before it.
+///
+/// It presents the objects of all layers as if they were stored in a single multi-index, with entry indices and pack
+/// indices counting the ones of all layers before them first.
+pub struct Chain {
+    /// All layers, starting with the base.
+    layers: Vec<File>,
+    /// For each layer, the amount of objects in all layers before it.
+    num_objects_in_base: Vec<EntryIndex>,
+    /// For each layer, the amount of indices in all layers before it.
+    num_indices_in_base: Vec<PackIndex>,
+}
+
+/// A multi-index along with the directory of the packs it refers to, for finding objects by looking them up in the multi-index
+/// instead of in the index of each pack.
+///
+/// Packs are only memory-mapped once an object is read from them, and at most [`max_open_packs`][Bundle::max_open_packs()]
+/// of them are mapped at a time, unmapping the least recently used one to make room for another. Indices in the same directory
+/// that aren't referred to by the multi-index are searched if an object isn't contained in it.
+pub struct Bundle {
+    multi_index: File,
+    /// The indices of packs next to the multi-index which it doesn't refer to, ordered by name.
+    fallback_indices: Vec<crate::index::File>,
+    /// The path to each pack, first for all indices of the multi-index, then for each of the `fallback_indices`.
+    pack_paths: Vec<PathBuf>,
+    /// The packs which are currently mapped, in the same order as `pack_paths`.
+    packs: Vec<Option<std::sync::Arc<crate::data::File>>>,
+    /// The positions of all mapped packs in `packs`, least recently used first.
+    recently_used_packs: std::collections::VecDeque<usize>,
+    max_open_packs: usize,
+}
+
+///
+pub mod bundle;
+
+///
+pub mod chain;
+
 ///
 pub mod write;
 
 ///
 mod access;
+pub use access::Entry;
+
+mod stale;
+pub use stale::Stale;
 
 ///
 pub mod verify;
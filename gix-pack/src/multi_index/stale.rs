@@ -0,0 +1,76 @@
+use std::path::{Path, PathBuf};
+
+use crate::multi_index::File;
+
+/// The differences between the indices referenced by a multi-index and the ones in its pack directory, as returned by
+/// [`File::is_stale()`].
+///
+/// All paths are file names relative to the pack directory, like `pack-<hash>.idx`.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct Stale {
+    /// Indices referenced by the multi-index whose index or pack file doesn't exist anymore.
+    pub missing_indices: Vec<PathBuf>,
+    /// Indices of packs in the pack directory which aren't referenced by the multi-index.
+    pub unreferenced_indices: Vec<PathBuf>,
+}
+
+impl Stale {
+    /// Return `true` if the multi-index doesn't refer to exactly the packs in its pack directory.
+    ///
+    /// Git ignores multi-indices with missing indices, while unreferenced indices merely mean their objects have to be
+    /// looked up separately.
+    pub fn is_stale(&self) -> bool {
+        !self.missing_indices.is_empty() || !self.unreferenced_indices.is_empty()
+    }
+}
+
+/// Staleness
+impl File {
+    /// Compare the indices we reference with the indices of packs in `pack_dir`, typically the directory containing this
+    /// file, and return the differences.
+    pub fn is_stale(&self, pack_dir: impl AsRef<Path>) -> std::io::Result<Stale> {
+        let pack_dir = pack_dir.as_ref();
+        let missing_indices = self
+            .index_names()
+            .iter()
+            .filter(|name| {
+                let path = pack_dir.join(name);
+                !path.is_file() || !path.with_extension("pack").is_file()
+            })
+            .cloned()
+            .collect();
+        let unreferenced_indices = crate::multi_index::write::index_paths_in_pack_dir(pack_dir, self.object_hash())?
+            .into_iter()
+            .filter_map(|path| path.file_name().map(PathBuf::from))
+            .filter(|name| !self.index_names().contains(name))
+            .collect();
+        Ok(Stale {
+            missing_indices,
+            unreferenced_indices,
+        })
+    }
+
+    /// Remove this file along with the reverse index and bitmap git derives from it if it [is stale][File::is_stale()]
+    /// in the directory containing it, and return the differences that made it stale, or `None` if it was kept.
+    ///
+    /// The multi-index is removed first so it can't be used anymore even if removing the files derived from it fails,
+    /// and files that were already removed don't count as error.
+    pub fn remove_if_stale(self) -> std::io::Result<Option<Stale>> {
+        let dir = self.path.parent().unwrap_or_else(|| Path::new(".")).to_owned();
+        let stale = self.is_stale(&dir)?;
+        if !stale.is_stale() {
+            return Ok(None);
+        }
+        let derived_files =
+            ["rev", "bitmap"].map(|extension| dir.join(format!("multi-pack-index-{}.{}", self.checksum(), extension)));
+        let path = self.path.clone();
+        drop(self);
+        for path in std::iter::once(path).chain(derived_files) {
+            match std::fs::remove_file(&path) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+        }
+        Ok(Some(stale))
+    }
+}
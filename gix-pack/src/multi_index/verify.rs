@@ -28,6 +28,11 @@ pub mod integrity {
         UnexpectedObjectCount { actual: usize, expected: usize },
         #[error("{id} wasn't found in the index referenced in the multi-pack index")]
         OidNotFound { id: gix_hash::ObjectId },
+        #[error("{id} of index '{index_name}' wasn't found in the multi-pack index")]
+        OidMissingInMultiIndex {
+            id: gix_hash::ObjectId,
+            index_name: std::path::PathBuf,
+        },
         #[error("The object id at multi-index entry {index} wasn't in order")]
         OutOfOrder { index: EntryIndex },
         #[error("The fan at index {index} is out of order as it's larger then the following value.")]
@@ -44,6 +49,9 @@ pub mod integrity {
         pub actual_index_checksum: gix_hash::ObjectId,
         /// The for each entry in [`index_names()`][super::File::index_names()] provide the corresponding pack traversal outcome.
         pub pack_traverse_statistics: Vec<crate::index::traverse::Statistics>,
+        /// For each entry in [`index_names()`][super::File::index_names()], the amount of objects the multi-index refers to
+        /// in it, which doesn't include objects that are also contained in another index that was chosen for them instead.
+        pub num_objects_per_index: Vec<usize>,
         /// The provided progress instance.
         pub progress: P,
     }
@@ -57,6 +65,8 @@ pub mod integrity {
         ChecksumBytes,
         /// The amount of objects whose offset has been checked.
         ObjectOffsets,
+        /// The amount of objects of each index that were checked to be contained in the multi-index.
+        IndexObjects,
     }
 
     impl From<ProgressId> for gix_features::progress::Id {
@@ -64,6 +74,7 @@ pub mod integrity {
             match v {
                 ProgressId::ChecksumBytes => *b"MVCK",
                 ProgressId::ObjectOffsets => *b"MVOF",
+                ProgressId::IndexObjects => *b"MVIO",
             }
         }
     }
@@ -95,7 +106,8 @@ impl File {
 
     /// Similar to [`verify_integrity()`][File::verify_integrity()] but without any deep inspection of objects.
     ///
-    /// Instead we only validate the contents of the multi-index itself.
+    /// Instead we only validate the contents of the multi-index itself, and that it refers to the objects of all of its indices
+    /// at the same offsets they have there.
     pub fn verify_integrity_fast<P>(
         &self,
         progress: P,
@@ -119,6 +131,9 @@ impl File {
 
     /// Similar to [`crate::Bundle::verify_integrity()`] but checks all contained indices and their packs.
     ///
+    /// Each object of the multi-index must be contained at the same offset in its index, and each object of an index must be
+    /// contained in the multi-index, possibly referring to another index that contains it as well.
+    ///
     /// Note that it's considered a failure if an index doesn't have a corresponding pack.
     pub fn verify_integrity<C, P, F>(
         &self,
@@ -170,6 +185,7 @@ impl File {
         }
 
         let mut pack_traverse_statistics = Vec::new();
+        let mut num_objects_per_index = Vec::with_capacity(self.index_names.len());
 
         let operation_start = Instant::now();
         let mut total_objects_checked = 0;
@@ -234,6 +250,34 @@ impl File {
                 index.as_ref().expect("just set")
             };
 
+            {
+                let start = Instant::now();
+                let mut objects_progress = progress.add_child_with_id(
+                    "verify objects are contained",
+                    integrity::ProgressId::IndexObjects.into(),
+                );
+                objects_progress.init(
+                    Some(index.num_objects() as usize),
+                    gix_features::progress::count("objects"),
+                );
+                for entry_index in 0..index.num_objects() {
+                    let oid = index.oid_at_index(entry_index);
+                    if self.lookup(oid).is_none() {
+                        return Err(index::traverse::Error::Processor(
+                            integrity::Error::OidMissingInMultiIndex {
+                                id: oid.to_owned(),
+                                index_name: index_file_name.clone(),
+                            },
+                        ));
+                    }
+                    objects_progress.inc();
+                }
+                if should_interrupt.load(std::sync::atomic::Ordering::Relaxed) {
+                    return Err(index::traverse::Error::Processor(integrity::Error::Interrupted));
+                }
+                objects_progress.show_throughput(start);
+            }
+
             let slice_end = pack_ids_slice.partition_point(|e| e.0 == pack_id as crate::data::Id);
             let multi_index_entries_to_check = &pack_ids_slice[..slice_end];
             {
@@ -241,7 +285,7 @@ impl File {
                 let mut offsets_progress =
                     progress.add_child_with_id("verify object offsets", integrity::ProgressId::ObjectOffsets.into());
                 offsets_progress.init(
-                    Some(pack_ids_and_offsets.len()),
+                    Some(multi_index_entries_to_check.len()),
                     gix_features::progress::count("objects"),
                 );
                 pack_ids_slice = &pack_ids_slice[slice_end..];
@@ -272,6 +316,7 @@ impl File {
             }
 
             total_objects_checked += multi_index_entries_to_check.len();
+            num_objects_per_index.push(multi_index_entries_to_check.len());
 
             if let Some(bundle) = bundle {
                 progress.set_name(format!("Validating {}", index_file_name.display()));
@@ -331,6 +376,7 @@ impl File {
         Ok(integrity::Outcome {
             actual_index_checksum,
             pack_traverse_statistics,
+            num_objects_per_index,
             progress,
         })
     }
@@ -0,0 +1,404 @@
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    ops::{Range, RangeInclusive},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use gix_features::{parallel, progress::Progress};
+
+use super::{Entry, Error};
+use crate::multi_index;
+
+/// The indices to merge the entries of, which are already sorted by id in each of them.
+pub(crate) struct Input<'a> {
+    /// Indices along with the pack index to assign to all of their entries.
+    pub(crate) indices: Vec<(&'a crate::index::File, multi_index::PackIndex)>,
+    /// A multi-index along with the pack index to assign to the entries of each of its indices, or `None` to skip them.
+    pub(crate) existing: Option<(&'a multi_index::File, Vec<Option<multi_index::PackIndex>>)>,
+}
+
+/// Entries sorted by id.
+type Source<'a> = Box<dyn Iterator<Item = Entry> + 'a>;
+
+/// The amount of entries after which interruptions are checked, which is often enough to abort quickly while merging or
+/// writing large amounts of them.
+const ENTRIES_PER_INTERRUPT_CHECK: usize = 4096;
+
+/// The amount of partitions whose entries are merged separately, one for each first byte of an id.
+const NUM_PARTITIONS: usize = 256;
+
+impl<'a> Input<'a> {
+    /// The amount of entries in all inputs, including the ones that are contained in more than one of them.
+    fn num_entries(&self) -> usize {
+        let num_existing_entries = self.existing.as_ref().map_or(0, |(existing, pack_indices)| {
+            (0..existing.num_objects())
+                .filter(|idx| pack_indices[existing.pack_id_and_pack_offset_at_index(*idx).0 as usize].is_some())
+                .count()
+        });
+        self.indices
+            .iter()
+            .map(|(index, _)| index.num_objects() as usize)
+            .sum::<usize>()
+            + num_existing_entries
+    }
+
+    /// Return the file name of each input along with the amount of its objects, in the order of [`sources()`][Input::sources()].
+    fn names_and_num_objects(&self) -> Vec<(String, usize)> {
+        let file_name = |path: &std::path::Path| {
+            path.file_name().map_or_else(
+                || path.display().to_string(),
+                |name| name.to_string_lossy().into_owned(),
+            )
+        };
+        let mut names = self
+            .indices
+            .iter()
+            .map(|(index, _)| (file_name(index.path()), index.num_objects() as usize))
+            .collect::<Vec<_>>();
+        if let Some((existing, _)) = &self.existing {
+            names.push((file_name(existing.path()), existing.num_objects() as usize));
+        }
+        names
+    }
+
+    /// Return the amount of entries of each input whose ids start with a byte in `first_bytes`, in the order of
+    /// [`sources()`][Input::sources()].
+    fn num_entries_per_source(&self, first_bytes: &RangeInclusive<u8>) -> Vec<usize> {
+        self.indices
+            .iter()
+            .map(|(index, _)| index.fan())
+            .chain(self.existing.as_ref().map(|(existing, _)| existing.fan()))
+            .map(|fan| entry_range(fan, first_bytes).len())
+            .collect()
+    }
+
+    /// Return the entries of each input whose ids start with a byte in `first_bytes`.
+    fn sources(&self, first_bytes: &RangeInclusive<u8>) -> Vec<Source<'_>> {
+        let mut sources = Vec::with_capacity(self.indices.len() + 1);
+        for &(index, pack_index) in &self.indices {
+            sources.push(Box::new(entry_range(index.fan(), first_bytes).map(move |idx| Entry {
+                id: index.oid_at_index(idx).to_owned(),
+                pack_index,
+                pack_offset: index.pack_offset_at_index(idx),
+            })) as Source<'_>);
+        }
+        if let Some((existing, pack_indices)) = &self.existing {
+            sources.push(Box::new(entry_range(existing.fan(), first_bytes).filter_map(
+                move |idx| {
+                    let (pack_index, pack_offset) = existing.pack_id_and_pack_offset_at_index(idx);
+                    pack_indices[pack_index as usize].map(|pack_index| Entry {
+                        id: existing.oid_at_index(idx).to_owned(),
+                        pack_index,
+                        pack_offset,
+                    })
+                },
+            )));
+        }
+        sources
+    }
+}
+
+/// Return the range of entries in a table with the fan-out table `fan` whose ids start with a byte in `first_bytes`.
+fn entry_range(fan: &[u32; 256], first_bytes: &RangeInclusive<u8>) -> Range<u32> {
+    let start = match *first_bytes.start() {
+        0 => 0,
+        byte => fan[byte as usize - 1],
+    };
+    start..fan[*first_bytes.end() as usize]
+}
+
+/// The entries of all inputs sorted by id, with only the one of the pack index with the lowest rank for each id.
+pub(crate) struct Sorted<'a> {
+    input: Input<'a>,
+    rank_by_pack_index: Vec<u32>,
+    num_entries: usize,
+    should_interrupt: &'a AtomicBool,
+    /// The merged entries of consecutive ranges of ids, or `None` if they don't fit into memory.
+    entries: Option<Vec<Vec<Entry>>>,
+    /// The amount of threads used for merging.
+    num_threads: usize,
+}
+
+impl<'a> Sorted<'a> {
+    /// Merge the entries of `input`, preferring the ones whose pack index has the lowest rank in `rank_by_pack_index`.
+    ///
+    /// If all entries fit into `max_memory` bytes, they are merged once in parallel with up to `thread_limit` threads, with
+    /// `progress` counting the objects read from all inputs and a child progress for each of them, named after its file.
+    /// Otherwise they are merged on the fly each time they are iterated.
+    /// Both are aborted soon after `should_interrupt` is set.
+    pub(crate) fn new<P>(
+        input: Input<'a>,
+        rank_by_pack_index: Vec<u32>,
+        max_memory: Option<usize>,
+        thread_limit: Option<usize>,
+        progress: &mut P,
+        should_interrupt: &'a AtomicBool,
+    ) -> Result<Self, Error>
+    where
+        P: Progress,
+    {
+        let num_entries = input.num_entries();
+        let mut sorted = Sorted {
+            input,
+            rank_by_pack_index,
+            num_entries,
+            should_interrupt,
+            entries: None,
+            num_threads: 1,
+        };
+        if max_memory.map_or(false, |max_memory| {
+            num_entries.saturating_mul(std::mem::size_of::<Entry>()) > max_memory
+        }) {
+            return Ok(sorted);
+        }
+
+        // Each partition contains the ids starting with the same byte, which yields enough partitions to keep all threads
+        // busy and to update the progress regularly, with the result being the same no matter how many threads are used.
+        let num_threads = parallel::num_threads(thread_limit).clamp(1, NUM_PARTITIONS);
+        progress.init(Some(num_entries), gix_features::progress::count("objects"));
+        let entries = parallel::in_parallel_if(
+            || num_threads > 1,
+            (0..NUM_PARTITIONS).map(|partition| partition as u8..=partition as u8),
+            thread_limit,
+            |_thread_id| (),
+            |first_bytes, _state| {
+                let merge = Merge::new(sorted.input.sources(&first_bytes), &sorted.rank_by_pack_index);
+                let entries = interruptible(merge, should_interrupt)
+                    .collect::<Result<_, _>>()
+                    .map_err(|_interrupted| Error::Interrupted)?;
+                Ok((
+                    *first_bytes.start() as usize,
+                    entries,
+                    sorted.input.num_entries_per_source(&first_bytes),
+                ))
+            },
+            reduce::EntriesInOrder::new(
+                NUM_PARTITIONS,
+                progress,
+                sorted.input.names_and_num_objects(),
+                should_interrupt,
+            ),
+        )?;
+        sorted.entries = Some(entries);
+        sorted.num_threads = num_threads;
+        Ok(sorted)
+    }
+
+    /// The amount of threads used for merging, which is one if entries are merged on the fly.
+    pub(crate) fn num_threads(&self) -> usize {
+        self.num_threads
+    }
+
+    /// The amount of entries of all inputs, including the ones that were deduplicated.
+    pub(crate) fn num_entries_with_duplicates(&self) -> usize {
+        self.num_entries
+    }
+
+    /// Return all entries sorted by id, without duplicates, or an error of kind [`Interrupted`][std::io::ErrorKind::Interrupted]
+    /// once an interruption was requested.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = std::io::Result<Entry>> + '_ {
+        let (in_memory, merged) = match &self.entries {
+            Some(entries) => (Some(entries.iter().flatten().copied()), None),
+            None => (
+                None,
+                Some(Merge::new(self.input.sources(&(0..=255)), &self.rank_by_pack_index)),
+            ),
+        };
+        interruptible(
+            in_memory.into_iter().flatten().chain(merged.into_iter().flatten()),
+            self.should_interrupt,
+        )
+    }
+
+    /// Return all entries contained in more than one input, each along with the pack indices of the entries with the same id
+    /// that were discarded in its favor in order of their rank, up to `max_duplicates` of them, or an error of kind
+    /// [`Interrupted`][std::io::ErrorKind::Interrupted] once an interruption was requested.
+    ///
+    /// As the discarded entries aren't kept, all inputs are merged once more to find them.
+    pub(crate) fn duplicates(
+        &self,
+        max_duplicates: Option<usize>,
+    ) -> std::io::Result<Vec<(Entry, Vec<multi_index::PackIndex>)>> {
+        let mut merge = Merge::new(self.input.sources(&(0..=255)), &self.rank_by_pack_index);
+        let mut duplicates = Vec::new();
+        let mut discarded = Vec::new();
+        for idx in 0.. {
+            if max_duplicates.map_or(false, |max_duplicates| duplicates.len() >= max_duplicates) {
+                break;
+            }
+            if idx % ENTRIES_PER_INTERRUPT_CHECK == 0 && self.should_interrupt.load(Ordering::Relaxed) {
+                return Err(std::io::Error::new(std::io::ErrorKind::Interrupted, "Interrupted"));
+            }
+            match merge.next_with_discarded(Some(&mut discarded)) {
+                Some(entry) if !discarded.is_empty() => duplicates.push((entry, std::mem::take(&mut discarded))),
+                Some(_) => {}
+                None => break,
+            }
+        }
+        Ok(duplicates)
+    }
+}
+
+/// Return `entries` until `should_interrupt` is set, which is checked every [`ENTRIES_PER_INTERRUPT_CHECK`] entries, and
+/// yield a single error of kind [`Interrupted`][std::io::ErrorKind::Interrupted] instead of the remaining ones.
+fn interruptible<'a>(
+    entries: impl Iterator<Item = Entry> + 'a,
+    should_interrupt: &'a AtomicBool,
+) -> impl Iterator<Item = std::io::Result<Entry>> + 'a {
+    let mut is_interrupted = false;
+    entries.enumerate().map_while(move |(idx, entry)| {
+        if is_interrupted {
+            return None;
+        }
+        if idx % ENTRIES_PER_INTERRUPT_CHECK == 0 && should_interrupt.load(Ordering::Relaxed) {
+            is_interrupted = true;
+            return Some(Err(std::io::Error::new(std::io::ErrorKind::Interrupted, "Interrupted")));
+        }
+        Some(Ok(entry))
+    })
+}
+
+/// A k-way merge of sources, yielding only the entry of the pack index with the lowest rank for each id.
+struct Merge<'a> {
+    sources: Vec<Source<'a>>,
+    heads: Vec<Option<Entry>>,
+    queue: BinaryHeap<Reverse<(gix_hash::ObjectId, u32, usize)>>,
+    rank_by_pack_index: &'a [u32],
+}
+
+impl<'a> Merge<'a> {
+    fn new(sources: Vec<Source<'a>>, rank_by_pack_index: &'a [u32]) -> Self {
+        let mut merge = Merge {
+            heads: sources.iter().map(|_| None).collect(),
+            queue: BinaryHeap::with_capacity(sources.len()),
+            sources,
+            rank_by_pack_index,
+        };
+        for source in 0..merge.sources.len() {
+            merge.advance(source);
+        }
+        merge
+    }
+
+    /// Return the current head of `source` and queue its next entry.
+    fn advance(&mut self, source: usize) -> Option<Entry> {
+        let head = self.heads[source].take();
+        if let Some(next) = self.sources[source].next() {
+            let rank = self.rank_by_pack_index[next.pack_index as usize];
+            self.queue.push(Reverse((next.id, rank, source)));
+            self.heads[source] = Some(next);
+        }
+        head
+    }
+
+    /// Return the next entry like [`Iterator::next()`], and push the pack indices of the entries with the same id that were
+    /// discarded in its favor to `discarded`, in order of their rank.
+    fn next_with_discarded(&mut self, mut discarded: Option<&mut Vec<multi_index::PackIndex>>) -> Option<Entry> {
+        let Reverse((_, _, source)) = self.queue.pop()?;
+        let entry = self.advance(source).expect("queued sources have a head");
+        while let Some(Reverse((id, _, source))) = self.queue.peek().copied() {
+            if id != entry.id {
+                break;
+            }
+            self.queue.pop();
+            let duplicate = self.advance(source).expect("queued sources have a head");
+            if let Some(discarded) = discarded.as_deref_mut() {
+                discarded.push(duplicate.pack_index);
+            }
+        }
+        Some(entry)
+    }
+}
+
+impl Iterator for Merge<'_> {
+    type Item = Entry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_with_discarded(None)
+    }
+}
+
+mod reduce {
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        time::Instant,
+    };
+
+    use gix_features::{parallel, progress::Progress};
+
+    use super::{Entry, Error};
+
+    /// Collects the merged entries of all partitions in the order of the partitions, no matter in which order they are produced.
+    pub(crate) struct EntriesInOrder<'a, P>
+    where
+        P: Progress,
+    {
+        entries_by_partition: Vec<Vec<Entry>>,
+        progress: &'a mut P,
+        /// The progress of each input, counting the objects read from it.
+        progress_by_source: Vec<P::SubProgress>,
+        start: Instant,
+        should_interrupt: &'a AtomicBool,
+    }
+
+    impl<'a, P> EntriesInOrder<'a, P>
+    where
+        P: Progress,
+    {
+        /// Create a new instance for `num_partitions`, with a child of `progress` for each input with the given name and
+        /// amount of objects.
+        pub(crate) fn new(
+            num_partitions: usize,
+            progress: &'a mut P,
+            names_and_num_objects: Vec<(String, usize)>,
+            should_interrupt: &'a AtomicBool,
+        ) -> Self {
+            let progress_by_source = names_and_num_objects
+                .into_iter()
+                .map(|(name, num_objects)| {
+                    let mut source_progress = progress.add_child(name);
+                    source_progress.init(Some(num_objects), gix_features::progress::count("objects"));
+                    source_progress
+                })
+                .collect();
+            EntriesInOrder {
+                entries_by_partition: (0..num_partitions).map(|_| Vec::new()).collect(),
+                progress,
+                progress_by_source,
+                start: Instant::now(),
+                should_interrupt,
+            }
+        }
+    }
+
+    impl<'a, P> parallel::Reduce for EntriesInOrder<'a, P>
+    where
+        P: Progress,
+    {
+        type Input = Result<(usize, Vec<Entry>, Vec<usize>), Error>;
+        type FeedProduce = ();
+        type Output = Vec<Vec<Entry>>;
+        type Error = Error;
+
+        fn feed(&mut self, item: Self::Input) -> Result<Self::FeedProduce, Self::Error> {
+            let (partition, entries, num_entries_per_source) = item?;
+            for (source_progress, num_entries) in self.progress_by_source.iter_mut().zip(&num_entries_per_source) {
+                source_progress.inc_by(*num_entries);
+            }
+            self.progress.inc_by(num_entries_per_source.iter().sum());
+            self.entries_by_partition[partition] = entries;
+            if self.should_interrupt.load(Ordering::Relaxed) {
+                return Err(Error::Interrupted);
+            }
+            Ok(())
+        }
+
+        fn finalize(mut self) -> Result<Self::Output, Self::Error> {
+            for source_progress in &mut self.progress_by_source {
+                source_progress.show_throughput(self.start);
+            }
+            Ok(self.entries_by_partition)
+        }
+    }
+}
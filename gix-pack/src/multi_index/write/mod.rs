@@ -0,0 +1,903 @@
+use std::{
+    convert::TryInto,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Instant, SystemTime},
+};
+
+use gix_features::progress::Progress;
+use gix_tempfile::{AutoRemove, ContainingDirectory};
+
+use crate::multi_index;
+
+mod merge;
+
+mod error {
+    /// The error returned by [multi_index::File::write_from_index_paths()][super::multi_index::File::write_from_index_paths()],
+    /// [multi_index::File::write_from_indices()][super::multi_index::File::write_from_indices()],
+    /// [multi_index::File::write_from_index_paths_to_directory()][super::multi_index::File::write_from_index_paths_to_directory()],
+    /// [multi_index::File::write_from_pack_dir()][super::multi_index::File::write_from_pack_dir()]
+    /// and [multi_index::File::write_updated()][super::multi_index::File::write_updated()].
+    #[derive(Debug, thiserror::Error)]
+    #[allow(missing_docs)]
+    pub enum Error {
+        #[error(transparent)]
+        Io(#[from] std::io::Error),
+        #[error("Interrupted")]
+        Interrupted,
+        #[error(transparent)]
+        OpenIndex(#[from] crate::index::init::Error),
+        #[error("Could not move the temporary multi-index file into its desired place")]
+        Persist(#[from] gix_tempfile::handle::persist::Error<gix_tempfile::handle::Writable>),
+        #[error("The index at '{path}' doesn't have an ASCII file name ending in '.idx'")]
+        InvalidIndexPath { path: std::path::PathBuf },
+        #[error("More than one index named '{name}' was provided")]
+        DuplicateIndexName { name: std::path::PathBuf },
+        #[error("A multi-index needs at least one index to refer to")]
+        NoIndices,
+        #[error("The preferred pack '{name}' isn't one of the packs to write into the multi-index")]
+        UnknownPreferredPack { name: std::path::PathBuf },
+        #[error("The index at '{path}' uses object hash {actual}, but {expected} was expected")]
+        ObjectHashMismatch {
+            path: std::path::PathBuf,
+            expected: gix_hash::Kind,
+            actual: gix_hash::Kind,
+        },
+        #[error("Multi-indices of version {} cannot be written, only {} is supported", *.0 as usize, crate::multi_index::Version::default() as usize)]
+        UnsupportedVersion(crate::multi_index::Version),
+        #[error("A multi-index can have at most 255 chunks, but {num_chunks} were planned")]
+        TooManyChunks { num_chunks: usize },
+        #[error("The chunk {:?} was planned, but there is no way to write it", std::str::from_utf8(.id))]
+        UnknownChunk { id: gix_chunk::Id },
+    }
+}
+pub use error::Error;
+
+/// An entry suitable for sorting and writing
+#[derive(Clone, Copy)]
+pub(crate) struct Entry {
+    pub(crate) id: gix_hash::ObjectId,
+    pub(crate) pack_index: u32,
+    pub(crate) pack_offset: crate::data::Offset,
+}
+
+/// Options for use in [`multi_index::File::write_from_index_paths()`].
+#[derive(Default)]
+pub struct Options {
+    /// The version of the multi-index to write, which fails with [`Error::UnsupportedVersion`] if it isn't the
+    /// [default][multi_index::Version::default()].
+    pub version: multi_index::Version,
+    /// The kind of hash to use for objects and to expect in the input files, which fails with [`Error::ObjectHashMismatch`]
+    /// for inputs with a different one before anything is written.
+    pub object_hash: gix_hash::Kind,
+    /// The amount of threads to use at most when merging the entries of all indices. If `None` or `Some(0)`, all logical
+    /// cores are used, and if `Some(1)` or without the `parallel` feature of `gix-features`, everything happens on the
+    /// current thread. The written multi-index is the same no matter how many threads are used.
+    pub thread_limit: Option<usize>,
+    /// The name of the pack whose objects are chosen over the same objects in other packs, like `pack-<hash>.pack` or the
+    /// name of its index. If `None`, the object of the index that was modified most recently is chosen, which is also how
+    /// ties between the other packs are broken. Indices with the same modification time are ordered by name, so the
+    /// object of the alphabetically first one is chosen among them.
+    ///
+    /// Note that the names of indices are always stored in alphabetical order, so the preferred pack isn't sorted first.
+    pub preferred_pack: Option<PathBuf>,
+    /// If `true`, indices whose objects are all taken from other indices aren't referred to by the multi-index, like
+    /// `git multi-pack-index expire` does it. They are listed in [`Outcome::redundant_index_names`] either way.
+    pub drop_redundant_indices: bool,
+    /// The amount of bytes the entries of all indices may occupy in memory at most, or `None` to keep all of them in memory.
+    ///
+    /// Each entry takes about 32 bytes. If more would be needed, the entries of all indices aren't merged once, but each time
+    /// they are needed for writing a part of the multi-index, which is slower and single-threaded, but needs almost no memory.
+    pub max_memory: Option<usize>,
+    /// If set, the modification time to assume for all indices instead of their actual one, so that the same indices always
+    /// produce the same multi-index, no matter when their files were created or touched. Honoring `SOURCE_DATE_EPOCH` is
+    /// up to the caller.
+    ///
+    /// As all indices appear to have been modified at the same time, the objects of the [preferred pack][Options::preferred_pack]
+    /// or of the alphabetically first index are chosen if they are contained in more than one of them. When
+    /// [updating a multi-index][multi_index::File::write_updated()], the objects of new indices are still chosen over existing ones.
+    pub mtime_override: Option<SystemTime>,
+    /// What to do if one of the indices given by path can't be opened, for instance because it is corrupt.
+    pub on_index_error: OnIndexError,
+    /// If `true`, list all objects contained in more than one index in [`Outcome::duplicates`] along with the indices whose
+    /// copies were discarded, which costs another pass over the entries of all indices. Otherwise only their amount is known.
+    pub collect_duplicates: bool,
+    /// The amount of duplicates to collect at most if [`collect_duplicates`][Options::collect_duplicates] is set, to limit
+    /// the memory needed for them, or `None` to collect all of them.
+    pub max_duplicates: Option<usize>,
+}
+
+/// What to do if an index can't be opened while writing a multi-index, as configured in [`Options::on_index_error`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OnIndexError {
+    /// Fail with [`Error::OpenIndex`] without writing anything.
+    #[default]
+    Fail,
+    /// Write the multi-index without the index and list it in [`Outcome::skipped_indices`] along with the error. Pack ids
+    /// refer to the remaining indices only. If no index could be opened, the error of the first one is returned as
+    /// if [`Fail`][OnIndexError::Fail] was used.
+    Skip,
+}
+
+/// An object that is contained in more than one index, as collected with [`Options::collect_duplicates`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Duplicate {
+    /// The id of the object.
+    pub id: gix_hash::ObjectId,
+    /// The name of the index whose copy of the object the multi-index refers to.
+    pub index_name: PathBuf,
+    /// The names of all other indices containing the object, whose copies were discarded, starting with the one that would
+    /// have been chosen next.
+    pub discarded_index_names: Vec<PathBuf>,
+}
+
+/// Options for use in [`multi_index::File::write_updated()`], in addition to [`Options`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UpdateOptions {
+    /// If `true`, drop the entries of all indices in the existing multi-index that don't exist next to it anymore, for
+    /// instance because their packs were removed. Otherwise all of its entries are kept.
+    pub drop_missing_indices: bool,
+}
+
+/// Options for use in [`multi_index::File::write_from_pack_dir()`], in addition to [`Options`].
+#[derive(Debug, Default, Clone)]
+pub struct PackDirOptions {
+    /// The names of packs whose indices shouldn't be referred to by the multi-index, like `pack-<hash>.pack` or the name of
+    /// their index or `.keep` file, for instance to leave out packs that are marked to be kept.
+    pub excluded_packs: Vec<PathBuf>,
+}
+
+/// The result of [`multi_index::File::write_from_index_paths()`], [`multi_index::File::write_from_index_paths_to_directory()`]
+/// and [`multi_index::File::write_updated()`].
+pub struct Outcome<P> {
+    /// The calculated multi-index checksum of the file at `multi_index_path`.
+    pub multi_index_checksum: gix_hash::ObjectId,
+    /// The path to the written multi-index file, if it was [written to a directory][multi_index::File::write_from_index_paths_to_directory()].
+    pub multi_index_path: Option<PathBuf>,
+    /// The amount of objects in the multi-index.
+    pub num_objects: multi_index::EntryIndex,
+    /// The amount of objects that were contained in more than one index, and thus were only written once.
+    pub num_duplicate_objects_removed: usize,
+    /// The names of all indices in the order they are stored in, which is the order in which pack ids refer to them.
+    pub index_names: Vec<PathBuf>,
+    /// For each entry in [`index_names`][Outcome::index_names], the amount of objects the multi-index refers to in it.
+    ///
+    /// If it is zero, all objects are also contained in other indices which were chosen instead, making the pack redundant.
+    pub num_objects_per_index: Vec<multi_index::EntryIndex>,
+    /// The names of all indices from which no object was taken as all of them are also contained in other indices, in
+    /// alphabetical order. Their packs can be deleted once the multi-index doesn't refer to them anymore, which is the
+    /// case if [`Options::drop_redundant_indices`] is set.
+    pub redundant_index_names: Vec<PathBuf>,
+    /// The amount of threads that were used to merge the entries of all indices, as derived from [`Options::thread_limit`].
+    ///
+    /// It's one if the entries didn't fit into [`Options::max_memory`] and were merged on the fly instead.
+    pub num_threads: usize,
+    /// The paths of all indices that couldn't be opened along with the reason, which are only skipped instead of causing
+    /// an error with [`OnIndexError::Skip`].
+    pub skipped_indices: Vec<(PathBuf, crate::index::init::Error)>,
+    /// All objects contained in more than one index in the order of their ids, if [`Options::collect_duplicates`] is set,
+    /// or up to [`Options::max_duplicates`] of them.
+    ///
+    /// Indices that are [dropped as redundant][Options::drop_redundant_indices] are listed here as well.
+    pub duplicates: Vec<Duplicate>,
+    /// The input progress
+    pub progress: P,
+}
+
+/// The progress ids used in [`write_from_index_paths()`][multi_index::File::write_from_index_paths()] and
+/// [`write_updated()`][multi_index::File::write_updated()].
+///
+/// Use this information to selectively extract the progress of interest in case the parent application has custom visualization.
+#[derive(Debug, Copy, Clone)]
+pub enum ProgressId {
+    /// Counts the objects read from all indices as they are merged into the multi-index, with a child for each index
+    /// that is named after it. It's then reused to count the objects while removing duplicates.
+    FromPathsCollectingEntries,
+    /// The amount of bytes written as part of the multi-index.
+    BytesWritten,
+}
+
+impl From<ProgressId> for gix_features::progress::Id {
+    fn from(v: ProgressId) -> Self {
+        match v {
+            ProgressId::FromPathsCollectingEntries => *b"MPCE",
+            ProgressId::BytesWritten => *b"MPBW",
+        }
+    }
+}
+
+impl multi_index::File {
+    pub(crate) const SIGNATURE: &'static [u8] = b"MIDX";
+    pub(crate) const HEADER_LEN: usize = 4 /*signature*/ +
+        1 /*version*/ +
+        1 /*object id version*/ +
+        1 /*num chunks */ +
+        1 /*num base files */ +
+        4 /*num pack files*/;
+
+    /// Create a new multi-index file for writing to `out` from the pack index files at `index_paths`.
+    /// Just like git, we refuse to write a multi-index without any index with [`Error::NoIndices`].
+    ///
+    /// Progress is sent to `progress` and interruptions checked via `should_interrupt`.
+    /// The already sorted entries of all indices are merged in parallel, unless the memory used for them is limited with
+    /// [`Options::max_memory`].
+    pub fn write_from_index_paths<P>(
+        index_paths: Vec<PathBuf>,
+        out: impl std::io::Write,
+        progress: P,
+        should_interrupt: &AtomicBool,
+        options: Options,
+    ) -> Result<Outcome<P>, Error>
+    where
+        P: Progress,
+    {
+        index_file_names(&index_paths)?;
+        let (indices, skipped_indices) = open_indices(
+            index_paths,
+            options.object_hash,
+            options.mtime_override,
+            options.on_index_error,
+        )?;
+        let mut outcome = Self::write_from_indices(indices, out, progress, should_interrupt, options)?;
+        outcome.skipped_indices = skipped_indices;
+        Ok(outcome)
+    }
+
+    /// Like [`write_from_index_paths()`][multi_index::File::write_from_index_paths()], but use the already opened `indices`
+    /// along with the path to take the name to store in the multi-index from, and their modification time, which is used
+    /// to choose the most recently modified index for objects that are contained in more than one of them unless
+    /// [`Options::mtime_override`] is set.
+    pub fn write_from_indices<P>(
+        mut indices: Vec<(crate::index::File, PathBuf, SystemTime)>,
+        out: impl std::io::Write,
+        mut progress: P,
+        should_interrupt: &AtomicBool,
+        Options {
+            version,
+            object_hash,
+            thread_limit,
+            preferred_pack,
+            drop_redundant_indices,
+            max_memory,
+            mtime_override,
+            on_index_error: _,
+            collect_duplicates,
+            max_duplicates,
+        }: Options,
+    ) -> Result<Outcome<P>, Error>
+    where
+        P: Progress,
+    {
+        check_version(version)?;
+        if indices.is_empty() {
+            return Err(Error::NoIndices);
+        }
+        for (index, path, _) in &indices {
+            check_object_hash(path, index.object_hash(), object_hash)?;
+        }
+        indices.sort_by(|(_, l, _), (_, r, _)| l.file_name().cmp(&r.file_name()));
+        let index_filenames_sorted = index_file_names(indices.iter().map(|(_, path, _)| path))?;
+        let preferred_pack_index = preferred_pack_index(preferred_pack.as_deref(), &index_filenames_sorted)?;
+        let mtimes = indices
+            .iter()
+            .map(|(_, _, mtime)| Some(mtime_override.unwrap_or(*mtime)))
+            .collect::<Vec<_>>();
+        let input = merge::Input {
+            indices: indices
+                .iter()
+                .enumerate()
+                .map(|(index_id, (index, _, _))| (index, index_id as multi_index::PackIndex))
+                .collect(),
+            existing: None,
+        };
+
+        let (entries, entries_progress) = collect_entries(
+            input,
+            rank_by_pack_index(&mtimes, preferred_pack_index),
+            max_memory,
+            thread_limit,
+            &mut progress,
+            should_interrupt,
+        )?;
+
+        Self::write_entries(
+            entries,
+            index_filenames_sorted,
+            out,
+            progress,
+            entries_progress,
+            should_interrupt,
+            version,
+            object_hash,
+            drop_redundant_indices,
+            collect_duplicates.then_some(max_duplicates),
+        )
+    }
+
+    /// Like [`write_from_index_paths()`][multi_index::File::write_from_index_paths()], but write the multi-index into a
+    /// temporary file in `directory` first, typically `objects/pack`, which is then moved to `multi-pack-index` in it so
+    /// readers never see a partially written file. Its path is returned in the
+    /// [`Outcome`][Outcome::multi_index_path].
+    ///
+    /// The temporary file is removed if writing fails or is interrupted.
+    pub fn write_from_index_paths_to_directory<P>(
+        index_paths: Vec<PathBuf>,
+        directory: impl AsRef<Path>,
+        progress: P,
+        should_interrupt: &AtomicBool,
+        options: Options,
+    ) -> Result<Outcome<P>, Error>
+    where
+        P: Progress,
+    {
+        let directory = directory.as_ref();
+        let mut file = gix_tempfile::new(directory, ContainingDirectory::Exists, AutoRemove::Tempfile)?;
+        let mut out = std::io::BufWriter::new(&mut file);
+        let mut outcome = Self::write_from_index_paths(index_paths, &mut out, progress, should_interrupt, options)?;
+        out.into_inner().map_err(|err| err.into_error())?;
+        file.with_mut(|file| file.as_file().sync_all())??;
+
+        let multi_index_path = directory.join("multi-pack-index");
+        file.persist(&multi_index_path)?;
+        outcome.multi_index_path = Some(multi_index_path);
+        Ok(outcome)
+    }
+
+    /// Write a multi-index for all packs in `pack_dir`, typically `objects/pack`, to `multi-pack-index` in it, just like
+    /// [`write_from_index_paths_to_directory()`][multi_index::File::write_from_index_paths_to_directory()] does for the indices
+    /// found by [`index_paths_in_pack_dir()`] while leaving out the [`excluded_packs`][PackDirOptions::excluded_packs].
+    ///
+    /// Progress is sent to `progress` and interruptions checked via `should_interrupt`.
+    pub fn write_from_pack_dir<P>(
+        pack_dir: impl AsRef<Path>,
+        progress: P,
+        should_interrupt: &AtomicBool,
+        options: Options,
+        PackDirOptions { excluded_packs }: PackDirOptions,
+    ) -> Result<Outcome<P>, Error>
+    where
+        P: Progress,
+    {
+        let pack_dir = pack_dir.as_ref();
+        let excluded_index_names = excluded_packs
+            .iter()
+            .filter_map(|name| name.file_name().map(|name| Path::new(name).with_extension("idx")))
+            .collect::<Vec<_>>();
+        let mut index_paths = index_paths_in_pack_dir(pack_dir, options.object_hash)?;
+        index_paths.retain(|path| {
+            path.file_name().map_or(true, |name| {
+                !excluded_index_names.iter().any(|excluded| excluded == name)
+            })
+        });
+        Self::write_from_index_paths_to_directory(index_paths, pack_dir, progress, should_interrupt, options)
+    }
+
+    /// Create a new multi-index file for writing to `out` with all entries of the `existing` multi-index, along with the ones
+    /// of the pack index files at `new_index_paths`, which is much faster than
+    /// [writing it from all index paths][multi_index::File::write_from_index_paths()] as only the new indices are read.
+    ///
+    /// Objects of new indices take precedence over the same objects in existing ones, as the newest index is chosen for
+    /// duplicate objects when writing a multi-index from scratch as well, unless the object is contained in the
+    /// [preferred pack][Options::preferred_pack]. New indices with the name of an existing one replace it.
+    /// If [`drop_missing_indices`][UpdateOptions::drop_missing_indices] is set, the indices of `existing` that don't exist
+    /// anymore next to it are dropped, which is the only time they are accessed. If no index remains, [`Error::NoIndices`]
+    /// is returned.
+    ///
+    /// Progress is sent to `progress` and interruptions checked via `should_interrupt`.
+    pub fn write_updated<P>(
+        existing: &multi_index::File,
+        new_index_paths: Vec<PathBuf>,
+        out: impl std::io::Write,
+        mut progress: P,
+        should_interrupt: &AtomicBool,
+        Options {
+            version,
+            object_hash,
+            thread_limit,
+            preferred_pack,
+            drop_redundant_indices,
+            max_memory,
+            mtime_override,
+            on_index_error,
+            collect_duplicates,
+            max_duplicates,
+        }: Options,
+        UpdateOptions { drop_missing_indices }: UpdateOptions,
+    ) -> Result<Outcome<P>, Error>
+    where
+        P: Progress,
+    {
+        check_version(version)?;
+        check_object_hash(existing.path(), existing.object_hash(), object_hash)?;
+        index_file_names(&new_index_paths)?;
+        let (new_indices, skipped_indices) =
+            open_indices(new_index_paths, object_hash, mtime_override, on_index_error)?;
+        let new_index_filenames = index_file_names(new_indices.iter().map(|(_, path, _)| path))?;
+        let existing_dir = existing.path().parent().expect("multi-index file is in a directory");
+        let is_kept = existing
+            .index_names()
+            .iter()
+            .map(|name| {
+                !new_index_filenames.contains(name) && (!drop_missing_indices || existing_dir.join(name).is_file())
+            })
+            .collect::<Vec<_>>();
+
+        let mut index_filenames_sorted = existing
+            .index_names()
+            .iter()
+            .zip(&is_kept)
+            .filter(|(_, is_kept)| **is_kept)
+            .map(|(name, _)| name.clone())
+            .chain(new_index_filenames.iter().cloned())
+            .collect::<Vec<_>>();
+        index_filenames_sorted.sort();
+        if index_filenames_sorted.is_empty() {
+            return Err(Error::NoIndices);
+        }
+        let index_id_by_name = |name: &PathBuf| {
+            index_filenames_sorted
+                .binary_search(name)
+                .expect("all names are present") as multi_index::PackIndex
+        };
+        let preferred_pack_index = preferred_pack_index(preferred_pack.as_deref(), &index_filenames_sorted)?;
+        let existing_index_ids = existing
+            .index_names()
+            .iter()
+            .zip(&is_kept)
+            .map(|(name, is_kept)| is_kept.then(|| index_id_by_name(name)))
+            .collect::<Vec<_>>();
+        // Existing entries are considered older than all new ones.
+        let mut mtimes = vec![None; index_filenames_sorted.len()];
+        for ((_, _, mtime), name) in new_indices.iter().zip(&new_index_filenames) {
+            mtimes[index_id_by_name(name) as usize] = Some(*mtime);
+        }
+        let input = merge::Input {
+            indices: new_indices
+                .iter()
+                .zip(&new_index_filenames)
+                .map(|((index, _, _), name)| (index, index_id_by_name(name)))
+                .collect(),
+            existing: Some((existing, existing_index_ids)),
+        };
+
+        let (entries, entries_progress) = collect_entries(
+            input,
+            rank_by_pack_index(&mtimes, preferred_pack_index),
+            max_memory,
+            thread_limit,
+            &mut progress,
+            should_interrupt,
+        )?;
+
+        let mut outcome = Self::write_entries(
+            entries,
+            index_filenames_sorted,
+            out,
+            progress,
+            entries_progress,
+            should_interrupt,
+            version,
+            object_hash,
+            drop_redundant_indices,
+            collect_duplicates.then_some(max_duplicates),
+        )?;
+        outcome.skipped_indices = skipped_indices;
+        Ok(outcome)
+    }
+
+    /// Write the deduplicated `entries` of the indices with `index_filenames_sorted` to `out` as multi-index, and remove
+    /// indices no object is taken from if `drop_redundant_indices` is set. `progress` is used for the individual steps,
+    /// and new progress is added to `parent_progress` for writing.
+    /// If `collect_duplicates` is `Some(max_duplicates)`, up to `max_duplicates` objects contained in more than one index are
+    /// collected as well.
+    #[allow(clippy::too_many_arguments)]
+    fn write_entries<P>(
+        entries: merge::Sorted<'_>,
+        index_filenames_sorted: Vec<PathBuf>,
+        out: impl std::io::Write,
+        mut parent_progress: P,
+        mut progress: P::SubProgress,
+        should_interrupt: &AtomicBool,
+        version: multi_index::Version,
+        object_hash: gix_hash::Kind,
+        drop_redundant_indices: bool,
+        collect_duplicates: Option<Option<usize>>,
+    ) -> Result<Outcome<P>, Error>
+    where
+        P: Progress,
+    {
+        let out = gix_features::hash::Write::new(out, object_hash);
+        let start = Instant::now();
+        progress.set_name("Deduplicate");
+        progress.init(
+            Some(entries.num_entries_with_duplicates()),
+            gix_features::progress::count("objects"),
+        );
+        let mut num_objects = 0;
+        let mut num_objects_per_index = vec![0; index_filenames_sorted.len()];
+        for entry in entries.iter() {
+            let entry = entry.map_err(io_or_interrupted)?;
+            num_objects += 1;
+            num_objects_per_index[entry.pack_index as usize] += 1;
+        }
+        let num_duplicate_objects_removed = entries.num_entries_with_duplicates() - num_objects;
+        let duplicates = match collect_duplicates {
+            Some(max_duplicates) => entries
+                .duplicates(max_duplicates)
+                .map_err(io_or_interrupted)?
+                .into_iter()
+                .map(|(entry, discarded)| Duplicate {
+                    id: entry.id,
+                    index_name: index_filenames_sorted[entry.pack_index as usize].clone(),
+                    discarded_index_names: discarded
+                        .into_iter()
+                        .map(|pack_index| index_filenames_sorted[pack_index as usize].clone())
+                        .collect(),
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        let redundant_index_names = index_filenames_sorted
+            .iter()
+            .zip(&num_objects_per_index)
+            .filter(|(_, num_objects)| **num_objects == 0)
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        let (index_filenames_sorted, num_objects_per_index, pack_index_by_previous_one) =
+            if drop_redundant_indices && !redundant_index_names.is_empty() {
+                // Pack ids are positions in the list of index names, which shift as redundant indices are removed before them.
+                let mut pack_index_by_previous_one = Vec::with_capacity(num_objects_per_index.len());
+                let mut next_pack_index = 0;
+                for num_objects in &num_objects_per_index {
+                    pack_index_by_previous_one.push(next_pack_index);
+                    if *num_objects != 0 {
+                        next_pack_index += 1;
+                    }
+                }
+                let (index_filenames_sorted, num_objects_per_index) = index_filenames_sorted
+                    .into_iter()
+                    .zip(num_objects_per_index)
+                    .filter(|(_, num_objects)| *num_objects != 0)
+                    .unzip();
+                (
+                    index_filenames_sorted,
+                    num_objects_per_index,
+                    Some(pack_index_by_previous_one),
+                )
+            } else {
+                (index_filenames_sorted, num_objects_per_index, None)
+            };
+        let sorted_entries = || {
+            entries.iter().map(|entry| {
+                entry.map(|mut entry| {
+                    if let Some(pack_index_by_previous_one) = &pack_index_by_previous_one {
+                        entry.pack_index = pack_index_by_previous_one[entry.pack_index as usize];
+                    }
+                    entry
+                })
+            })
+        };
+        progress.inc_by(entries.num_entries_with_duplicates());
+        progress.show_throughput(start);
+        if should_interrupt.load(Ordering::Relaxed) {
+            return Err(Error::Interrupted);
+        }
+
+        let mut cf = gix_chunk::file::Index::for_writing();
+        cf.plan_chunk(
+            multi_index::chunk::index_names::ID,
+            multi_index::chunk::index_names::storage_size(&index_filenames_sorted),
+        );
+        cf.plan_chunk(multi_index::chunk::fanout::ID, multi_index::chunk::fanout::SIZE as u64);
+        cf.plan_chunk(
+            multi_index::chunk::lookup::ID,
+            multi_index::chunk::lookup::storage_size(num_objects, object_hash),
+        );
+        cf.plan_chunk(
+            multi_index::chunk::offsets::ID,
+            multi_index::chunk::offsets::storage_size(num_objects),
+        );
+
+        let num_large_offsets =
+            multi_index::chunk::large_offsets::num_large_offsets(entries.iter()).map_err(io_or_interrupted)?;
+        if let Some(num_large_offsets) = num_large_offsets {
+            cf.plan_chunk(
+                multi_index::chunk::large_offsets::ID,
+                multi_index::chunk::large_offsets::storage_size(num_large_offsets),
+            );
+        }
+
+        let mut write_progress =
+            parent_progress.add_child_with_id("Writing multi-index", ProgressId::BytesWritten.into());
+        let write_start = Instant::now();
+        write_progress.init(
+            Some(cf.planned_storage_size() as usize + Self::HEADER_LEN),
+            gix_features::progress::bytes(),
+        );
+        let mut out = gix_features::progress::Write {
+            inner: out,
+            progress: write_progress,
+        };
+
+        let num_chunks = cf.num_chunks().try_into().map_err(|_| Error::TooManyChunks {
+            num_chunks: cf.num_chunks(),
+        })?;
+        let bytes_written = Self::write_header(
+            &mut out,
+            version,
+            num_chunks,
+            index_filenames_sorted.len() as u32,
+            object_hash,
+        )?;
+
+        {
+            progress.set_name("Writing chunks");
+            progress.init(Some(cf.num_chunks()), gix_features::progress::count("chunks"));
+
+            let mut chunk_write = cf.into_write(&mut out, bytes_written)?;
+            while let Some(chunk_to_write) = chunk_write.next_chunk() {
+                match (chunk_to_write, num_large_offsets) {
+                    (multi_index::chunk::index_names::ID, _) => {
+                        multi_index::chunk::index_names::write(&index_filenames_sorted, &mut chunk_write)
+                    }
+                    (multi_index::chunk::fanout::ID, _) => {
+                        multi_index::chunk::fanout::write(sorted_entries(), &mut chunk_write)
+                    }
+                    (multi_index::chunk::lookup::ID, _) => {
+                        multi_index::chunk::lookup::write(sorted_entries(), &mut chunk_write)
+                    }
+                    (multi_index::chunk::offsets::ID, _) => multi_index::chunk::offsets::write(
+                        sorted_entries(),
+                        num_large_offsets.is_some(),
+                        &mut chunk_write,
+                    ),
+                    (multi_index::chunk::large_offsets::ID, Some(num_large_offsets)) => {
+                        multi_index::chunk::large_offsets::write(sorted_entries(), num_large_offsets, &mut chunk_write)
+                    }
+                    (unknown, _) => return Err(Error::UnknownChunk { id: unknown }),
+                }
+                .map_err(io_or_interrupted)?;
+                progress.inc();
+                if should_interrupt.load(Ordering::Relaxed) {
+                    return Err(Error::Interrupted);
+                }
+            }
+        }
+
+        // write trailing checksum
+        let multi_index_checksum: gix_hash::ObjectId = out.inner.hash.digest().into();
+        out.inner.inner.write_all(multi_index_checksum.as_slice())?;
+        out.progress.show_throughput(write_start);
+        Ok(Outcome {
+            multi_index_checksum,
+            multi_index_path: None,
+            num_objects: num_objects as multi_index::EntryIndex,
+            num_duplicate_objects_removed,
+            index_names: index_filenames_sorted,
+            num_objects_per_index,
+            redundant_index_names,
+            num_threads: entries.num_threads(),
+            skipped_indices: Vec::new(),
+            duplicates,
+            progress: parent_progress,
+        })
+    }
+
+    fn write_header(
+        mut out: impl std::io::Write,
+        version: multi_index::Version,
+        num_chunks: u8,
+        num_indices: u32,
+        object_hash: gix_hash::Kind,
+    ) -> std::io::Result<usize> {
+        out.write_all(Self::SIGNATURE)?;
+        out.write_all(&[version as u8])?;
+        out.write_all(&[object_hash as u8])?;
+        out.write_all(&[num_chunks])?;
+        out.write_all(&[0])?; /* unused number of base files */
+        out.write_all(&num_indices.to_be_bytes())?;
+
+        Ok(Self::HEADER_LEN)
+    }
+}
+
+/// Merge the entries of `input` with the pack indices ranked by `rank_by_pack_index` as configured by `max_memory` and
+/// `thread_limit`, and return them along with the child of `progress` which shows the progress of collecting them, for
+/// reuse in the following steps.
+fn collect_entries<'a, P>(
+    input: merge::Input<'a>,
+    rank_by_pack_index: Vec<u32>,
+    max_memory: Option<usize>,
+    thread_limit: Option<usize>,
+    progress: &mut P,
+    should_interrupt: &'a AtomicBool,
+) -> Result<(merge::Sorted<'a>, P::SubProgress), Error>
+where
+    P: Progress,
+{
+    let start = Instant::now();
+    let mut entries_progress =
+        progress.add_child_with_id("Collecting entries", ProgressId::FromPathsCollectingEntries.into());
+    entries_progress.init(None, gix_features::progress::count("objects"));
+    let entries = merge::Sorted::new(
+        input,
+        rank_by_pack_index,
+        max_memory,
+        thread_limit,
+        &mut entries_progress,
+        should_interrupt,
+    )?;
+    entries_progress.show_throughput(start);
+    Ok((entries, entries_progress))
+}
+
+/// Return the paths to all indices in `pack_dir`, typically `objects/pack`, sorted by name, whose pack exists next to them.
+///
+/// Only files named `pack-<hash>.idx` with a hash of `object_hash` count as index, so temporary files, reverse indices, the
+/// multi-index and anything else in `pack_dir` are ignored.
+pub fn index_paths_in_pack_dir(
+    pack_dir: impl AsRef<Path>,
+    object_hash: gix_hash::Kind,
+) -> std::io::Result<Vec<PathBuf>> {
+    let is_index_name = |name: &str| {
+        name.strip_prefix("pack-")
+            .and_then(|name| name.strip_suffix(".idx"))
+            .map_or(false, |hash| {
+                hash.len() == object_hash.len_in_hex() && hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+            })
+    };
+    let mut index_paths = Vec::new();
+    for entry in std::fs::read_dir(pack_dir)? {
+        let path = entry?.path();
+        if path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map_or(false, is_index_name)
+            && path.is_file()
+            && path.with_extension("pack").is_file()
+        {
+            index_paths.push(path);
+        }
+    }
+    index_paths.sort();
+    Ok(index_paths)
+}
+
+/// Return the file names of all `index_paths` as they are stored in a multi-index, or fail if one of them isn't the path to an
+/// index with an ASCII name, like `pack-<hash>.idx`, or if two of them have the same name.
+fn index_file_names<'a>(index_paths: impl IntoIterator<Item = &'a PathBuf>) -> Result<Vec<PathBuf>, Error> {
+    let mut names = Vec::new();
+    for path in index_paths {
+        let name = path
+            .file_name()
+            .map(PathBuf::from)
+            .filter(|name| {
+                multi_index::chunk::index_names::is_valid_name(name)
+                    && name.to_str().map_or(false, |name| name.is_ascii())
+            })
+            .ok_or_else(|| Error::InvalidIndexPath { path: path.clone() })?;
+        if names.contains(&name) {
+            return Err(Error::DuplicateIndexName { name });
+        }
+        names.push(name);
+    }
+    Ok(names)
+}
+
+/// Fail if `version` can't be written.
+fn check_version(version: multi_index::Version) -> Result<(), Error> {
+    if version != multi_index::Version::default() {
+        return Err(Error::UnsupportedVersion(version));
+    }
+    Ok(())
+}
+
+/// Turn `err` into [`Error::Interrupted`] if it signals that iterating the entries was interrupted.
+fn io_or_interrupted(err: std::io::Error) -> Error {
+    if err.kind() == std::io::ErrorKind::Interrupted {
+        Error::Interrupted
+    } else {
+        Error::Io(err)
+    }
+}
+
+/// Fail if the file at `path` uses the `actual` object hash, which isn't the `expected` one.
+fn check_object_hash(path: &Path, actual: gix_hash::Kind, expected: gix_hash::Kind) -> Result<(), Error> {
+    if actual != expected {
+        return Err(Error::ObjectHashMismatch {
+            path: path.to_owned(),
+            expected,
+            actual,
+        });
+    }
+    Ok(())
+}
+
+/// Return the position of the index of `preferred_pack` in `index_filenames_sorted`, or fail if it isn't contained.
+fn preferred_pack_index(
+    preferred_pack: Option<&Path>,
+    index_filenames_sorted: &[PathBuf],
+) -> Result<Option<multi_index::PackIndex>, Error> {
+    preferred_pack
+        .map(|name| {
+            let index_name = name.file_name().map(|name| Path::new(name).with_extension("idx"));
+            index_name
+                .and_then(|index_name| index_filenames_sorted.binary_search(&index_name).ok())
+                .map(|idx| idx as multi_index::PackIndex)
+                .ok_or_else(|| Error::UnknownPreferredPack { name: name.to_owned() })
+        })
+        .transpose()
+}
+
+/// Open the indices at `index_paths` along with their modification time, or `mtime_override` if set, as expected by
+/// [`write_from_indices()`][multi_index::File::write_from_indices()], and return them along with the ones that couldn't be
+/// opened if `on_index_error` allows to skip them.
+#[allow(clippy::type_complexity)]
+fn open_indices(
+    index_paths: Vec<PathBuf>,
+    object_hash: gix_hash::Kind,
+    mtime_override: Option<SystemTime>,
+    on_index_error: OnIndexError,
+) -> Result<
+    (
+        Vec<(crate::index::File, PathBuf, SystemTime)>,
+        Vec<(PathBuf, crate::index::init::Error)>,
+    ),
+    Error,
+> {
+    let mut indices = Vec::with_capacity(index_paths.len());
+    let mut skipped_indices = Vec::new();
+    for path in index_paths {
+        let mtime = mtime_override.unwrap_or_else(|| {
+            path.metadata()
+                .and_then(|m| m.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH)
+        });
+        match crate::index::File::at(&path, object_hash) {
+            Ok(index) => indices.push((index, path, mtime)),
+            Err(err) if on_index_error == OnIndexError::Skip => skipped_indices.push((path, err)),
+            Err(err) => return Err(err.into()),
+        }
+    }
+    if indices.is_empty() && !skipped_indices.is_empty() {
+        let (_, err) = skipped_indices.swap_remove(0);
+        return Err(err.into());
+    }
+    Ok((indices, skipped_indices))
+}
+
+/// Return the rank of each pack index whose index was last modified at the time in `mtimes`, which is lower for the
+/// indices whose objects are chosen first if they are contained in more than one of them. These are the ones of the pack
+/// at `preferred_pack_index`, followed by the ones of the most recently modified indices and the ones without modification
+/// time, with ties broken by their pack index and thus by their name.
+fn rank_by_pack_index(mtimes: &[Option<SystemTime>], preferred_pack_index: Option<multi_index::PackIndex>) -> Vec<u32> {
+    let mut pack_indices_by_rank = (0..mtimes.len()).collect::<Vec<_>>();
+    pack_indices_by_rank.sort_by_key(|idx| {
+        (
+            Some(*idx as multi_index::PackIndex) != preferred_pack_index,
+            std::cmp::Reverse(mtimes[*idx]),
+            *idx,
+        )
+    });
+    let mut rank_by_pack_index = vec![0; mtimes.len()];
+    for (rank, idx) in pack_indices_by_rank.into_iter().enumerate() {
+        rank_by_pack_index[idx] = rank as u32;
+    }
+    rank_by_pack_index
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::size_of;
+
+    use super::Entry;
+
+    #[test]
+    fn size_of_entry() {
+        assert_eq!(
+            size_of::<Entry>(),
+            32,
+            "this shouldn't change without us noticing as there is one per object in all indices"
+        );
+    }
+}
@@ -1,4 +1,7 @@
-use std::{path::Path, sync::atomic::AtomicBool};
+use std::{
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 use gix_features::progress::Progress;
 
@@ -26,8 +29,9 @@ pub fn fan(data: &[u32]) -> Option<usize> {
 }
 
 /// Calculate the hash of the given kind by trying to read the file from disk at `data_path` or falling back on the mapped content in `data`.
-/// `Ok(desired_hash)` or `Err(Some(actual_hash))` is returned if the hash matches or mismatches.
-/// If the `Err(None)` is returned, the operation was interrupted.
+/// `Ok(desired_hash)` is returned if the hash matches, and an error if it mismatches or if the operation was interrupted.
+///
+/// Either way the data is hashed in chunks, so `should_interrupt` is honored and `progress` shows the throughput in bytes.
 pub fn checksum_on_disk_or_mmap(
     data_path: &Path,
     data: &[u8],
@@ -37,24 +41,26 @@ pub fn checksum_on_disk_or_mmap(
     should_interrupt: &AtomicBool,
 ) -> Result<gix_hash::ObjectId, checksum::Error> {
     let data_len_without_trailer = data.len() - object_hash.len_in_bytes();
-    let actual = match gix_features::hash::bytes_of_file(
+    let actual = gix_features::hash::bytes_of_file(
         data_path,
         data_len_without_trailer,
         object_hash,
         &mut progress,
         should_interrupt,
-    ) {
-        Ok(id) => id,
-        Err(err) if err.kind() == std::io::ErrorKind::Interrupted => return Err(checksum::Error::Interrupted),
-        Err(_io_err) => {
-            let start = std::time::Instant::now();
-            let mut hasher = gix_features::hash::hasher(object_hash);
-            hasher.update(&data[..data_len_without_trailer]);
-            progress.inc_by(data_len_without_trailer);
-            progress.show_throughput(start);
-            gix_hash::ObjectId::from(hasher.digest())
+    )
+    .or_else(|_io_err| {
+        if should_interrupt.load(Ordering::Relaxed) {
+            return Err(checksum::Error::Interrupted);
         }
-    };
+        gix_features::hash::bytes(
+            &data[..data_len_without_trailer],
+            data_len_without_trailer,
+            object_hash,
+            &mut progress,
+            should_interrupt,
+        )
+        .map_err(|_interrupted| checksum::Error::Interrupted)
+    })?;
 
     if actual == expected {
         Ok(actual)
@@ -0,0 +1,16 @@
+#!/bin/bash
+set -eu -o pipefail
+
+git init -q
+git checkout -q -b main
+
+echo first > first
+git add first
+git commit -qm first
+git repack -q
+
+echo second > second
+git add second
+git commit -qm second
+# keep the first pack so all of its objects are contained in both packs
+git repack -aq
@@ -0,0 +1,22 @@
+#!/bin/bash
+set -eu -o pipefail
+
+git init -q
+git config pack.writeReverseIndex true
+git checkout -q -b main
+
+for name in first second third; do
+  echo $name > $name
+  seq 1000 | sed "s/^/$name /" > $name-lines
+  git add $name $name-lines
+  git commit -qm $name
+done
+git repack -q
+
+for name in fourth fifth; do
+  echo $name > $name
+  git add $name
+  git commit -qm $name
+done
+# only pack the new objects so each object is contained in exactly one pack
+git repack -q
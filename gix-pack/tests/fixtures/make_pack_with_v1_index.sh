@@ -0,0 +1,25 @@
+#!/bin/bash
+set -eu -o pipefail
+
+git init -q
+git checkout -q -b main
+
+for name in first second third; do
+  echo $name > $name
+  git add $name
+  git commit -qm $name
+done
+git repack -q
+
+# keep the version 2 index of the first pack for comparison before replacing it with a version 1 index
+mkdir v2-index
+pack=$(ls .git/objects/pack/pack-*.pack)
+cp ${pack%.pack}.idx v2-index/
+rm ${pack%.pack}.idx
+git index-pack --index-version=1 -o ${pack%.pack}.idx $pack >/dev/null
+
+echo fourth > fourth
+git add fourth
+git commit -qm fourth
+# the objects of the new commit are in a second pack with a version 2 index
+git repack -q
@@ -504,3 +504,159 @@ fn iter() -> Result<(), Box<dyn std::error::Error>> {
     }
     Ok(())
 }
+
+#[test]
+fn lookup_prefix_finds_the_same_candidates_as_multi_indices() -> Result<(), Box<dyn std::error::Error>> {
+    let pack_dir = crate::scripted_fixture_read_only("make_pack_gen_repo_multi_index.sh")?.join(".git/objects/pack");
+    let multi_index = pack::multi_index::File::at(pack_dir.join("multi-pack-index"))?;
+    assert_eq!(multi_index.num_indices(), 1);
+    let file = index::File::at(pack_dir.join(&multi_index.index_names()[0]), gix_hash::Kind::Sha1)?;
+    assert_eq!(file.num_objects(), multi_index.num_objects());
+
+    let mut num_ambiguous = 0;
+    for entry in file.iter() {
+        for hex_len in gix_hash::Prefix::MIN_HEX_LEN..=7 {
+            let prefix = gix_hash::Prefix::new(entry.oid, hex_len)?;
+            let (mut candidates, mut expected_candidates) = (0..0, 0..0);
+            let res = file.lookup_prefix(prefix, Some(&mut candidates));
+            assert_eq!(
+                res,
+                multi_index.lookup_prefix(prefix, Some(&mut expected_candidates)),
+                "{}",
+                prefix
+            );
+            assert_eq!(candidates, expected_candidates);
+            assert_eq!(file.lookup_prefix(prefix, None), res);
+            assert!(candidates
+                .clone()
+                .all(|idx| prefix.cmp_oid(file.oid_at_index(idx)).is_eq()));
+            assert!(
+                candidates.start == 0 || prefix.cmp_oid(file.oid_at_index(candidates.start - 1)).is_gt(),
+                "all candidates are found"
+            );
+            assert!(
+                candidates.end == file.num_objects() || prefix.cmp_oid(file.oid_at_index(candidates.end)).is_lt(),
+                "all candidates are found"
+            );
+            match res.expect("object exists") {
+                Ok(idx) => assert_eq!(file.oid_at_index(idx), entry.oid),
+                Err(()) => {
+                    assert!(candidates.len() > 1);
+                    num_ambiguous += 1;
+                }
+            }
+        }
+    }
+    assert_ne!(num_ambiguous, 0, "some short prefixes are ambiguous");
+    Ok(())
+}
+
+mod iter_by_pack_offset {
+    use gix_pack::index::{self, reverse};
+
+    use crate::{
+        fixture_path,
+        pack::{INDEX_V1, SMALL_PACK_INDEX, V2_PACKS_AND_INDICES},
+    };
+
+    /// Return all entries of `file` sorted by pack offset after collecting them.
+    fn sorted_entries(file: &index::File) -> Vec<index::Entry> {
+        let mut entries: Vec<_> = file.iter().collect();
+        entries.sort_by_key(|entry| entry.pack_offset);
+        entries
+    }
+
+    #[test]
+    fn entries_are_ordered_by_pack_offset() -> crate::Result {
+        let mut positions = Vec::new();
+        for index_path in V2_PACKS_AND_INDICES
+            .iter()
+            .map(|(index, _)| *index)
+            .chain(Some(INDEX_V1))
+        {
+            let file = index::File::at(fixture_path(index_path), gix_hash::Kind::Sha1)?;
+            let expected = sorted_entries(&file);
+            let entries = file.iter_by_pack_offset()?;
+            assert_eq!(entries.len(), file.num_objects() as usize);
+            assert_eq!(entries.collect::<Vec<_>>(), expected);
+            assert_eq!(
+                file.iter_by_pack_offset_with_buffer(&mut positions)?
+                    .collect::<Vec<_>>(),
+                expected
+            );
+            assert_eq!(positions, file.reverse_index()?);
+            for (position, entry) in positions.iter().zip(&expected) {
+                assert_eq!(file.entry_at_index(*position), *entry);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn buffers_are_reused() -> crate::Result {
+        let file = index::File::at(fixture_path(SMALL_PACK_INDEX), gix_hash::Kind::Sha1)?;
+        let mut positions = Vec::with_capacity(file.num_objects() as usize);
+        positions.push(42);
+        let buffer = positions.as_ptr();
+        assert_eq!(file.iter_by_pack_offset_with_buffer(&mut positions)?.count(), 42);
+        assert_eq!(positions.as_ptr(), buffer, "no reallocation happened");
+        assert_eq!(
+            positions.len(),
+            file.num_objects() as usize,
+            "the buffer was cleared first"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn large_offsets_are_ordered_after_small_ones() -> crate::Result {
+        let dir = tempfile::TempDir::new()?;
+        let path = dir.path().join("pack-large.idx");
+        let offsets = [5 << 32, 12, 1 << 31, (1 << 31) - 1, 1 << 32];
+        let entries: Vec<_> = offsets
+            .iter()
+            .enumerate()
+            .map(|(idx, offset)| ([idx as u8 * 50; 20], *offset))
+            .collect();
+        crate::write_index(&path, &entries)?;
+        let file = index::File::at(&path, gix_hash::Kind::Sha1)?;
+
+        assert_eq!(
+            file.iter_by_pack_offset()?
+                .map(|entry| (entry.pack_offset, entry.oid.as_bytes()[0]))
+                .collect::<Vec<_>>(),
+            vec![
+                (12, 50),
+                ((1 << 31) - 1, 150),
+                (1 << 31, 100),
+                (1 << 32, 200),
+                (5 << 32, 0)
+            ]
+        );
+        assert_eq!(file.reverse_index()?, vec![1, 3, 2, 4, 0]);
+        Ok(())
+    }
+
+    #[test]
+    fn duplicate_offsets_are_an_error() -> crate::Result {
+        let dir = tempfile::TempDir::new()?;
+        let path = dir.path().join("pack-duplicate.idx");
+        crate::write_index(&path, &[([1; 20], 1 << 33), ([2; 20], 12), ([3; 20], 1 << 33)])?;
+        let file = index::File::at(&path, gix_hash::Kind::Sha1)?;
+
+        let err = file.iter_by_pack_offset().map(|_| ()).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                reverse::Error::DuplicateOffset {
+                    offset: 0x2_0000_0000,
+                    first: 0,
+                    second: 2
+                }
+            ),
+            "{:?}",
+            err
+        );
+        Ok(())
+    }
+}
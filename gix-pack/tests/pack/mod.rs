@@ -24,6 +24,49 @@ pub fn hex_to_id(hex: &str) -> ObjectId {
 
 pub type Result<T = ()> = std::result::Result<T, Box<dyn std::error::Error>>;
 
+/// Write a version 2 index with `entries` of made-up object ids and their pack offsets to `path`, whose pack doesn't need
+/// to exist for reading the index or writing a multi-index. `entries` must be sorted by id.
+///
+/// Offsets that don't fit into 31 bits are stored in the table of 64 bit offsets, just like git does it.
+pub fn write_index(path: &std::path::Path, entries: &[([u8; 20], u64)]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    const HIGH_BIT: u32 = 1 << 31;
+    let mut fan = [0u32; 256];
+    for (id, _) in entries {
+        fan[id[0] as usize] += 1;
+    }
+    let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+    out.write_all(b"\xfftOc")?;
+    out.write_all(&2u32.to_be_bytes())?;
+    let mut num_ids = 0;
+    for count in fan {
+        num_ids += count;
+        out.write_all(&num_ids.to_be_bytes())?;
+    }
+    for (id, _) in entries {
+        out.write_all(id)?;
+    }
+    for _crc32 in entries {
+        out.write_all(&0u32.to_be_bytes())?;
+    }
+    let mut large_offsets = Vec::new();
+    for (_, offset) in entries {
+        let offset = if *offset < u64::from(HIGH_BIT) {
+            *offset as u32
+        } else {
+            large_offsets.push(*offset);
+            (large_offsets.len() as u32 - 1) | HIGH_BIT
+        };
+        out.write_all(&offset.to_be_bytes())?;
+    }
+    for offset in large_offsets {
+        out.write_all(&offset.to_be_bytes())?;
+    }
+    out.write_all(&[0; 40])?;
+    out.flush()
+}
+
 #[cfg(not(windows))]
 pub fn fixup(v: Vec<u8>) -> Vec<u8> {
     v
@@ -46,6 +46,44 @@ fn lookup_prefix() {
     }
 }
 
+#[test]
+fn lookup_prefix_finds_all_candidates_within_a_fan_out_bucket() {
+    let (file, _path) = multi_index();
+    let oids: Vec<_> = file.iter().map(|e| e.oid).collect();
+    let mut num_ambiguous = 0;
+    let mut num_bucket_edges = 0;
+    for (idx, oid) in oids.iter().enumerate() {
+        let is_bucket_edge = [idx.checked_sub(1), Some(idx + 1)]
+            .iter()
+            .flatten()
+            .filter_map(|idx| oids.get(*idx))
+            .any(|other| other.first_byte() != oid.first_byte());
+        num_bucket_edges += usize::from(is_bucket_edge);
+
+        let prefix = gix_hash::Prefix::new(oid, gix_hash::Prefix::MIN_HEX_LEN).unwrap();
+        let expected = {
+            let start = oids
+                .iter()
+                .position(|oid| prefix.cmp_oid(oid).is_eq())
+                .expect("present") as u32;
+            let len = oids.iter().filter(|oid| prefix.cmp_oid(oid).is_eq()).count() as u32;
+            start..start + len
+        };
+        let mut candidates = 0..0;
+        let res = file.lookup_prefix(prefix, Some(&mut candidates)).expect("object found");
+        assert_eq!(candidates, expected, "{}", oid);
+        assert_eq!(file.lookup_prefix(prefix, None), Some(res));
+        if expected.len() > 1 {
+            assert_eq!(res, Err(()));
+            num_ambiguous += 1;
+        } else {
+            assert_eq!(res, Ok(expected.start));
+        }
+    }
+    assert_ne!(num_ambiguous, 0, "some short prefixes are ambiguous");
+    assert_ne!(num_bucket_edges, 0, "objects are at the edge of their fan-out bucket");
+}
+
 #[test]
 fn lookup_missing() {
     let (file, _path) = multi_index();
@@ -66,6 +104,16 @@ fn general() {
     assert_eq!(file.num_indices(), 1);
     assert_eq!(file.object_hash(), gix_hash::Kind::Sha1);
     assert_eq!(file.num_objects(), 868);
+    assert_eq!(file.fan()[255], file.num_objects());
+    let mut objects_per_first_byte = [0u32; 256];
+    for entry in file.iter() {
+        objects_per_first_byte[entry.oid.first_byte() as usize] += 1;
+    }
+    let mut num_objects = 0;
+    for (first_byte, count) in objects_per_first_byte.iter().enumerate() {
+        num_objects += count;
+        assert_eq!(file.fan()[first_byte], num_objects);
+    }
     assert_eq!(file.checksum(), hex_to_id("39a3804d0a84de609e4fcb49e66dc1297c75ca11"));
     // assert_eq!()
     assert_eq!(
@@ -96,3 +144,79 @@ fn general() {
     }
     assert_eq!(count, file.num_objects());
 }
+
+#[test]
+fn objects_resolve_to_the_pack_and_offset_of_their_index() -> crate::Result {
+    let dir = tempfile::TempDir::new()?;
+    let input_indices = std::fs::read_dir(gix_testtools::fixture_path_standalone("objects/pack"))?
+        .map(|entry| entry.map(|e| e.path()))
+        .filter(|path| {
+            path.as_ref()
+                .map_or(true, |p| p.extension().and_then(|e| e.to_str()) == Some("idx"))
+        })
+        .take(2)
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(input_indices.len(), 2);
+    let output_path = dir.path().join("multi-pack-index");
+    gix_pack::multi_index::File::write_from_index_paths(
+        input_indices.clone(),
+        std::fs::File::create(&output_path)?,
+        gix_features::progress::Discard,
+        &std::sync::atomic::AtomicBool::new(false),
+        gix_pack::multi_index::write::Options::default(),
+    )?;
+    let file = gix_pack::multi_index::File::at(&output_path)?;
+
+    let mut num_objects = 0;
+    for index_path in &input_indices {
+        let index = gix_pack::index::File::at(index_path, gix_hash::Kind::Sha1)?;
+        let index_name = PathBuf::from(index_path.file_name().expect("present"));
+        for entry in index.iter() {
+            let (pack_index, pack_offset) = file
+                .pack_id_and_pack_offset(entry.oid)
+                .expect("all objects of all indices are present");
+            if file.index_names()[pack_index as usize] == index_name {
+                assert_eq!(pack_offset, entry.pack_offset, "{}", entry.oid);
+                assert_eq!(file.pack_name(pack_index), Some(index_name.with_extension("pack")));
+                assert_eq!(
+                    file.pack_path(pack_index, index_path.parent().expect("in directory")),
+                    Some(index_path.with_extension("pack"))
+                );
+                num_objects += 1;
+            }
+        }
+    }
+    assert_eq!(
+        num_objects,
+        file.num_objects(),
+        "each object is found in the index the multi-index points to"
+    );
+    assert_eq!(file.pack_id_and_pack_offset(gix_hash::Kind::Sha1.null()), None);
+    assert_eq!(file.pack_name(file.num_indices()), None);
+
+    for (index, entry) in file.iter().enumerate() {
+        let found = file
+            .lookup_offset(entry.pack_index, entry.pack_offset)
+            .expect("each entry is found at its offset");
+        assert_eq!(found, index as u32);
+        assert_eq!(file.oid_at_index(found), entry.oid, "round-trips to the same object");
+        assert_eq!(
+            file.lookup_offset(entry.pack_index, entry.pack_offset + 1),
+            None,
+            "no other object starts right behind the header of this one"
+        );
+        assert_eq!(file.lookup_offset(file.num_indices(), entry.pack_offset), None);
+    }
+    Ok(())
+}
+
+#[test]
+fn lookup_offset_finds_entries_by_their_offset_in_a_pack() {
+    let (file, _path) = multi_index();
+    for (idx, pack_offset) in [(0u32, 25267u64), (140, 30421), (867, 24540)] {
+        assert_eq!(file.lookup_offset(0, pack_offset), Some(idx));
+    }
+    assert_eq!(file.lookup_offset(0, 0), None, "the pack header isn't an object");
+    assert_eq!(file.lookup_offset(0, u64::MAX), None);
+    assert_eq!(file.lookup_offset(1, 25267), None, "there is only one pack");
+}
@@ -0,0 +1,143 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::AtomicBool,
+};
+
+use gix_features::progress;
+use gix_pack::multi_index::Bundle;
+
+/// Copy all fixture packs into a new directory and write a multi-index for the first `num_indices` of their indices, ordered
+/// by name. Return the directory along with all index paths, ordered by name.
+fn pack_dir_with_multi_index(num_indices: usize) -> crate::Result<(tempfile::TempDir, Vec<PathBuf>)> {
+    let dir = tempfile::TempDir::new()?;
+    let mut index_paths = Vec::new();
+    for entry in std::fs::read_dir(gix_testtools::fixture_path_standalone("objects/pack"))? {
+        let path = entry?.path();
+        let copied_path = dir.path().join(path.file_name().expect("present"));
+        std::fs::copy(&path, &copied_path)?;
+        if copied_path.extension().map_or(false, |ext| ext == "idx") {
+            index_paths.push(copied_path);
+        }
+    }
+    index_paths.sort();
+    gix_pack::multi_index::File::write_from_index_paths(
+        index_paths[..num_indices].to_vec(),
+        std::fs::File::create(dir.path().join("multi-pack-index"))?,
+        progress::Discard,
+        &AtomicBool::new(false),
+        Default::default(),
+    )?;
+    Ok((dir, index_paths))
+}
+
+fn bundle(dir: &Path, max_open_packs: usize) -> crate::Result<Bundle> {
+    let multi_index = gix_pack::multi_index::File::at(dir.join("multi-pack-index"))?;
+    Ok(Bundle::new(multi_index, dir, max_open_packs)?)
+}
+
+/// Assert that all objects of the packs of `index_paths` are found in `bundle` with the same data as in their own pack, while
+/// never mapping more packs than allowed.
+fn assert_all_objects_are_found(bundle: &mut Bundle, index_paths: &[PathBuf]) -> crate::Result {
+    let (mut expected, mut actual) = (Vec::new(), Vec::new());
+    for index_path in index_paths {
+        let pack = gix_pack::Bundle::at(index_path, gix_hash::Kind::Sha1)?;
+        for entry in pack.index.iter() {
+            let expected_kind = pack
+                .find(entry.oid, &mut expected, &mut gix_pack::cache::Never)?
+                .expect("present")
+                .0
+                .kind;
+            assert!(bundle.contains(entry.oid));
+            let (object, _location) = bundle
+                .find(entry.oid, &mut actual, &mut gix_pack::cache::Never)?
+                .expect("all objects of all packs are found");
+            assert_eq!(object.kind, expected_kind, "{}", entry.oid);
+            assert_eq!(object.data, expected.as_slice(), "{}", entry.oid);
+            assert!(
+                bundle.num_open_packs() <= bundle.max_open_packs(),
+                "the least recently used pack is unmapped to make room for another one"
+            );
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn objects_of_all_packs_are_found_while_mapping_at_most_the_given_amount_of_packs() -> crate::Result {
+    let (dir, index_paths) = pack_dir_with_multi_index(3)?;
+    for (max_open_packs, expected_max_open_packs) in [(0, 1), (1, 1), (2, 2), (64, 64)] {
+        let mut bundle = bundle(dir.path(), max_open_packs)?;
+        assert_eq!(bundle.max_open_packs(), expected_max_open_packs);
+        assert!(
+            bundle.fallback_indices().is_empty(),
+            "all indices are in the multi-index"
+        );
+        assert_eq!(bundle.num_open_packs(), 0, "packs are only mapped on first use");
+
+        assert_all_objects_are_found(&mut bundle, &index_paths)?;
+        assert_eq!(
+            bundle.num_open_packs(),
+            index_paths.len().min(expected_max_open_packs),
+            "packs stay mapped until the limit is reached"
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn objects_of_indices_not_referred_to_by_the_multi_index_are_found_in_their_packs() -> crate::Result {
+    let (dir, index_paths) = pack_dir_with_multi_index(1)?;
+    let mut bundle = bundle(dir.path(), 1)?;
+    assert_eq!(bundle.multi_index().num_indices(), 1);
+    assert_eq!(
+        bundle.fallback_indices().len(),
+        2,
+        "all other indices in the directory are used as fallback"
+    );
+
+    assert_all_objects_are_found(&mut bundle, &index_paths)?;
+    assert_eq!(bundle.num_open_packs(), 1);
+    Ok(())
+}
+
+#[test]
+fn missing_objects_are_not_found_without_mapping_packs() -> crate::Result {
+    let (dir, _index_paths) = pack_dir_with_multi_index(2)?;
+    let mut bundle = bundle(dir.path(), 1)?;
+    let missing = gix_hash::ObjectId::null(gix_hash::Kind::Sha1);
+    assert!(!bundle.contains(missing));
+    assert!(bundle
+        .find(missing, &mut Vec::new(), &mut gix_pack::cache::Never)?
+        .is_none());
+    assert_eq!(bundle.num_open_packs(), 0);
+    Ok(())
+}
+
+#[test]
+fn packs_which_are_missing_are_an_error_once_objects_are_read_from_them() -> crate::Result {
+    let (dir, index_paths) = pack_dir_with_multi_index(3)?;
+    std::fs::remove_file(index_paths[0].with_extension("pack"))?;
+    let mut bundle = bundle(dir.path(), 1)?;
+    let index = gix_pack::index::File::at(&index_paths[0], gix_hash::Kind::Sha1)?;
+    let id = index
+        .iter()
+        .map(|entry| entry.oid)
+        .find(|id| {
+            bundle
+                .multi_index()
+                .pack_id_and_pack_offset(id)
+                .map_or(false, |(pack_index, _)| pack_index == 0)
+        })
+        .expect("at least one object is taken from the first pack");
+    assert!(bundle.contains(id), "the lookup only needs the multi-index");
+    let err = bundle
+        .find(id, &mut Vec::new(), &mut gix_pack::cache::Never)
+        .map(|_| ())
+        .unwrap_err();
+    assert!(
+        matches!(err, gix_pack::multi_index::bundle::find::Error::OpenPack(_)),
+        "{:?}",
+        err
+    );
+    Ok(())
+}
@@ -0,0 +1,195 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::AtomicBool,
+};
+
+use gix_features::progress;
+use gix_pack::multi_index::{
+    chain::init::{Error, DIR_NAME, FILE_NAME},
+    Chain,
+};
+use gix_testtools::fixture_path_standalone;
+
+/// Return the paths to three indices without any objects in common.
+fn input_indices() -> crate::Result<Vec<PathBuf>> {
+    let mut paths = std::fs::read_dir(fixture_path_standalone("objects/pack"))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    paths.retain(|path| path.extension().and_then(|e| e.to_str()) == Some("idx"));
+    paths.sort();
+    assert_eq!(paths.len(), 3);
+    Ok(paths)
+}
+
+/// Write a multi-index with all `index_paths` to `path` and return its checksum.
+fn write_multi_index(index_paths: Vec<PathBuf>, path: &Path) -> crate::Result<gix_hash::ObjectId> {
+    Ok(gix_pack::multi_index::File::write_from_index_paths(
+        index_paths,
+        std::fs::File::create(path)?,
+        progress::Discard,
+        &AtomicBool::new(false),
+        gix_pack::multi_index::write::Options::default(),
+    )?
+    .multi_index_checksum)
+}
+
+/// Write a chain into the pack directory in `dir` in the same layout git uses, with a layer for each set of indices in
+/// `layers`, and return the paths to the layers.
+fn write_chain(dir: &Path, layers: Vec<Vec<PathBuf>>) -> crate::Result<Vec<PathBuf>> {
+    let chain_dir = dir.join(DIR_NAME);
+    std::fs::create_dir_all(&chain_dir)?;
+    let mut chain_file = String::new();
+    let mut layer_paths = Vec::new();
+    for index_paths in layers {
+        let tmp_path = chain_dir.join("tmp.midx");
+        let checksum = write_multi_index(index_paths, &tmp_path)?;
+        let layer_path = chain_dir.join(format!("multi-pack-index-{}.midx", checksum));
+        std::fs::rename(tmp_path, &layer_path)?;
+        chain_file.push_str(&format!("{}\n", checksum));
+        layer_paths.push(layer_path);
+    }
+    std::fs::write(chain_dir.join(FILE_NAME), chain_file)?;
+    Ok(layer_paths)
+}
+
+#[test]
+fn layers_are_presented_as_a_single_multi_index() -> crate::Result {
+    let dir = tempfile::TempDir::new()?;
+    let indices = input_indices()?;
+    write_chain(dir.path(), vec![vec![indices[2].clone()], indices[..2].to_vec()])?;
+    let monolithic_path = dir.path().join("all.midx");
+    write_multi_index(indices.clone(), &monolithic_path)?;
+    let monolithic = gix_pack::multi_index::File::at(&monolithic_path)?;
+
+    for chain in [Chain::at(dir.path())?, Chain::at(dir.path().join(DIR_NAME))?] {
+        assert_eq!(chain.layers().len(), 2);
+        assert_eq!(chain.num_objects(), monolithic.num_objects());
+        assert_eq!(chain.num_indices(), 3);
+        assert_eq!(chain.object_hash(), gix_hash::Kind::Sha1);
+        let file_name = |path: &PathBuf| PathBuf::from(path.file_name().expect("present"));
+        assert_eq!(
+            chain.index_names().cloned().collect::<Vec<_>>(),
+            vec![file_name(&indices[2]), file_name(&indices[0]), file_name(&indices[1])],
+            "the indices of the base come first"
+        );
+        assert_eq!(chain.pack_name(3), None);
+
+        for entry in monolithic.iter() {
+            let index = chain.lookup(entry.oid).expect("all objects are present");
+            assert_eq!(chain.oid_at_index(index), entry.oid);
+            let (pack_index, pack_offset) = chain.pack_id_and_pack_offset_at_index(index);
+            assert_eq!(pack_offset, entry.pack_offset);
+            assert_eq!(chain.pack_name(pack_index), monolithic.pack_name(entry.pack_index));
+            assert_eq!(
+                chain.pack_id_and_pack_offset(entry.oid),
+                Some((pack_index, pack_offset))
+            );
+        }
+        assert_eq!(chain.iter().len(), monolithic.num_objects() as usize);
+        for (index, entry) in chain.iter().enumerate() {
+            assert_eq!(chain.lookup(entry.oid), Some(index as u32));
+            assert_eq!(chain.entry_at_index(index as u32), entry);
+        }
+        assert_eq!(chain.lookup(gix_hash::Kind::Sha1.null()), None);
+    }
+    Ok(())
+}
+
+#[test]
+fn multi_index_files_are_preferred_over_chains_and_have_a_single_layer() -> crate::Result {
+    let dir = tempfile::TempDir::new()?;
+    let indices = input_indices()?;
+    write_chain(dir.path(), vec![vec![indices[0].clone()]])?;
+    let path = dir.path().join("multi-pack-index");
+    write_multi_index(indices, &path)?;
+
+    for chain in [Chain::at(dir.path())?, Chain::at(&path)?] {
+        assert_eq!(chain.layers().len(), 1);
+        assert_eq!(chain.layers()[0].path(), path);
+        assert_eq!(chain.num_indices(), 3);
+    }
+    Ok(())
+}
+
+#[test]
+fn layers_must_have_the_checksum_listed_in_the_chain_file() -> crate::Result {
+    let dir = tempfile::TempDir::new()?;
+    let indices = input_indices()?;
+    let layers = write_chain(dir.path(), vec![vec![indices[0].clone()], vec![indices[1].clone()]])?;
+    std::fs::copy(&layers[0], &layers[1])?;
+
+    let err = Chain::at(dir.path()).map(|_| ()).unwrap_err();
+    assert!(
+        matches!(&err, Error::LayerChecksumMismatch { path, .. } if *path == layers[1]),
+        "{:?}",
+        err
+    );
+    Ok(())
+}
+
+#[test]
+fn chain_files_must_list_checksums_of_at_least_one_layer() -> crate::Result {
+    let dir = tempfile::TempDir::new()?;
+    write_chain(dir.path(), Vec::new())?;
+    let err = Chain::at(dir.path()).map(|_| ()).unwrap_err();
+    assert!(matches!(err, Error::NoLayers), "{:?}", err);
+
+    std::fs::write(dir.path().join(DIR_NAME).join(FILE_NAME), "not a checksum\n")?;
+    let err = Chain::at(dir.path()).map(|_| ()).unwrap_err();
+    assert!(
+        matches!(&err, Error::InvalidLayerChecksum { line, .. } if line == "not a checksum"),
+        "{:?}",
+        err
+    );
+    Ok(())
+}
+
+#[test]
+fn declared_base_files_must_match_the_amount_of_layers_before() -> crate::Result {
+    let dir = tempfile::TempDir::new()?;
+    let indices = input_indices()?;
+    let layers = write_chain(dir.path(), vec![vec![indices[0].clone()], vec![indices[1].clone()]])?;
+    let set_num_base_files = |num_base_files: u8| -> std::io::Result<()> {
+        const NUM_BASE_FILES_OFFSET: usize = 7;
+        let mut data = std::fs::read(&layers[1])?;
+        data[NUM_BASE_FILES_OFFSET] = num_base_files;
+        std::fs::write(&layers[1], data)
+    };
+
+    set_num_base_files(1)?;
+    let chain = Chain::at(dir.path())?;
+    assert_eq!(chain.layers()[1].num_base_files(), 1);
+    assert_eq!(chain.num_indices(), 2);
+
+    set_num_base_files(2)?;
+    let err = Chain::at(dir.path()).map(|_| ()).unwrap_err();
+    assert!(
+        matches!(
+            err,
+            Error::BaseFilesMismatch {
+                expected: 1,
+                actual: 2,
+                ..
+            }
+        ),
+        "{:?}",
+        err
+    );
+    Ok(())
+}
+
+#[test]
+fn paths_without_multi_index_are_an_error() -> crate::Result {
+    let dir = tempfile::TempDir::new()?;
+    let missing = dir.path().join("missing");
+    let err = Chain::at(&missing).map(|_| ()).unwrap_err();
+    assert!(
+        matches!(&err, Error::InvalidPath(path) if *path == missing),
+        "{:?}",
+        err
+    );
+
+    let err = Chain::at(dir.path()).map(|_| ()).unwrap_err();
+    assert!(matches!(err, Error::Io { .. }), "{:?}", err);
+    Ok(())
+}
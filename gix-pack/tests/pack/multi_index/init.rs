@@ -0,0 +1,411 @@
+use std::{
+    convert::TryInto,
+    path::{Path, PathBuf},
+};
+
+use gix_pack::multi_index::{init::Error, File};
+
+use super::multi_index;
+
+/// The length of the header, after which the chunk table with entries of 4 bytes id and 8 bytes offset follows.
+const HEADER_LEN: usize = 12;
+const TOC_ENTRY_LEN: usize = 12;
+
+fn write_to_tempdir(data: &[u8]) -> crate::Result<(tempfile::TempDir, PathBuf)> {
+    let dir = tempfile::TempDir::new()?;
+    let path = dir.path().join("multi-pack-index");
+    std::fs::write(&path, data)?;
+    Ok((dir, path))
+}
+
+fn multi_index_data() -> crate::Result<Vec<u8>> {
+    Ok(std::fs::read(multi_index().1)?)
+}
+
+fn open(path: &Path) -> Error {
+    File::at(path).map(|_| ()).expect_err("the file is invalid")
+}
+
+#[test]
+fn files_without_signature_are_not_a_multi_index() -> crate::Result {
+    for data in [
+        &b""[..],
+        b"MID",
+        b"CGPH and some more data that makes it long enough to be a multi-index",
+    ] {
+        let (_dir, path) = write_to_tempdir(data)?;
+        let err = open(&path);
+        assert!(
+            matches!(&err, Error::NotAMultiIndex { path: err_path } if err_path == &path),
+            "{:?}",
+            err
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn unsupported_versions_are_rejected() -> crate::Result {
+    let mut data = multi_index_data()?;
+    data[4] = 2;
+    let (_dir, path) = write_to_tempdir(&data)?;
+    let err = open(&path);
+    assert!(matches!(err, Error::UnsupportedVersion { version: 2 }), "{:?}", err);
+    Ok(())
+}
+
+#[test]
+fn corrupt_chunk_tables_are_rejected() -> crate::Result {
+    let data = multi_index_data()?;
+    let num_chunks = data[6] as usize;
+
+    let mut missing_sentinel = data.clone();
+    missing_sentinel[HEADER_LEN + num_chunks * TOC_ENTRY_LEN] = b'X';
+    let mut non_incremental_offsets = data.clone();
+    non_incremental_offsets[HEADER_LEN + TOC_ENTRY_LEN + 4..][..8].copy_from_slice(&0u64.to_be_bytes());
+    let mut duplicate_chunk = data;
+    let first_id: [u8; 4] = duplicate_chunk[HEADER_LEN..][..4].try_into()?;
+    duplicate_chunk[HEADER_LEN + TOC_ENTRY_LEN..][..4].copy_from_slice(&first_id);
+
+    for data in [missing_sentinel, non_incremental_offsets, duplicate_chunk] {
+        let (_dir, path) = write_to_tempdir(&data)?;
+        let err = open(&path);
+        assert!(matches!(err, Error::ChunkFileDecode(_)), "{:?}", err);
+    }
+    Ok(())
+}
+
+#[test]
+fn mandatory_chunks_must_be_present() -> crate::Result {
+    let mut data = multi_index_data()?;
+    let num_chunks = data[6] as usize;
+    let lookup_chunk_id = (0..num_chunks)
+        .map(|idx| HEADER_LEN + idx * TOC_ENTRY_LEN)
+        .find(|ofs| &data[*ofs..][..4] == b"OIDL")
+        .expect("lookup chunk is present");
+    data[lookup_chunk_id..][..4].copy_from_slice(b"XXXX");
+    let (_dir, path) = write_to_tempdir(&data)?;
+    let err = open(&path);
+    assert!(matches!(err, Error::MissingChunk(_)), "{:?}", err);
+    Ok(())
+}
+
+#[test]
+fn fan_out_tables_must_be_sorted() -> crate::Result {
+    let mut data = multi_index_data()?;
+    let num_chunks = data[6] as usize;
+    let fan_ofs = (0..num_chunks)
+        .map(|idx| HEADER_LEN + idx * TOC_ENTRY_LEN)
+        .find(|ofs| &data[*ofs..][..4] == b"OIDF")
+        .map(|ofs| u64::from_be_bytes(data[ofs + 4..][..8].try_into().expect("8 bytes")) as usize)
+        .expect("fan-out chunk is present");
+    data[fan_ofs..][..4].copy_from_slice(&u32::MAX.to_be_bytes());
+    let (_dir, path) = write_to_tempdir(&data)?;
+    let err = open(&path);
+    assert!(matches!(err, Error::MultiPackFanOrder), "{:?}", err);
+    Ok(())
+}
+
+#[test]
+fn index_names_must_be_file_names_of_indices() -> crate::Result {
+    let data = multi_index_data()?;
+    let names_ofs = data
+        .windows(5)
+        .position(|w| w == b"pack-")
+        .expect("index name is present");
+    for replacement in [&b"/"[..], b"../", b"a/"] {
+        let mut data = data.clone();
+        data[names_ofs..][..replacement.len()].copy_from_slice(replacement);
+        let (_dir, path) = write_to_tempdir(&data)?;
+        let err = open(&path);
+        assert!(
+            matches!(
+                err,
+                Error::PackNames(gix_pack::multi_index::chunk::index_names::decode::Error::InvalidName { .. })
+            ),
+            "{:?}",
+            err
+        );
+    }
+    Ok(())
+}
+
+mod index_names {
+    use std::path::PathBuf;
+
+    use gix_pack::multi_index::chunk::index_names::{decode::Error, from_bytes};
+
+    #[test]
+    fn valid_names_are_returned_in_order() {
+        assert_eq!(
+            from_bytes(b"a.idx\0bc.idx\0\0\0\0", 2).expect("valid"),
+            vec![PathBuf::from("a.idx"), PathBuf::from("bc.idx")]
+        );
+    }
+
+    #[test]
+    fn names_must_be_unique_and_sorted() {
+        assert!(matches!(
+            from_bytes(b"a.idx\0a.idx\0", 2),
+            Err(Error::DuplicateName { path }) if path == std::path::Path::new("a.idx")
+        ));
+        assert!(matches!(
+            from_bytes(b"b.idx\0a.idx\0", 2),
+            Err(Error::NotOrderedAlphabetically { path, previous })
+                if path == std::path::Path::new("a.idx") && previous == std::path::Path::new("b.idx")
+        ));
+    }
+
+    #[test]
+    fn names_must_be_null_terminated() {
+        assert!(matches!(
+            from_bytes(b"a.idx\0b.idx", 2),
+            Err(Error::MissingNullByte { index: 1 })
+        ));
+    }
+
+    #[test]
+    fn chunks_may_only_contain_null_bytes_for_alignment_after_the_names() {
+        for (chunk, expected, actual) in [
+            (&b"a.idx\0b.idx\0"[..], 12, 12),
+            (b"a.idx\0b.idx\0\0\0", 12, 14),
+            (b"a.idx\0b.idx\0\0\0\0\0", 12, 16),
+            (b"ab.idx\0\0", 8, 8),
+            (b"ab.idx\0\0\0", 8, 9),
+            (b"ab.idx\0", 8, 7),
+        ] {
+            let num_packs = chunk.iter().filter(|b| **b == b'.').count() as u32;
+            match from_bytes(chunk, num_packs) {
+                Ok(_) => assert_eq!(expected, actual, "only chunks with the expected size are valid"),
+                Err(Error::SizeMismatch {
+                    expected: err_expected,
+                    actual: err_actual,
+                }) => assert_eq!((err_expected, err_actual), (expected, actual)),
+                Err(err) => panic!("unexpected error: {:?}", err),
+            }
+        }
+        assert!(matches!(
+            from_bytes(b"a.idx\0bc.idx\0X\0\0", 2),
+            Err(Error::UnknownTrailerBytes)
+        ));
+    }
+
+    #[test]
+    fn names_must_not_point_outside_of_the_pack_directory() {
+        for name in [
+            &b"/a.idx"[..],
+            b"../a.idx",
+            b"dir/a.idx",
+            b"..",
+            b"a.pack",
+            b"a",
+            b"",
+            b"C:a.idx",
+            b"C:\\a.idx",
+            b"dir\\a.idx",
+        ] {
+            let mut chunk = name.to_vec();
+            chunk.push(0);
+            let err = from_bytes(&chunk, 1).expect_err("invalid name");
+            assert!(matches!(err, Error::InvalidName { .. }), "{:?}", err);
+        }
+    }
+}
+
+#[test]
+fn unknown_chunks_are_ignored_but_listed() -> crate::Result {
+    let data = multi_index_data()?;
+    let num_chunks = data[6] as usize;
+    let toc_len = (num_chunks + 1) * TOC_ENTRY_LEN;
+    let (toc, rest) = data[HEADER_LEN..].split_at(toc_len);
+    let (chunks, trailer) = rest.split_at(rest.len() - gix_hash::Kind::Sha1.len_in_bytes());
+    let extra_chunk = *b"fakedata";
+
+    let mut with_unknown_chunk = data[..HEADER_LEN].to_vec();
+    with_unknown_chunk[6] += 1;
+    let shifted =
+        |offset: &[u8]| (u64::from_be_bytes(offset.try_into().expect("8 bytes")) + TOC_ENTRY_LEN as u64).to_be_bytes();
+    for entry in toc[..num_chunks * TOC_ENTRY_LEN].chunks(TOC_ENTRY_LEN) {
+        with_unknown_chunk.extend_from_slice(&entry[..4]);
+        with_unknown_chunk.extend_from_slice(&shifted(&entry[4..]));
+    }
+    let sentinel = &toc[num_chunks * TOC_ENTRY_LEN..];
+    with_unknown_chunk.extend_from_slice(b"XXXX");
+    with_unknown_chunk.extend_from_slice(&shifted(&sentinel[4..]));
+    with_unknown_chunk.extend_from_slice(&sentinel[..4]);
+    with_unknown_chunk
+        .extend_from_slice(&(u64::from_be_bytes(shifted(&sentinel[4..])) + extra_chunk.len() as u64).to_be_bytes());
+    with_unknown_chunk.extend_from_slice(chunks);
+    with_unknown_chunk.extend_from_slice(&extra_chunk);
+    with_unknown_chunk.extend_from_slice(trailer);
+
+    let (_dir, path) = write_to_tempdir(&with_unknown_chunk)?;
+    let file = File::at(&path)?;
+    let extra_chunk_start = u64::from_be_bytes(shifted(&sentinel[4..]));
+    let extra_chunk_range = extra_chunk_start..extra_chunk_start + extra_chunk.len() as u64;
+    assert_eq!(file.unknown_chunks(), &[(*b"XXXX", extra_chunk_range.clone())]);
+    assert_eq!(
+        &with_unknown_chunk[extra_chunk_range.start as usize..extra_chunk_range.end as usize],
+        &extra_chunk
+    );
+    let (expected, _) = multi_index();
+    assert!(expected.unknown_chunks().is_empty());
+    assert_eq!(file.num_objects(), expected.num_objects());
+    assert_eq!(file.index_names(), expected.index_names());
+    for idx in [0, expected.num_objects() / 2, expected.num_objects() - 1] {
+        assert_eq!(file.oid_at_index(idx), expected.oid_at_index(idx));
+        assert_eq!(
+            file.pack_id_and_pack_offset_at_index(idx),
+            expected.pack_id_and_pack_offset_at_index(idx)
+        );
+    }
+    Ok(())
+}
+
+mod offsets {
+    use gix_pack::multi_index::{init::Error, File};
+
+    use super::{write_to_tempdir, HEADER_LEN, TOC_ENTRY_LEN};
+
+    const HIGH_BIT: u32 = 1 << 31;
+
+    /// Return the chunks of a multi-index for a single index with an object for each of `offsets` as stored in the offsets
+    /// chunk, along with a large offsets chunk with `large_offsets` if there are any.
+    fn chunks(offsets: &[u32], large_offsets: &[u64]) -> Vec<([u8; 4], Vec<u8>)> {
+        let num_objects = offsets.len() as u32;
+        // Each made-up object id starts with a different byte, beginning with 1.
+        let fan = (0..=255u32)
+            .flat_map(|byte| byte.min(num_objects).to_be_bytes())
+            .collect();
+        let ids = (1..=num_objects).flat_map(|byte| [byte as u8; 20]).collect();
+        // The pack id of all objects is 0, so each entry is the offset in the lower 4 bytes.
+        let offsets = offsets
+            .iter()
+            .flat_map(|offset| u64::from(*offset).to_be_bytes())
+            .collect();
+        let mut chunks = vec![
+            (*b"PNAM", b"pack-a.idx\0\0".to_vec()),
+            (*b"OIDF", fan),
+            (*b"OIDL", ids),
+            (*b"OOFF", offsets),
+        ];
+        if !large_offsets.is_empty() {
+            chunks.push((
+                *b"LOFF",
+                large_offsets.iter().flat_map(|offset| offset.to_be_bytes()).collect(),
+            ));
+        }
+        chunks
+    }
+
+    /// Return a multi-index file with `chunks` and a valid checksum.
+    fn multi_index_data(chunks: &[([u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let mut data = b"MIDX".to_vec();
+        data.extend_from_slice(&[1, 1, chunks.len() as u8, 0]);
+        data.extend_from_slice(&1u32.to_be_bytes());
+        let mut offset = (HEADER_LEN + (chunks.len() + 1) * TOC_ENTRY_LEN) as u64;
+        for (id, chunk) in chunks {
+            data.extend_from_slice(id);
+            data.extend_from_slice(&offset.to_be_bytes());
+            offset += chunk.len() as u64;
+        }
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&offset.to_be_bytes());
+        for (_, chunk) in chunks {
+            data.extend_from_slice(chunk);
+        }
+        let mut hasher = gix_features::hash::hasher(gix_hash::Kind::Sha1);
+        hasher.update(&data);
+        data.extend_from_slice(&hasher.digest());
+        data
+    }
+
+    fn open(chunks: &[([u8; 4], Vec<u8>)]) -> Result<File, Error> {
+        let (_dir, path) = write_to_tempdir(&multi_index_data(chunks)).expect("temporary file can be written");
+        File::at(path)
+    }
+
+    #[test]
+    fn large_offsets_beyond_a_truncated_large_offsets_chunk_are_an_error() -> crate::Result {
+        let offsets = [
+            12,
+            HIGH_BIT,
+            HIGH_BIT | 1,
+            HIGH_BIT | 2,
+            HIGH_BIT | 3,
+            HIGH_BIT | u32::MAX,
+        ];
+        let all_large_offsets = [1 << 32, 2 << 32, 3 << 32, 4 << 32];
+        for num_large_offsets in 1..=all_large_offsets.len() {
+            let err = open(&chunks(&offsets, &all_large_offsets[..num_large_offsets]))
+                .map(|_| ())
+                .unwrap_err();
+            let expected_index = offsets
+                .iter()
+                .position(|offset| offset & HIGH_BIT == HIGH_BIT && (offset ^ HIGH_BIT) as usize >= num_large_offsets)
+                .expect("the last large offset is always out of bounds");
+            assert!(
+                matches!(
+                    err,
+                    Error::LargeOffsetOutOfBounds {
+                        index,
+                        large_offset_index,
+                        num_large_offsets: err_num_large_offsets,
+                    } if index as usize == expected_index
+                        && large_offset_index == offsets[expected_index] ^ HIGH_BIT
+                        && err_num_large_offsets == num_large_offsets
+                ),
+                "{:?}",
+                err
+            );
+        }
+
+        let file = open(&chunks(&offsets[..offsets.len() - 1], &all_large_offsets))?;
+        assert_eq!(file.pack_id_and_pack_offset_at_index(0), (0, 12));
+        for (index, expected) in all_large_offsets.iter().enumerate() {
+            assert_eq!(file.pack_id_and_pack_offset_at_index(index as u32 + 1), (0, *expected));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn large_offsets_chunks_may_have_more_entries_than_referenced() -> crate::Result {
+        let file = open(&chunks(&[HIGH_BIT | 1, 12], &[1 << 32, 2 << 32, 3 << 32]))?;
+        assert_eq!(file.pack_id_and_pack_offset_at_index(0), (0, 2 << 32));
+        assert_eq!(file.pack_id_and_pack_offset_at_index(1), (0, 12));
+        Ok(())
+    }
+
+    #[test]
+    fn large_offsets_chunks_must_consist_of_8_byte_entries() {
+        for len in [1, 4, 7, 9, 12] {
+            let mut chunks = chunks(&[HIGH_BIT], &[1 << 32, 2 << 32]);
+            chunks.last_mut().expect("large offsets").1.truncate(len);
+            let err = open(&chunks).map(|_| ()).unwrap_err();
+            assert!(
+                matches!(err, Error::InvalidChunkSize { id, .. } if id == *b"LOFF"),
+                "{:?}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn offsets_chunks_must_have_an_entry_for_each_object() {
+        for (num_objects, offsets_len) in [(2, 8), (2, 12), (2, 20), (2, 24), (0, 8)] {
+            let mut chunks = chunks(&vec![12; num_objects], &[]);
+            let offsets = &mut chunks[3].1;
+            offsets.resize(offsets_len, 0);
+            if num_objects == 0 {
+                // Chunks can't be empty, so use an invalid object id for the lookup chunk to reach the offsets chunk.
+                chunks[2].1 = vec![0; 4];
+            }
+            let err = open(&chunks).map(|_| ()).unwrap_err();
+            assert!(
+                matches!(err, Error::InvalidChunkSize { id, .. } if id == *b"OOFF"),
+                "{:?}",
+                err
+            );
+        }
+    }
+}
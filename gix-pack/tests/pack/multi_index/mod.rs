@@ -12,6 +12,16 @@ fn multi_index() -> (File, PathBuf) {
 
 mod access;
 
+mod bundle;
+
+mod chain;
+
+mod init;
+
+mod reverse;
+
+mod stale;
+
 mod verify;
 
 mod write;
@@ -0,0 +1,85 @@
+use std::{path::PathBuf, sync::atomic::AtomicBool};
+
+use gix_features::progress;
+use gix_pack::index::reverse;
+
+/// Return the paths to the indices of two packs without any objects in common, each with a reverse index written by git.
+fn indices_with_reverse_index() -> crate::Result<Vec<PathBuf>> {
+    let pack_dir = crate::scripted_fixture_read_only("make_pack_with_reverse_index.sh")?.join(".git/objects/pack");
+    let mut paths = gix_pack::multi_index::write::index_paths_in_pack_dir(pack_dir, gix_hash::Kind::Sha1)?;
+    assert_eq!(paths.len(), 2);
+    for path in &paths {
+        assert!(path.with_extension("rev").is_file(), "git writes reverse indices");
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Write the reverse index with `positions` for the pack of `index` and return its bytes.
+fn write_reverse_index(positions: &[u32], index: &gix_pack::index::File) -> crate::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let checksum = reverse::write(positions, gix_hash::Kind::Sha1, &index.pack_checksum(), &mut out)?;
+    assert_eq!(
+        checksum.as_slice(),
+        &out[out.len() - 20..],
+        "the checksum is written last"
+    );
+    Ok(out)
+}
+
+#[test]
+fn reverse_indices_from_multi_index_data_are_the_same_as_the_ones_of_git() -> crate::Result {
+    let index_paths = indices_with_reverse_index()?;
+    let dir = tempfile::TempDir::new()?;
+    let multi_index_path = dir.path().join("multi-pack-index");
+    gix_pack::multi_index::File::write_from_index_paths(
+        index_paths.clone(),
+        std::fs::File::create(&multi_index_path)?,
+        progress::Discard,
+        &AtomicBool::new(false),
+        gix_pack::multi_index::write::Options::default(),
+    )?;
+    let multi_index = gix_pack::multi_index::File::at(&multi_index_path)?;
+
+    for (pack_index, index_path) in index_paths.iter().enumerate() {
+        let index = gix_pack::index::File::at(index_path, gix_hash::Kind::Sha1)?;
+        let positions = multi_index.reverse_index(pack_index as u32)?;
+        assert_eq!(positions.len(), index.num_objects() as usize);
+        assert_eq!(positions, index.reverse_index()?, "both agree on the order of objects");
+        assert!(
+            positions
+                .windows(2)
+                .all(|pair| index.pack_offset_at_index(pair[0]) < index.pack_offset_at_index(pair[1])),
+            "positions are ordered by pack offset"
+        );
+
+        let expected = std::fs::read(index_path.with_extension("rev"))?;
+        assert_eq!(&expected[..4], reverse::SIGNATURE);
+        assert_eq!(
+            write_reverse_index(&positions, &index)?,
+            expected,
+            "the file is byte-identical to the one written by git"
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn duplicate_offsets_are_an_error() {
+    assert_eq!(reverse::from_offsets([30, 12, 20]).expect("unique"), vec![1, 2, 0]);
+    assert_eq!(reverse::from_offsets(None).expect("empty"), Vec::<u32>::new());
+
+    let err = reverse::from_offsets([30, 12, 20, 12]).unwrap_err();
+    assert!(
+        matches!(
+            err,
+            reverse::Error::DuplicateOffset {
+                offset: 12,
+                first: 1,
+                second: 3
+            }
+        ),
+        "{:?}",
+        err
+    );
+}
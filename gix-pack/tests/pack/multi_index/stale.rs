@@ -0,0 +1,90 @@
+use std::{path::PathBuf, sync::atomic::AtomicBool};
+
+use gix_features::progress;
+use gix_pack::multi_index::{File, Stale};
+
+/// Return a directory with a copy of all fixture packs and a multi-index referencing them, along with the names of the indices.
+fn pack_dir_with_multi_index() -> crate::Result<(tempfile::TempDir, Vec<PathBuf>)> {
+    let dir = tempfile::TempDir::new()?;
+    let mut index_names = Vec::new();
+    for entry in std::fs::read_dir(gix_testtools::fixture_path_standalone("objects/pack"))? {
+        let path = entry?.path();
+        let name = PathBuf::from(path.file_name().expect("present"));
+        std::fs::copy(&path, dir.path().join(&name))?;
+        if path.extension().and_then(|e| e.to_str()) == Some("idx") {
+            index_names.push(name);
+        }
+    }
+    index_names.sort();
+    File::write_from_pack_dir(
+        dir.path(),
+        progress::Discard,
+        &AtomicBool::new(false),
+        gix_pack::multi_index::write::Options::default(),
+        Default::default(),
+    )?;
+    Ok((dir, index_names))
+}
+
+fn multi_index(dir: &tempfile::TempDir) -> crate::Result<File> {
+    Ok(File::at(dir.path().join("multi-pack-index"))?)
+}
+
+#[test]
+fn multi_indices_referring_to_all_packs_are_not_stale() -> crate::Result {
+    let (dir, _) = pack_dir_with_multi_index()?;
+    let stale = multi_index(&dir)?.is_stale(dir.path())?;
+    assert_eq!(stale, Stale::default());
+    assert!(!stale.is_stale());
+
+    assert_eq!(multi_index(&dir)?.remove_if_stale()?, None);
+    assert!(dir.path().join("multi-pack-index").is_file(), "it is kept");
+    Ok(())
+}
+
+#[test]
+fn removed_packs_and_added_packs_make_multi_indices_stale() -> crate::Result {
+    let (dir, index_names) = pack_dir_with_multi_index()?;
+    std::fs::remove_file(dir.path().join(&index_names[0]).with_extension("pack"))?;
+    std::fs::remove_file(dir.path().join(&index_names[1]))?;
+    let added = PathBuf::from(format!("pack-{}.idx", "f".repeat(40)));
+    std::fs::copy(dir.path().join(&index_names[2]), dir.path().join(&added))?;
+    std::fs::copy(
+        dir.path().join(&index_names[2]).with_extension("pack"),
+        dir.path().join(&added).with_extension("pack"),
+    )?;
+
+    let stale = multi_index(&dir)?.is_stale(dir.path())?;
+    assert_eq!(
+        stale,
+        Stale {
+            missing_indices: index_names[..2].to_vec(),
+            unreferenced_indices: vec![added],
+        }
+    );
+    assert!(stale.is_stale());
+    Ok(())
+}
+
+#[test]
+fn stale_multi_indices_are_removed_along_with_files_derived_from_them() -> crate::Result {
+    let (dir, index_names) = pack_dir_with_multi_index()?;
+    let checksum = multi_index(&dir)?.checksum();
+    let derived_files =
+        ["rev", "bitmap"].map(|extension| dir.path().join(format!("multi-pack-index-{}.{}", checksum, extension)));
+    for path in &derived_files[..1] {
+        std::fs::write(path, b"derived")?;
+    }
+    let unrelated = dir.path().join(format!("multi-pack-index-{}.rev", "f".repeat(40)));
+    std::fs::write(&unrelated, b"unrelated")?;
+    std::fs::remove_file(dir.path().join(&index_names[2]))?;
+
+    let stale = multi_index(&dir)?.remove_if_stale()?.expect("stale");
+    assert_eq!(stale.missing_indices, vec![index_names[2].clone()]);
+    assert!(!dir.path().join("multi-pack-index").exists());
+    for path in &derived_files {
+        assert!(!path.exists(), "missing derived files are no error");
+    }
+    assert!(unrelated.is_file(), "files derived from other multi-indices are kept");
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use std::sync::atomic::AtomicBool;
+use std::{path::Path, sync::atomic::AtomicBool};
 
 use gix_features::progress;
 use maplit::btreemap;
@@ -15,6 +15,71 @@ fn checksum() -> crate::Result {
     Ok(())
 }
 
+#[test]
+fn checksum_verification_can_be_interrupted() {
+    let (file, _) = multi_index();
+    let err = file
+        .verify_checksum(progress::Discard, &AtomicBool::new(true))
+        .unwrap_err();
+    assert!(
+        matches!(err, gix_pack::multi_index::verify::checksum::Error::Interrupted),
+        "{:?}",
+        err
+    );
+}
+
+#[test]
+fn checksum_mismatches_are_detected_on_demand_or_when_opening() -> crate::Result {
+    let (_, path) = multi_index();
+    let mut data = std::fs::read(&path)?;
+    let last_byte_before_trailer = data.len() - gix_hash::Kind::Sha1.len_in_bytes() - 1;
+    data[last_byte_before_trailer] ^= 1;
+    let dir = tempfile::TempDir::new()?;
+    let path = dir.path().join("multi-pack-index");
+    std::fs::write(&path, data)?;
+
+    let file = gix_pack::multi_index::File::at(&path)?;
+    let err = file
+        .verify_checksum(progress::Discard, &AtomicBool::new(false))
+        .unwrap_err();
+    assert!(
+        matches!(err, gix_pack::multi_index::verify::checksum::Error::Mismatch { expected, .. } if expected == file.checksum()),
+        "{:?}",
+        err
+    );
+
+    let err =
+        gix_pack::multi_index::File::at_opts(&path, gix_pack::multi_index::init::Options { verify_checksum: true })
+            .map(|_| ())
+            .unwrap_err();
+    assert!(
+        matches!(
+            &err,
+            gix_pack::multi_index::init::Error::Checksum {
+                source: gix_pack::multi_index::verify::checksum::Error::Mismatch { .. },
+                path: err_path
+            } if *err_path == path
+        ),
+        "{:?}",
+        err
+    );
+    assert!(
+        gix_pack::multi_index::File::at_opts(&path, Default::default()).is_ok(),
+        "the checksum isn't verified by default"
+    );
+
+    let (_, valid_path) = multi_index();
+    let file = gix_pack::multi_index::File::at_opts(
+        valid_path,
+        gix_pack::multi_index::init::Options { verify_checksum: true },
+    )?;
+    assert_eq!(
+        file.verify_checksum(progress::Discard, &AtomicBool::new(false))?,
+        file.checksum()
+    );
+    Ok(())
+}
+
 #[test]
 fn integrity() {
     let (file, _) = multi_index();
@@ -22,6 +87,7 @@ fn integrity() {
         .verify_integrity(progress::Discard, &AtomicBool::new(false), Default::default())
         .unwrap();
     assert_eq!(outcome.actual_index_checksum, file.checksum());
+    assert_eq!(outcome.num_objects_per_index, vec![868]);
     assert_eq!(
         outcome.pack_traverse_statistics,
         vec![gix_pack::index::traverse::Statistics {
@@ -50,3 +116,68 @@ fn integrity() {
         }]
     );
 }
+
+fn write_multi_index(dir: &Path, index_paths: Vec<std::path::PathBuf>) -> crate::Result<gix_pack::multi_index::File> {
+    let path = dir.join("multi-pack-index");
+    gix_pack::multi_index::File::write_from_index_paths(
+        index_paths,
+        std::fs::File::create(&path)?,
+        progress::Discard,
+        &AtomicBool::new(false),
+        gix_pack::multi_index::write::Options::default(),
+    )?;
+    Ok(gix_pack::multi_index::File::at(path)?)
+}
+
+fn copy_fixture_packs(dir: &Path) -> crate::Result<Vec<std::path::PathBuf>> {
+    let mut index_paths = Vec::new();
+    for entry in std::fs::read_dir(gix_testtools::fixture_path_standalone("objects/pack"))? {
+        let path = entry?.path();
+        let copied_path = dir.join(path.file_name().expect("present"));
+        std::fs::copy(&path, &copied_path)?;
+        if copied_path.extension().and_then(|e| e.to_str()) == Some("idx") {
+            index_paths.push(copied_path);
+        }
+    }
+    index_paths.sort();
+    Ok(index_paths)
+}
+
+#[test]
+fn integrity_reports_the_amount_of_objects_per_index() -> crate::Result {
+    let dir = tempfile::TempDir::new()?;
+    let index_paths = copy_fixture_packs(dir.path())?;
+    let file = write_multi_index(dir.path(), index_paths)?;
+
+    let outcome = file.verify_integrity(progress::Discard, &AtomicBool::new(false), Default::default())?;
+    assert_eq!(outcome.num_objects_per_index, vec![30, 42, 67]);
+    assert_eq!(
+        outcome.num_objects_per_index.iter().sum::<usize>(),
+        file.num_objects() as usize
+    );
+    assert_eq!(outcome.pack_traverse_statistics.len(), 3);
+    Ok(())
+}
+
+#[test]
+fn integrity_fails_if_objects_of_an_index_are_missing() -> crate::Result {
+    let dir = tempfile::TempDir::new()?;
+    let index_paths = copy_fixture_packs(dir.path())?;
+    let file = write_multi_index(dir.path(), vec![index_paths[0].clone()])?;
+    std::fs::copy(&index_paths[1], &index_paths[0])?;
+
+    let err = file
+        .verify_integrity_fast(progress::Discard, &AtomicBool::new(false))
+        .map(|_| ())
+        .unwrap_err();
+    assert!(
+        matches!(
+            &err,
+            gix_pack::multi_index::verify::integrity::Error::OidMissingInMultiIndex { index_name, .. }
+                if index_name == Path::new(index_paths[0].file_name().expect("present"))
+        ),
+        "{:?}",
+        err
+    );
+    Ok(())
+}
@@ -5,9 +5,7 @@ use gix_testtools::fixture_path_standalone;
 
 use crate::hex_to_id;
 
-#[test]
-fn from_paths() -> crate::Result {
-    let dir = tempfile::TempDir::new()?;
+fn input_indices() -> crate::Result<Vec<PathBuf>> {
     let input_indices = std::fs::read_dir(fixture_path_standalone("objects/pack"))?
         .filter_map(|r| {
             r.ok()
@@ -16,6 +14,13 @@ fn from_paths() -> crate::Result {
         })
         .collect::<Vec<_>>();
     assert_eq!(input_indices.len(), 3);
+    Ok(input_indices)
+}
+
+#[test]
+fn from_paths() -> crate::Result {
+    let dir = tempfile::TempDir::new()?;
+    let input_indices = input_indices()?;
     let output_path = dir.path().join("multi-pack-index");
     let mut out = std::fs::OpenOptions::new()
         .write(true)
@@ -26,18 +31,20 @@ fn from_paths() -> crate::Result {
         &mut out,
         progress::Discard,
         &AtomicBool::new(false),
-        gix_pack::multi_index::write::Options {
-            object_hash: gix_hash::Kind::Sha1,
-        },
+        gix_pack::multi_index::write::Options::default(),
     )?;
 
     assert_eq!(
         outcome.multi_index_checksum,
         hex_to_id("d34d327039a3554f8a644b29e07b903fa71ef269")
     );
+    assert_eq!(outcome.num_objects, 139);
+    assert_eq!(outcome.num_duplicate_objects_removed, 0);
+    assert_eq!(outcome.num_objects_per_index, vec![30, 42, 67]);
 
     let file = gix_pack::multi_index::File::at(output_path)?;
     assert_eq!(file.num_indices(), 3);
+    assert_eq!(file.index_names(), outcome.index_names);
     assert_eq!(
         file.index_names(),
         vec![
@@ -66,3 +73,1642 @@ fn from_paths() -> crate::Result {
     assert_eq!(outcome.0, file.checksum());
     Ok(())
 }
+
+#[test]
+fn from_indices_is_the_same_as_from_paths() -> crate::Result {
+    let indices = input_indices()?
+        .into_iter()
+        .rev()
+        .map(|path| {
+            let index = gix_pack::index::File::at(&path, gix_hash::Kind::Sha1)?;
+            Ok((index, path, std::time::SystemTime::UNIX_EPOCH))
+        })
+        .collect::<crate::Result<Vec<_>>>()?;
+    let mut out = Vec::new();
+    let outcome = gix_pack::multi_index::File::write_from_indices(
+        indices,
+        &mut out,
+        progress::Discard,
+        &AtomicBool::new(false),
+        gix_pack::multi_index::write::Options::default(),
+    )?;
+    assert_eq!(
+        outcome.multi_index_checksum,
+        hex_to_id("d34d327039a3554f8a644b29e07b903fa71ef269"),
+        "the same multi-index is produced, as the objects in the fixture are unique"
+    );
+    assert_eq!(outcome.num_objects_per_index, vec![30, 42, 67]);
+    assert!(outcome.multi_index_path.is_none());
+    Ok(())
+}
+
+#[test]
+fn from_paths_entries_can_be_iterated_in_order() -> crate::Result {
+    let mut input_indices = input_indices()?;
+    input_indices.sort();
+    let mut out = Vec::new();
+    gix_pack::multi_index::File::write_from_index_paths(
+        input_indices.clone(),
+        &mut out,
+        progress::Discard,
+        &AtomicBool::new(false),
+        gix_pack::multi_index::write::Options::default(),
+    )?;
+    let dir = tempfile::TempDir::new()?;
+    let output_path = dir.path().join("multi-pack-index");
+    std::fs::write(&output_path, out)?;
+    let file = gix_pack::multi_index::File::at(output_path)?;
+
+    let mut expected = Vec::new();
+    for (pack_index, index_path) in input_indices.iter().enumerate() {
+        let mtime = std::fs::metadata(index_path)?.modified()?;
+        let index = gix_pack::index::File::at(index_path, gix_hash::Kind::Sha1)?;
+        expected.extend(index.iter().map(|e| {
+            (
+                mtime,
+                gix_pack::multi_index::Entry {
+                    oid: e.oid,
+                    pack_offset: e.pack_offset,
+                    pack_index: pack_index as gix_pack::multi_index::PackIndex,
+                },
+            )
+        }));
+    }
+    expected.sort_by(|(l_mtime, l), (r_mtime, r)| {
+        l.oid
+            .cmp(&r.oid)
+            .then_with(|| l_mtime.cmp(r_mtime).reverse())
+            .then_with(|| l.pack_index.cmp(&r.pack_index))
+    });
+    expected.dedup_by_key(|(_, e)| e.oid);
+    let expected: Vec<_> = expected.into_iter().map(|(_, e)| e).collect();
+
+    let iter = file.iter();
+    assert_eq!(iter.len(), file.num_objects() as usize);
+    let actual: Vec<_> = iter.collect();
+    assert_eq!(actual, expected, "the iterator yields exactly what the writer wrote");
+    for (idx, entry) in actual.iter().enumerate() {
+        assert_eq!(&file.entry_at_index(idx as u32), entry);
+    }
+    Ok(())
+}
+
+#[test]
+fn from_paths_stores_index_names_in_order_regardless_of_their_directory() -> crate::Result {
+    let dir = tempfile::TempDir::new()?;
+    let mut input_indices = input_indices()?;
+    input_indices.sort();
+    let mut expected_names = Vec::new();
+    // Put the indices into directories that sort in reverse order.
+    for (idx, index_path) in input_indices.iter_mut().enumerate() {
+        let index_dir = dir.path().join(format!("{}", 9 - idx));
+        std::fs::create_dir(&index_dir)?;
+        let name = index_path.file_name().expect("present");
+        let copied_path = index_dir.join(name);
+        std::fs::copy(&index_path, &copied_path)?;
+        expected_names.push(PathBuf::from(name));
+        *index_path = copied_path;
+    }
+
+    let output_path = dir.path().join("multi-pack-index");
+    gix_pack::multi_index::File::write_from_index_paths(
+        input_indices,
+        std::fs::File::create(&output_path)?,
+        progress::Discard,
+        &AtomicBool::new(false),
+        gix_pack::multi_index::write::Options::default(),
+    )?;
+    let file = gix_pack::multi_index::File::at(output_path)?;
+    assert_eq!(file.index_names(), expected_names);
+    Ok(())
+}
+
+#[test]
+fn from_paths_rejects_invalid_or_duplicate_index_names() -> crate::Result {
+    let input_indices = input_indices()?;
+    let write = |index_paths| {
+        gix_pack::multi_index::File::write_from_index_paths(
+            index_paths,
+            Vec::new(),
+            progress::Discard,
+            &AtomicBool::new(false),
+            gix_pack::multi_index::write::Options::default(),
+        )
+        .map(|_| ())
+        .unwrap_err()
+    };
+
+    for invalid_path in [
+        input_indices[0].with_extension("pack"),
+        input_indices[0].join(".."),
+        input_indices[0].with_file_name("pack-ä.idx"),
+    ] {
+        let err = write(vec![input_indices[0].clone(), invalid_path.clone()]);
+        assert!(
+            matches!(&err, gix_pack::multi_index::write::Error::InvalidIndexPath { path } if path == &invalid_path),
+            "{:?}",
+            err
+        );
+    }
+
+    let name = PathBuf::from(input_indices[0].file_name().expect("present"));
+    let err = write(vec![input_indices[0].clone(), PathBuf::from("elsewhere").join(&name)]);
+    assert!(
+        matches!(&err, gix_pack::multi_index::write::Error::DuplicateIndexName { name: dup } if dup == &name),
+        "{:?}",
+        err
+    );
+    Ok(())
+}
+
+mod to_directory {
+    use std::sync::atomic::AtomicBool;
+
+    use gix_features::progress;
+
+    fn dir_entries(dir: &std::path::Path) -> crate::Result<Vec<std::path::PathBuf>> {
+        Ok(std::fs::read_dir(dir)?
+            .map(|e| e.map(|e| e.path()))
+            .collect::<Result<_, _>>()?)
+    }
+
+    #[test]
+    fn the_multi_index_is_moved_into_place() -> crate::Result {
+        let dir = tempfile::TempDir::new()?;
+        let outcome = gix_pack::multi_index::File::write_from_index_paths_to_directory(
+            super::input_indices()?,
+            dir.path(),
+            progress::Discard,
+            &AtomicBool::new(false),
+            gix_pack::multi_index::write::Options::default(),
+        )?;
+        let expected_path = dir.path().join("multi-pack-index");
+        assert_eq!(outcome.multi_index_path.as_ref(), Some(&expected_path));
+        assert_eq!(
+            dir_entries(dir.path())?,
+            vec![expected_path.clone()],
+            "no temporary file is left"
+        );
+
+        let file = gix_pack::multi_index::File::at(&expected_path)?;
+        assert_eq!(file.checksum(), outcome.multi_index_checksum);
+        assert_eq!(
+            file.verify_checksum(progress::Discard, &AtomicBool::new(false))?,
+            outcome.multi_index_checksum
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn existing_files_are_untouched_on_interruption() -> crate::Result {
+        let dir = tempfile::TempDir::new()?;
+        let multi_index_path = dir.path().join("multi-pack-index");
+        std::fs::write(&multi_index_path, b"previous")?;
+        let err = gix_pack::multi_index::File::write_from_index_paths_to_directory(
+            super::input_indices()?,
+            dir.path(),
+            progress::Discard,
+            &AtomicBool::new(true),
+            gix_pack::multi_index::write::Options::default(),
+        )
+        .map(|_| ())
+        .unwrap_err();
+        assert!(
+            matches!(err, gix_pack::multi_index::write::Error::Interrupted),
+            "{:?}",
+            err
+        );
+        assert_eq!(
+            dir_entries(dir.path())?,
+            vec![multi_index_path.clone()],
+            "the temporary file was removed"
+        );
+        assert_eq!(std::fs::read(multi_index_path)?, b"previous");
+        Ok(())
+    }
+}
+
+mod preferred_pack {
+    use std::{path::PathBuf, sync::atomic::AtomicBool};
+
+    use gix_features::progress;
+
+    /// Return the paths to the index with the objects of the first commit, and the one with all objects.
+    fn indices_with_duplicate_objects() -> crate::Result<(PathBuf, PathBuf)> {
+        let pack_dir =
+            crate::scripted_fixture_read_only("make_pack_with_duplicate_objects.sh")?.join(".git/objects/pack");
+        let mut indices = Vec::new();
+        for entry in std::fs::read_dir(pack_dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) == Some("idx") {
+                let num_objects = gix_pack::index::File::at(&path, gix_hash::Kind::Sha1)?.num_objects();
+                indices.push((num_objects, path));
+            }
+        }
+        indices.sort();
+        assert_eq!(indices.len(), 2);
+        let all = indices.pop().expect("two").1;
+        let first = indices.pop().expect("one").1;
+        Ok((first, all))
+    }
+
+    fn write(index_paths: Vec<PathBuf>, preferred_pack: Option<PathBuf>) -> crate::Result<gix_pack::multi_index::File> {
+        let dir = tempfile::TempDir::new()?;
+        let output_path = dir.path().join("multi-pack-index");
+        gix_pack::multi_index::File::write_from_index_paths(
+            index_paths,
+            std::fs::File::create(&output_path)?,
+            progress::Discard,
+            &AtomicBool::new(false),
+            gix_pack::multi_index::write::Options {
+                preferred_pack,
+                ..Default::default()
+            },
+        )?;
+        Ok(gix_pack::multi_index::File::at(output_path)?)
+    }
+
+    /// Return the name of the index each object of the index at `index_path` is taken from in `file`.
+    fn index_names_of_objects_in(
+        file: &gix_pack::multi_index::File,
+        index_path: &std::path::Path,
+    ) -> crate::Result<Vec<PathBuf>> {
+        let index = gix_pack::index::File::at(index_path, gix_hash::Kind::Sha1)?;
+        Ok(index
+            .iter()
+            .map(|entry| {
+                let (pack_index, _) = file
+                    .pack_id_and_pack_offset(entry.oid)
+                    .expect("all objects are present");
+                file.index_names()[pack_index as usize].clone()
+            })
+            .collect())
+    }
+
+    #[test]
+    fn duplicates_are_taken_from_a_single_index_by_default() -> crate::Result {
+        let (first, all) = indices_with_duplicate_objects()?;
+        let file = write(vec![first.clone(), all.clone()], None)?;
+        let mut names = index_names_of_objects_in(&file, &first)?;
+        names.dedup();
+        assert_eq!(
+            names.len(),
+            1,
+            "the index modified most recently, or the first one on ties"
+        );
+        assert_eq!(
+            file.num_objects(),
+            gix_pack::index::File::at(&all, gix_hash::Kind::Sha1)?.num_objects()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn duplicates_are_taken_from_the_preferred_pack() -> crate::Result {
+        let (first, all) = indices_with_duplicate_objects()?;
+        for preferred in [&first, &all] {
+            let name = PathBuf::from(preferred.file_name().expect("present"));
+            for preferred_pack in [name.clone(), name.with_extension("pack"), preferred.to_owned()] {
+                let file = write(vec![first.clone(), all.clone()], Some(preferred_pack))?;
+                assert!(index_names_of_objects_in(&file, &first)?
+                    .iter()
+                    .all(|index_name| *index_name == name));
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn discarded_duplicates_are_collected_on_request() -> crate::Result {
+        let (first, all) = indices_with_duplicate_objects()?;
+        let dir = tempfile::TempDir::new()?;
+        let copy = dir.path().join(format!("pack-{}.idx", "f".repeat(40)));
+        std::fs::copy(&first, &copy)?;
+        let name = |path: &PathBuf| PathBuf::from(path.file_name().expect("present"));
+        let write = |collect_duplicates: bool, max_duplicates: Option<usize>, drop_redundant_indices: bool| {
+            gix_pack::multi_index::File::write_from_index_paths(
+                vec![first.clone(), all.clone(), copy.clone()],
+                std::io::sink(),
+                progress::Discard,
+                &AtomicBool::new(false),
+                gix_pack::multi_index::write::Options {
+                    preferred_pack: Some(name(&all)),
+                    drop_redundant_indices,
+                    mtime_override: Some(std::time::SystemTime::UNIX_EPOCH),
+                    collect_duplicates,
+                    max_duplicates,
+                    ..Default::default()
+                },
+            )
+        };
+
+        let outcome = write(false, None, false)?;
+        assert!(outcome.duplicates.is_empty(), "nothing is collected by default");
+
+        let first_index = gix_pack::index::File::at(&first, gix_hash::Kind::Sha1)?;
+        let outcome = write(true, None, false)?;
+        assert_eq!(outcome.duplicates.len(), first_index.num_objects() as usize);
+        assert_eq!(
+            outcome
+                .duplicates
+                .iter()
+                .map(|duplicate| duplicate.discarded_index_names.len())
+                .sum::<usize>(),
+            outcome.num_duplicate_objects_removed
+        );
+        for (duplicate, entry) in outcome.duplicates.iter().zip(first_index.iter()) {
+            assert_eq!(duplicate.id, entry.oid, "duplicates are sorted by id");
+            assert_eq!(
+                duplicate.index_name,
+                name(&all),
+                "the copy of the preferred pack is chosen"
+            );
+            assert_eq!(
+                duplicate.discarded_index_names,
+                vec![name(&first), name(&copy)],
+                "the copy that would have been chosen next comes first"
+            );
+        }
+
+        let capped = write(true, Some(2), false)?;
+        assert_eq!(capped.duplicates, outcome.duplicates[..2]);
+        assert_eq!(
+            capped.num_duplicate_objects_removed, outcome.num_duplicate_objects_removed,
+            "the cap only affects the collected duplicates"
+        );
+
+        let dropped = write(true, None, true)?;
+        assert_eq!(dropped.index_names, vec![name(&all)]);
+        assert_eq!(
+            dropped.duplicates, outcome.duplicates,
+            "redundant indices are listed even if they are dropped"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn duplicates_are_taken_from_the_index_with_the_newest_given_modification_time() -> crate::Result {
+        let (first, all) = indices_with_duplicate_objects()?;
+        let (older, newer) = (
+            std::time::SystemTime::UNIX_EPOCH,
+            std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1),
+        );
+        for (first_mtime, all_mtime, expected) in [(newer, older, &first), (older, newer, &all)] {
+            let indices = vec![
+                (
+                    gix_pack::index::File::at(&first, gix_hash::Kind::Sha1)?,
+                    first.clone(),
+                    first_mtime,
+                ),
+                (
+                    gix_pack::index::File::at(&all, gix_hash::Kind::Sha1)?,
+                    all.clone(),
+                    all_mtime,
+                ),
+            ];
+            let dir = tempfile::TempDir::new()?;
+            let output_path = dir.path().join("multi-pack-index");
+            gix_pack::multi_index::File::write_from_indices(
+                indices,
+                std::fs::File::create(&output_path)?,
+                progress::Discard,
+                &AtomicBool::new(false),
+                gix_pack::multi_index::write::Options::default(),
+            )?;
+            let file = gix_pack::multi_index::File::at(output_path)?;
+            let expected_name = PathBuf::from(expected.file_name().expect("present"));
+            assert!(index_names_of_objects_in(&file, &first)?
+                .iter()
+                .all(|index_name| *index_name == expected_name));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn duplicates_are_chosen_by_name_regardless_of_modification_times_with_mtime_override() -> crate::Result {
+        let (first, all) = indices_with_duplicate_objects()?;
+        let dir = tempfile::TempDir::new()?;
+        let index_paths = [&first, &all]
+            .iter()
+            .map(|path| {
+                let copy = dir.path().join(path.file_name().expect("present"));
+                std::fs::copy(path, &copy).map(|_| copy)
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let set_mtimes = |seconds: [i64; 2]| -> std::io::Result<()> {
+            for (path, seconds) in index_paths.iter().zip(seconds) {
+                filetime::set_file_mtime(path, filetime::FileTime::from_unix_time(seconds, 0))?;
+            }
+            Ok(())
+        };
+        let write = |mtime_override| -> crate::Result<(gix_hash::ObjectId, gix_pack::multi_index::File)> {
+            let output_path = dir.path().join("multi-pack-index");
+            let outcome = gix_pack::multi_index::File::write_from_index_paths(
+                index_paths.clone(),
+                std::fs::File::create(&output_path)?,
+                progress::Discard,
+                &AtomicBool::new(false),
+                gix_pack::multi_index::write::Options {
+                    mtime_override,
+                    ..Default::default()
+                },
+            )?;
+            Ok((
+                outcome.multi_index_checksum,
+                gix_pack::multi_index::File::at(output_path)?,
+            ))
+        };
+        let mtime_override = Some(std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000));
+
+        set_mtimes([1, 2])?;
+        let (checksum_without_override, _) = write(None)?;
+        let (checksum, file) = write(mtime_override)?;
+        set_mtimes([2, 1])?;
+        assert_ne!(
+            write(None)?.0,
+            checksum_without_override,
+            "without override, objects are taken from the index modified most recently"
+        );
+        assert_eq!(
+            write(mtime_override)?.0,
+            checksum,
+            "with override, modification times don't affect the output"
+        );
+
+        let first_name = file.index_names()[0].clone();
+        assert!(
+            index_names_of_objects_in(&file, &first)?
+                .iter()
+                .all(|index_name| *index_name == first_name),
+            "all duplicates are taken from the alphabetically first index"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn outcome_reports_duplicates_and_redundant_packs() -> crate::Result {
+        let (first, all) = indices_with_duplicate_objects()?;
+        let num_objects = |path: &PathBuf| -> crate::Result<u32> {
+            Ok(gix_pack::index::File::at(path, gix_hash::Kind::Sha1)?.num_objects())
+        };
+        let mut index_names = vec![
+            PathBuf::from(first.file_name().expect("present")),
+            PathBuf::from(all.file_name().expect("present")),
+        ];
+        index_names.sort();
+        let outcome = gix_pack::multi_index::File::write_from_index_paths(
+            vec![first.clone(), all.clone()],
+            Vec::new(),
+            progress::Discard,
+            &AtomicBool::new(false),
+            gix_pack::multi_index::write::Options {
+                preferred_pack: Some(all.clone()),
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(outcome.num_objects, num_objects(&all)?);
+        assert_eq!(outcome.num_duplicate_objects_removed, num_objects(&first)? as usize);
+        assert_eq!(outcome.index_names, index_names);
+        let expected_objects_per_index: Vec<_> = index_names
+            .iter()
+            .map(|name| {
+                if all.ends_with(name) {
+                    num_objects(&all).expect("valid")
+                } else {
+                    0
+                }
+            })
+            .collect();
+        assert_eq!(
+            outcome.num_objects_per_index, expected_objects_per_index,
+            "the first pack is redundant as all of its objects are taken from the preferred pack"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn redundant_indices_can_be_dropped() -> crate::Result {
+        let (first, all) = indices_with_duplicate_objects()?;
+        let dir = tempfile::TempDir::new()?;
+        let mut index_paths = Vec::new();
+        for index_path in super::input_indices()?.into_iter().chain([first.clone(), all.clone()]) {
+            for path in [index_path.clone(), index_path.with_extension("pack")] {
+                std::fs::copy(&path, dir.path().join(path.file_name().expect("present")))?;
+            }
+            index_paths.push(dir.path().join(index_path.file_name().expect("present")));
+        }
+        let first_name = PathBuf::from(first.file_name().expect("present"));
+
+        let output_path = dir.path().join("multi-pack-index");
+        for drop_redundant_indices in [false, true] {
+            let outcome = gix_pack::multi_index::File::write_from_index_paths(
+                index_paths.clone(),
+                std::fs::File::create(&output_path)?,
+                progress::Discard,
+                &AtomicBool::new(false),
+                gix_pack::multi_index::write::Options {
+                    preferred_pack: Some(all.clone()),
+                    drop_redundant_indices,
+                    ..Default::default()
+                },
+            )?;
+            assert_eq!(outcome.redundant_index_names, vec![first_name.clone()]);
+            assert_eq!(
+                outcome.index_names.contains(&first_name),
+                !drop_redundant_indices,
+                "redundant indices are only referred to if they are not dropped"
+            );
+            assert_eq!(outcome.index_names.len(), outcome.num_objects_per_index.len());
+
+            let file = gix_pack::multi_index::File::at(&output_path)?;
+            assert_eq!(file.index_names(), outcome.index_names);
+            assert_eq!(file.num_objects(), outcome.num_objects);
+            let verify_outcome =
+                file.verify_integrity(progress::Discard, &AtomicBool::new(false), Default::default())?;
+            assert_eq!(
+                verify_outcome
+                    .num_objects_per_index
+                    .iter()
+                    .map(|n| *n as u32)
+                    .collect::<Vec<_>>(),
+                outcome.num_objects_per_index,
+                "pack ids are remapped to the remaining indices"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn merging_sorted_indices_yields_the_same_file_as_sorting_all_entries() -> crate::Result {
+        let (first, all) = indices_with_duplicate_objects()?;
+        for (preferred, drop_redundant_indices, expected) in [
+            (&first, false, "636434660fc844256997bb36dd377628a4d171a4"),
+            (&first, true, "636434660fc844256997bb36dd377628a4d171a4"),
+            (&all, false, "d8165b28cdded7599ad3ddb030c1d0b0280629e9"),
+            (&all, true, "285e33133c18bc60d0402c3589fd479d8ee46f55"),
+        ] {
+            for (thread_limit, max_memory) in [(Some(1), None), (Some(3), None), (None, None), (None, Some(0))] {
+                let outcome = gix_pack::multi_index::File::write_from_index_paths(
+                    vec![first.clone(), all.clone()],
+                    Vec::new(),
+                    progress::Discard,
+                    &AtomicBool::new(false),
+                    gix_pack::multi_index::write::Options {
+                        thread_limit,
+                        preferred_pack: preferred.file_name().map(PathBuf::from),
+                        drop_redundant_indices,
+                        max_memory,
+                        ..Default::default()
+                    },
+                )?;
+                assert_eq!(
+                    outcome.multi_index_checksum,
+                    crate::hex_to_id(expected),
+                    "the checksums were produced by sorting all entries of all indices"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn duplicates_are_chosen_the_same_way_with_limited_memory() -> crate::Result {
+        let (first, all) = indices_with_duplicate_objects()?;
+        let write = |preferred_pack: Option<PathBuf>, drop_redundant_indices, max_memory| -> crate::Result<_> {
+            let mut out = Vec::new();
+            let outcome = gix_pack::multi_index::File::write_from_index_paths(
+                vec![first.clone(), all.clone()],
+                &mut out,
+                progress::Discard,
+                &AtomicBool::new(false),
+                gix_pack::multi_index::write::Options {
+                    preferred_pack,
+                    drop_redundant_indices,
+                    max_memory,
+                    ..Default::default()
+                },
+            )?;
+            Ok((
+                out,
+                outcome.num_duplicate_objects_removed,
+                outcome.redundant_index_names,
+            ))
+        };
+        for preferred_pack in [None, first.file_name(), all.file_name()] {
+            let preferred_pack = preferred_pack.map(PathBuf::from);
+            for drop_redundant_indices in [false, true] {
+                let expected = write(preferred_pack.clone(), drop_redundant_indices, None)?;
+                for max_memory in [0, 100, 1000] {
+                    assert_eq!(
+                        write(preferred_pack.clone(), drop_redundant_indices, Some(max_memory))?,
+                        expected,
+                        "entries are merged on the fly just like they are merged in memory"
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_preferred_packs_are_an_error() -> crate::Result {
+        let (first, all) = indices_with_duplicate_objects()?;
+        let err = write(vec![first], Some(all.with_extension("pack")))
+            .map(|_| ())
+            .unwrap_err();
+        assert!(
+            matches!(
+                err.downcast_ref::<gix_pack::multi_index::write::Error>(),
+                Some(gix_pack::multi_index::write::Error::UnknownPreferredPack { .. })
+            ),
+            "{:?}",
+            err
+        );
+        Ok(())
+    }
+}
+
+#[test]
+fn from_paths_with_limited_memory_produces_the_same_file() -> crate::Result {
+    for max_memory in [Some(0), Some(1024), Some(usize::MAX)] {
+        let mut out = Vec::new();
+        let outcome = gix_pack::multi_index::File::write_from_index_paths(
+            input_indices()?,
+            &mut out,
+            progress::Discard,
+            &AtomicBool::new(false),
+            gix_pack::multi_index::write::Options {
+                max_memory,
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(
+            outcome.multi_index_checksum,
+            hex_to_id("d34d327039a3554f8a644b29e07b903fa71ef269"),
+            "{:?}",
+            max_memory
+        );
+        assert_eq!(outcome.num_objects, 139);
+        assert_eq!(outcome.num_objects_per_index, vec![30, 42, 67]);
+        if max_memory != Some(usize::MAX) {
+            assert_eq!(outcome.num_threads, 1, "entries are merged on the fly");
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn from_paths_without_indices_is_an_error() -> crate::Result {
+    let dir = tempfile::TempDir::new()?;
+    let mut out = Vec::new();
+    let err = gix_pack::multi_index::File::write_from_index_paths(
+        Vec::new(),
+        &mut out,
+        progress::Discard,
+        &AtomicBool::new(false),
+        gix_pack::multi_index::write::Options::default(),
+    )
+    .map(|_| ())
+    .unwrap_err();
+    assert!(
+        matches!(err, gix_pack::multi_index::write::Error::NoIndices),
+        "{:?}",
+        err
+    );
+    assert!(out.is_empty(), "nothing is written");
+
+    let err = gix_pack::multi_index::File::write_from_index_paths_to_directory(
+        Vec::new(),
+        dir.path(),
+        progress::Discard,
+        &AtomicBool::new(false),
+        gix_pack::multi_index::write::Options::default(),
+    )
+    .map(|_| ())
+    .unwrap_err();
+    assert!(
+        matches!(err, gix_pack::multi_index::write::Error::NoIndices),
+        "{:?}",
+        err
+    );
+    assert_eq!(std::fs::read_dir(dir.path())?.count(), 0, "no file is left behind");
+    Ok(())
+}
+
+#[test]
+fn from_paths_with_any_amount_of_threads_is_deterministic() -> crate::Result {
+    let mut checksums = Vec::new();
+    for thread_limit in [Some(1), Some(2), Some(3), None] {
+        let mut out = Vec::new();
+        let outcome = gix_pack::multi_index::File::write_from_index_paths(
+            input_indices()?,
+            &mut out,
+            progress::Discard,
+            &AtomicBool::new(false),
+            gix_pack::multi_index::write::Options {
+                thread_limit,
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(
+            outcome.num_threads,
+            gix_features::parallel::num_threads(thread_limit).min(256),
+            "the amount of threads is reported for logging"
+        );
+        checksums.push(outcome.multi_index_checksum);
+    }
+    assert!(
+        checksums.windows(2).all(|w| w[0] == w[1]),
+        "entries are collected in the order of indices: {:?}",
+        checksums
+    );
+    Ok(())
+}
+
+#[test]
+fn from_paths_can_be_interrupted() -> crate::Result {
+    let err = gix_pack::multi_index::File::write_from_index_paths(
+        input_indices()?,
+        Vec::new(),
+        progress::Discard,
+        &AtomicBool::new(true),
+        gix_pack::multi_index::write::Options {
+            thread_limit: Some(2),
+            ..Default::default()
+        },
+    )
+    .map(|_| ())
+    .unwrap_err();
+    assert!(
+        matches!(err, gix_pack::multi_index::write::Error::Interrupted),
+        "{:?}",
+        err
+    );
+    Ok(())
+}
+
+mod pack_dir {
+    use std::{
+        path::{Path, PathBuf},
+        sync::atomic::AtomicBool,
+    };
+
+    use gix_features::progress;
+    use gix_pack::multi_index::write::{index_paths_in_pack_dir, Options, PackDirOptions};
+
+    const INDEX_NAMES: [&str; 3] = [
+        "pack-11fdfa9e156ab73caae3b6da867192221f2089c2.idx",
+        "pack-a2bf8e71d8c18879e499335762dd95119d93d9f1.idx",
+        "pack-c0438c19fb16422b6bbcce24387b3264416d485b.idx",
+    ];
+
+    /// Return a directory with all fixture packs along with files that look like indices, but aren't indices of packs.
+    fn pack_dir_with_decoys() -> crate::Result<tempfile::TempDir> {
+        let dir = tempfile::TempDir::new()?;
+        let fixture_dir = gix_testtools::fixture_path_standalone("objects/pack");
+        for entry in std::fs::read_dir(&fixture_dir)? {
+            let path = entry?.path();
+            std::fs::copy(&path, dir.path().join(path.file_name().expect("present")))?;
+        }
+        let index = fixture_dir.join(INDEX_NAMES[0]);
+        let pack = index.with_extension("pack");
+        let copy = |from: &Path, name: &str| std::fs::copy(from, dir.path().join(name));
+        copy(&index, "tmp_idx_Fbu3nd")?;
+        copy(&pack, "tmp_pack_Fbu3nd")?;
+        copy(&index, "pack-11fdfa9e156ab73caae3b6da867192221f2089c2.rev")?;
+        copy(&index, "pack-11fdfa9e156ab73caae3b6da867192221f2089c2.keep")?;
+        copy(&index, "pack-11FDFA9E156AB73CAAE3B6DA867192221F2089C2.idx")?;
+        copy(&pack, "pack-11FDFA9E156AB73CAAE3B6DA867192221F2089C2.pack")?;
+        copy(&index, "pack-11fdfa9e.idx")?;
+        copy(&pack, "pack-11fdfa9e.pack")?;
+        copy(&index, "pack-0000000000000000000000000000000000000000.idx")?;
+        copy(&index, "other-11fdfa9e156ab73caae3b6da867192221f2089c2.idx")?;
+        copy(&pack, "other-11fdfa9e156ab73caae3b6da867192221f2089c2.pack")?;
+        std::fs::create_dir(dir.path().join("pack-1111111111111111111111111111111111111111.idx"))?;
+        copy(&pack, "pack-1111111111111111111111111111111111111111.pack")?;
+        std::fs::write(dir.path().join("multi-pack-index"), b"previous")?;
+        Ok(dir)
+    }
+
+    #[test]
+    fn only_indices_of_packs_are_discovered_in_order() -> crate::Result {
+        let dir = pack_dir_with_decoys()?;
+        assert_eq!(
+            index_paths_in_pack_dir(dir.path(), gix_hash::Kind::Sha1)?,
+            INDEX_NAMES.iter().map(|name| dir.path().join(name)).collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn the_multi_index_is_written_into_the_pack_dir() -> crate::Result {
+        let dir = pack_dir_with_decoys()?;
+        let outcome = gix_pack::multi_index::File::write_from_pack_dir(
+            dir.path(),
+            progress::Discard,
+            &AtomicBool::new(false),
+            Options::default(),
+            PackDirOptions::default(),
+        )?;
+        let expected_path = dir.path().join("multi-pack-index");
+        assert_eq!(outcome.multi_index_path.as_ref(), Some(&expected_path));
+        assert_eq!(
+            outcome.index_names,
+            INDEX_NAMES.iter().map(PathBuf::from).collect::<Vec<_>>()
+        );
+
+        let file = gix_pack::multi_index::File::at(&expected_path)?;
+        assert_eq!(file.checksum(), outcome.multi_index_checksum);
+        assert_eq!(file.num_objects(), 139);
+        Ok(())
+    }
+
+    #[test]
+    fn excluded_packs_are_left_out() -> crate::Result {
+        let dir = pack_dir_with_decoys()?;
+        let outcome = gix_pack::multi_index::File::write_from_pack_dir(
+            dir.path(),
+            progress::Discard,
+            &AtomicBool::new(false),
+            Options::default(),
+            PackDirOptions {
+                excluded_packs: vec![
+                    PathBuf::from("pack-11fdfa9e156ab73caae3b6da867192221f2089c2.keep"),
+                    dir.path().join("pack-a2bf8e71d8c18879e499335762dd95119d93d9f1.pack"),
+                    PathBuf::from("pack-unknown.pack"),
+                ],
+            },
+        )?;
+        assert_eq!(outcome.index_names, vec![PathBuf::from(INDEX_NAMES[2])]);
+        Ok(())
+    }
+}
+
+mod corrupt_indices {
+    use std::{
+        path::{Path, PathBuf},
+        sync::atomic::AtomicBool,
+    };
+
+    use gix_features::progress;
+    use gix_pack::multi_index::write::{Error, OnIndexError, Options, UpdateOptions};
+
+    fn options(on_index_error: OnIndexError) -> Options {
+        Options {
+            on_index_error,
+            ..Default::default()
+        }
+    }
+
+    /// Copy all fixture packs into `dir` and truncate the second index to half its size, returning the sorted index paths.
+    fn packs_with_truncated_index(dir: &Path) -> crate::Result<Vec<PathBuf>> {
+        let mut index_paths = Vec::new();
+        for entry in std::fs::read_dir(gix_testtools::fixture_path_standalone("objects/pack"))? {
+            let path = entry?.path();
+            let copied_path = dir.join(path.file_name().expect("present"));
+            std::fs::copy(&path, &copied_path)?;
+            if copied_path.extension().and_then(|e| e.to_str()) == Some("idx") {
+                index_paths.push(copied_path);
+            }
+        }
+        index_paths.sort();
+        let truncated = std::fs::OpenOptions::new().write(true).open(&index_paths[1])?;
+        truncated.set_len(truncated.metadata()?.len() / 2)?;
+        Ok(index_paths)
+    }
+
+    fn write(
+        index_paths: Vec<PathBuf>,
+        on_index_error: OnIndexError,
+    ) -> Result<(Vec<u8>, gix_pack::multi_index::write::Outcome<progress::Discard>), Error> {
+        let mut out = Vec::new();
+        let outcome = gix_pack::multi_index::File::write_from_index_paths(
+            index_paths,
+            &mut out,
+            progress::Discard,
+            &AtomicBool::new(false),
+            options(on_index_error),
+        )?;
+        Ok((out, outcome))
+    }
+
+    #[test]
+    fn truncated_indices_are_an_error_by_default() -> crate::Result {
+        let dir = tempfile::TempDir::new()?;
+        let index_paths = packs_with_truncated_index(dir.path())?;
+        let err = write(index_paths, OnIndexError::default()).map(|_| ()).unwrap_err();
+        assert!(
+            matches!(err, Error::OpenIndex(gix_pack::index::init::Error::Corrupt { .. })),
+            "{:?}",
+            err
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn truncated_indices_can_be_skipped() -> crate::Result {
+        let dir = tempfile::TempDir::new()?;
+        let index_paths = packs_with_truncated_index(dir.path())?;
+        let (out, outcome) = write(index_paths.clone(), OnIndexError::Skip)?;
+        assert_eq!(outcome.skipped_indices.len(), 1);
+        let (skipped_path, err) = &outcome.skipped_indices[0];
+        assert_eq!(skipped_path, &index_paths[1]);
+        assert!(matches!(err, gix_pack::index::init::Error::Corrupt { .. }), "{:?}", err);
+
+        let (expected, expected_outcome) =
+            write(vec![index_paths[0].clone(), index_paths[2].clone()], OnIndexError::Fail)?;
+        assert_eq!(
+            outcome.index_names, expected_outcome.index_names,
+            "pack ids refer to the remaining indices"
+        );
+        assert_eq!(out, expected, "the result is the same as if the index wasn't given");
+
+        let path = dir.path().join("multi-pack-index");
+        std::fs::write(&path, out)?;
+        gix_pack::multi_index::File::at(path)?.verify_integrity_fast(progress::Discard, &AtomicBool::new(false))?;
+        Ok(())
+    }
+
+    #[test]
+    fn nothing_is_written_if_no_index_can_be_opened() -> crate::Result {
+        let dir = tempfile::TempDir::new()?;
+        let index_paths = packs_with_truncated_index(dir.path())?;
+        let err = write(vec![index_paths[1].clone()], OnIndexError::Skip)
+            .map(|_| ())
+            .unwrap_err();
+        assert!(
+            matches!(err, Error::OpenIndex(gix_pack::index::init::Error::Corrupt { .. })),
+            "{:?}",
+            err
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn truncated_indices_can_be_skipped_when_updating() -> crate::Result {
+        let dir = tempfile::TempDir::new()?;
+        let index_paths = packs_with_truncated_index(dir.path())?;
+        let (out, _) = write(vec![index_paths[0].clone()], OnIndexError::Fail)?;
+        let path = dir.path().join("multi-pack-index");
+        std::fs::write(&path, out)?;
+        let existing = gix_pack::multi_index::File::at(&path)?;
+
+        let outcome = gix_pack::multi_index::File::write_updated(
+            &existing,
+            vec![index_paths[1].clone(), index_paths[2].clone()],
+            std::io::sink(),
+            progress::Discard,
+            &AtomicBool::new(false),
+            options(OnIndexError::Skip),
+            UpdateOptions::default(),
+        )?;
+        let file_name = |path: &PathBuf| PathBuf::from(path.file_name().expect("present"));
+        assert_eq!(
+            outcome.index_names,
+            vec![file_name(&index_paths[0]), file_name(&index_paths[2])]
+        );
+        assert_eq!(outcome.skipped_indices.len(), 1);
+        assert_eq!(outcome.skipped_indices[0].0, index_paths[1]);
+        Ok(())
+    }
+}
+
+mod progress_per_index {
+    use std::{
+        path::PathBuf,
+        sync::{atomic::AtomicBool, Arc, Mutex},
+    };
+
+    use gix_features::progress::{Id, MessageLevel, Progress, Step, Unit};
+
+    /// The name, maximum and last step of a progress, as it was dropped.
+    type Record = (String, Option<Step>, Step);
+
+    /// A progress that records its state when dropped.
+    struct Recorder {
+        name: String,
+        max: Option<Step>,
+        step: Step,
+        records: Arc<Mutex<Vec<Record>>>,
+    }
+
+    impl Recorder {
+        fn new(name: impl Into<String>, records: &Arc<Mutex<Vec<Record>>>) -> Self {
+            Recorder {
+                name: name.into(),
+                max: None,
+                step: 0,
+                records: records.clone(),
+            }
+        }
+    }
+
+    impl Drop for Recorder {
+        fn drop(&mut self) {
+            self.records
+                .lock()
+                .expect("not poisoned")
+                .push((self.name.clone(), self.max, self.step));
+        }
+    }
+
+    impl Progress for Recorder {
+        type SubProgress = Recorder;
+
+        fn add_child(&mut self, name: impl Into<String>) -> Self::SubProgress {
+            Recorder::new(name, &self.records)
+        }
+
+        fn add_child_with_id(&mut self, name: impl Into<String>, _id: Id) -> Self::SubProgress {
+            Recorder::new(name, &self.records)
+        }
+
+        fn init(&mut self, max: Option<Step>, _unit: Option<Unit>) {
+            self.max = max;
+            self.step = 0;
+        }
+
+        fn set(&mut self, step: Step) {
+            self.step = step;
+        }
+
+        fn step(&self) -> Step {
+            self.step
+        }
+
+        fn inc_by(&mut self, step: Step) {
+            self.step += step;
+        }
+
+        fn set_name(&mut self, name: impl Into<String>) {
+            self.name = name.into();
+        }
+
+        fn name(&self) -> Option<String> {
+            Some(self.name.clone())
+        }
+
+        fn id(&self) -> Id {
+            *b"TEST"
+        }
+
+        fn message(&mut self, _level: MessageLevel, _message: impl Into<String>) {}
+    }
+
+    #[test]
+    fn each_index_has_its_own_progress_while_collecting_entries() -> crate::Result {
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let index_paths = super::input_indices()?;
+        for thread_limit in [Some(1), None] {
+            records.lock().expect("not poisoned").clear();
+            gix_pack::multi_index::File::write_from_index_paths(
+                index_paths.clone(),
+                std::io::sink(),
+                Recorder::new("root", &records),
+                &AtomicBool::new(false),
+                gix_pack::multi_index::write::Options {
+                    thread_limit,
+                    ..Default::default()
+                },
+            )?;
+
+            let records = records.lock().expect("not poisoned");
+            for path in &index_paths {
+                let name = path.file_name().expect("present").to_string_lossy();
+                let num_objects = gix_pack::index::File::at(path, gix_hash::Kind::Sha1)?.num_objects() as usize;
+                assert!(
+                    records.contains(&(name.clone().into_owned(), Some(num_objects), num_objects)),
+                    "all {} objects of {} are counted in its progress, but got {:?}",
+                    num_objects,
+                    name,
+                    records
+                );
+            }
+            let num_index_records = records
+                .iter()
+                .filter(|(name, _, _)| {
+                    index_paths
+                        .iter()
+                        .any(|path| PathBuf::from(name) == path.file_name().expect("present"))
+                })
+                .count();
+            assert_eq!(num_index_records, index_paths.len(), "there is one progress per index");
+        }
+        Ok(())
+    }
+}
+
+mod interrupt {
+    use std::{
+        path::Path,
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    use gix_features::progress;
+    use gix_pack::multi_index::write::{Error, Options};
+
+    const NUM_OBJECTS: u64 = 200_000;
+
+    /// Write a version 2 index with `NUM_OBJECTS` made-up objects to `path`.
+    fn write_large_index(path: &Path) -> std::io::Result<()> {
+        let entries: Vec<_> = (0..NUM_OBJECTS)
+            .map(|idx| {
+                let mut id = [0u8; 20];
+                id[..8].copy_from_slice(&(idx * (u64::MAX / NUM_OBJECTS)).to_be_bytes());
+                (id, 12 + idx * 16)
+            })
+            .collect();
+        crate::write_index(path, &entries)
+    }
+
+    /// A writer which requests an interruption once `interrupt_after` bytes were written to it.
+    struct InterruptingWriter<'a> {
+        should_interrupt: &'a AtomicBool,
+        interrupt_after: usize,
+        bytes_written: usize,
+    }
+
+    impl std::io::Write for InterruptingWriter<'_> {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.bytes_written += buf.len();
+            if self.bytes_written >= self.interrupt_after {
+                self.should_interrupt.store(true, Ordering::Relaxed);
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn writing_large_chunks_is_aborted_soon_after_an_interrupt_is_requested() -> crate::Result {
+        let dir = tempfile::TempDir::new()?;
+        let index_path = dir.path().join("pack-large.idx");
+        write_large_index(&index_path)?;
+        // An interruption in the middle of the lookup table, which has 20 bytes per object.
+        let interrupt_after = 64 * 1024;
+        let max_entries_after_interrupt = 10_000;
+
+        for max_memory in [None, Some(0)] {
+            let should_interrupt = AtomicBool::new(false);
+            let mut out = InterruptingWriter {
+                should_interrupt: &should_interrupt,
+                interrupt_after,
+                bytes_written: 0,
+            };
+            let err = gix_pack::multi_index::File::write_from_index_paths(
+                vec![index_path.clone()],
+                &mut out,
+                progress::Discard,
+                &should_interrupt,
+                Options {
+                    max_memory,
+                    ..Default::default()
+                },
+            )
+            .map(|_| ())
+            .unwrap_err();
+            assert!(matches!(err, Error::Interrupted), "{:?}", err);
+            assert!(
+                out.bytes_written - interrupt_after < max_entries_after_interrupt * 20,
+                "{} bytes were written after the interrupt was requested, with {:?} bytes of memory",
+                out.bytes_written - interrupt_after,
+                max_memory
+            );
+        }
+        Ok(())
+    }
+}
+
+mod large_offsets {
+    use std::{convert::TryInto, ops::Range, path::Path, sync::atomic::AtomicBool};
+
+    use gix_features::progress;
+    use gix_pack::multi_index::{init, write::Options};
+
+    const HIGH_BIT: u32 = 1 << 31;
+
+    /// Return entries with made-up ids for all `offsets`, sorted by id.
+    fn entries(offsets: &[u64]) -> Vec<([u8; 20], u64)> {
+        offsets
+            .iter()
+            .enumerate()
+            .map(|(idx, offset)| ([idx as u8 + 1; 20], *offset))
+            .collect()
+    }
+
+    /// Write an index with `entries` into `dir` and a multi-index for it, returning the latter.
+    fn write(dir: &Path, entries: &[([u8; 20], u64)]) -> crate::Result<gix_pack::multi_index::File> {
+        let index_path = dir.join("pack-huge.idx");
+        crate::write_index(&index_path, entries)?;
+        let path = dir.join("multi-pack-index");
+        gix_pack::multi_index::File::write_from_index_paths(
+            vec![index_path],
+            std::fs::File::create(&path)?,
+            progress::Discard,
+            &AtomicBool::new(false),
+            Options::default(),
+        )?;
+        Ok(gix_pack::multi_index::File::at(path)?)
+    }
+
+    /// Return the range of bytes of the chunk with `id` in the multi-index `data`, if present.
+    fn chunk_range(data: &[u8], id: &[u8; 4]) -> Option<Range<usize>> {
+        const HEADER_LEN: usize = 12;
+        const TOC_ENTRY_LEN: usize = 4 + 8;
+        let num_chunks = data[6] as usize;
+        let offset_at = |entry: usize| {
+            let start = HEADER_LEN + entry * TOC_ENTRY_LEN + 4;
+            u64::from_be_bytes(data[start..][..8].try_into().expect("8 bytes")) as usize
+        };
+        (0..num_chunks)
+            .find(|entry| &data[HEADER_LEN + entry * TOC_ENTRY_LEN..][..4] == id)
+            .map(|entry| offset_at(entry)..offset_at(entry + 1))
+    }
+
+    /// Return the 32 bit offsets as stored in the offsets chunk of `data`.
+    fn stored_offsets(data: &[u8]) -> Vec<u32> {
+        data[chunk_range(data, b"OOFF").expect("always present")]
+            .chunks(8)
+            .map(|entry| u32::from_be_bytes(entry[4..].try_into().expect("4 bytes")))
+            .collect()
+    }
+
+    fn assert_offsets_round_trip(file: &gix_pack::multi_index::File, entries: &[([u8; 20], u64)]) {
+        assert_eq!(file.num_objects() as usize, entries.len());
+        for (entry, (id, offset)) in file.iter().zip(entries) {
+            assert_eq!(entry.oid.as_bytes(), id);
+            assert_eq!(entry.pack_offset, *offset, "{}", entry.oid);
+            assert_eq!(file.pack_id_and_pack_offset(entry.oid), Some((0, *offset)));
+        }
+    }
+
+    #[test]
+    fn offsets_beyond_4gib_are_stored_in_the_large_offsets_chunk() -> crate::Result {
+        let dir = tempfile::TempDir::new()?;
+        let entries = entries(&[
+            12,
+            0x7fff_ffff,
+            0x8000_0000,
+            0xffff_ffff,
+            0x1_0000_0000,
+            0x12_3456_789a,
+            u64::MAX,
+        ]);
+        let file = write(dir.path(), &entries)?;
+        assert_offsets_round_trip(&file, &entries);
+
+        let data = std::fs::read(file.path())?;
+        assert_eq!(
+            stored_offsets(&data),
+            vec![
+                12,
+                0x7fff_ffff,
+                HIGH_BIT,
+                HIGH_BIT | 1,
+                HIGH_BIT | 2,
+                HIGH_BIT | 3,
+                HIGH_BIT | 4
+            ],
+            "all offsets that don't fit into 31 bits refer to their position in the large offsets chunk"
+        );
+        let large_offsets: Vec<_> = data[chunk_range(&data, b"LOFF").expect("needed for offsets beyond 4GiB")]
+            .chunks(8)
+            .map(|offset| u64::from_be_bytes(offset.try_into().expect("8 bytes")))
+            .collect();
+        assert_eq!(
+            large_offsets,
+            vec![0x8000_0000, 0xffff_ffff, 0x1_0000_0000, 0x12_3456_789a, u64::MAX]
+        );
+
+        file.verify_integrity_fast(progress::Discard, &AtomicBool::new(false))?;
+        Ok(())
+    }
+
+    #[test]
+    fn offsets_up_to_4gib_are_stored_directly_without_large_offsets_chunk() -> crate::Result {
+        let dir = tempfile::TempDir::new()?;
+        let entries = entries(&[12, 0x8000_0000, 0xffff_ffff]);
+        let file = write(dir.path(), &entries)?;
+        assert_offsets_round_trip(&file, &entries);
+
+        let data = std::fs::read(file.path())?;
+        assert_eq!(
+            chunk_range(&data, b"LOFF"),
+            None,
+            "git only writes large offsets if there are offsets that don't fit into 32 bits"
+        );
+        assert_eq!(stored_offsets(&data), vec![12, 0x8000_0000, 0xffff_ffff]);
+        Ok(())
+    }
+
+    #[test]
+    fn large_offsets_out_of_bounds_are_detected() -> crate::Result {
+        let dir = tempfile::TempDir::new()?;
+        let entries = entries(&[12, 0x1_0000_0000]);
+        let path = write(dir.path(), &entries)?.path().to_owned();
+
+        let mut data = std::fs::read(&path)?;
+        let offsets = chunk_range(&data, b"OOFF").expect("always present");
+        let second_offset = offsets.start + 8 + 4;
+        data[second_offset..][..4].copy_from_slice(&(HIGH_BIT | 1).to_be_bytes());
+        let checksum_offset = data.len() - 20;
+        let mut hasher = gix_features::hash::hasher(gix_hash::Kind::Sha1);
+        hasher.update(&data[..checksum_offset]);
+        data[checksum_offset..].copy_from_slice(&hasher.digest());
+        std::fs::write(&path, data)?;
+
+        let err = gix_pack::multi_index::File::at(&path).map(|_| ()).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                init::Error::LargeOffsetOutOfBounds {
+                    index: 1,
+                    large_offset_index: 1,
+                    num_large_offsets: 1
+                }
+            ),
+            "opening the file fails so accessing the offset can't read beyond the large offsets chunk: {:?}",
+            err
+        );
+        Ok(())
+    }
+}
+
+mod update {
+    use std::{path::Path, sync::atomic::AtomicBool};
+
+    use gix_features::progress;
+    use gix_pack::multi_index::write::{Options, UpdateOptions};
+
+    fn write_from_index_paths(paths: &[&Path], out: &Path) -> crate::Result<gix_pack::multi_index::File> {
+        gix_pack::multi_index::File::write_from_index_paths(
+            paths.iter().map(|p| p.to_path_buf()).collect(),
+            std::fs::File::create(out)?,
+            progress::Discard,
+            &AtomicBool::new(false),
+            Options::default(),
+        )?;
+        Ok(gix_pack::multi_index::File::at(out)?)
+    }
+
+    /// Copy all indices next to the multi-index to be written, to be able to remove them.
+    fn indices_in_tempdir() -> crate::Result<(tempfile::TempDir, Vec<std::path::PathBuf>)> {
+        let dir = tempfile::TempDir::new()?;
+        let mut paths = Vec::new();
+        for index in super::input_indices()? {
+            let path = dir.path().join(index.file_name().expect("present"));
+            std::fs::copy(index, &path)?;
+            paths.push(path);
+        }
+        paths.sort();
+        Ok((dir, paths))
+    }
+
+    #[test]
+    fn new_indices_are_added_to_the_existing_entries() -> crate::Result {
+        let (dir, paths) = indices_in_tempdir()?;
+        let expected = write_from_index_paths(&[&paths[0], &paths[1], &paths[2]], &dir.path().join("expected"))?;
+
+        for (existing, new) in [(vec![0, 1], vec![2]), (vec![2], vec![0, 1]), (vec![0, 2], vec![1, 2])] {
+            let existing_paths: Vec<_> = existing.iter().map(|idx| paths[*idx].as_path()).collect();
+            let existing = write_from_index_paths(&existing_paths, &dir.path().join("multi-pack-index"))?;
+            let mut out = Vec::new();
+            let outcome = gix_pack::multi_index::File::write_updated(
+                &existing,
+                new.iter().map(|idx| paths[*idx].clone()).collect(),
+                &mut out,
+                progress::Discard,
+                &AtomicBool::new(false),
+                Options::default(),
+                UpdateOptions::default(),
+            )?;
+            assert_eq!(
+                outcome.multi_index_checksum,
+                expected.checksum(),
+                "the same file is written as if all indices were read"
+            );
+            assert_eq!(&out[out.len() - 20..], expected.checksum().as_slice());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn new_indices_are_added_with_limited_memory() -> crate::Result {
+        let (dir, paths) = indices_in_tempdir()?;
+        let expected = write_from_index_paths(&[&paths[0], &paths[1], &paths[2]], &dir.path().join("expected"))?;
+        let existing = write_from_index_paths(&[&paths[0], &paths[2]], &dir.path().join("multi-pack-index"))?;
+
+        let outcome = gix_pack::multi_index::File::write_updated(
+            &existing,
+            vec![paths[1].clone(), paths[2].clone()],
+            Vec::new(),
+            progress::Discard,
+            &AtomicBool::new(false),
+            Options {
+                max_memory: Some(0),
+                ..Default::default()
+            },
+            UpdateOptions::default(),
+        )?;
+        assert_eq!(outcome.multi_index_checksum, expected.checksum());
+        Ok(())
+    }
+
+    #[test]
+    fn missing_indices_can_be_dropped() -> crate::Result {
+        let (dir, paths) = indices_in_tempdir()?;
+        let existing =
+            write_from_index_paths(&[&paths[0], &paths[1], &paths[2]], &dir.path().join("multi-pack-index"))?;
+        std::fs::remove_file(&paths[1])?;
+
+        let update = |drop_missing_indices| -> crate::Result<gix_hash::ObjectId> {
+            Ok(gix_pack::multi_index::File::write_updated(
+                &existing,
+                Vec::new(),
+                Vec::new(),
+                progress::Discard,
+                &AtomicBool::new(false),
+                Options::default(),
+                UpdateOptions { drop_missing_indices },
+            )?
+            .multi_index_checksum)
+        };
+        assert_eq!(update(false)?, existing.checksum(), "nothing changes by default");
+
+        let expected = write_from_index_paths(&[&paths[0], &paths[2]], &dir.path().join("expected"))?;
+        assert_eq!(update(true)?, expected.checksum());
+        Ok(())
+    }
+
+    #[test]
+    fn an_error_is_returned_if_no_index_remains() -> crate::Result {
+        let (dir, paths) = indices_in_tempdir()?;
+        let existing = write_from_index_paths(&[&paths[0]], &dir.path().join("multi-pack-index"))?;
+        std::fs::remove_file(&paths[0])?;
+
+        let mut out = Vec::new();
+        let err = gix_pack::multi_index::File::write_updated(
+            &existing,
+            Vec::new(),
+            &mut out,
+            progress::Discard,
+            &AtomicBool::new(false),
+            Options::default(),
+            UpdateOptions {
+                drop_missing_indices: true,
+            },
+        )
+        .map(|_| ())
+        .unwrap_err();
+        assert!(
+            matches!(err, gix_pack::multi_index::write::Error::NoIndices),
+            "{:?}",
+            err
+        );
+        assert!(out.is_empty(), "nothing is written");
+        Ok(())
+    }
+}
+
+mod v1_index {
+    use std::{path::Path, sync::atomic::AtomicBool};
+
+    use gix_features::progress;
+    use gix_pack::multi_index::write::Options;
+
+    /// Return a copy of the pack directory written by git with a version 1 index for the first pack and a version 2 index
+    /// for the second one, along with the version 2 index git originally wrote for the first pack.
+    fn pack_dir_with_v1_index() -> crate::Result<(tempfile::TempDir, std::path::PathBuf)> {
+        let fixture = crate::scripted_fixture_read_only("make_pack_with_v1_index.sh")?;
+        let dir = tempfile::TempDir::new()?;
+        for entry in std::fs::read_dir(fixture.join(".git/objects/pack"))? {
+            let path = entry?.path();
+            std::fs::copy(&path, dir.path().join(path.file_name().expect("present")))?;
+        }
+        let mut v2_indices = std::fs::read_dir(fixture.join("v2-index"))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        assert_eq!(v2_indices.len(), 1);
+        Ok((dir, v2_indices.pop().expect("one")))
+    }
+
+    fn write_from_pack_dir(pack_dir: &Path) -> crate::Result<gix_pack::multi_index::File> {
+        gix_pack::multi_index::File::write_from_pack_dir(
+            pack_dir,
+            progress::Discard,
+            &AtomicBool::new(false),
+            Options::default(),
+            Default::default(),
+        )?;
+        Ok(gix_pack::multi_index::File::at(pack_dir.join("multi-pack-index"))?)
+    }
+
+    #[test]
+    fn objects_of_v1_indices_are_written_like_the_ones_of_v2_indices() -> crate::Result {
+        let (dir, v2_index) = pack_dir_with_v1_index()?;
+        let v1_index = dir.path().join(v2_index.file_name().expect("present"));
+        let versions = gix_pack::multi_index::write::index_paths_in_pack_dir(dir.path(), gix_hash::Kind::Sha1)?
+            .iter()
+            .map(|path| Ok(gix_pack::index::File::at(path, gix_hash::Kind::Sha1)?.version()))
+            .collect::<crate::Result<Vec<_>>>()?;
+        assert_eq!(
+            versions.iter().filter(|v| **v == gix_pack::index::Version::V1).count(),
+            1,
+            "git wrote a version 1 index"
+        );
+        assert!(versions.contains(&gix_pack::index::Version::V2));
+
+        let with_v1_index = write_from_pack_dir(dir.path())?;
+        let outcome = with_v1_index.verify_integrity(progress::Discard, &AtomicBool::new(false), Default::default())?;
+        assert_eq!(
+            outcome.num_objects_per_index.iter().sum::<usize>(),
+            with_v1_index.num_objects() as usize
+        );
+        let index = gix_pack::index::File::at(&v1_index, gix_hash::Kind::Sha1)?;
+        for entry in index.iter() {
+            let (pack_index, pack_offset) = with_v1_index.pack_id_and_pack_offset(entry.oid).expect("present");
+            assert_eq!(pack_offset, entry.pack_offset);
+            assert_eq!(
+                with_v1_index.index_names()[pack_index as usize],
+                Path::new(v1_index.file_name().expect("present"))
+            );
+        }
+
+        std::fs::remove_file(&v1_index)?;
+        std::fs::copy(&v2_index, &v1_index)?;
+        let with_v2_index = write_from_pack_dir(dir.path())?;
+        assert_eq!(
+            with_v1_index.checksum(),
+            with_v2_index.checksum(),
+            "the multi-index is the same no matter the index version"
+        );
+        Ok(())
+    }
+
+    /// Write a version 1 index with `entries` of made-up object ids and their pack offsets to `path`, like
+    /// [`crate::write_index()`] does it for version 2 indices. `entries` must be sorted by id.
+    fn write_v1_index(path: &Path, entries: &[([u8; 20], u32)]) -> std::io::Result<()> {
+        use std::io::Write;
+
+        let mut fan = [0u32; 256];
+        for (id, _) in entries {
+            fan[id[0] as usize] += 1;
+        }
+        let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+        let mut num_ids = 0;
+        for count in fan {
+            num_ids += count;
+            out.write_all(&num_ids.to_be_bytes())?;
+        }
+        for (id, offset) in entries {
+            out.write_all(&offset.to_be_bytes())?;
+            out.write_all(id)?;
+        }
+        out.write_all(&[0; 40])?;
+        out.flush()
+    }
+
+    #[test]
+    fn offsets_of_v1_indices_with_the_high_bit_set_are_offsets_and_not_positions_of_large_offsets() -> crate::Result {
+        let dir = tempfile::TempDir::new()?;
+        let v1_index = dir.path().join("pack-a-v1.idx");
+        let v1_entries = [
+            ([1; 20], 12),
+            ([2; 20], 0x7fff_ffff),
+            ([3; 20], 0x8000_0000),
+            ([4; 20], u32::MAX),
+        ];
+        write_v1_index(&v1_index, &v1_entries)?;
+        assert_eq!(
+            gix_pack::index::File::at(&v1_index, gix_hash::Kind::Sha1)?.version(),
+            gix_pack::index::Version::V1,
+            "version 1 indices can store offsets up to 4GiB, but not beyond"
+        );
+
+        let v2_index = dir.path().join("pack-b-v2.idx");
+        crate::write_index(&v2_index, &[([5; 20], 0x1_0000_0000)])?;
+        for index_paths in [vec![v1_index.clone()], vec![v1_index.clone(), v2_index.clone()]] {
+            let path = dir.path().join("multi-pack-index");
+            gix_pack::multi_index::File::write_from_index_paths(
+                index_paths,
+                std::fs::File::create(&path)?,
+                progress::Discard,
+                &AtomicBool::new(false),
+                Options::default(),
+            )?;
+            let file = gix_pack::multi_index::File::at(&path)?;
+            for (id, offset) in &v1_entries {
+                assert_eq!(
+                    file.pack_id_and_pack_offset(gix_hash::ObjectId::from(*id)),
+                    Some((0, u64::from(*offset))),
+                    "offsets are the same with and without a large offsets chunk for the version 2 index"
+                );
+            }
+            file.verify_integrity_fast(progress::Discard, &AtomicBool::new(false))?;
+        }
+        Ok(())
+    }
+}
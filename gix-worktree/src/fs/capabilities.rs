@@ -10,6 +10,7 @@ impl Default for Capabilities {
             ignore_case: true,
             executable_bit: false,
             symlink: false,
+            nanoseconds: true,
         }
     }
 }
@@ -22,6 +23,7 @@ impl Default for Capabilities {
             ignore_case: true,
             executable_bit: true,
             symlink: true,
+            nanoseconds: true,
         }
     }
 }
@@ -34,6 +36,7 @@ impl Default for Capabilities {
             ignore_case: false,
             executable_bit: true,
             symlink: true,
+            nanoseconds: true,
         }
     }
 }
@@ -52,9 +55,28 @@ impl Capabilities {
             ignore_case: Self::probe_ignore_case(root).unwrap_or(ctx.ignore_case),
             precompose_unicode: Self::probe_precompose_unicode(root).unwrap_or(ctx.precompose_unicode),
             executable_bit: Self::probe_file_mode(root).unwrap_or(ctx.executable_bit),
+            nanoseconds: Self::probe_nanoseconds(root).unwrap_or(ctx.nanoseconds),
         }
     }
 
+    fn probe_nanoseconds(root: &Path) -> std::io::Result<bool> {
+        // A file system with nanoseconds could record 0 by chance, which is unlikely enough to not matter.
+        let test_path = root.join("_test_nanoseconds");
+        let res = std::fs::OpenOptions::new()
+            .create_new(true)
+            .write(true)
+            .open(&test_path)
+            .and_then(|f| f.metadata())
+            .and_then(|m| m.modified())
+            .map(|mtime| {
+                mtime
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map_or(false, |d| d.subsec_nanos() != 0)
+            });
+        std::fs::remove_file(test_path)?;
+        res
+    }
+
     #[cfg(unix)]
     fn probe_file_mode(root: &Path) -> std::io::Result<bool> {
         use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
@@ -21,6 +21,10 @@ pub struct Capabilities {
     /// If true, the file system supports symbolic links and we should try to create them. Otherwise symbolic links will be checked
     /// out as files which contain the link as text.
     pub symlink: bool,
+    /// If true, the file system records the nanoseconds of file timestamps, which makes them trustworthy when comparing
+    /// stat information. Otherwise timestamps are truncated to seconds or worse, like on HFS+ or FAT, and their nanoseconds
+    /// are ignored.
+    pub nanoseconds: bool,
 }
 
 /// A stack of path components with the delegation of side-effects as the currently set path changes, component by component.
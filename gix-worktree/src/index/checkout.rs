@@ -77,6 +77,25 @@ impl Default for Options {
         }
     }
 }
+
+impl Options {
+    /// Return the options to record and compare the stat information of checked out files with, as derived from the
+    /// capabilities of the file system and the stat configuration.
+    pub fn stat_options(&self) -> gix_index::entry::stat::Options {
+        gix_index::entry::stat::Options {
+            trust_executable_bit: self.fs.executable_bit,
+            has_symlinks: self.fs.symlink,
+            trust_ctime: self.trust_ctime,
+            trust_nsec: self.fs.nanoseconds,
+            check_stat: if self.check_stat {
+                gix_index::entry::stat::CheckStat::Default
+            } else {
+                gix_index::entry::stat::CheckStat::Minimal
+            },
+            ..Default::default()
+        }
+    }
+}
 #[derive(Debug, thiserror::Error)]
 pub enum Error<E: std::error::Error + Send + Sync + 'static> {
     #[error("Could not convert path to UTF8: {}", .path)]
@@ -1,4 +1,4 @@
-use std::{convert::TryInto, fs::OpenOptions, io::Write, path::Path, time::Duration};
+use std::{fs::OpenOptions, io::Write, path::Path};
 
 use bstr::BStr;
 use gix_hash::oid;
@@ -18,7 +18,14 @@ pub fn checkout<Find, E>(
     entry: &mut Entry,
     entry_path: &BStr,
     Context { find, path_cache, buf }: Context<'_, Find>,
-    index::checkout::Options {
+    options: index::checkout::Options,
+) -> Result<usize, index::checkout::Error<E>>
+where
+    Find: for<'a> FnMut(&oid, &'a mut Vec<u8>) -> Result<gix_object::BlobRef<'a>, E>,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let stat_options = options.stat_options();
+    let index::checkout::Options {
         fs: fs::Capabilities {
             symlink,
             executable_bit,
@@ -27,12 +34,7 @@ pub fn checkout<Find, E>(
         destination_is_initially_empty,
         overwrite_existing,
         ..
-    }: index::checkout::Options,
-) -> Result<usize, index::checkout::Error<E>>
-where
-    Find: for<'a> FnMut(&oid, &'a mut Vec<u8>) -> Result<gix_object::BlobRef<'a>, E>,
-    E: std::error::Error + Send + Sync + 'static,
-{
+    } = options;
     let dest_relative = gix_path::try_from_bstr(entry_path).map_err(|_| index::checkout::Error::IllformedUtf8 {
         path: entry_path.to_owned(),
     })?;
@@ -71,7 +73,7 @@ where
             }
             // NOTE: we don't call `file.sync_all()` here knowing that some filesystems don't handle this well.
             //       revisit this once there is a bug to fix.
-            update_fstat(entry, file.metadata()?)?;
+            entry.set_stat_from_metadata(&file.metadata()?, stat_options);
             file.close()?;
             obj.data.len()
         }
@@ -94,7 +96,7 @@ where
                 file.close()?;
             }
 
-            update_fstat(entry, std::fs::symlink_metadata(dest)?)?;
+            entry.set_stat_from_metadata(&std::fs::symlink_metadata(dest)?, stat_options);
             obj.data.len()
         }
         gix_index::entry::Mode::DIR => todo!(),
@@ -162,28 +164,3 @@ fn open_options(path: &Path, destination_is_initially_empty: bool, overwrite_exi
         .write(true);
     options
 }
-
-fn update_fstat<E>(entry: &mut Entry, meta: std::fs::Metadata) -> Result<(), index::checkout::Error<E>>
-where
-    E: std::error::Error + Send + Sync + 'static,
-{
-    let ctime = meta
-        .created()
-        .map_or(Ok(Duration::default()), |x| x.duration_since(std::time::UNIX_EPOCH))?;
-    let mtime = meta
-        .modified()
-        .map_or(Ok(Duration::default()), |x| x.duration_since(std::time::UNIX_EPOCH))?;
-
-    let stat = &mut entry.stat;
-    stat.mtime.secs = mtime
-        .as_secs()
-        .try_into()
-        .expect("by 2038 we found a solution for this");
-    stat.mtime.nsecs = mtime.subsec_nanos();
-    stat.ctime.secs = ctime
-        .as_secs()
-        .try_into()
-        .expect("by 2038 we found a solution for this");
-    stat.ctime.nsecs = ctime.subsec_nanos();
-    Ok(())
-}
@@ -4,6 +4,11 @@ fn from_probing_cwd() {
     std::fs::File::create(dir.path().join("config")).unwrap();
     let ctx = gix_worktree::fs::Capabilities::probe(dir.path());
     dbg!(ctx);
+    #[cfg(any(target_os = "linux", windows))]
+    assert!(
+        ctx.nanoseconds,
+        "the file systems of CI for these platforms record nanoseconds"
+    );
     let entries: Vec<_> = std::fs::read_dir(dir.path())
         .unwrap()
         .filter_map(Result::ok)
@@ -172,6 +172,33 @@ fn allow_or_disallow_symlinks() -> crate::Result {
     Ok(())
 }
 
+#[test]
+fn checked_out_entries_match_their_files() -> crate::Result {
+    let mut opts = opts_from_probe();
+    for nanoseconds in [false, true] {
+        opts.fs.nanoseconds = nanoseconds;
+        let (_source_tree, destination, index, outcome) =
+            checkout_index_in_tmp_dir(opts.clone(), "make_mixed_without_submodules")?;
+        assert!(outcome.errors.is_empty());
+
+        let stat_options = gix_index::entry::stat::Options {
+            use_nsec: true,
+            ..opts.stat_options()
+        };
+        for entry in index.entries() {
+            let path = destination.path().join(gix_path::from_bstr(entry.path(&index)));
+            assert!(
+                entry.stat_matches(&std::fs::symlink_metadata(&path)?, stat_options),
+                "{path:?} must not appear modified right after checkout"
+            );
+            if !nanoseconds {
+                assert_eq!(entry.stat.mtime.nsecs, 0, "untrusted nanoseconds aren't recorded");
+            }
+        }
+    }
+    Ok(())
+}
+
 #[test]
 fn keep_going_collects_results() {
     let mut opts = opts_from_probe();
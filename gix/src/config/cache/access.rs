@@ -180,6 +180,8 @@ impl Cache {
                 ignore_case: boolean(self, "core.ignoreCase", &Core::IGNORE_CASE, false)?,
                 executable_bit: boolean(self, "core.fileMode", &Core::FILE_MODE, true)?,
                 symlink: boolean(self, "core.symlinks", &Core::SYMLINKS, true)?,
+                // There is no configuration for it, so we assume what's typical for the platform.
+                nanoseconds: gix_worktree::fs::Capabilities::default().nanoseconds,
             },
             thread_limit,
             destination_is_initially_empty: false,
@@ -82,6 +82,12 @@ impl crate::Repository {
                 thread_limit,
                 min_extension_block_in_bytes_for_threading: 0,
                 expected_checksum: None,
+                guess_entry_offsets_for_threading: false,
+                verify_checksum: false,
+                verify_tree: false,
+                verify_paths: None,
+                extensions: gix_index::decode::Load::All,
+                mode: gix_index::decode::Mode::Strict,
             },
         )
         .map_err(Into::into)
@@ -97,9 +103,7 @@ impl crate::Repository {
                 || self.index_path().metadata().and_then(|m| m.modified()).ok(),
                 || {
                     self.open_index().map(Some).or_else(|err| match err {
-                        worktree::open_index::Error::IndexFile(gix_index::file::init::Error::Io(err))
-                            if err.kind() == std::io::ErrorKind::NotFound =>
-                        {
+                        worktree::open_index::Error::IndexFile(gix_index::file::init::Error::NotFound { .. }) => {
                             Ok(None)
                         }
                         err => Err(err),
@@ -109,10 +113,9 @@ impl crate::Repository {
             .and_then(|opt| match opt {
                 Some(index) => Ok(index),
                 None => Err(worktree::open_index::Error::IndexFile(
-                    gix_index::file::init::Error::Io(std::io::Error::new(
-                        std::io::ErrorKind::NotFound,
-                        format!("Could not find index file at {:?} for opening.", self.index_path()),
-                    )),
+                    gix_index::file::init::Error::NotFound {
+                        path: self.index_path(),
+                    },
                 )),
             })
     }
@@ -565,7 +565,11 @@ pub fn main() -> Result<()> {
                                 core::pack::multi_index::verify(multi_index_path, progress, &should_interrupt)
                             },
                         ),
-                        free::pack::multi_index::Subcommands::Create { index_paths } => prepare_and_run(
+                        free::pack::multi_index::Subcommands::Create {
+                            index_paths,
+                            preferred_pack,
+                            drop_redundant_packs,
+                        } => prepare_and_run(
                             "pack-multi-index-create",
                             verbose,
                             progress,
@@ -578,6 +582,9 @@ pub fn main() -> Result<()> {
                                     progress,
                                     &should_interrupt,
                                     object_hash,
+                                    thread_limit,
+                                    preferred_pack,
+                                    drop_redundant_packs,
                                 )
                             },
                         ),
@@ -295,6 +295,14 @@ pub mod pack {
             Verify,
             /// Create a multi-pack index from one or more pack index files, overwriting possibloy existing files.
             Create {
+                /// The name of the pack whose objects should be used if they are contained in more than one pack.
+                ///
+                /// Otherwise objects are taken from the pack whose index was modified most recently.
+                #[clap(long)]
+                preferred_pack: Option<PathBuf>,
+                /// Don't refer to packs whose objects are all taken from other packs, so they can be deleted afterwards.
+                #[clap(long)]
+                drop_redundant_packs: bool,
                 /// Paths to the pack index files to read (with .idx extension).
                 ///
                 /// Note for the multi-index to be useful, it should be side-by-side with the supplied `.idx` files.